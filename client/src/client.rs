@@ -1,6 +1,7 @@
 pub use subxt::events::Events;
 pub use subxt::PolkadotConfig;
 
+use crate::error::ClientError;
 use crate::types::{
     Contract, ContractResources, Farm, FarmPolicy, Hash, Node, NodePower, RuntimeEvents, Twin,
 };
@@ -24,104 +25,80 @@ const BLOCK_TIME_SECONDS: i64 = 6;
 #[async_trait::async_trait]
 pub trait RuntimeClient {
     /// Get all events in a block.
-    async fn events(
-        &self,
-        block: Option<Hash>,
-    ) -> Result<Vec<RuntimeEvents>, Box<dyn std::error::Error>>;
+    async fn events(&self, block: Option<Hash>) -> Result<Vec<RuntimeEvents>, ClientError>;
 
     /// Get the hash of a block at the given height. Note that in this case, block is actually the
     /// height rather than the hash to query at.
-    async fn hash_at_height(
-        &self,
-        block: Option<u32>,
-    ) -> Result<Option<Hash>, Box<dyn std::error::Error>>;
+    async fn hash_at_height(&self, block: Option<u32>) -> Result<Option<Hash>, ClientError>;
 
     /// Get the on chain timestamp of the block, in seconds since the UNIX epoch.
-    async fn timestamp(&self, block: Option<Hash>) -> Result<u64, Box<dyn std::error::Error>>;
+    async fn timestamp(&self, block: Option<Hash>) -> Result<u64, ClientError>;
 
     /// Get the twin referenced by this ID.
-    async fn twin(
-        &self,
-        id: u32,
-        block: Option<Hash>,
-    ) -> Result<Option<Twin>, Box<dyn std::error::Error>>;
+    async fn twin(&self, id: u32, block: Option<Hash>) -> Result<Option<Twin>, ClientError>;
 
     /// Get the amount of twins on the grid.
-    async fn twin_count(&self, block: Option<Hash>) -> Result<u32, Box<dyn std::error::Error>>;
+    async fn twin_count(&self, block: Option<Hash>) -> Result<u32, ClientError>;
 
     /// Get the farm referenced by this ID.
-    async fn farm(
-        &self,
-        id: u32,
-        block: Option<Hash>,
-    ) -> Result<Option<Farm>, Box<dyn std::error::Error>>;
+    async fn farm(&self, id: u32, block: Option<Hash>) -> Result<Option<Farm>, ClientError>;
 
     /// Get the payout address of the farm referenced by this ID.
     async fn farm_payout_address(
         &self,
         id: u32,
         block: Option<Hash>,
-    ) -> Result<Option<String>, Box<dyn std::error::Error>>;
+    ) -> Result<Option<String>, ClientError>;
 
     /// Get the amount of farms on the grid.
-    async fn farm_count(&self, block: Option<Hash>) -> Result<u32, Box<dyn std::error::Error>>;
+    async fn farm_count(&self, block: Option<Hash>) -> Result<u32, ClientError>;
 
     /// Get the node referenced by this ID.
-    async fn node(
-        &self,
-        id: u32,
-        block: Option<Hash>,
-    ) -> Result<Option<Node>, Box<dyn std::error::Error>>;
+    async fn node(&self, id: u32, block: Option<Hash>) -> Result<Option<Node>, ClientError>;
 
     /// Get the amount of nodes on the grid.
-    async fn node_count(&self, block: Option<Hash>) -> Result<u32, Box<dyn std::error::Error>>;
+    async fn node_count(&self, block: Option<Hash>) -> Result<u32, ClientError>;
 
     /// Get the contract referenced by this ID.
-    async fn contract(
-        &self,
-        id: u64,
-        block: Option<Hash>,
-    ) -> Result<Option<Contract>, Box<dyn std::error::Error>>;
+    async fn contract(&self, id: u64, block: Option<Hash>)
+        -> Result<Option<Contract>, ClientError>;
 
     /// Get the resources of the contract referenced by this ID.
     async fn contract_resources(
         &self,
         id: u64,
         block: Option<Hash>,
-    ) -> Result<Option<ContractResources>, Box<dyn std::error::Error>>;
+    ) -> Result<Option<ContractResources>, ClientError>;
 
     /// Get the amount of contracts on the grid.
-    async fn contract_count(&self, block: Option<Hash>) -> Result<u64, Box<dyn std::error::Error>>;
+    async fn contract_count(&self, block: Option<Hash>) -> Result<u64, ClientError>;
 
     /// Get the farming policy referenced by this ID.
     async fn farming_policy(
         &self,
         id: u32,
         block: Option<Hash>,
-    ) -> Result<Option<FarmPolicy>, Box<dyn std::error::Error>>;
+    ) -> Result<Option<FarmPolicy>, ClientError>;
 
     /// Get the amount of farming policies on the grid.
-    async fn farming_policy_count(
-        &self,
-        block: Option<Hash>,
-    ) -> Result<u32, Box<dyn std::error::Error>>;
+    async fn farming_policy_count(&self, block: Option<Hash>) -> Result<u32, ClientError>;
+
+    /// Get the connection price for new nodes, in mUSD.
+    async fn connection_price(&self, block: Option<Hash>) -> Result<u32, ClientError>;
 
     /// Get the NodePower for a node
     async fn node_power(
         &self,
         id: u32,
         block: Option<Hash>,
-    ) -> Result<Option<NodePower>, Box<dyn std::error::Error>>;
+    ) -> Result<Option<NodePower>, ClientError>;
 }
 
 /// Find the height of the chain at the given timestamp.
 ///
 /// This method takes any client, since we assume that the basic storage does not change, and is
 /// therefore consistent across multiple chain versions.
-pub async fn height_at_timestamp(
-    client: &dyn RuntimeClient,
-    ts: i64,
-) -> Result<u32, Box<dyn std::error::Error>> {
+pub async fn height_at_timestamp(client: &dyn RuntimeClient, ts: i64) -> Result<u32, ClientError> {
     let latest_ts = (client.timestamp(None).await? / 1000) as i64;
     if latest_ts < ts {
         panic!(
@@ -1,48 +1,70 @@
 use crate::client::RuntimeClient;
+use crate::error::ClientError;
 use crate::runtimes::{
     v115::types::{
-        V115Contract, V115ContractCreatedEvent, V115ContractNruConsumptionReceivedEvent,
-        V115ContractResources, V115ContractUpdatedResourcesEvent, V115Farm, V115FarmingPolicy,
-        V115Node, V115NodeStoredEvent, V115NodeUpdatedEvent, V115NodeUptimeReportedEvent, V115Twin,
+        V115ConnectionPriceSetEvent, V115Contract, V115ContractCreatedEvent,
+        V115ContractGracePeriodEndedEvent, V115ContractGracePeriodStartedEvent,
+        V115ContractNruConsumptionReceivedEvent, V115ContractResources,
+        V115ContractUpdatedResourcesEvent, V115Farm, V115FarmDeletedEvent, V115FarmingPolicy,
+        V115FarmingPolicySetEvent, V115FarmingPolicyStoredEvent, V115FarmingPolicyUpdatedEvent,
+        V115Node, V115NodeCertificationSetEvent, V115NodeContractCanceledEvent,
+        V115NodeDeletedEvent, V115NodeStoredEvent, V115NodeUpdatedEvent,
+        V115NodeUptimeReportedEvent, V115RentContractCanceledEvent, V115Twin, V115TwinDeletedEvent,
     },
     v123::types::{
-        V123Contract, V123ContractCreatedEvent, V123ContractNruConsumptionReceivedEvent,
-        V123ContractResources, V123ContractUpdatedResourcesEvent, V123Farm, V123FarmingPolicy,
-        V123Node, V123NodeStoredEvent, V123NodeUpdatedEvent, V123NodeUptimeReportedEvent, V123Twin,
+        V123ConnectionPriceSetEvent, V123Contract, V123ContractCreatedEvent,
+        V123ContractGracePeriodEndedEvent, V123ContractGracePeriodStartedEvent,
+        V123ContractNruConsumptionReceivedEvent, V123ContractResources,
+        V123ContractUpdatedResourcesEvent, V123Farm, V123FarmDeletedEvent, V123FarmingPolicy,
+        V123FarmingPolicySetEvent, V123FarmingPolicyStoredEvent, V123FarmingPolicyUpdatedEvent,
+        V123Node, V123NodeCertificationSetEvent, V123NodeContractCanceledEvent,
+        V123NodeDeletedEvent, V123NodeStoredEvent, V123NodeUpdatedEvent,
+        V123NodeUptimeReportedEvent, V123RentContractCanceledEvent, V123Twin, V123TwinDeletedEvent,
     },
     v131::types::{
-        V131Contract, V131ContractCreatedEvent, V131ContractNruConsumptionReceivedEvent,
-        V131ContractResources, V131ContractUpdatedResourcesEvent, V131Farm, V131FarmingPolicy,
-        V131Node, V131NodePower, V131NodeStoredEvent, V131NodeUpdatedEvent,
+        V131ConnectionPriceSetEvent, V131Contract, V131ContractCreatedEvent,
+        V131ContractGracePeriodEndedEvent, V131ContractGracePeriodStartedEvent,
+        V131ContractNruConsumptionReceivedEvent, V131ContractResources,
+        V131ContractUpdatedResourcesEvent, V131Farm, V131FarmDeletedEvent, V131FarmingPolicy,
+        V131FarmingPolicySetEvent, V131FarmingPolicyStoredEvent, V131FarmingPolicyUpdatedEvent,
+        V131Node, V131NodeCertificationSetEvent, V131NodeContractCanceledEvent,
+        V131NodeDeletedEvent, V131NodePower, V131NodeStoredEvent, V131NodeUpdatedEvent,
         V131NodeUptimeReportedEvent, V131PowerStateChangedEvent, V131PowerTargetChangedEvent,
-        V131Twin,
+        V131RentContractCanceledEvent, V131Twin, V131TwinDeletedEvent,
     },
     v141::types::{
-        V141Contract, V141ContractCreatedEvent, V141ContractNruConsumptionReceivedEvent,
-        V141ContractResources, V141ContractUpdatedResourcesEvent, V141Farm, V141FarmingPolicy,
-        V141Node, V141NodePower, V141NodeStoredEvent, V141NodeUpdatedEvent,
+        V141ConnectionPriceSetEvent, V141Contract, V141ContractCreatedEvent,
+        V141ContractGracePeriodEndedEvent, V141ContractGracePeriodStartedEvent,
+        V141ContractNruConsumptionReceivedEvent, V141ContractResources,
+        V141ContractUpdatedResourcesEvent, V141Farm, V141FarmDeletedEvent, V141FarmingPolicy,
+        V141FarmingPolicySetEvent, V141FarmingPolicyStoredEvent, V141FarmingPolicyUpdatedEvent,
+        V141Node, V141NodeCertificationSetEvent, V141NodeContractCanceledEvent,
+        V141NodeDeletedEvent, V141NodePower, V141NodeStoredEvent, V141NodeUpdatedEvent,
         V141NodeUptimeReportedEvent, V141PowerStateChangedEvent, V141PowerTargetChangedEvent,
-        V141Twin,
+        V141RentContractCanceledEvent, V141Twin, V141TwinDeletedEvent,
     },
+    DecoderRegistry, Runtime,
 };
 use crate::types::{
     Contract, ContractResources, Farm, FarmPolicy, Hash, Node, NodePower, RuntimeEvents, Twin,
-    CONTRACTS, CONTRACT_CREATED, CONTRACT_ID, FARMING_POLICIES, FARMING_POLICY_ID, FARMS, FARM_ID,
-    FARM_PAYOUT_V2_ADDRESS, NODES, NODE_CONTRACT_RESOURCES, NODE_ID, NODE_POWER, NODE_STORED,
-    NODE_UPDATED, NODE_UPTIME_REPORTED, NRU_CONSUMPTION_RECEIVED, POWER_STATE_CHANGED,
-    POWER_TARGET_CHANGED, SMART_CONTRACT_MODULE, TFGRID_MODULE, TIMESTAMP_MODULE, TIMESTAMP_NOW,
-    TWINS, TWIN_ID, UPDATE_USED_RESOURCES,
+    CONNECTION_PRICE, CONNECTION_PRICE_SET, CONTRACTS, CONTRACT_CREATED,
+    CONTRACT_GRACE_PERIOD_ENDED, CONTRACT_GRACE_PERIOD_STARTED, CONTRACT_ID, FARMING_POLICIES,
+    FARMING_POLICY_ID, FARMING_POLICY_SET, FARMING_POLICY_STORED, FARMING_POLICY_UPDATED, FARMS,
+    FARM_DELETED, FARM_ID, FARM_PAYOUT_V2_ADDRESS, NODES, NODE_CERTIFICATION_SET,
+    NODE_CONTRACT_CANCELLED, NODE_CONTRACT_RESOURCES, NODE_DELETED, NODE_ID, NODE_POWER,
+    NODE_STORED, NODE_UPDATED, NODE_UPTIME_REPORTED, NRU_CONSUMPTION_RECEIVED, POWER_STATE_CHANGED,
+    POWER_TARGET_CHANGED, RENT_CONTRACT_CANCELLED, SMART_CONTRACT_MODULE, TFGRID_MODULE,
+    TIMESTAMP_MODULE, TIMESTAMP_NOW, TWINS, TWIN_DELETED, TWIN_ID, UPDATE_USED_RESOURCES,
 };
-use std::{error, fmt};
+use codec::Decode;
+use std::{collections::HashMap, error, fmt, future::Future, sync::Mutex};
 use subxt::storage::DynamicStorageAddress;
 use subxt::{
     dynamic::Value,
-    // events::Events,
+    events::Events,
     rpc::types::{BlockNumber, NumberOrHex},
-    OnlineClient,
-    PolkadotConfig,
+    Metadata, OnlineClient, PolkadotConfig,
 };
-use tokio::join;
 
 #[derive(Debug, Clone)]
 pub enum Error {
@@ -73,33 +95,143 @@ impl fmt::Display for Error {
 
 impl error::Error for Error {}
 
+/// Maximum amount of blocks of which the spec version is remembered.
+const SPEC_VERSION_CACHE_SIZE: usize = 1024;
+
+/// Decode an event with the event type of the runtime, and convert it with the given closure. The
+/// event types are given for all runtimes, from the newest to the oldest.
+macro_rules! decode_event {
+    ($evt:expr, $runtime:expr, [$v141:ty, $v131:ty, $v123:ty, $v115:ty], $convert:expr) => {
+        match $runtime {
+            Runtime::V141 => $evt.as_event::<$v141>()?.map($convert),
+            Runtime::V131 => $evt.as_event::<$v131>()?.map($convert),
+            Runtime::V123 => $evt.as_event::<$v123>()?.map($convert),
+            Runtime::V115 => $evt.as_event::<$v115>()?.map($convert),
+        }
+    };
+}
+
+/// Decode storage with the type of a runtime, and convert it to the common type.
+fn decode<T: Decode + Into<U>, U>(data: Vec<u8>, error: Error) -> Result<U, Error> {
+    codec::decode_from_bytes::<T>(data.into())
+        .map(Into::into)
+        .map_err(|_| error)
+}
+
+/// Values which are fetched from the chain at most once per key. A cache with a capacity is
+/// cleared once it is full.
+struct Cache<K, V> {
+    entries: Mutex<HashMap<K, V>>,
+    capacity: Option<usize>,
+}
+
+impl<K: Eq + std::hash::Hash, V: Clone> Cache<K, V> {
+    fn new(capacity: Option<usize>) -> Self {
+        Cache {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+
+    /// The cached value of the key, or the fetched value if it is not cached yet. Failed fetches
+    /// are not cached.
+    async fn get_or_fetch<F, Fut>(&self, key: K, fetch: F) -> Result<V, ClientError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, ClientError>>,
+    {
+        let cached = self.entries.lock().unwrap().get(&key).cloned();
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+        let value = fetch().await?;
+        let mut entries = self.entries.lock().unwrap();
+        if self
+            .capacity
+            .is_some_and(|capacity| entries.len() >= capacity)
+        {
+            entries.clear();
+        }
+        entries.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
 pub struct DynamicClient {
     api: OnlineClient<PolkadotConfig>,
+    registry: DecoderRegistry,
+    /// Spec versions of recently decoded blocks, so the runtime version of a block is only
+    /// fetched once.
+    spec_versions: Cache<Hash, u32>,
+    /// Metadata of every spec version seen so far. The metadata only changes with a runtime
+    /// upgrade, so it is fetched once per upgrade rather than for every block.
+    metadata: Cache<u32, Metadata>,
 }
 
 impl DynamicClient {
-    pub async fn new(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(url: &str) -> Result<Self, ClientError> {
         let api = OnlineClient::from_url(url).await?;
-        Ok(DynamicClient { api })
+        Ok(DynamicClient {
+            api,
+            registry: DecoderRegistry::default(),
+            spec_versions: Cache::new(Some(SPEC_VERSION_CACHE_SIZE)),
+            metadata: Cache::new(None),
+        })
+    }
+
+    /// Decode blocks with the runtimes in the given registry, instead of the default one.
+    pub fn with_registry(mut self, registry: DecoderRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// The runtime which decodes the data of a block.
+    async fn runtime(&self, block: Option<Hash>) -> Result<Runtime, ClientError> {
+        let spec_version = self.spec_version(block).await?;
+        self.registry.resolve(spec_version)
+    }
+
+    /// The spec version of the runtime which produced a block.
+    async fn spec_version(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        let fetch = || async { Ok(self.api.rpc().runtime_version(block).await?.spec_version) };
+        match block {
+            Some(hash) => self.spec_versions.get_or_fetch(hash, fetch).await,
+            // The latest block changes, so its spec version can't be cached.
+            None => fetch().await,
+        }
+    }
+
+    /// The metadata of a spec version, fetched from the given block if it is not cached yet.
+    async fn metadata(&self, block: Hash, spec_version: u32) -> Result<Metadata, ClientError> {
+        self.metadata
+            .get_or_fetch(spec_version, || async {
+                Ok(self.api.rpc().metadata(Some(block)).await?)
+            })
+            .await
     }
 }
 
 #[async_trait::async_trait]
 impl RuntimeClient for DynamicClient {
     /// Get all events in a block.
-    async fn events(
-        &self,
-        block: Option<Hash>,
-    ) -> Result<Vec<RuntimeEvents>, Box<dyn std::error::Error>> {
-        let (meta, runtime_v) = join!(
-            self.api.rpc().metadata(block),
-            self.api.rpc().runtime_version(block),
-        );
-
-        self.api.set_runtime_version(runtime_v?);
-        self.api.set_metadata(meta?);
-
-        let b_events = self.api.events().at(block).await?;
+    async fn events(&self, block: Option<Hash>) -> Result<Vec<RuntimeEvents>, ClientError> {
+        let hash = match block {
+            Some(hash) => hash,
+            None => self
+                .api
+                .rpc()
+                .block_hash(None)
+                .await?
+                .ok_or(ClientError::MissingBlock)?,
+        };
+        let spec_version = self.spec_version(Some(hash)).await?;
+        let runtime = self.registry.resolve(spec_version)?;
+
+        // The events are decoded with the metadata of their own runtime, without changing the
+        // metadata of the client, which may be in use by other calls.
+        let metadata = self.metadata(hash, spec_version).await?;
+        let b_events =
+            Events::<PolkadotConfig>::new_from_client(metadata, hash, self.api.clone()).await?;
 
         let mut events: Vec<RuntimeEvents> = vec![];
         for event in b_events.iter() {
@@ -108,101 +240,229 @@ impl RuntimeClient for DynamicClient {
             }
             let evt = event?;
 
-            match (evt.pallet_name(), evt.variant_name()) {
-                (TFGRID_MODULE, NODE_STORED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141NodeStoredEvent>() {
-                        events.push(RuntimeEvents::NodeStoredEvent(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V131NodeStoredEvent>() {
-                        events.push(RuntimeEvents::NodeStoredEvent(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V123NodeStoredEvent>() {
-                        events.push(RuntimeEvents::NodeStoredEvent(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V115NodeStoredEvent>() {
-                        events.push(RuntimeEvents::NodeStoredEvent(evt.0.into()));
-                    };
-                }
-                (TFGRID_MODULE, NODE_UPDATED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141NodeUpdatedEvent>() {
-                        events.push(RuntimeEvents::NodeUpdatedEvent(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V131NodeUpdatedEvent>() {
-                        events.push(RuntimeEvents::NodeUpdatedEvent(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V123NodeUpdatedEvent>() {
-                        events.push(RuntimeEvents::NodeUpdatedEvent(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V115NodeUpdatedEvent>() {
-                        events.push(RuntimeEvents::NodeUpdatedEvent(evt.0.into()));
-                    };
-                }
-                (TFGRID_MODULE, NODE_UPTIME_REPORTED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141NodeUptimeReportedEvent>() {
-                        events.push(RuntimeEvents::NodeUptimeReported(evt.0, evt.1, evt.2));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V131NodeUptimeReportedEvent>() {
-                        events.push(RuntimeEvents::NodeUptimeReported(evt.0, evt.1, evt.2));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V123NodeUptimeReportedEvent>() {
-                        events.push(RuntimeEvents::NodeUptimeReported(evt.0, evt.1, evt.2));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V115NodeUptimeReportedEvent>() {
-                        events.push(RuntimeEvents::NodeUptimeReported(evt.0, evt.1, evt.2));
-                    };
-                }
-                (SMART_CONTRACT_MODULE, UPDATE_USED_RESOURCES) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141ContractUpdatedResourcesEvent>() {
-                        events.push(RuntimeEvents::ContractUsedResourcesUpdated(evt.0.into()));
-                    } else if let Ok(Some(evt)) =
-                        evt.as_event::<V131ContractUpdatedResourcesEvent>()
-                    {
-                        events.push(RuntimeEvents::ContractUsedResourcesUpdated(evt.0.into()));
-                    } else if let Ok(Some(evt)) =
-                        evt.as_event::<V123ContractUpdatedResourcesEvent>()
-                    {
-                        events.push(RuntimeEvents::ContractUsedResourcesUpdated(evt.0.into()));
-                    } else if let Ok(Some(evt)) =
-                        evt.as_event::<V115ContractUpdatedResourcesEvent>()
-                    {
-                        events.push(RuntimeEvents::ContractUsedResourcesUpdated(evt.0.into()));
-                    };
-                }
-                (SMART_CONTRACT_MODULE, NRU_CONSUMPTION_RECEIVED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141ContractNruConsumptionReceivedEvent>()
-                    {
-                        events.push(RuntimeEvents::NruConsumptionReceived(evt.0.into()));
-                    } else if let Ok(Some(evt)) =
-                        evt.as_event::<V131ContractNruConsumptionReceivedEvent>()
-                    {
-                        events.push(RuntimeEvents::NruConsumptionReceived(evt.0.into()));
-                    } else if let Ok(Some(evt)) =
-                        evt.as_event::<V123ContractNruConsumptionReceivedEvent>()
-                    {
-                        events.push(RuntimeEvents::NruConsumptionReceived(evt.0.into()));
-                    } else if let Ok(Some(evt)) =
-                        evt.as_event::<V115ContractNruConsumptionReceivedEvent>()
-                    {
-                        events.push(RuntimeEvents::NruConsumptionReceived(evt.0.into()));
-                    };
-                }
-                (SMART_CONTRACT_MODULE, CONTRACT_CREATED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141ContractCreatedEvent>() {
-                        events.push(RuntimeEvents::ContractCreated(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V131ContractCreatedEvent>() {
-                        events.push(RuntimeEvents::ContractCreated(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V123ContractCreatedEvent>() {
-                        events.push(RuntimeEvents::ContractCreated(evt.0.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V115ContractCreatedEvent>() {
-                        events.push(RuntimeEvents::ContractCreated(evt.0.into()));
-                    };
-                }
-                (TFGRID_MODULE, POWER_STATE_CHANGED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141PowerStateChangedEvent>() {
-                        events.push(RuntimeEvents::PowerStateChanged(evt.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V131PowerStateChangedEvent>() {
-                        events.push(RuntimeEvents::PowerStateChanged(evt.into()));
-                    };
-                }
-                (TFGRID_MODULE, POWER_TARGET_CHANGED) => {
-                    if let Ok(Some(evt)) = evt.as_event::<V141PowerTargetChangedEvent>() {
-                        events.push(RuntimeEvents::PowerTargetChanged(evt.into()));
-                    } else if let Ok(Some(evt)) = evt.as_event::<V131PowerTargetChangedEvent>() {
-                        events.push(RuntimeEvents::PowerTargetChanged(evt.into()));
-                    };
-                }
-                (_m, _e) => (),
+            let event = match (evt.pallet_name(), evt.variant_name()) {
+                (TFGRID_MODULE, NODE_STORED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141NodeStoredEvent,
+                        V131NodeStoredEvent,
+                        V123NodeStoredEvent,
+                        V115NodeStoredEvent
+                    ],
+                    |e| RuntimeEvents::NodeStoredEvent(e.0.into())
+                ),
+                (TFGRID_MODULE, NODE_UPDATED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141NodeUpdatedEvent,
+                        V131NodeUpdatedEvent,
+                        V123NodeUpdatedEvent,
+                        V115NodeUpdatedEvent
+                    ],
+                    |e| RuntimeEvents::NodeUpdatedEvent(e.0.into())
+                ),
+                (TFGRID_MODULE, NODE_UPTIME_REPORTED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141NodeUptimeReportedEvent,
+                        V131NodeUptimeReportedEvent,
+                        V123NodeUptimeReportedEvent,
+                        V115NodeUptimeReportedEvent
+                    ],
+                    |e| RuntimeEvents::NodeUptimeReported(e.0, e.1, e.2)
+                ),
+                (SMART_CONTRACT_MODULE, UPDATE_USED_RESOURCES) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141ContractUpdatedResourcesEvent,
+                        V131ContractUpdatedResourcesEvent,
+                        V123ContractUpdatedResourcesEvent,
+                        V115ContractUpdatedResourcesEvent
+                    ],
+                    |e| RuntimeEvents::ContractUsedResourcesUpdated(e.0.into())
+                ),
+                (SMART_CONTRACT_MODULE, NRU_CONSUMPTION_RECEIVED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141ContractNruConsumptionReceivedEvent,
+                        V131ContractNruConsumptionReceivedEvent,
+                        V123ContractNruConsumptionReceivedEvent,
+                        V115ContractNruConsumptionReceivedEvent
+                    ],
+                    |e| RuntimeEvents::NruConsumptionReceived(e.0.into())
+                ),
+                (SMART_CONTRACT_MODULE, CONTRACT_CREATED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141ContractCreatedEvent,
+                        V131ContractCreatedEvent,
+                        V123ContractCreatedEvent,
+                        V115ContractCreatedEvent
+                    ],
+                    |e| RuntimeEvents::ContractCreated(e.0.into())
+                ),
+                (TFGRID_MODULE, POWER_STATE_CHANGED) => match runtime {
+                    Runtime::V141 => evt
+                        .as_event::<V141PowerStateChangedEvent>()?
+                        .map(|e| RuntimeEvents::PowerStateChanged(e.into())),
+                    Runtime::V131 => evt
+                        .as_event::<V131PowerStateChangedEvent>()?
+                        .map(|e| RuntimeEvents::PowerStateChanged(e.into())),
+                    // Power management was introduced in runtime 131.
+                    Runtime::V123 | Runtime::V115 => None,
+                },
+                (TFGRID_MODULE, POWER_TARGET_CHANGED) => match runtime {
+                    Runtime::V141 => evt
+                        .as_event::<V141PowerTargetChangedEvent>()?
+                        .map(|e| RuntimeEvents::PowerTargetChanged(e.into())),
+                    Runtime::V131 => evt
+                        .as_event::<V131PowerTargetChangedEvent>()?
+                        .map(|e| RuntimeEvents::PowerTargetChanged(e.into())),
+                    // Power management was introduced in runtime 131.
+                    Runtime::V123 | Runtime::V115 => None,
+                },
+                (TFGRID_MODULE, NODE_DELETED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141NodeDeletedEvent,
+                        V131NodeDeletedEvent,
+                        V123NodeDeletedEvent,
+                        V115NodeDeletedEvent
+                    ],
+                    |e| RuntimeEvents::NodeDeleted(e.0)
+                ),
+                (TFGRID_MODULE, FARM_DELETED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141FarmDeletedEvent,
+                        V131FarmDeletedEvent,
+                        V123FarmDeletedEvent,
+                        V115FarmDeletedEvent
+                    ],
+                    |e| RuntimeEvents::FarmDeleted(e.0)
+                ),
+                (TFGRID_MODULE, TWIN_DELETED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141TwinDeletedEvent,
+                        V131TwinDeletedEvent,
+                        V123TwinDeletedEvent,
+                        V115TwinDeletedEvent
+                    ],
+                    |e| RuntimeEvents::TwinDeleted(e.0)
+                ),
+                (SMART_CONTRACT_MODULE, NODE_CONTRACT_CANCELLED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141NodeContractCanceledEvent,
+                        V131NodeContractCanceledEvent,
+                        V123NodeContractCanceledEvent,
+                        V115NodeContractCanceledEvent
+                    ],
+                    |e| RuntimeEvents::NodeContractCanceled(e.into())
+                ),
+                (SMART_CONTRACT_MODULE, RENT_CONTRACT_CANCELLED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141RentContractCanceledEvent,
+                        V131RentContractCanceledEvent,
+                        V123RentContractCanceledEvent,
+                        V115RentContractCanceledEvent
+                    ],
+                    |e| RuntimeEvents::RentContractCanceled(e.contract_id)
+                ),
+                (SMART_CONTRACT_MODULE, CONTRACT_GRACE_PERIOD_STARTED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141ContractGracePeriodStartedEvent,
+                        V131ContractGracePeriodStartedEvent,
+                        V123ContractGracePeriodStartedEvent,
+                        V115ContractGracePeriodStartedEvent
+                    ],
+                    |e| RuntimeEvents::ContractGracePeriodStarted(e.into())
+                ),
+                (SMART_CONTRACT_MODULE, CONTRACT_GRACE_PERIOD_ENDED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141ContractGracePeriodEndedEvent,
+                        V131ContractGracePeriodEndedEvent,
+                        V123ContractGracePeriodEndedEvent,
+                        V115ContractGracePeriodEndedEvent
+                    ],
+                    |e| RuntimeEvents::ContractGracePeriodEnded(e.into())
+                ),
+                (TFGRID_MODULE, NODE_CERTIFICATION_SET) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141NodeCertificationSetEvent,
+                        V131NodeCertificationSetEvent,
+                        V123NodeCertificationSetEvent,
+                        V115NodeCertificationSetEvent
+                    ],
+                    |e| RuntimeEvents::NodeCertificationSet(e.into())
+                ),
+                (TFGRID_MODULE, FARMING_POLICY_SET) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141FarmingPolicySetEvent,
+                        V131FarmingPolicySetEvent,
+                        V123FarmingPolicySetEvent,
+                        V115FarmingPolicySetEvent
+                    ],
+                    |e| RuntimeEvents::FarmingPolicySet(e.into())
+                ),
+                (TFGRID_MODULE, FARMING_POLICY_UPDATED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141FarmingPolicyUpdatedEvent,
+                        V131FarmingPolicyUpdatedEvent,
+                        V123FarmingPolicyUpdatedEvent,
+                        V115FarmingPolicyUpdatedEvent
+                    ],
+                    |e| RuntimeEvents::FarmingPolicyUpdated(e.0.into())
+                ),
+                (TFGRID_MODULE, FARMING_POLICY_STORED) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141FarmingPolicyStoredEvent,
+                        V131FarmingPolicyStoredEvent,
+                        V123FarmingPolicyStoredEvent,
+                        V115FarmingPolicyStoredEvent
+                    ],
+                    |e| RuntimeEvents::FarmingPolicyStored(e.0.into())
+                ),
+                (TFGRID_MODULE, CONNECTION_PRICE_SET) => decode_event!(
+                    evt,
+                    runtime,
+                    [
+                        V141ConnectionPriceSetEvent,
+                        V131ConnectionPriceSetEvent,
+                        V123ConnectionPriceSetEvent,
+                        V115ConnectionPriceSetEvent
+                    ],
+                    |e| RuntimeEvents::ConnectionPriceSet(e.0)
+                ),
+                (_m, _e) => None,
+            };
+            if let Some(event) = event {
+                events.push(event);
             }
         }
         Ok(events)
@@ -210,10 +470,7 @@ impl RuntimeClient for DynamicClient {
 
     /// Get the hash of a block at the given height. Note that in this case, block is actually the
     /// height rather than the hash to query at.
-    async fn hash_at_height(
-        &self,
-        block: Option<u32>,
-    ) -> Result<Option<Hash>, Box<dyn std::error::Error>> {
+    async fn hash_at_height(&self, block: Option<u32>) -> Result<Option<Hash>, ClientError> {
         Ok(self
             .api
             .rpc()
@@ -222,7 +479,7 @@ impl RuntimeClient for DynamicClient {
     }
 
     /// Get the on chain timestamp of the block, in seconds since the UNIX epoch.
-    async fn timestamp(&self, block: Option<Hash>) -> Result<u64, Box<dyn std::error::Error>> {
+    async fn timestamp(&self, block: Option<Hash>) -> Result<u64, ClientError> {
         let storage_address: DynamicStorageAddress<Value> =
             subxt::dynamic::storage(TIMESTAMP_MODULE, TIMESTAMP_NOW, vec![]);
         let result = self
@@ -238,11 +495,7 @@ impl RuntimeClient for DynamicClient {
     }
 
     /// Get the twin referenced by this ID.
-    async fn twin(
-        &self,
-        id: u32,
-        block: Option<Hash>,
-    ) -> Result<Option<Twin>, Box<dyn std::error::Error>> {
+    async fn twin(&self, id: u32, block: Option<Hash>) -> Result<Option<Twin>, ClientError> {
         let storage_address =
             subxt::dynamic::storage(TFGRID_MODULE, TWINS, vec![Value::u128(id.into())]);
         let result = self
@@ -259,21 +512,18 @@ impl RuntimeClient for DynamicClient {
 
         let r: Vec<u8> = result.unwrap().into_encoded();
 
-        if let Ok(twin) = codec::decode_from_bytes::<V141Twin>(r.clone().into()) {
-            Ok(Some(twin.into()))
-        } else if let Ok(twin) = codec::decode_from_bytes::<V131Twin>(r.clone().into()) {
-            Ok(Some(twin.into()))
-        } else if let Ok(twin) = codec::decode_from_bytes::<V123Twin>(r.clone().into()) {
-            Ok(Some(twin.into()))
-        } else if let Ok(twin) = codec::decode_from_bytes::<V115Twin>(r.clone().into()) {
-            Ok(Some(twin.into()))
-        } else {
-            return Err(Error::ErrorDecodingTwin.into());
-        }
+        let twin = match self.runtime(block).await? {
+            Runtime::V141 => decode::<V141Twin, _>(r, Error::ErrorDecodingTwin)?,
+            Runtime::V131 => decode::<V131Twin, _>(r, Error::ErrorDecodingTwin)?,
+            Runtime::V123 => decode::<V123Twin, _>(r, Error::ErrorDecodingTwin)?,
+            Runtime::V115 => decode::<V115Twin, _>(r, Error::ErrorDecodingTwin)?,
+        };
+
+        Ok(Some(twin))
     }
 
     /// Get the amount of twins on the grid.
-    async fn twin_count(&self, block: Option<Hash>) -> Result<u32, Box<dyn std::error::Error>> {
+    async fn twin_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
         let storage_address: DynamicStorageAddress<Value> =
             subxt::dynamic::storage(TFGRID_MODULE, TWIN_ID, vec![]);
         let result = self
@@ -289,11 +539,7 @@ impl RuntimeClient for DynamicClient {
     }
 
     /// Get the farm referenced by this ID.
-    async fn farm(
-        &self,
-        id: u32,
-        block: Option<Hash>,
-    ) -> Result<Option<Farm>, Box<dyn std::error::Error>> {
+    async fn farm(&self, id: u32, block: Option<Hash>) -> Result<Option<Farm>, ClientError> {
         let storage_address =
             subxt::dynamic::storage(TFGRID_MODULE, FARMS, vec![Value::u128(id.into())]);
         let result = self
@@ -310,17 +556,14 @@ impl RuntimeClient for DynamicClient {
 
         let r: Vec<u8> = result.unwrap().into_encoded();
 
-        if let Ok(farm) = codec::decode_from_bytes::<V141Farm>(r.clone().into()) {
-            Ok(Some(farm.into()))
-        } else if let Ok(farm) = codec::decode_from_bytes::<V131Farm>(r.clone().into()) {
-            Ok(Some(farm.into()))
-        } else if let Ok(farm) = codec::decode_from_bytes::<V123Farm>(r.clone().into()) {
-            Ok(Some(farm.into()))
-        } else if let Ok(farm) = codec::decode_from_bytes::<V115Farm>(r.clone().into()) {
-            Ok(Some(farm.into()))
-        } else {
-            return Err(Error::ErrorDecodingFarm.into());
-        }
+        let farm = match self.runtime(block).await? {
+            Runtime::V141 => decode::<V141Farm, _>(r, Error::ErrorDecodingFarm)?,
+            Runtime::V131 => decode::<V131Farm, _>(r, Error::ErrorDecodingFarm)?,
+            Runtime::V123 => decode::<V123Farm, _>(r, Error::ErrorDecodingFarm)?,
+            Runtime::V115 => decode::<V115Farm, _>(r, Error::ErrorDecodingFarm)?,
+        };
+
+        Ok(Some(farm))
     }
 
     /// Get the payout address of the farm referenced by this ID.
@@ -328,7 +571,7 @@ impl RuntimeClient for DynamicClient {
         &self,
         id: u32,
         block: Option<Hash>,
-    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<String>, ClientError> {
         let storage_address = subxt::dynamic::storage(
             TFGRID_MODULE,
             FARM_PAYOUT_V2_ADDRESS,
@@ -352,7 +595,7 @@ impl RuntimeClient for DynamicClient {
     }
 
     /// Get the amount of farms on the grid.
-    async fn farm_count(&self, block: Option<Hash>) -> Result<u32, Box<dyn std::error::Error>> {
+    async fn farm_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
         let storage_address: DynamicStorageAddress<Value> =
             subxt::dynamic::storage(TFGRID_MODULE, FARM_ID, vec![]);
         let result = self
@@ -368,11 +611,7 @@ impl RuntimeClient for DynamicClient {
     }
 
     /// Get the node referenced by this ID.
-    async fn node(
-        &self,
-        id: u32,
-        block: Option<Hash>,
-    ) -> Result<Option<Node>, Box<dyn std::error::Error>> {
+    async fn node(&self, id: u32, block: Option<Hash>) -> Result<Option<Node>, ClientError> {
         let storage_address =
             subxt::dynamic::storage(TFGRID_MODULE, NODES, vec![Value::u128(id.into())]);
         let result = self
@@ -389,21 +628,18 @@ impl RuntimeClient for DynamicClient {
 
         let r: Vec<u8> = result.unwrap().into_encoded();
 
-        if let Ok(node) = codec::decode_from_bytes::<V141Node>(r.clone().into()) {
-            Ok(Some(node.into()))
-        } else if let Ok(node) = codec::decode_from_bytes::<V131Node>(r.clone().into()) {
-            Ok(Some(node.into()))
-        } else if let Ok(node) = codec::decode_from_bytes::<V123Node>(r.clone().into()) {
-            Ok(Some(node.into()))
-        } else if let Ok(node) = codec::decode_from_bytes::<V115Node>(r.clone().into()) {
-            Ok(Some(node.into()))
-        } else {
-            return Err(Error::ErrorDecodingNode.into());
-        }
+        let node = match self.runtime(block).await? {
+            Runtime::V141 => decode::<V141Node, _>(r, Error::ErrorDecodingNode)?,
+            Runtime::V131 => decode::<V131Node, _>(r, Error::ErrorDecodingNode)?,
+            Runtime::V123 => decode::<V123Node, _>(r, Error::ErrorDecodingNode)?,
+            Runtime::V115 => decode::<V115Node, _>(r, Error::ErrorDecodingNode)?,
+        };
+
+        Ok(Some(node))
     }
 
     /// Get the amount of nodes on the grid.
-    async fn node_count(&self, block: Option<Hash>) -> Result<u32, Box<dyn std::error::Error>> {
+    async fn node_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
         let storage_address: DynamicStorageAddress<Value> =
             subxt::dynamic::storage(TFGRID_MODULE, NODE_ID, vec![]);
         let result = self
@@ -423,7 +659,7 @@ impl RuntimeClient for DynamicClient {
         &self,
         id: u64,
         block: Option<Hash>,
-    ) -> Result<Option<Contract>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<Contract>, ClientError> {
         let storage_address = subxt::dynamic::storage(
             SMART_CONTRACT_MODULE,
             CONTRACTS,
@@ -443,17 +679,14 @@ impl RuntimeClient for DynamicClient {
 
         let r = result.unwrap().into_encoded();
 
-        if let Ok(contract) = codec::decode_from_bytes::<V141Contract>(r.clone().into()) {
-            Ok(Some(contract.into()))
-        } else if let Ok(contract) = codec::decode_from_bytes::<V131Contract>(r.clone().into()) {
-            Ok(Some(contract.into()))
-        } else if let Ok(contract) = codec::decode_from_bytes::<V123Contract>(r.clone().into()) {
-            Ok(Some(contract.into()))
-        } else if let Ok(contract) = codec::decode_from_bytes::<V115Contract>(r.clone().into()) {
-            Ok(Some(contract.into()))
-        } else {
-            return Err(Error::ErrorDecodingContract.into());
-        }
+        let contract = match self.runtime(block).await? {
+            Runtime::V141 => decode::<V141Contract, _>(r, Error::ErrorDecodingContract)?,
+            Runtime::V131 => decode::<V131Contract, _>(r, Error::ErrorDecodingContract)?,
+            Runtime::V123 => decode::<V123Contract, _>(r, Error::ErrorDecodingContract)?,
+            Runtime::V115 => decode::<V115Contract, _>(r, Error::ErrorDecodingContract)?,
+        };
+
+        Ok(Some(contract))
     }
 
     /// Get the resources of the contract referenced by this ID.
@@ -461,7 +694,7 @@ impl RuntimeClient for DynamicClient {
         &self,
         id: u64,
         block: Option<Hash>,
-    ) -> Result<Option<ContractResources>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<ContractResources>, ClientError> {
         let storage_address = subxt::dynamic::storage(
             SMART_CONTRACT_MODULE,
             NODE_CONTRACT_RESOURCES,
@@ -481,27 +714,26 @@ impl RuntimeClient for DynamicClient {
 
         let r = result.unwrap().into_encoded();
 
-        if let Ok(contract) = codec::decode_from_bytes::<V141ContractResources>(r.clone().into()) {
-            Ok(Some(contract.into()))
-        } else if let Ok(contract) =
-            codec::decode_from_bytes::<V131ContractResources>(r.clone().into())
-        {
-            Ok(Some(contract.into()))
-        } else if let Ok(contract) =
-            codec::decode_from_bytes::<V123ContractResources>(r.clone().into())
-        {
-            Ok(Some(contract.into()))
-        } else if let Ok(contract) =
-            codec::decode_from_bytes::<V115ContractResources>(r.clone().into())
-        {
-            Ok(Some(contract.into()))
-        } else {
-            return Err(Error::ErrorDecodingContractResources.into());
-        }
+        let contract = match self.runtime(block).await? {
+            Runtime::V141 => {
+                decode::<V141ContractResources, _>(r, Error::ErrorDecodingContractResources)?
+            }
+            Runtime::V131 => {
+                decode::<V131ContractResources, _>(r, Error::ErrorDecodingContractResources)?
+            }
+            Runtime::V123 => {
+                decode::<V123ContractResources, _>(r, Error::ErrorDecodingContractResources)?
+            }
+            Runtime::V115 => {
+                decode::<V115ContractResources, _>(r, Error::ErrorDecodingContractResources)?
+            }
+        };
+
+        Ok(Some(contract))
     }
 
     /// Get the amount of contracts on the grid.
-    async fn contract_count(&self, block: Option<Hash>) -> Result<u64, Box<dyn std::error::Error>> {
+    async fn contract_count(&self, block: Option<Hash>) -> Result<u64, ClientError> {
         let storage_address: DynamicStorageAddress<Value> =
             subxt::dynamic::storage(SMART_CONTRACT_MODULE, CONTRACT_ID, vec![]);
         let result = self
@@ -521,7 +753,7 @@ impl RuntimeClient for DynamicClient {
         &self,
         id: u32,
         block: Option<Hash>,
-    ) -> Result<Option<FarmPolicy>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<FarmPolicy>, ClientError> {
         let storage_address = subxt::dynamic::storage(
             TFGRID_MODULE,
             FARMING_POLICIES,
@@ -541,24 +773,18 @@ impl RuntimeClient for DynamicClient {
 
         let r = result.unwrap().into_encoded();
 
-        if let Ok(policy) = codec::decode_from_bytes::<V141FarmingPolicy>(r.clone().into()) {
-            Ok(Some(policy.into()))
-        } else if let Ok(policy) = codec::decode_from_bytes::<V131FarmingPolicy>(r.clone().into()) {
-            Ok(Some(policy.into()))
-        } else if let Ok(policy) = codec::decode_from_bytes::<V123FarmingPolicy>(r.clone().into()) {
-            Ok(Some(policy.into()))
-        } else if let Ok(policy) = codec::decode_from_bytes::<V115FarmingPolicy>(r.clone().into()) {
-            Ok(Some(policy.into()))
-        } else {
-            return Err(Error::ErrorDecodingFarmingPolicy.into());
-        }
+        let policy = match self.runtime(block).await? {
+            Runtime::V141 => decode::<V141FarmingPolicy, _>(r, Error::ErrorDecodingFarmingPolicy)?,
+            Runtime::V131 => decode::<V131FarmingPolicy, _>(r, Error::ErrorDecodingFarmingPolicy)?,
+            Runtime::V123 => decode::<V123FarmingPolicy, _>(r, Error::ErrorDecodingFarmingPolicy)?,
+            Runtime::V115 => decode::<V115FarmingPolicy, _>(r, Error::ErrorDecodingFarmingPolicy)?,
+        };
+
+        Ok(Some(policy))
     }
 
     /// Get the amount of farming policies on the grid.
-    async fn farming_policy_count(
-        &self,
-        block: Option<Hash>,
-    ) -> Result<u32, Box<dyn std::error::Error>> {
+    async fn farming_policy_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
         let storage_address: DynamicStorageAddress<Value> =
             subxt::dynamic::storage(TFGRID_MODULE, FARMING_POLICY_ID, vec![]);
         let result = self
@@ -573,12 +799,27 @@ impl RuntimeClient for DynamicClient {
         Ok(result.as_u128().map_or(0, |x| x as u32))
     }
 
+    async fn connection_price(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        let storage_address: DynamicStorageAddress<Value> =
+            subxt::dynamic::storage(TFGRID_MODULE, CONNECTION_PRICE, vec![]);
+        let result = self
+            .api
+            .storage()
+            .at(block)
+            .await?
+            .fetch_or_default(&storage_address)
+            .await?
+            .to_value()?;
+
+        Ok(result.as_u128().map_or(0, |x| x as u32))
+    }
+
     /// Get the NodePower for a node
     async fn node_power(
         &self,
         id: u32,
         block: Option<Hash>,
-    ) -> Result<Option<NodePower>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<NodePower>, ClientError> {
         let storage_address =
             subxt::dynamic::storage(TFGRID_MODULE, NODE_POWER, vec![Value::u128(id.into())]);
         let result = self
@@ -595,12 +836,67 @@ impl RuntimeClient for DynamicClient {
 
         let r = result.unwrap().into_encoded();
 
-        if let Ok(node_power) = codec::decode_from_bytes::<V141NodePower>(r.clone().into()) {
-            Ok(Some(node_power.into()))
-        } else if let Ok(node_power) = codec::decode_from_bytes::<V131NodePower>(r.into()) {
-            Ok(Some(node_power.into()))
-        } else {
-            return Err(Error::ErrorDecodingFarmingPolicy.into());
-        }
+        let node_power = match self.runtime(block).await? {
+            Runtime::V141 => decode::<V141NodePower, _>(r, Error::ErrorDecodingNodePower)?,
+            Runtime::V131 => decode::<V131NodePower, _>(r, Error::ErrorDecodingNodePower)?,
+            // Power management was introduced in runtime 131.
+            Runtime::V123 | Runtime::V115 => return Err(Error::ErrorDecodingNodePower.into()),
+        };
+
+        Ok(Some(node_power))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Fetch the value through the cache, counting the fetches.
+    async fn fetch(cache: &Cache<u32, u32>, key: u32, value: u32, fetches: &AtomicUsize) -> u32 {
+        cache
+            .get_or_fetch(key, || async {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                Ok(value)
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn values_are_fetched_once_per_key() {
+        let cache = Cache::new(None);
+        let fetches = AtomicUsize::new(0);
+        assert_eq!(fetch(&cache, 141, 1, &fetches).await, 1);
+        // The cached value is used, even if the chain would return something else.
+        assert_eq!(fetch(&cache, 141, 2, &fetches).await, 1);
+        assert_eq!(fetch(&cache, 131, 3, &fetches).await, 3);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_fetches_are_not_cached() {
+        let cache = Cache::new(None);
+        let result = cache
+            .get_or_fetch(141, || async { Err(ClientError::Timeout) })
+            .await;
+        assert!(matches!(result, Err(ClientError::Timeout)));
+        let fetches = AtomicUsize::new(0);
+        assert_eq!(fetch(&cache, 141, 1, &fetches).await, 1);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn full_cache_is_cleared() {
+        let cache = Cache::new(Some(2));
+        let fetches = AtomicUsize::new(0);
+        fetch(&cache, 1, 1, &fetches).await;
+        fetch(&cache, 2, 2, &fetches).await;
+        fetch(&cache, 3, 3, &fetches).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        // Adding the third key cleared the first ones.
+        fetch(&cache, 3, 3, &fetches).await;
+        fetch(&cache, 1, 1, &fetches).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 4);
     }
 }
@@ -0,0 +1,128 @@
+use crate::dynamic;
+use crate::types::Hash;
+use std::{error, fmt};
+
+/// An error returned by a [`RuntimeClient`](crate::client::RuntimeClient).
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request to the chain node failed, e.g. because the connection dropped.
+    /// These errors are usually transient, and the request can be retried.
+    Transport(subxt::Error),
+    /// The chain node did not answer the request in time.
+    Timeout,
+    /// Data returned by the chain node could not be decoded.
+    Decode(String),
+    /// The requested block does not exist on the chain.
+    MissingBlock,
+    /// The block was produced by a runtime which is not in the decoder registry.
+    UnsupportedRuntime { spec_version: u32 },
+    /// Chain nodes returned different hashes for the block at the height, i.e. some of them are
+    /// on a different fork.
+    InconsistentChain {
+        height: u32,
+        hashes: Vec<(String, Hash)>,
+    },
+}
+
+impl ClientError {
+    /// Whether the error is transient, i.e. the same request might succeed if it is retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ClientError::Transport(_) | ClientError::Timeout)
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "request to chain failed: {e}"),
+            ClientError::Timeout => write!(f, "request to chain timed out"),
+            ClientError::Decode(e) => write!(f, "failed to decode chain data: {e}"),
+            ClientError::MissingBlock => write!(f, "block not found"),
+            ClientError::UnsupportedRuntime { spec_version } => {
+                write!(f, "unsupported runtime spec version {spec_version}")
+            }
+            ClientError::InconsistentChain { height, hashes } => {
+                write!(f, "chain nodes disagree on block {height}:")?;
+                for (url, hash) in hashes {
+                    write!(f, " {url} has {hash:?}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ClientError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<subxt::Error> for ClientError {
+    fn from(e: subxt::Error) -> Self {
+        match e {
+            subxt::Error::Io(_) | subxt::Error::Rpc(_) => ClientError::Transport(e),
+            e => ClientError::Decode(e.to_string()),
+        }
+    }
+}
+
+impl From<codec::Error> for ClientError {
+    fn from(e: codec::Error) -> Self {
+        ClientError::Decode(e.to_string())
+    }
+}
+
+impl From<dynamic::Error> for ClientError {
+    fn from(e: dynamic::Error) -> Self {
+        ClientError::Decode(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_transport_errors_and_timeouts_are_transient() {
+        let io = subxt::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        assert!(ClientError::from(io).is_transient());
+        assert!(ClientError::Timeout.is_transient());
+        for e in [
+            ClientError::Decode("bad data".to_string()),
+            ClientError::MissingBlock,
+            ClientError::UnsupportedRuntime { spec_version: 200 },
+            ClientError::InconsistentChain {
+                height: 1,
+                hashes: vec![],
+            },
+        ] {
+            assert!(!e.is_transient(), "{e}");
+        }
+    }
+
+    #[test]
+    fn decoding_errors_are_not_transport_errors() {
+        let e = ClientError::from(codec::Error::from("bad data"));
+        assert!(matches!(e, ClientError::Decode(_)));
+        let e = ClientError::from(subxt::Error::Other("bad data".to_string()));
+        assert!(matches!(e, ClientError::Decode(_)));
+    }
+
+    #[test]
+    fn inconsistent_chain_names_the_nodes() {
+        let e = ClientError::InconsistentChain {
+            height: 7,
+            hashes: vec![
+                ("wss://a".to_string(), Hash::repeat_byte(1)),
+                ("wss://b".to_string(), Hash::repeat_byte(2)),
+            ],
+        };
+        let message = e.to_string();
+        assert!(message.starts_with("chain nodes disagree on block 7:"));
+        assert!(message.contains("wss://a") && message.contains("wss://b"));
+    }
+}
@@ -1,7 +1,11 @@
 pub mod client;
 pub mod dynamic;
+pub mod error;
 pub mod events;
+pub mod multi;
+pub mod retry;
 pub mod runtimes;
+pub mod stream;
 pub mod types;
 
 #[cfg(test)]
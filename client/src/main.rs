@@ -1,9 +1,15 @@
 use tfchain_client::client::RuntimeClient;
 use tfchain_client::dynamic;
 
+/// Chain to connect to if no websocket url is passed.
+const DEFAULT_URL: &str = "wss://tfchain.grid.tf:443";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let dyn_cl = dynamic::DynamicClient::new("wss://tfchain.grid.tf:443").await?;
+    let url = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_URL.to_string());
+    let dyn_cl = dynamic::DynamicClient::new(&url).await?;
 
     // let block_before_upgrade = dyn_cl.hash_at_height(Some(5710579 as u32)).await?;
     // let time = dyn_cl.timestamp(block_before_upgrade).await?;
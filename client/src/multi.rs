@@ -0,0 +1,378 @@
+use crate::client::RuntimeClient;
+use crate::dynamic::DynamicClient;
+use crate::error::ClientError;
+use crate::types::{
+    Contract, ContractResources, Farm, FarmPolicy, Hash, Node, NodePower, RuntimeEvents, Twin,
+};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time a chain node gets to answer a request, before the request is sent to another node.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time a chain node which failed is left alone, before requests are sent to it again.
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// A chain node which serves the requests of a [`MultiClient`].
+struct Endpoint {
+    url: String,
+    state: Mutex<EndpointState>,
+}
+
+enum EndpointState {
+    /// Connected, and the last request succeeded.
+    Up(Arc<DynamicClient>),
+    /// The last request or connection attempt failed. The node is not used before the given
+    /// time, unless no other node is available.
+    Down(Instant),
+}
+
+impl Endpoint {
+    fn new(url: &str) -> Self {
+        Endpoint {
+            url: url.to_string(),
+            state: Mutex::new(EndpointState::Down(Instant::now())),
+        }
+    }
+
+    /// Whether requests can be sent to the node.
+    fn is_available(&self) -> bool {
+        match &*self.state.lock().unwrap() {
+            EndpointState::Up(_) => true,
+            EndpointState::Down(until) => Instant::now() >= *until,
+        }
+    }
+
+    fn mark_down(&self) {
+        *self.state.lock().unwrap() = EndpointState::Down(Instant::now() + RECONNECT_DELAY);
+    }
+
+    /// The connection to the node, which is set up again if the node was down.
+    async fn client(&self) -> Result<Arc<DynamicClient>, ClientError> {
+        let connected = match &*self.state.lock().unwrap() {
+            EndpointState::Up(client) => Some(client.clone()),
+            EndpointState::Down(_) => None,
+        };
+        if let Some(client) = connected {
+            return Ok(client);
+        }
+        match DynamicClient::new(&self.url).await {
+            Ok(client) => {
+                let client = Arc::new(client);
+                *self.state.lock().unwrap() = EndpointState::Up(client.clone());
+                Ok(client)
+            }
+            Err(e) => {
+                self.mark_down();
+                Err(e)
+            }
+        }
+    }
+
+    /// Send a request to the node. The node is marked as down if the request fails with a
+    /// transient error, or if it is not answered in time.
+    async fn request<T, F, Fut>(&self, request: &F) -> Result<T, ClientError>
+    where
+        F: Fn(Arc<DynamicClient>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let client = self.client().await?;
+        let result = match tokio::time::timeout(REQUEST_TIMEOUT, request(client)).await {
+            Ok(result) => result,
+            Err(_) => Err(ClientError::Timeout),
+        };
+        if matches!(&result, Err(e) if e.is_transient()) {
+            self.mark_down();
+        }
+        result
+    }
+}
+
+/// A [`RuntimeClient`] which spreads its requests over multiple chain nodes of the same chain.
+///
+/// Requests are sent to the nodes in turn. If a node fails to answer a request, because the
+/// connection dropped or the node stalled, the request is sent to the next node, and the failing
+/// node is left alone for a while before it is used again.
+///
+/// Optionally, the hash of every block looked up by height is compared between all available
+/// nodes. All other requests are made at a block hash, so this is enough to notice a node which
+/// follows a different fork before any of its data is used.
+pub struct MultiClient {
+    endpoints: Vec<Endpoint>,
+    /// Index of the node to send the next request to.
+    next: AtomicUsize,
+    cross_check: bool,
+}
+
+impl MultiClient {
+    /// Connect to the chain nodes with the given websocket urls. Nodes which can't be reached
+    /// are tried again later, an error is only returned if none of them can be reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no urls are given.
+    pub async fn new(urls: &[String]) -> Result<Self, ClientError> {
+        assert!(!urls.is_empty(), "at least one chain node is required");
+        let client = MultiClient {
+            endpoints: urls.iter().map(|url| Endpoint::new(url)).collect(),
+            next: AtomicUsize::new(0),
+            cross_check: false,
+        };
+        let mut last_error = None;
+        for endpoint in &client.endpoints {
+            if let Err(e) = endpoint.client().await {
+                last_error = Some(e);
+            }
+        }
+        match last_error {
+            Some(e) if client.available_endpoints().is_empty() => Err(e),
+            _ => Ok(client),
+        }
+    }
+
+    /// Compare the hashes of blocks looked up by height between all available chain nodes.
+    pub fn with_cross_check(mut self, cross_check: bool) -> Self {
+        self.cross_check = cross_check;
+        self
+    }
+
+    /// Check all chain nodes, including the ones which failed recently, by requesting their
+    /// latest block. Returns the urls of the nodes which failed the check.
+    pub async fn check_health(&self) -> Vec<String> {
+        let mut failed = vec![];
+        for endpoint in &self.endpoints {
+            let check = endpoint
+                .request(
+                    &|client: Arc<DynamicClient>| async move { client.hash_at_height(None).await },
+                )
+                .await;
+            if check.is_err() {
+                failed.push(endpoint.url.clone());
+            }
+        }
+        failed
+    }
+
+    /// Available nodes, in the order in which the next request should try them.
+    fn available_endpoints(&self) -> Vec<&Endpoint> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.endpoints.len();
+        (0..count)
+            .map(|i| &self.endpoints[(start + i) % count])
+            .filter(|endpoint| endpoint.is_available())
+            .collect()
+    }
+
+    /// The nodes to try the next request on, in order: the available nodes, or all of them if no
+    /// node is available, in case one recovered early.
+    fn failover_order(&self) -> Vec<&Endpoint> {
+        let endpoints = self.available_endpoints();
+        if endpoints.is_empty() {
+            return self.endpoints.iter().collect();
+        }
+        endpoints
+    }
+
+    /// Send a request to the nodes in turn, until one of them answers.
+    async fn call<T, F, Fut>(&self, request: F) -> Result<T, ClientError>
+    where
+        F: Fn(Arc<DynamicClient>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut last_error = None;
+        for endpoint in self.failover_order() {
+            match endpoint.request(&request).await {
+                Err(e) if e.is_transient() => last_error = Some(e),
+                result => return result,
+            }
+        }
+        Err(last_error.expect("at least one chain node is tried"))
+    }
+
+    /// Check that all available nodes which know the block at the height agree on its hash.
+    async fn verify_hash(&self, height: u32, hash: Hash) -> Result<(), ClientError> {
+        let mut hashes = vec![];
+        for endpoint in self.available_endpoints() {
+            let other = endpoint
+                .request(&|client: Arc<DynamicClient>| async move {
+                    client.hash_at_height(Some(height)).await
+                })
+                .await;
+            // A node which lags behind, or which fails, is not known to be on another fork.
+            if let Ok(Some(other)) = other {
+                hashes.push((endpoint.url.clone(), other));
+            }
+        }
+        if hashes.iter().any(|(_, other)| *other != hash) {
+            return Err(ClientError::InconsistentChain { height, hashes });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl RuntimeClient for MultiClient {
+    async fn events(&self, block: Option<Hash>) -> Result<Vec<RuntimeEvents>, ClientError> {
+        self.call(|client| async move { client.events(block).await })
+            .await
+    }
+
+    async fn hash_at_height(&self, block: Option<u32>) -> Result<Option<Hash>, ClientError> {
+        let hash = self
+            .call(|client| async move { client.hash_at_height(block).await })
+            .await?;
+        if let (true, Some(height), Some(hash)) = (self.cross_check, block, hash) {
+            self.verify_hash(height, hash).await?;
+        }
+        Ok(hash)
+    }
+
+    async fn timestamp(&self, block: Option<Hash>) -> Result<u64, ClientError> {
+        self.call(|client| async move { client.timestamp(block).await })
+            .await
+    }
+
+    async fn twin(&self, id: u32, block: Option<Hash>) -> Result<Option<Twin>, ClientError> {
+        self.call(|client| async move { client.twin(id, block).await })
+            .await
+    }
+
+    async fn twin_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.call(|client| async move { client.twin_count(block).await })
+            .await
+    }
+
+    async fn farm(&self, id: u32, block: Option<Hash>) -> Result<Option<Farm>, ClientError> {
+        self.call(|client| async move { client.farm(id, block).await })
+            .await
+    }
+
+    async fn farm_payout_address(
+        &self,
+        id: u32,
+        block: Option<Hash>,
+    ) -> Result<Option<String>, ClientError> {
+        self.call(|client| async move { client.farm_payout_address(id, block).await })
+            .await
+    }
+
+    async fn farm_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.call(|client| async move { client.farm_count(block).await })
+            .await
+    }
+
+    async fn node(&self, id: u32, block: Option<Hash>) -> Result<Option<Node>, ClientError> {
+        self.call(|client| async move { client.node(id, block).await })
+            .await
+    }
+
+    async fn node_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.call(|client| async move { client.node_count(block).await })
+            .await
+    }
+
+    async fn contract(
+        &self,
+        id: u64,
+        block: Option<Hash>,
+    ) -> Result<Option<Contract>, ClientError> {
+        self.call(|client| async move { client.contract(id, block).await })
+            .await
+    }
+
+    async fn contract_resources(
+        &self,
+        id: u64,
+        block: Option<Hash>,
+    ) -> Result<Option<ContractResources>, ClientError> {
+        self.call(|client| async move { client.contract_resources(id, block).await })
+            .await
+    }
+
+    async fn contract_count(&self, block: Option<Hash>) -> Result<u64, ClientError> {
+        self.call(|client| async move { client.contract_count(block).await })
+            .await
+    }
+
+    async fn farming_policy(
+        &self,
+        id: u32,
+        block: Option<Hash>,
+    ) -> Result<Option<FarmPolicy>, ClientError> {
+        self.call(|client| async move { client.farming_policy(id, block).await })
+            .await
+    }
+
+    async fn farming_policy_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.call(|client| async move { client.farming_policy_count(block).await })
+            .await
+    }
+
+    async fn connection_price(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.call(|client| async move { client.connection_price(block).await })
+            .await
+    }
+
+    async fn node_power(
+        &self,
+        id: u32,
+        block: Option<Hash>,
+    ) -> Result<Option<NodePower>, ClientError> {
+        self.call(|client| async move { client.node_power(id, block).await })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client for the urls, without connecting to them.
+    fn client(urls: &[&str]) -> MultiClient {
+        MultiClient {
+            endpoints: urls.iter().map(|url| Endpoint::new(url)).collect(),
+            next: AtomicUsize::new(0),
+            cross_check: false,
+        }
+    }
+
+    fn urls(endpoints: Vec<&Endpoint>) -> Vec<&str> {
+        endpoints.iter().map(|e| e.url.as_str()).collect()
+    }
+
+    #[test]
+    fn requests_start_at_the_next_node_in_turn() {
+        let client = client(&["a", "b", "c"]);
+        assert_eq!(urls(client.failover_order()), vec!["a", "b", "c"]);
+        assert_eq!(urls(client.failover_order()), vec!["b", "c", "a"]);
+        assert_eq!(urls(client.failover_order()), vec!["c", "a", "b"]);
+        assert_eq!(urls(client.failover_order()), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn nodes_which_are_down_are_skipped() {
+        let client = client(&["a", "b", "c"]);
+        client.endpoints[1].mark_down();
+        assert_eq!(urls(client.failover_order()), vec!["a", "c"]);
+        assert_eq!(urls(client.failover_order()), vec!["c", "a"]);
+    }
+
+    #[test]
+    fn all_nodes_are_tried_if_none_is_available() {
+        let client = client(&["a", "b"]);
+        for endpoint in &client.endpoints {
+            endpoint.mark_down();
+        }
+        assert!(client.available_endpoints().is_empty());
+        assert_eq!(urls(client.failover_order()), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_nodes_are_marked_down() {
+        let client = client(&["ws://127.0.0.1:1", "ws://127.0.0.1:2"]);
+        let result = client.timestamp(None).await;
+        assert!(result.is_err());
+        assert!(client.endpoints.iter().all(|e| !e.is_available()));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::client::RuntimeClient;
+use crate::error::ClientError;
+use crate::types::{
+    Contract, ContractResources, Farm, FarmPolicy, Hash, Node, NodePower, RuntimeEvents, Twin,
+};
+use std::future::Future;
+use std::time::Duration;
+
+/// Exponential backoff between retries of a failed request.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between retries.
+    pub max_delay: Duration,
+    /// Factor by which the delay grows after every retry.
+    pub factor: u32,
+    /// Amount of retries after which the error is returned to the caller.
+    pub max_retries: u32,
+}
+
+impl Default for Backoff {
+    /// Retries for about 3.5 minutes before giving up, which covers the usual restart of a chain
+    /// node.
+    fn default() -> Self {
+        Backoff {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            factor: 2,
+            max_retries: 12,
+        }
+    }
+}
+
+/// A [`RuntimeClient`] which retries requests of another client which fail with a transient
+/// error, waiting between attempts according to a [`Backoff`]. Other errors are returned
+/// immediately.
+pub struct RetryClient<C> {
+    inner: C,
+    backoff: Backoff,
+}
+
+impl<C: RuntimeClient> RetryClient<C> {
+    pub fn new(inner: C, backoff: Backoff) -> Self {
+        RetryClient { inner, backoff }
+    }
+
+    /// The wrapped client.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    async fn retry<T, F, Fut>(&self, request: F) -> Result<T, ClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        with_retries(&self.backoff, request).await
+    }
+}
+
+/// Call the request until it succeeds, fails with an error which is not transient, or the
+/// retries of the backoff are exhausted.
+pub(crate) async fn with_retries<T, F, Fut>(backoff: &Backoff, request: F) -> Result<T, ClientError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, ClientError>>,
+{
+    let mut delay = backoff.initial_delay;
+    let mut retries = 0;
+    loop {
+        match request().await {
+            Err(e) if e.is_transient() && retries < backoff.max_retries => {
+                retries += 1;
+                tokio::time::sleep(delay).await;
+                delay = delay.saturating_mul(backoff.factor).min(backoff.max_delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: RuntimeClient + Sync> RuntimeClient for RetryClient<C> {
+    async fn events(&self, block: Option<Hash>) -> Result<Vec<RuntimeEvents>, ClientError> {
+        self.retry(|| self.inner.events(block)).await
+    }
+
+    async fn hash_at_height(&self, block: Option<u32>) -> Result<Option<Hash>, ClientError> {
+        self.retry(|| self.inner.hash_at_height(block)).await
+    }
+
+    async fn timestamp(&self, block: Option<Hash>) -> Result<u64, ClientError> {
+        self.retry(|| self.inner.timestamp(block)).await
+    }
+
+    async fn twin(&self, id: u32, block: Option<Hash>) -> Result<Option<Twin>, ClientError> {
+        self.retry(|| self.inner.twin(id, block)).await
+    }
+
+    async fn twin_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.retry(|| self.inner.twin_count(block)).await
+    }
+
+    async fn farm(&self, id: u32, block: Option<Hash>) -> Result<Option<Farm>, ClientError> {
+        self.retry(|| self.inner.farm(id, block)).await
+    }
+
+    async fn farm_payout_address(
+        &self,
+        id: u32,
+        block: Option<Hash>,
+    ) -> Result<Option<String>, ClientError> {
+        self.retry(|| self.inner.farm_payout_address(id, block))
+            .await
+    }
+
+    async fn farm_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.retry(|| self.inner.farm_count(block)).await
+    }
+
+    async fn node(&self, id: u32, block: Option<Hash>) -> Result<Option<Node>, ClientError> {
+        self.retry(|| self.inner.node(id, block)).await
+    }
+
+    async fn node_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.retry(|| self.inner.node_count(block)).await
+    }
+
+    async fn contract(
+        &self,
+        id: u64,
+        block: Option<Hash>,
+    ) -> Result<Option<Contract>, ClientError> {
+        self.retry(|| self.inner.contract(id, block)).await
+    }
+
+    async fn contract_resources(
+        &self,
+        id: u64,
+        block: Option<Hash>,
+    ) -> Result<Option<ContractResources>, ClientError> {
+        self.retry(|| self.inner.contract_resources(id, block))
+            .await
+    }
+
+    async fn contract_count(&self, block: Option<Hash>) -> Result<u64, ClientError> {
+        self.retry(|| self.inner.contract_count(block)).await
+    }
+
+    async fn farming_policy(
+        &self,
+        id: u32,
+        block: Option<Hash>,
+    ) -> Result<Option<FarmPolicy>, ClientError> {
+        self.retry(|| self.inner.farming_policy(id, block)).await
+    }
+
+    async fn farming_policy_count(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.retry(|| self.inner.farming_policy_count(block)).await
+    }
+
+    async fn connection_price(&self, block: Option<Hash>) -> Result<u32, ClientError> {
+        self.retry(|| self.inner.connection_price(block)).await
+    }
+
+    async fn node_power(
+        &self,
+        id: u32,
+        block: Option<Hash>,
+    ) -> Result<Option<NodePower>, ClientError> {
+        self.retry(|| self.inner.node_power(id, block)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn backoff() -> Backoff {
+        Backoff {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            factor: 2,
+            max_retries: 3,
+        }
+    }
+
+    /// Call the request through the retries, and return the result and the amount of attempts.
+    /// Every attempt fails with the error the closure gives for it.
+    async fn attempt(
+        error: impl Fn(u32) -> Option<ClientError>,
+    ) -> (Result<u32, ClientError>, u32) {
+        let attempts = AtomicU32::new(0);
+        let result = with_retries(&backoff(), || async {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            match error(attempt) {
+                Some(e) => Err(e),
+                None => Ok(attempt),
+            }
+        })
+        .await;
+        (result, attempts.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let (result, attempts) = attempt(|n| (n < 3).then_some(ClientError::Timeout)).await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn error_is_returned_once_retries_are_exhausted() {
+        let (result, attempts) = attempt(|_| Some(ClientError::Timeout)).await;
+        assert!(matches!(result, Err(ClientError::Timeout)));
+        // The first attempt and every retry.
+        assert_eq!(attempts, backoff().max_retries + 1);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let (result, attempts) = attempt(|_| Some(ClientError::MissingBlock)).await;
+        assert!(matches!(result, Err(ClientError::MissingBlock)));
+        assert_eq!(attempts, 1);
+
+        let (result, attempts) = attempt(|n| {
+            Some(if n == 1 {
+                ClientError::Timeout
+            } else {
+                ClientError::UnsupportedRuntime { spec_version: 200 }
+            })
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(ClientError::UnsupportedRuntime { spec_version: 200 })
+        ));
+        assert_eq!(attempts, 2);
+    }
+}
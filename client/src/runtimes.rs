@@ -1,4 +1,179 @@
+//! Generated types of the runtime versions with a different storage or event layout, and the
+//! registry which maps the spec version of a block to the runtime its data is decoded with.
+
+use crate::error::ClientError;
+
 pub mod v115;
 pub mod v123;
 pub mod v131;
 pub mod v141;
+
+/// The latest spec version which has been checked against the types of [`Runtime::V141`]. Newer
+/// runtimes are refused until they are added to the registry, either as a new runtime or by
+/// extending the range of an existing one.
+pub const LATEST_SPEC_VERSION: u32 = 141;
+
+/// A runtime with generated types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    V115,
+    V123,
+    V131,
+    V141,
+}
+
+/// A range of spec versions of which the data is decoded with the types of a runtime.
+#[derive(Debug, Clone)]
+pub struct SpecRange {
+    /// First spec version in the range.
+    pub first: u32,
+    /// Last spec version in the range, inclusive.
+    pub last: u32,
+    pub runtime: Runtime,
+}
+
+/// Mapping of spec versions to runtimes. Every spec version maps to at most a single runtime, so
+/// the data of a block is never decoded with the types of a runtime it does not belong to.
+#[derive(Debug, Clone)]
+pub struct DecoderRegistry {
+    /// Ranges ordered by their first spec version, without overlap.
+    ranges: Vec<SpecRange>,
+}
+
+impl Default for DecoderRegistry {
+    /// The ranges of all runtimes with generated types. A runtime covers its own spec version up
+    /// to the spec version of the next runtime.
+    fn default() -> Self {
+        DecoderRegistry::new(vec![
+            SpecRange {
+                first: 115,
+                last: 122,
+                runtime: Runtime::V115,
+            },
+            SpecRange {
+                first: 123,
+                last: 130,
+                runtime: Runtime::V123,
+            },
+            SpecRange {
+                first: 131,
+                last: 140,
+                runtime: Runtime::V131,
+            },
+            SpecRange {
+                first: 141,
+                last: LATEST_SPEC_VERSION,
+                runtime: Runtime::V141,
+            },
+        ])
+    }
+}
+
+impl DecoderRegistry {
+    /// Create a registry from a set of ranges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is empty, or if ranges overlap.
+    pub fn new(mut ranges: Vec<SpecRange>) -> Self {
+        ranges.sort_by_key(|range| range.first);
+        for range in &ranges {
+            assert!(
+                range.first <= range.last,
+                "spec range {}..={} is empty",
+                range.first,
+                range.last
+            );
+        }
+        for pair in ranges.windows(2) {
+            assert!(
+                pair[0].last < pair[1].first,
+                "spec ranges {}..={} and {}..={} overlap",
+                pair[0].first,
+                pair[0].last,
+                pair[1].first,
+                pair[1].last
+            );
+        }
+        DecoderRegistry { ranges }
+    }
+
+    /// Add a range of spec versions which are decoded with the types of a runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty, or if it overlaps with an existing range.
+    pub fn with_range(mut self, first: u32, last: u32, runtime: Runtime) -> Self {
+        self.ranges.push(SpecRange {
+            first,
+            last,
+            runtime,
+        });
+        DecoderRegistry::new(self.ranges)
+    }
+
+    /// All ranges in the registry, ordered by spec version.
+    pub fn ranges(&self) -> &[SpecRange] {
+        &self.ranges
+    }
+
+    /// The runtime which decodes the data of the given spec version.
+    pub fn resolve(&self, spec_version: u32) -> Result<Runtime, ClientError> {
+        self.ranges
+            .iter()
+            .find(|range| range.first <= spec_version && spec_version <= range.last)
+            .map(|range| range.runtime)
+            .ok_or(ClientError::UnsupportedRuntime { spec_version })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_versions_resolve_to_their_runtime() {
+        let registry = DecoderRegistry::default();
+        for (spec_version, runtime) in [
+            (115, Runtime::V115),
+            (122, Runtime::V115),
+            (123, Runtime::V123),
+            (131, Runtime::V131),
+            (140, Runtime::V131),
+            (LATEST_SPEC_VERSION, Runtime::V141),
+        ] {
+            assert_eq!(registry.resolve(spec_version).unwrap(), runtime);
+        }
+        for spec_version in [114, LATEST_SPEC_VERSION + 1] {
+            assert!(matches!(
+                registry.resolve(spec_version),
+                Err(ClientError::UnsupportedRuntime { spec_version: v }) if v == spec_version
+            ));
+        }
+    }
+
+    #[test]
+    fn registry_can_be_extended() {
+        let registry = DecoderRegistry::default().with_range(142, 145, Runtime::V141);
+        assert_eq!(registry.resolve(143).unwrap(), Runtime::V141);
+        assert!(registry.resolve(146).is_err());
+        let firsts: Vec<_> = registry.ranges().iter().map(|r| r.first).collect();
+        assert_eq!(firsts, vec![115, 123, 131, 141, 142]);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlap")]
+    fn overlapping_ranges_are_refused() {
+        DecoderRegistry::default().with_range(140, 142, Runtime::V141);
+    }
+
+    #[test]
+    #[should_panic(expected = "empty")]
+    fn empty_ranges_are_refused() {
+        DecoderRegistry::new(vec![SpecRange {
+            first: 2,
+            last: 1,
+            runtime: Runtime::V115,
+        }]);
+    }
+}
@@ -32,10 +32,11 @@ pub use super::runtime::api::runtime_types::{
     },
 };
 use crate::types::{
-    Cause, Contract, ContractData, ContractResources, ContractState, Domain, EntityProof, Farm,
-    FarmCertification, FarmPolicy, FarmingPolicyLimit, Interface, Location, NameContract, Node,
-    NodeCertification, NodeContract, NruConsumption, PubIPConfig, PublicConfig, PublicIP,
-    RentContract, Resources, Twin,
+    Cause, Contract, ContractData, ContractGracePeriodEnded, ContractGracePeriodStarted,
+    ContractResources, ContractState, Domain, EntityProof, Farm, FarmCertification, FarmPolicy,
+    FarmingPolicyLimit, FarmingPolicySet, Interface, Location, NameContract, Node,
+    NodeCertification, NodeCertificationSet, NodeContract, NodeContractCanceled, NruConsumption,
+    PubIPConfig, PublicConfig, PublicIP, RentContract, Resources, Twin,
 };
 use subxt::utils::AccountId32;
 
@@ -64,6 +65,26 @@ pub type V115ContractUpdatedResourcesEvent =
     super::runtime::api::smart_contract_module::events::UpdatedUsedResources;
 pub type V115ContractNruConsumptionReceivedEvent =
     super::runtime::api::smart_contract_module::events::NruConsumptionReportReceived;
+pub type V115NodeDeletedEvent = super::runtime::api::tfgrid_module::events::NodeDeleted;
+pub type V115FarmDeletedEvent = super::runtime::api::tfgrid_module::events::FarmDeleted;
+pub type V115TwinDeletedEvent = super::runtime::api::tfgrid_module::events::TwinDeleted;
+pub type V115NodeContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::NodeContractCanceled;
+pub type V115RentContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::RentContractCanceled;
+pub type V115ContractGracePeriodStartedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodStarted;
+pub type V115ContractGracePeriodEndedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodEnded;
+pub type V115ConnectionPriceSetEvent =
+    super::runtime::api::tfgrid_module::events::ConnectionPriceSet;
+pub type V115NodeCertificationSetEvent =
+    super::runtime::api::tfgrid_module::events::NodeCertificationSet;
+pub type V115FarmingPolicySetEvent = super::runtime::api::tfgrid_module::events::FarmingPolicySet;
+pub type V115FarmingPolicyUpdatedEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyUpdated;
+pub type V115FarmingPolicyStoredEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyStored;
 
 impl From<RuntimeTwin<RuntimeTwinIP, AccountId32>> for Twin {
     fn from(rt: RuntimeTwin<RuntimeTwinIP, AccountId32>) -> Self {
@@ -522,3 +543,68 @@ impl From<RuntimeNruResources> for NruConsumption {
         }
     }
 }
+
+impl From<V115NodeContractCanceledEvent> for NodeContractCanceled {
+    fn from(ncc: V115NodeContractCanceledEvent) -> Self {
+        let V115NodeContractCanceledEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = ncc;
+        NodeContractCanceled {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V115ContractGracePeriodStartedEvent> for ContractGracePeriodStarted {
+    fn from(gps: V115ContractGracePeriodStartedEvent) -> Self {
+        let V115ContractGracePeriodStartedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        } = gps;
+        ContractGracePeriodStarted {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        }
+    }
+}
+
+impl From<V115ContractGracePeriodEndedEvent> for ContractGracePeriodEnded {
+    fn from(gpe: V115ContractGracePeriodEndedEvent) -> Self {
+        let V115ContractGracePeriodEndedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = gpe;
+        ContractGracePeriodEnded {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V115NodeCertificationSetEvent> for NodeCertificationSet {
+    fn from(ncs: V115NodeCertificationSetEvent) -> Self {
+        NodeCertificationSet {
+            node_id: ncs.0,
+            certification: ncs.1.into(),
+        }
+    }
+}
+
+impl From<V115FarmingPolicySetEvent> for FarmingPolicySet {
+    fn from(fps: V115FarmingPolicySetEvent) -> Self {
+        FarmingPolicySet {
+            farm_id: fps.0,
+            limit: fps.1.map(FarmingPolicyLimit::from),
+        }
+    }
+}
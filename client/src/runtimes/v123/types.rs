@@ -28,10 +28,11 @@ use super::runtime::api::runtime_types::{
     },
 };
 use crate::types::{
-    Cause, Contract, ContractData, ContractResources, ContractState, Domain, EntityProof, Farm,
-    FarmCertification, FarmPolicy, FarmingPolicyLimit, Interface, Location, NameContract, Node,
-    NodeCertification, NodeContract, NruConsumption, PubIPConfig, PublicConfig, PublicIP,
-    RentContract, Resources, Twin,
+    Cause, Contract, ContractData, ContractGracePeriodEnded, ContractGracePeriodStarted,
+    ContractResources, ContractState, Domain, EntityProof, Farm, FarmCertification, FarmPolicy,
+    FarmingPolicyLimit, FarmingPolicySet, Interface, Location, NameContract, Node,
+    NodeCertification, NodeCertificationSet, NodeContract, NodeContractCanceled, NruConsumption,
+    PubIPConfig, PublicConfig, PublicIP, RentContract, Resources, Twin,
 };
 use subxt::utils::AccountId32;
 
@@ -56,6 +57,26 @@ pub type V123ContractUpdatedResourcesEvent =
     super::runtime::api::smart_contract_module::events::UpdatedUsedResources;
 pub type V123ContractNruConsumptionReceivedEvent =
     super::runtime::api::smart_contract_module::events::NruConsumptionReportReceived;
+pub type V123NodeDeletedEvent = super::runtime::api::tfgrid_module::events::NodeDeleted;
+pub type V123FarmDeletedEvent = super::runtime::api::tfgrid_module::events::FarmDeleted;
+pub type V123TwinDeletedEvent = super::runtime::api::tfgrid_module::events::TwinDeleted;
+pub type V123NodeContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::NodeContractCanceled;
+pub type V123RentContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::RentContractCanceled;
+pub type V123ContractGracePeriodStartedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodStarted;
+pub type V123ContractGracePeriodEndedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodEnded;
+pub type V123ConnectionPriceSetEvent =
+    super::runtime::api::tfgrid_module::events::ConnectionPriceSet;
+pub type V123NodeCertificationSetEvent =
+    super::runtime::api::tfgrid_module::events::NodeCertificationSet;
+pub type V123FarmingPolicySetEvent = super::runtime::api::tfgrid_module::events::FarmingPolicySet;
+pub type V123FarmingPolicyUpdatedEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyUpdated;
+pub type V123FarmingPolicyStoredEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyStored;
 
 impl From<RuntimeTwin<RuntimeTwinIP, AccountId32>> for Twin {
     fn from(rt: RuntimeTwin<RuntimeTwinIP, AccountId32>) -> Self {
@@ -468,3 +489,68 @@ impl From<RuntimeNruResources> for NruConsumption {
         }
     }
 }
+
+impl From<V123NodeContractCanceledEvent> for NodeContractCanceled {
+    fn from(ncc: V123NodeContractCanceledEvent) -> Self {
+        let V123NodeContractCanceledEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = ncc;
+        NodeContractCanceled {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V123ContractGracePeriodStartedEvent> for ContractGracePeriodStarted {
+    fn from(gps: V123ContractGracePeriodStartedEvent) -> Self {
+        let V123ContractGracePeriodStartedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        } = gps;
+        ContractGracePeriodStarted {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        }
+    }
+}
+
+impl From<V123ContractGracePeriodEndedEvent> for ContractGracePeriodEnded {
+    fn from(gpe: V123ContractGracePeriodEndedEvent) -> Self {
+        let V123ContractGracePeriodEndedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = gpe;
+        ContractGracePeriodEnded {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V123NodeCertificationSetEvent> for NodeCertificationSet {
+    fn from(ncs: V123NodeCertificationSetEvent) -> Self {
+        NodeCertificationSet {
+            node_id: ncs.0,
+            certification: ncs.1.into(),
+        }
+    }
+}
+
+impl From<V123FarmingPolicySetEvent> for FarmingPolicySet {
+    fn from(fps: V123FarmingPolicySetEvent) -> Self {
+        FarmingPolicySet {
+            farm_id: fps.0,
+            limit: fps.1.map(FarmingPolicyLimit::from),
+        }
+    }
+}
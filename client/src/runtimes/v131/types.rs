@@ -28,11 +28,12 @@ use super::runtime::api::runtime_types::{
     },
 };
 use crate::types::{
-    Cause, Contract, ContractData, ContractResources, ContractState, Domain, EntityProof, Farm,
-    FarmCertification, FarmPolicy, FarmingPolicyLimit, Interface, Location, NameContract, Node,
-    NodeCertification, NodeContract, NodePower, NruConsumption, Power, PowerState,
-    PowerStateChanged, PowerTargetChanged, PubIPConfig, PublicConfig, PublicIP, RentContract,
-    Resources, Twin,
+    Cause, Contract, ContractData, ContractGracePeriodEnded, ContractGracePeriodStarted,
+    ContractResources, ContractState, Domain, EntityProof, Farm, FarmCertification, FarmPolicy,
+    FarmingPolicyLimit, FarmingPolicySet, Interface, Location, NameContract, Node,
+    NodeCertification, NodeCertificationSet, NodeContract, NodeContractCanceled, NodePower,
+    NruConsumption, Power, PowerState, PowerStateChanged, PowerTargetChanged, PubIPConfig,
+    PublicConfig, PublicIP, RentContract, Resources, Twin,
 };
 use subxt::utils::AccountId32;
 
@@ -63,6 +64,26 @@ pub type V131ContractNruConsumptionReceivedEvent =
 pub type V131PowerTargetChangedEvent =
     super::runtime::api::tfgrid_module::events::PowerTargetChanged;
 pub type V131PowerStateChangedEvent = super::runtime::api::tfgrid_module::events::PowerStateChanged;
+pub type V131NodeDeletedEvent = super::runtime::api::tfgrid_module::events::NodeDeleted;
+pub type V131FarmDeletedEvent = super::runtime::api::tfgrid_module::events::FarmDeleted;
+pub type V131TwinDeletedEvent = super::runtime::api::tfgrid_module::events::TwinDeleted;
+pub type V131NodeContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::NodeContractCanceled;
+pub type V131RentContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::RentContractCanceled;
+pub type V131ContractGracePeriodStartedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodStarted;
+pub type V131ContractGracePeriodEndedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodEnded;
+pub type V131ConnectionPriceSetEvent =
+    super::runtime::api::tfgrid_module::events::ConnectionPriceSet;
+pub type V131NodeCertificationSetEvent =
+    super::runtime::api::tfgrid_module::events::NodeCertificationSet;
+pub type V131FarmingPolicySetEvent = super::runtime::api::tfgrid_module::events::FarmingPolicySet;
+pub type V131FarmingPolicyUpdatedEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyUpdated;
+pub type V131FarmingPolicyStoredEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyStored;
 
 impl From<RuntimeTwin<AccountId32>> for Twin {
     fn from(rt: RuntimeTwin<AccountId32>) -> Self {
@@ -535,3 +556,68 @@ impl From<V131PowerTargetChangedEvent> for PowerTargetChanged {
         }
     }
 }
+
+impl From<V131NodeContractCanceledEvent> for NodeContractCanceled {
+    fn from(ncc: V131NodeContractCanceledEvent) -> Self {
+        let V131NodeContractCanceledEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = ncc;
+        NodeContractCanceled {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V131ContractGracePeriodStartedEvent> for ContractGracePeriodStarted {
+    fn from(gps: V131ContractGracePeriodStartedEvent) -> Self {
+        let V131ContractGracePeriodStartedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        } = gps;
+        ContractGracePeriodStarted {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        }
+    }
+}
+
+impl From<V131ContractGracePeriodEndedEvent> for ContractGracePeriodEnded {
+    fn from(gpe: V131ContractGracePeriodEndedEvent) -> Self {
+        let V131ContractGracePeriodEndedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = gpe;
+        ContractGracePeriodEnded {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V131NodeCertificationSetEvent> for NodeCertificationSet {
+    fn from(ncs: V131NodeCertificationSetEvent) -> Self {
+        NodeCertificationSet {
+            node_id: ncs.0,
+            certification: ncs.1.into(),
+        }
+    }
+}
+
+impl From<V131FarmingPolicySetEvent> for FarmingPolicySet {
+    fn from(fps: V131FarmingPolicySetEvent) -> Self {
+        FarmingPolicySet {
+            farm_id: fps.0,
+            limit: fps.1.map(FarmingPolicyLimit::from),
+        }
+    }
+}
@@ -28,11 +28,12 @@ use super::runtime::api::runtime_types::{
     },
 };
 use crate::types::{
-    Cause, Contract, ContractData, ContractResources, ContractState, Domain, EntityProof, Farm,
-    FarmCertification, FarmPolicy, FarmingPolicyLimit, Interface, Location, NameContract, Node,
-    NodeCertification, NodeContract, NodePower, NruConsumption, Power, PowerState,
-    PowerStateChanged, PowerTargetChanged, PubIPConfig, PublicConfig, PublicIP, RentContract,
-    Resources, Twin,
+    Cause, Contract, ContractData, ContractGracePeriodEnded, ContractGracePeriodStarted,
+    ContractResources, ContractState, Domain, EntityProof, Farm, FarmCertification, FarmPolicy,
+    FarmingPolicyLimit, FarmingPolicySet, Interface, Location, NameContract, Node,
+    NodeCertification, NodeCertificationSet, NodeContract, NodeContractCanceled, NodePower,
+    NruConsumption, Power, PowerState, PowerStateChanged, PowerTargetChanged, PubIPConfig,
+    PublicConfig, PublicIP, RentContract, Resources, Twin,
 };
 use subxt::utils::AccountId32;
 
@@ -63,6 +64,26 @@ pub type V141ContractNruConsumptionReceivedEvent =
 pub type V141PowerTargetChangedEvent =
     super::runtime::api::tfgrid_module::events::PowerTargetChanged;
 pub type V141PowerStateChangedEvent = super::runtime::api::tfgrid_module::events::PowerStateChanged;
+pub type V141NodeDeletedEvent = super::runtime::api::tfgrid_module::events::NodeDeleted;
+pub type V141FarmDeletedEvent = super::runtime::api::tfgrid_module::events::FarmDeleted;
+pub type V141TwinDeletedEvent = super::runtime::api::tfgrid_module::events::TwinDeleted;
+pub type V141NodeContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::NodeContractCanceled;
+pub type V141RentContractCanceledEvent =
+    super::runtime::api::smart_contract_module::events::RentContractCanceled;
+pub type V141ContractGracePeriodStartedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodStarted;
+pub type V141ContractGracePeriodEndedEvent =
+    super::runtime::api::smart_contract_module::events::ContractGracePeriodEnded;
+pub type V141ConnectionPriceSetEvent =
+    super::runtime::api::tfgrid_module::events::ConnectionPriceSet;
+pub type V141NodeCertificationSetEvent =
+    super::runtime::api::tfgrid_module::events::NodeCertificationSet;
+pub type V141FarmingPolicySetEvent = super::runtime::api::tfgrid_module::events::FarmingPolicySet;
+pub type V141FarmingPolicyUpdatedEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyUpdated;
+pub type V141FarmingPolicyStoredEvent =
+    super::runtime::api::tfgrid_module::events::FarmingPolicyStored;
 
 impl From<RuntimeTwin<AccountId32>> for Twin {
     fn from(rt: RuntimeTwin<AccountId32>) -> Self {
@@ -535,3 +556,68 @@ impl From<V141PowerTargetChangedEvent> for PowerTargetChanged {
         }
     }
 }
+
+impl From<V141NodeContractCanceledEvent> for NodeContractCanceled {
+    fn from(ncc: V141NodeContractCanceledEvent) -> Self {
+        let V141NodeContractCanceledEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = ncc;
+        NodeContractCanceled {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V141ContractGracePeriodStartedEvent> for ContractGracePeriodStarted {
+    fn from(gps: V141ContractGracePeriodStartedEvent) -> Self {
+        let V141ContractGracePeriodStartedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        } = gps;
+        ContractGracePeriodStarted {
+            contract_id,
+            node_id,
+            twin_id,
+            block_number,
+        }
+    }
+}
+
+impl From<V141ContractGracePeriodEndedEvent> for ContractGracePeriodEnded {
+    fn from(gpe: V141ContractGracePeriodEndedEvent) -> Self {
+        let V141ContractGracePeriodEndedEvent {
+            contract_id,
+            node_id,
+            twin_id,
+        } = gpe;
+        ContractGracePeriodEnded {
+            contract_id,
+            node_id,
+            twin_id,
+        }
+    }
+}
+
+impl From<V141NodeCertificationSetEvent> for NodeCertificationSet {
+    fn from(ncs: V141NodeCertificationSetEvent) -> Self {
+        NodeCertificationSet {
+            node_id: ncs.0,
+            certification: ncs.1.into(),
+        }
+    }
+}
+
+impl From<V141FarmingPolicySetEvent> for FarmingPolicySet {
+    fn from(fps: V141FarmingPolicySetEvent) -> Self {
+        FarmingPolicySet {
+            farm_id: fps.0,
+            limit: fps.1.map(FarmingPolicyLimit::from),
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use crate::client::RuntimeClient;
+use crate::error::ClientError;
+use crate::retry::{with_retries, Backoff};
+use crate::types::{Hash, RuntimeEvents};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// A block in an [`EventStream`]: its height, its hash, its timestamp in seconds since the UNIX
+/// epoch, and its events.
+pub type BlockEvents = (u32, Hash, i64, Vec<RuntimeEvents>);
+
+/// Settings of an [`EventStream`].
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+    /// Amount of blocks which are fetched concurrently.
+    pub concurrency: usize,
+    /// Amount of blocks every concurrent fetch runs ahead of the consumer of the stream.
+    pub prefetch: usize,
+    /// Backoff between attempts to fetch a block which failed with a transient error.
+    pub backoff: Backoff,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        StreamOptions {
+            concurrency: 8,
+            prefetch: 5,
+            backoff: Backoff::default(),
+        }
+    }
+}
+
+/// The events of a range of blocks, delivered in order of height.
+///
+/// Blocks are fetched by concurrent tasks, each of which fetches every n-th block of the range.
+/// The stream takes the blocks from the tasks in turn, so they are delivered in order even
+/// though they are fetched out of order. A block which fails with a transient error is fetched
+/// again according to the backoff. If it still fails, the error is delivered in place of the
+/// block, and the stream ends.
+///
+/// Dropping the stream cancels the tasks.
+pub struct EventStream {
+    receivers: Vec<mpsc::Receiver<Result<BlockEvents, ClientError>>>,
+    tasks: Vec<JoinHandle<()>>,
+    /// Index of the receiver which delivers the next block.
+    next: usize,
+    done: bool,
+}
+
+impl EventStream {
+    /// Stream the blocks in the range, with the given clients. The concurrent tasks use the
+    /// clients in turn, so the requests can be spread over multiple connections.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no clients are given, or if the concurrency or the prefetch is 0.
+    pub fn new(
+        clients: Vec<Arc<dyn RuntimeClient + Send + Sync>>,
+        range: RangeInclusive<u32>,
+        options: StreamOptions,
+    ) -> Self {
+        assert!(!clients.is_empty(), "at least one client is required");
+        assert!(options.concurrency > 0, "concurrency must be at least 1");
+        assert!(options.prefetch > 0, "prefetch must be at least 1");
+
+        let (start, end) = range.into_inner();
+        let mut receivers = Vec::with_capacity(options.concurrency);
+        let mut tasks = Vec::with_capacity(options.concurrency);
+        for i in 0..options.concurrency {
+            let client = clients[i % clients.len()].clone();
+            let (tx, rx) = mpsc::channel(options.prefetch);
+            let backoff = options.backoff.clone();
+            let step = options.concurrency as u64;
+            // Heights are counted in u64, so the last step can't overflow at the end of the range.
+            let first = start as u64 + i as u64;
+            tasks.push(tokio::spawn(async move {
+                let mut height = first;
+                while height <= end as u64 {
+                    let block =
+                        with_retries(&backoff, || fetch_block(client.as_ref(), height as u32))
+                            .await;
+                    let failed = block.is_err();
+                    // The stream is gone, or there is nothing to fetch after a failed block.
+                    if tx.send(block).await.is_err() || failed {
+                        break;
+                    }
+                    height += step;
+                }
+            }));
+            receivers.push(rx);
+        }
+
+        EventStream {
+            receivers,
+            tasks,
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// The next block of the range. Returns `None` once all blocks are delivered, or after an
+    /// error was delivered.
+    pub async fn next(&mut self) -> Option<Result<BlockEvents, ClientError>> {
+        if self.done {
+            return None;
+        }
+        let block = self.receivers[self.next].recv().await;
+        self.next = (self.next + 1) % self.receivers.len();
+        match block {
+            Some(Ok(block)) => Some(Ok(block)),
+            // The task of the next height stopped, because the range is complete or because the
+            // block failed. Blocks after it might still be buffered by other tasks, but those
+            // would leave a gap.
+            other => {
+                self.done = true;
+                other
+            }
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Fetch the hash, timestamp and events of the block at the height.
+async fn fetch_block(
+    client: &(dyn RuntimeClient + Send + Sync),
+    height: u32,
+) -> Result<BlockEvents, ClientError> {
+    let hash = client
+        .hash_at_height(Some(height))
+        .await?
+        .ok_or(ClientError::MissingBlock)?;
+    let events = client.events(Some(hash)).await?;
+    let timestamp = client.timestamp(Some(hash)).await? / 1000;
+    Ok((height, hash, timestamp as i64, events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Contract, ContractResources, Farm, FarmPolicy, Node, NodePower, Twin};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// A chain with a block every 6 seconds, each with an uptime report of node 1 with the height
+    /// of the block as uptime. Lower blocks take longer to fetch, so the concurrent tasks finish
+    /// them out of order.
+    #[derive(Default)]
+    struct MockClient {
+        /// Height of a block which can't be decoded.
+        broken: Option<u32>,
+        /// Height of a block whose first request times out.
+        flaky: Option<u32>,
+        timed_out: AtomicBool,
+    }
+
+    fn block_hash(height: u32) -> Hash {
+        Hash::from_low_u64_be(height as u64)
+    }
+
+    fn block_height(hash: Option<Hash>) -> u32 {
+        hash.expect("requests are made at a block").to_low_u64_be() as u32
+    }
+
+    /// The result of a request the mock chain doesn't serve, as the stream doesn't make it.
+    fn unsupported<T>() -> Result<T, ClientError> {
+        Err(ClientError::Decode(
+            "not served by the mock chain".to_string(),
+        ))
+    }
+
+    #[async_trait::async_trait]
+    impl RuntimeClient for MockClient {
+        async fn events(&self, block: Option<Hash>) -> Result<Vec<RuntimeEvents>, ClientError> {
+            let height = block_height(block);
+            Ok(vec![RuntimeEvents::NodeUptimeReported(
+                1,
+                height as u64 * 6,
+                height as u64,
+            )])
+        }
+
+        async fn hash_at_height(&self, block: Option<u32>) -> Result<Option<Hash>, ClientError> {
+            let height = block.expect("blocks are requested by height");
+            tokio::time::sleep(Duration::from_millis(20 - height as u64 % 20)).await;
+            if self.broken == Some(height) {
+                return Err(ClientError::Decode("broken block".to_string()));
+            }
+            if self.flaky == Some(height) && !self.timed_out.swap(true, Ordering::Relaxed) {
+                return Err(ClientError::Timeout);
+            }
+            Ok(Some(block_hash(height)))
+        }
+
+        async fn timestamp(&self, block: Option<Hash>) -> Result<u64, ClientError> {
+            Ok(block_height(block) as u64 * 6000)
+        }
+
+        async fn twin(&self, _: u32, _: Option<Hash>) -> Result<Option<Twin>, ClientError> {
+            unsupported()
+        }
+
+        async fn twin_count(&self, _: Option<Hash>) -> Result<u32, ClientError> {
+            unsupported()
+        }
+
+        async fn farm(&self, _: u32, _: Option<Hash>) -> Result<Option<Farm>, ClientError> {
+            unsupported()
+        }
+
+        async fn farm_payout_address(
+            &self,
+            _: u32,
+            _: Option<Hash>,
+        ) -> Result<Option<String>, ClientError> {
+            unsupported()
+        }
+
+        async fn farm_count(&self, _: Option<Hash>) -> Result<u32, ClientError> {
+            unsupported()
+        }
+
+        async fn node(&self, _: u32, _: Option<Hash>) -> Result<Option<Node>, ClientError> {
+            unsupported()
+        }
+
+        async fn node_count(&self, _: Option<Hash>) -> Result<u32, ClientError> {
+            unsupported()
+        }
+
+        async fn contract(&self, _: u64, _: Option<Hash>) -> Result<Option<Contract>, ClientError> {
+            unsupported()
+        }
+
+        async fn contract_resources(
+            &self,
+            _: u64,
+            _: Option<Hash>,
+        ) -> Result<Option<ContractResources>, ClientError> {
+            unsupported()
+        }
+
+        async fn contract_count(&self, _: Option<Hash>) -> Result<u64, ClientError> {
+            unsupported()
+        }
+
+        async fn farming_policy(
+            &self,
+            _: u32,
+            _: Option<Hash>,
+        ) -> Result<Option<FarmPolicy>, ClientError> {
+            unsupported()
+        }
+
+        async fn farming_policy_count(&self, _: Option<Hash>) -> Result<u32, ClientError> {
+            unsupported()
+        }
+
+        async fn connection_price(&self, _: Option<Hash>) -> Result<u32, ClientError> {
+            unsupported()
+        }
+
+        async fn node_power(
+            &self,
+            _: u32,
+            _: Option<Hash>,
+        ) -> Result<Option<NodePower>, ClientError> {
+            unsupported()
+        }
+    }
+
+    /// Stream blocks 1 to 40 from the client, with 3 concurrent fetches over 2 connections.
+    fn stream(client: MockClient) -> EventStream {
+        let client: Arc<dyn RuntimeClient + Send + Sync> = Arc::new(client);
+        let options = StreamOptions {
+            concurrency: 3,
+            prefetch: 2,
+            backoff: Backoff {
+                initial_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+                factor: 1,
+                max_retries: 1,
+            },
+        };
+        EventStream::new(vec![client.clone(), client], 1..=40, options)
+    }
+
+    #[tokio::test]
+    async fn blocks_are_delivered_in_order() {
+        let mut stream = stream(MockClient::default());
+        let mut heights = vec![];
+        while let Some(block) = stream.next().await {
+            let (height, hash, ts, events) = block.unwrap();
+            assert_eq!(hash, block_hash(height));
+            assert_eq!(ts, height as i64 * 6);
+            assert!(matches!(
+                events[..],
+                [RuntimeEvents::NodeUptimeReported(1, _, uptime)] if uptime == height as u64
+            ));
+            heights.push(height);
+        }
+        assert_eq!(heights, (1..=40).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let mut stream = stream(MockClient {
+            flaky: Some(7),
+            ..Default::default()
+        });
+        let mut heights = vec![];
+        while let Some(block) = stream.next().await {
+            heights.push(block.unwrap().0);
+        }
+        assert_eq!(heights, (1..=40).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn stream_stops_at_the_first_failed_block() {
+        let mut stream = stream(MockClient {
+            broken: Some(12),
+            ..Default::default()
+        });
+        for expected in 1..12 {
+            let (height, ..) = stream.next().await.unwrap().unwrap();
+            assert_eq!(height, expected);
+        }
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(ClientError::Decode(_)))
+        ));
+        // Blocks after the failed one are not delivered, even if they were fetched.
+        assert!(stream.next().await.is_none());
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -1,5 +1,6 @@
 /// The hash type used on the TfChain.
 pub type Hash = subxt::utils::H256;
+use codec::{Decode, Encode};
 use subxt::utils::AccountId32;
 /// Public Key type, this is a placeholder.
 pub type PublicKey = [u8; 32];
@@ -11,6 +12,9 @@ pub const TFGRID_MODULE: &str = "TfgridModule";
 pub const NODE_STORED: &str = "NodeStored";
 pub const NODE_UPDATED: &str = "NodeUpdated";
 pub const NODE_UPTIME_REPORTED: &str = "NodeUptimeReported";
+pub const NODE_DELETED: &str = "NodeDeleted";
+pub const FARM_DELETED: &str = "FarmDeleted";
+pub const TWIN_DELETED: &str = "TwinDeleted";
 pub const FARMING_POLICIES: &str = "FarmingPoliciesMap";
 pub const FARMING_POLICY_ID: &str = "FarmingPolicyID";
 pub const NODE_ID: &str = "NodeID";
@@ -20,6 +24,12 @@ pub const FARM_PAYOUT_V2_ADDRESS: &str = "FarmPayoutV2AddressByFarmID";
 pub const TWINS: &str = "Twins";
 pub const TWIN_ID: &str = "TwinID";
 pub const FARMS: &str = "Farms";
+pub const CONNECTION_PRICE: &str = "ConnectionPrice";
+pub const CONNECTION_PRICE_SET: &str = "ConnectionPriceSet";
+pub const NODE_CERTIFICATION_SET: &str = "NodeCertificationSet";
+pub const FARMING_POLICY_SET: &str = "FarmingPolicySet";
+pub const FARMING_POLICY_UPDATED: &str = "FarmingPolicyUpdated";
+pub const FARMING_POLICY_STORED: &str = "FarmingPolicyStored";
 pub const NODE_POWER: &str = "NodePower";
 pub const POWER_TARGET_CHANGED: &str = "PowerTargetChanged";
 pub const POWER_STATE_CHANGED: &str = "PowerStateChanged";
@@ -29,6 +39,9 @@ pub const UPDATE_USED_RESOURCES: &str = "UpdatedUsedResources";
 pub const NRU_CONSUMPTION_RECEIVED: &str = "NruConsumptionReportReceived";
 pub const CONTRACT_CREATED: &str = "ContractCreated";
 pub const NODE_CONTRACT_CANCELLED: &str = "NodeContractCanceled";
+pub const RENT_CONTRACT_CANCELLED: &str = "RentContractCanceled";
+pub const CONTRACT_GRACE_PERIOD_STARTED: &str = "ContractGracePeriodStarted";
+pub const CONTRACT_GRACE_PERIOD_ENDED: &str = "ContractGracePeriodEnded";
 pub const NODE_CONTRACT_RESOURCES: &str = "NodeContractResources";
 pub const CONTRACT_ID: &str = "ContractID";
 pub const CONTRACTS: &str = "Contracts";
@@ -36,7 +49,7 @@ pub const CONTRACTS: &str = "Contracts";
 pub const TIMESTAMP_MODULE: &str = "Timestamp";
 pub const TIMESTAMP_NOW: &str = "Now";
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Twin {
     pub version: u32,
     pub id: u32,
@@ -47,7 +60,7 @@ pub struct Twin {
     pub pk: Option<Vec<u8>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Farm {
     pub version: u32,
     pub id: u32,
@@ -60,7 +73,7 @@ pub struct Farm {
     pub farming_policy_limits: Option<FarmingPolicyLimit>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct PublicIP {
     // Not ideal but there is no type in std to represent IP+subnet currently, could make this a
     // tuple of (IP, mask).
@@ -70,7 +83,7 @@ pub struct PublicIP {
     pub contract_id: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct FarmingPolicyLimit {
     pub farming_policy_id: u32,
     pub cu: Option<u64>,
@@ -80,13 +93,13 @@ pub struct FarmingPolicyLimit {
     pub node_certification: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum FarmCertification {
     Gold,
     NotCertified,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Node {
     pub version: u32,
     pub id: u32,
@@ -107,7 +120,7 @@ pub struct Node {
     pub connection_price: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Interface {
     pub name: String,
     // This really should be a [u8;6], but the chain saves this as a string currently;
@@ -115,7 +128,7 @@ pub struct Interface {
     pub ips: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Resources {
     pub hru: u64,
     pub sru: u64,
@@ -123,13 +136,13 @@ pub struct Resources {
     pub mru: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Location {
     pub longitude: String,
     pub latitude: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Contract {
     pub version: u32,
     pub state: ContractState,
@@ -139,27 +152,27 @@ pub struct Contract {
     pub solution_provider_id: Option<u64>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum ContractState {
     Created,
     Deleted(Cause),
     GracePeriod(u64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum Cause {
     CanceledByUser,
     OutOfFunds,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum ContractData {
     NodeContract(NodeContract),
     NameContract(NameContract),
     RentContract(RentContract),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct NodeContract {
     pub node_id: u32,
     pub deployment_hash: Hash,
@@ -168,45 +181,45 @@ pub struct NodeContract {
     pub public_ips_list: Vec<PublicIP>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct NameContract {
     pub name: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct RentContract {
     pub node_id: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct PublicConfig {
     pub ip4: PubIPConfig,
     pub ip6: Option<PubIPConfig>,
     pub domain: Option<Domain>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct PubIPConfig {
     pub ip: String,
     pub gw: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct Domain(pub String);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct ContractResources {
     pub contract_id: u64,
     pub used: Resources,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct EntityProof {
     pub entity_id: u32,
     pub signature: Vec<u8>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct FarmPolicy {
     pub version: u32,
     pub id: u32,
@@ -224,13 +237,13 @@ pub struct FarmPolicy {
     pub farm_certification: FarmCertification,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
 pub enum NodeCertification {
     Certified,
     Diy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct NruConsumption {
     pub contract_id: u64,
     pub timestamp: u64,
@@ -238,39 +251,73 @@ pub struct NruConsumption {
     pub nru: u64,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
 pub enum PowerState {
     Up,
     Down(u32),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Encode, Decode)]
 pub enum Power {
     Up,
     Down,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct NodePower {
     pub state: PowerState,
     pub target: Power,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct PowerStateChanged {
     pub farm_id: u32,
     pub node_id: u32,
     pub power_state: PowerState,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
 pub struct PowerTargetChanged {
     pub farm_id: u32,
     pub node_id: u32,
     pub power_target: Power,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct NodeContractCanceled {
+    pub contract_id: u64,
+    pub node_id: u32,
+    pub twin_id: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct ContractGracePeriodStarted {
+    pub contract_id: u64,
+    pub node_id: u32,
+    pub twin_id: u32,
+    pub block_number: u64,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct ContractGracePeriodEnded {
+    pub contract_id: u64,
+    pub node_id: u32,
+    pub twin_id: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct NodeCertificationSet {
+    pub node_id: u32,
+    pub certification: NodeCertification,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct FarmingPolicySet {
+    pub farm_id: u32,
+    pub limit: Option<FarmingPolicyLimit>,
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
 pub enum RuntimeEvents {
     NodeStoredEvent(Node),
     NodeUpdatedEvent(Node),
@@ -280,4 +327,16 @@ pub enum RuntimeEvents {
     NruConsumptionReceived(NruConsumption),
     PowerStateChanged(PowerStateChanged),
     PowerTargetChanged(PowerTargetChanged),
+    NodeDeleted(u32),
+    FarmDeleted(u32),
+    TwinDeleted(u32),
+    NodeContractCanceled(NodeContractCanceled),
+    RentContractCanceled(u64),
+    ContractGracePeriodStarted(ContractGracePeriodStarted),
+    ContractGracePeriodEnded(ContractGracePeriodEnded),
+    ConnectionPriceSet(u32),
+    NodeCertificationSet(NodeCertificationSet),
+    FarmingPolicySet(FarmingPolicySet),
+    FarmingPolicyUpdated(FarmPolicy),
+    FarmingPolicyStored(FarmPolicy),
 }
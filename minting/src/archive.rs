@@ -0,0 +1,399 @@
+//! Archives of the chain data used to mint a period.
+//!
+//! Importing all blocks of a period takes a long time, and puts a lot of load on the RPC nodes.
+//! An archive holds everything the [`MintingEngine`](crate::engine::MintingEngine) consumes for
+//! a period, so a minting run can be repeated without any network access.
+//!
+//! An archive is a directory with 2 files:
+//!
+//! - `header`: the SCALE encoded [`ArchiveHeader`], holding the period, the [`ArchiveSettings`] of
+//!   the run, the [`Snapshot`] and the [`Backlog`] of receipts which were not fully paid at the
+//!   time of the run.
+//! - `blocks`: every imported block as an [`ArchivedBlock`], in order. Every block is SCALE encoded
+//!   and prefixed with its encoded length as a little endian u32.
+
+use crate::{
+    backlog::Backlog, capacity::CapacityPolicy, period::Period, pricing::PriceOverrides,
+    rules::RuleSet, snapshot::Snapshot,
+};
+use codec::{Decode, Encode};
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+use tfchain_client::types::{Hash, RuntimeEvents};
+
+/// Version of the archive format. Increment this if the layout of any archived type changes.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const HEADER_FILE: &str = "header";
+const BLOCKS_FILE: &str = "blocks";
+
+/// General information about an archived period.
+#[derive(Encode, Decode)]
+pub struct ArchiveHeader {
+    pub version: u32,
+    pub period: Period,
+    pub settings: ArchiveSettings,
+    pub snapshot: Snapshot,
+    /// The backlog of receipts which were not fully paid when the archive was recorded. This is
+    /// used instead of querying the payment ledger.
+    pub backlog: Backlog,
+}
+
+/// The settings the period was minted with when the archive was recorded. Replaying the archive
+/// with other settings would not reproduce the recorded run, so this is refused.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ArchiveSettings {
+    /// Name of the minted network.
+    pub network: String,
+    /// Name of the tokenomics rule set.
+    pub rule_set: String,
+    /// Hash of the tokenomics rule set, see [`RuleSet::hash`].
+    pub rule_set_hash: [u8; 32],
+    /// The connection price overrides for the period, as node id and price in mUSD.
+    pub price_overrides: Vec<(u32, u32)>,
+    pub capacity_policy: CapacityPolicy,
+}
+
+impl ArchiveSettings {
+    /// The settings to mint the period with the given offset on a network.
+    pub fn new(
+        network: &str,
+        rules: &RuleSet,
+        price_overrides: &PriceOverrides,
+        capacity_policy: CapacityPolicy,
+        period: i64,
+    ) -> Self {
+        ArchiveSettings {
+            network: network.to_string(),
+            rule_set: rules.name.clone(),
+            rule_set_hash: rules.hash(),
+            price_overrides: price_overrides.in_period(period),
+            capacity_policy,
+        }
+    }
+
+    /// Describe the settings which differ from the recorded settings.
+    pub fn mismatches(&self, recorded: &ArchiveSettings) -> Vec<String> {
+        let mut mismatches = vec![];
+        if self.network != recorded.network {
+            mismatches.push(format!(
+                "network {} was recorded, not {}",
+                recorded.network, self.network
+            ));
+        }
+        if self.rule_set_hash != recorded.rule_set_hash {
+            mismatches.push(format!(
+                "rule set {} ({}) was recorded, not {} ({})",
+                recorded.rule_set,
+                hex::encode(recorded.rule_set_hash),
+                self.rule_set,
+                hex::encode(self.rule_set_hash)
+            ));
+        }
+        if self.price_overrides != recorded.price_overrides {
+            mismatches.push(format!(
+                "{} price overrides were recorded, which differ from the {} given",
+                recorded.price_overrides.len(),
+                self.price_overrides.len()
+            ));
+        }
+        if self.capacity_policy != recorded.capacity_policy {
+            mismatches.push(format!(
+                "capacity increases {} and decreases {} were recorded, not {} and {}",
+                recorded.capacity_policy.increase,
+                recorded.capacity_policy.decrease,
+                self.capacity_policy.increase,
+                self.capacity_policy.decrease
+            ));
+        }
+        mismatches
+    }
+}
+
+/// A single block in the archive.
+#[derive(Encode, Decode)]
+pub struct ArchivedBlock {
+    pub height: u32,
+    pub hash: Hash,
+    /// Timestamp of the block, in seconds since the UNIX epoch.
+    pub timestamp: i64,
+    pub events: Vec<RuntimeEvents>,
+}
+
+/// Records an archive while a period is being minted.
+pub struct ArchiveWriter {
+    blocks: BufWriter<fs::File>,
+}
+
+impl ArchiveWriter {
+    /// Create a new archive in the given directory. The directory is created if it does not
+    /// exist, and any existing archive in it is overwritten.
+    pub fn create(
+        dir: &Path,
+        period: Period,
+        settings: &ArchiveSettings,
+        snapshot: &Snapshot,
+        backlog: &Backlog,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        // Snapshot is not clone, so encode the header by hand. The encoding of a struct is the
+        // concatenation of the encoding of its fields.
+        let mut header = Vec::new();
+        ARCHIVE_VERSION.encode_to(&mut header);
+        period.encode_to(&mut header);
+        settings.encode_to(&mut header);
+        snapshot.encode_to(&mut header);
+        backlog.encode_to(&mut header);
+        fs::write(dir.join(HEADER_FILE), header)?;
+
+        Ok(ArchiveWriter {
+            blocks: BufWriter::new(fs::File::create(dir.join(BLOCKS_FILE))?),
+        })
+    }
+
+    /// Append a block to the archive. Blocks must be written in order.
+    pub fn write_block(
+        &mut self,
+        height: u32,
+        hash: Hash,
+        timestamp: i64,
+        events: &[RuntimeEvents],
+    ) -> io::Result<()> {
+        let mut data = Vec::new();
+        height.encode_to(&mut data);
+        hash.encode_to(&mut data);
+        timestamp.encode_to(&mut data);
+        events.encode_to(&mut data);
+        self.blocks.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.blocks.write_all(&data)
+    }
+
+    /// Flush all data to disk.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.blocks.flush()
+    }
+}
+
+/// An archive on disk.
+pub struct Archive {
+    dir: PathBuf,
+    header: ArchiveHeader,
+}
+
+impl Archive {
+    /// Open the archive in the given directory.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let data = fs::read(dir.join(HEADER_FILE))?;
+        let header = ArchiveHeader::decode(&mut data.as_slice())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if header.version != ARCHIVE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported archive version {}, expected {ARCHIVE_VERSION}",
+                    header.version
+                ),
+            ));
+        }
+        Ok(Archive {
+            dir: dir.to_path_buf(),
+            header,
+        })
+    }
+
+    /// The header of the archive.
+    pub fn header(&self) -> &ArchiveHeader {
+        &self.header
+    }
+
+    /// Check that the archive was recorded with the given settings, so replaying it reproduces
+    /// the recorded run.
+    pub fn check_settings(&self, settings: &ArchiveSettings) -> io::Result<()> {
+        let mismatches = settings.mismatches(&self.header.settings);
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "archive was recorded with other settings: {}",
+                mismatches.join("; ")
+            ),
+        ))
+    }
+
+    /// Split the archive in its header and a reader over the archived blocks.
+    pub fn into_parts(self) -> io::Result<(ArchiveHeader, BlockReader)> {
+        let blocks = BlockReader {
+            reader: BufReader::new(fs::File::open(self.dir.join(BLOCKS_FILE))?),
+        };
+        Ok((self.header, blocks))
+    }
+}
+
+/// Reads the archived blocks in order.
+pub struct BlockReader {
+    reader: BufReader<fs::File>,
+}
+
+impl Iterator for BlockReader {
+    type Item = io::Result<ArchivedBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut len = [0; 4];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        }
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        if let Err(e) = self.reader.read_exact(&mut data) {
+            return Some(Err(e));
+        }
+        Some(
+            ArchivedBlock::decode(&mut data.as_slice())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capacity::CapacityChange;
+    use std::collections::BTreeMap;
+
+    /// A fresh directory for an archive.
+    fn archive_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("archive-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn settings() -> ArchiveSettings {
+        ArchiveSettings::new(
+            "mainnet",
+            &RuleSet::builtin("mainnet", 70),
+            &PriceOverrides::default(),
+            CapacityPolicy::default(),
+            70,
+        )
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            start_block: 10,
+            end_block: 20,
+            start_block_hash: Hash::repeat_byte(1),
+            end_block_hash: Hash::repeat_byte(2),
+            start_block_ts: 1_000,
+            nodes: vec![],
+            power_states: BTreeMap::new(),
+            power_down_timestamps: BTreeMap::from([(1, 500)]),
+            contracts: vec![],
+            connection_price: 80,
+            farms: BTreeMap::new(),
+            twins: BTreeMap::new(),
+            payout_addresses: BTreeMap::from([(1, "GFARM".to_string())]),
+            farming_policies: BTreeMap::new(),
+        }
+    }
+
+    /// Record an archive with 2 blocks in the directory.
+    fn record(dir: &Path, settings: &ArchiveSettings) {
+        let mut writer = ArchiveWriter::create(
+            dir,
+            Period::at_offset(70),
+            settings,
+            &snapshot(),
+            &Backlog::default(),
+        )
+        .unwrap();
+        writer
+            .write_block(10, Hash::repeat_byte(1), 1_000, &[])
+            .unwrap();
+        writer
+            .write_block(
+                11,
+                Hash::repeat_byte(3),
+                1_006,
+                &[RuntimeEvents::NodeDeleted(1), RuntimeEvents::FarmDeleted(2)],
+            )
+            .unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn recorded_archive_is_read_back() {
+        let dir = archive_dir("round-trip");
+        let settings = settings();
+        record(&dir, &settings);
+
+        let archive = Archive::open(&dir).unwrap();
+        archive.check_settings(&settings).unwrap();
+        let (header, blocks) = archive.into_parts().unwrap();
+        assert_eq!(header.version, ARCHIVE_VERSION);
+        assert!(header.period == Period::at_offset(70));
+        assert_eq!(header.settings, settings);
+        assert_eq!(header.snapshot.encode(), snapshot().encode());
+
+        let blocks: Vec<_> = blocks.collect::<io::Result<_>>().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].height, 10);
+        assert!(blocks[0].events.is_empty());
+        assert_eq!(blocks[1].height, 11);
+        assert_eq!(blocks[1].hash, Hash::repeat_byte(3));
+        assert_eq!(blocks[1].timestamp, 1_006);
+        assert!(matches!(
+            blocks[1].events[..],
+            [RuntimeEvents::NodeDeleted(1), RuntimeEvents::FarmDeleted(2)]
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replay_with_other_settings_is_refused() {
+        let dir = archive_dir("settings");
+        record(&dir, &settings());
+        let archive = Archive::open(&dir).unwrap();
+
+        let mut other = settings();
+        other.capacity_policy.increase = CapacityChange::Apply;
+        other.network = "testnet".to_string();
+        let err = archive.check_settings(&other).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(settings().mismatches(&other).len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archive_of_other_version_is_refused() {
+        let dir = archive_dir("version");
+        record(&dir, &settings());
+        let header = dir.join(HEADER_FILE);
+        let mut data = fs::read(&header).unwrap();
+        data[..4].copy_from_slice(&(ARCHIVE_VERSION + 1).to_le_bytes());
+        fs::write(&header, data).unwrap();
+        assert!(Archive::open(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_block_is_an_error() {
+        let dir = archive_dir("truncated");
+        record(&dir, &settings());
+        let blocks = dir.join(BLOCKS_FILE);
+        let data = fs::read(&blocks).unwrap();
+        fs::write(&blocks, &data[..data.len() - 1]).unwrap();
+
+        let (_, blocks) = Archive::open(&dir).unwrap().into_parts().unwrap();
+        let blocks: Vec<_> = blocks.collect();
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].is_ok());
+        assert!(blocks[1].is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,497 @@
+//! The backlog of receipts which are not fully paid yet, across all past periods.
+//!
+//! Every receipt which should be paid is tracked in the backlog until it is paid or abandoned:
+//!
+//! - `unpaid`: the receipt (or its last retry) is not fully paid, and will be retried in the next
+//!   run.
+//! - `retried`: a retry receipt has been created for the receipt. Payment of both the retry
+//!   receipt and the original receipt is checked in the next run, as the original payment might
+//!   still have been made after the retry was created. If neither is fully paid, the receipt
+//!   becomes unpaid again.
+//! - `paid`: the receipt is fully paid.
+//! - `abandoned`: the receipt is older than the maximum retry age, and won't be retried anymore.
+//!
+//! The backlog is stored as a JSON file, keyed by the hex encoded hash of the original receipt.
+
+use crate::{
+    period::Period,
+    receipt::{RetryPayoutReceipt, Reward},
+    reconcile::ExpectedPayment,
+};
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+};
+
+/// The default amount of periods after which an unpaid receipt is abandoned.
+pub const DEFAULT_MAX_RETRY_AGE: i64 = 12;
+
+/// The kind of receipt which is tracked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, Encode, Decode)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptKind {
+    Minting,
+    Fixup,
+    /// A retry receipt which was created before the backlog existed.
+    Retry,
+}
+
+/// The payment state of a tracked receipt.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize, Encode, Decode)]
+#[serde(rename_all = "lowercase", tag = "state")]
+pub enum PaymentState {
+    Unpaid,
+    Retried {
+        /// Hex encoded hash of the last retry receipt.
+        retry_receipt: String,
+        /// Offset of the period in which the retry was created.
+        retry_period: i64,
+    },
+    Paid,
+    Abandoned,
+}
+
+/// A receipt which is tracked in the backlog.
+#[derive(Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct OutstandingPayment {
+    pub kind: ReceiptKind,
+    /// The period the receipt was created for.
+    pub period: Period,
+    pub farm_id: u32,
+    /// The address where the payment is expected, this is updated on every retry.
+    pub stellar_payout_address: String,
+    /// The part of the reward which is not paid yet.
+    pub reward: Reward,
+    pub state: PaymentState,
+    /// The address the original receipt is paid to. This is empty in backlogs which were saved
+    /// before it was tracked.
+    #[serde(default)]
+    pub original_payout_address: String,
+    /// The amount of TFT units of the original receipt, 0 if it is not known.
+    #[serde(default)]
+    pub original_tft: u64,
+}
+
+impl OutstandingPayment {
+    /// A receipt which is not paid yet.
+    pub fn unpaid(
+        kind: ReceiptKind,
+        period: Period,
+        farm_id: u32,
+        stellar_payout_address: String,
+        reward: Reward,
+    ) -> Self {
+        OutstandingPayment {
+            kind,
+            period,
+            farm_id,
+            original_payout_address: stellar_payout_address.clone(),
+            original_tft: reward.tft,
+            stellar_payout_address,
+            reward,
+            state: PaymentState::Unpaid,
+        }
+    }
+
+    /// The payment which is expected for the original receipt with the given hash. If the
+    /// original receipt is not known, this is the current expectation.
+    fn original_payment(&self, receipt: [u8; 32]) -> ExpectedPayment {
+        if self.original_tft == 0 {
+            return ExpectedPayment {
+                receipt,
+                destination: self.stellar_payout_address.clone(),
+                amount: self.reward.tft,
+            };
+        }
+        ExpectedPayment {
+            receipt,
+            destination: self.original_payout_address.clone(),
+            amount: self.original_tft,
+        }
+    }
+
+    /// Whether the receipt still needs to be paid.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.state,
+            PaymentState::Unpaid | PaymentState::Retried { .. }
+        )
+    }
+}
+
+/// All tracked receipts.
+#[derive(Default, Clone, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+pub struct Backlog {
+    receipts: BTreeMap<String, OutstandingPayment>,
+}
+
+impl Backlog {
+    /// Load the backlog from a file. If the file does not exist, an empty backlog is returned.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Backlog::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save the backlog to a file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Start tracking a receipt. Receipts which are already tracked are not changed.
+    pub fn track(&mut self, receipt: [u8; 32], payment: OutstandingPayment) {
+        self.receipts.entry(hex::encode(receipt)).or_insert(payment);
+    }
+
+    /// All tracked receipts, keyed by the hex encoded hash of the receipt.
+    pub fn receipts(&self) -> &BTreeMap<String, OutstandingPayment> {
+        &self.receipts
+    }
+
+    /// The amount of receipts which still need to be paid.
+    pub fn open(&self) -> usize {
+        self.receipts.values().filter(|p| p.is_open()).count()
+    }
+
+    /// The payments which are expected for all open receipts. For retried receipts, both the
+    /// payment of the original receipt and the payment of the last retry receipt are expected.
+    pub fn expected_payments(&self) -> Vec<ExpectedPayment> {
+        let mut expected = Vec::new();
+        for (hash, payment) in &self.receipts {
+            let Some(receipt) = decode_hash(hash) else {
+                continue;
+            };
+            match &payment.state {
+                PaymentState::Unpaid => expected.push(payment.original_payment(receipt)),
+                PaymentState::Retried { retry_receipt, .. } => {
+                    // The original payment can only be checked if it is known.
+                    if payment.original_tft > 0 {
+                        expected.push(payment.original_payment(receipt));
+                    }
+                    if let Some(retry) = decode_hash(retry_receipt) {
+                        expected.push(ExpectedPayment {
+                            receipt: retry,
+                            destination: payment.stellar_payout_address.clone(),
+                            amount: payment.reward.tft,
+                        });
+                    }
+                }
+                PaymentState::Paid | PaymentState::Abandoned => {}
+            }
+        }
+        expected
+    }
+
+    /// Update the open receipts with the outstanding amounts found by reconciling the expected
+    /// payments. The outstanding amounts are keyed by memo, open receipts for which no amount is
+    /// outstanding are paid. A retried receipt is paid if either the original receipt or the
+    /// retry receipt is paid, otherwise the smallest outstanding amount of the two remains.
+    pub fn apply_reconciliation(&mut self, outstanding: &HashMap<[u8; 32], u64>) {
+        for (hash, payment) in self.receipts.iter_mut() {
+            let Some(receipt) = decode_hash(hash) else {
+                continue;
+            };
+            let original = outstanding.get(&receipt).copied().unwrap_or(0);
+            let remaining = match &payment.state {
+                PaymentState::Unpaid => original,
+                PaymentState::Retried { retry_receipt, .. } => {
+                    let Some(retry) = decode_hash(retry_receipt) else {
+                        continue;
+                    };
+                    let retry = outstanding.get(&retry).copied().unwrap_or(0);
+                    if payment.original_tft > 0 {
+                        retry.min(original)
+                    } else {
+                        retry
+                    }
+                }
+                PaymentState::Paid | PaymentState::Abandoned => continue,
+            };
+            if remaining == 0 {
+                payment.state = PaymentState::Paid;
+            } else {
+                payment.reward = outstanding_reward(payment.reward, remaining);
+                payment.state = PaymentState::Unpaid;
+            }
+        }
+    }
+
+    /// Abandon all open receipts for periods more than `max_age` periods before the given period,
+    /// with periods counted from the first period of the network starting at `period_epoch`.
+    /// Returns the amount of abandoned receipts.
+    pub fn abandon_older_than(&mut self, period: Period, period_epoch: i64, max_age: i64) -> usize {
+        let offset = period.offset_from(period_epoch);
+        let mut abandoned = 0;
+        for payment in self.receipts.values_mut() {
+            if payment.is_open() && offset - payment.period.offset_from(period_epoch) > max_age {
+                payment.state = PaymentState::Abandoned;
+                abandoned += 1;
+            }
+        }
+        abandoned
+    }
+
+    /// Create a retry receipt for every unpaid receipt, paid to the current payout address of the
+    /// farm. The receipts are marked as retried in the retry period, counted from the first period
+    /// of the network starting at `period_epoch`.
+    pub fn retries(
+        &mut self,
+        retry_period: Period,
+        period_epoch: i64,
+        payout_addresses: &BTreeMap<u32, String>,
+    ) -> Vec<RetryPayoutReceipt> {
+        let mut retries = Vec::new();
+        for (hash, payment) in self.receipts.iter_mut() {
+            // no point in retrying empty rewards
+            if payment.state != PaymentState::Unpaid || payment.reward.tft == 0 {
+                continue;
+            }
+            let retry = RetryPayoutReceipt {
+                failed_payout_period: payment.period,
+                retry_period,
+                farm_id: payment.farm_id,
+                previous_stellar_payout_address: payment.stellar_payout_address.clone(),
+                stellar_payout_address: payout_addresses
+                    .get(&payment.farm_id)
+                    .cloned()
+                    .unwrap_or_default(),
+                retry_for_receipt: hash.clone(),
+                reward: payment.reward,
+            };
+            payment.stellar_payout_address = retry.stellar_payout_address.clone();
+            payment.state = PaymentState::Retried {
+                retry_receipt: hex::encode(retry.hash()),
+                retry_period: retry_period.offset_from(period_epoch),
+            };
+            retries.push(retry);
+        }
+        retries
+    }
+}
+
+/// Decode a hex encoded receipt hash.
+fn decode_hash(hash: &str) -> Option<[u8; 32]> {
+    let mut receipt = [0; 32];
+    hex::decode_to_slice(hash, &mut receipt).ok()?;
+    Some(receipt)
+}
+
+/// The part of a reward which still needs to be paid, given the outstanding amount of TFT units.
+/// The mUSD value is scaled with the outstanding part of the reward.
+fn outstanding_reward(reward: Reward, outstanding: u64) -> Reward {
+    if reward.tft == 0 || outstanding >= reward.tft {
+        return reward;
+    }
+    Reward {
+        musd: (reward.musd as u128 * outstanding as u128 / reward.tft as u128) as u64,
+        tft: outstanding,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period::FIRST_PERIOD_START_TIMESTAMP;
+
+    const RECEIPT: [u8; 32] = [1; 32];
+
+    fn backlog(tft: u64) -> Backlog {
+        let mut backlog = Backlog::default();
+        backlog.track(
+            RECEIPT,
+            OutstandingPayment::unpaid(
+                ReceiptKind::Minting,
+                Period::at_offset(70),
+                1,
+                "GA".to_string(),
+                Reward { musd: tft * 2, tft },
+            ),
+        );
+        backlog
+    }
+
+    fn payment(backlog: &Backlog) -> &OutstandingPayment {
+        &backlog.receipts()[&hex::encode(RECEIPT)]
+    }
+
+    fn expected(backlog: &Backlog) -> Vec<([u8; 32], String, u64)> {
+        backlog
+            .expected_payments()
+            .into_iter()
+            .map(|p| (p.receipt, p.destination, p.amount))
+            .collect()
+    }
+
+    /// Create the retries of the unpaid receipts, to the new address of the farm. Returns the
+    /// hash of the retry receipt.
+    fn retry(backlog: &mut Backlog, period: i64) -> [u8; 32] {
+        let addresses = BTreeMap::from([(1, "GB".to_string())]);
+        let retries = backlog.retries(
+            Period::at_offset(period),
+            FIRST_PERIOD_START_TIMESTAMP,
+            &addresses,
+        );
+        assert_eq!(retries.len(), 1);
+        assert_eq!(retries[0].previous_stellar_payout_address, "GA");
+        assert_eq!(retries[0].stellar_payout_address, "GB");
+        retries[0].hash()
+    }
+
+    #[test]
+    fn unpaid_receipt_is_retried_and_paid() {
+        let mut backlog = backlog(100);
+        assert_eq!(expected(&backlog), vec![(RECEIPT, "GA".to_string(), 100)]);
+        backlog.apply_reconciliation(&HashMap::from([(RECEIPT, 100)]));
+        assert_eq!(payment(&backlog).state, PaymentState::Unpaid);
+
+        let retry = retry(&mut backlog, 71);
+        assert_eq!(
+            payment(&backlog).state,
+            PaymentState::Retried {
+                retry_receipt: hex::encode(retry),
+                retry_period: 71
+            }
+        );
+        // Both the original receipt and the retry are checked.
+        assert_eq!(
+            expected(&backlog),
+            vec![
+                (RECEIPT, "GA".to_string(), 100),
+                (retry, "GB".to_string(), 100)
+            ]
+        );
+
+        backlog.apply_reconciliation(&HashMap::from([(RECEIPT, 100)]));
+        assert_eq!(payment(&backlog).state, PaymentState::Paid);
+        assert_eq!(backlog.open(), 0);
+        assert!(backlog.expected_payments().is_empty());
+    }
+
+    #[test]
+    fn late_original_payment_settles_retry() {
+        let mut backlog = backlog(100);
+        let retry = retry(&mut backlog, 71);
+        backlog.apply_reconciliation(&HashMap::from([(retry, 100)]));
+        assert_eq!(payment(&backlog).state, PaymentState::Paid);
+    }
+
+    #[test]
+    fn underpaid_retry_is_retried_again() {
+        let mut backlog = backlog(100);
+        let first = retry(&mut backlog, 71);
+        backlog.apply_reconciliation(&HashMap::from([(RECEIPT, 100), (first, 40)]));
+        assert_eq!(payment(&backlog).state, PaymentState::Unpaid);
+        assert_eq!(payment(&backlog).reward.tft, 40);
+        assert_eq!(payment(&backlog).reward.musd, 80);
+
+        let addresses = BTreeMap::from([(1, "GB".to_string())]);
+        let retries = backlog.retries(
+            Period::at_offset(72),
+            FIRST_PERIOD_START_TIMESTAMP,
+            &addresses,
+        );
+        assert_eq!(retries.len(), 1);
+        let second = retries[0].hash();
+        assert_ne!(first, second);
+        // The original receipt is still expected in full, at the original address.
+        assert_eq!(
+            expected(&backlog),
+            vec![
+                (RECEIPT, "GA".to_string(), 100),
+                (second, "GB".to_string(), 40)
+            ]
+        );
+
+        // A partial late payment of the original receipt lowers the outstanding amount.
+        backlog.apply_reconciliation(&HashMap::from([(RECEIPT, 30), (second, 40)]));
+        assert_eq!(payment(&backlog).state, PaymentState::Unpaid);
+        assert_eq!(payment(&backlog).reward.tft, 30);
+    }
+
+    #[test]
+    fn retry_period_is_counted_from_the_network_epoch() {
+        let mut backlog = backlog(100);
+        // A network whose first period started 10 periods after the first period of mainnet.
+        let epoch = Period::at_offset(10).start();
+        let addresses = BTreeMap::from([(1, "GB".to_string())]);
+        let retries = backlog.retries(Period::at_offset(71), epoch, &addresses);
+        assert_eq!(retries.len(), 1);
+        assert!(matches!(
+            payment(&backlog).state,
+            PaymentState::Retried {
+                retry_period: 61,
+                ..
+            }
+        ));
+        assert_eq!(
+            backlog.abandon_older_than(Period::at_offset(82), epoch, 12),
+            0
+        );
+        assert_eq!(
+            backlog.abandon_older_than(Period::at_offset(83), epoch, 12),
+            1
+        );
+    }
+
+    #[test]
+    fn unpaid_retry_is_abandoned() {
+        let mut backlog = backlog(100);
+        let retry = retry(&mut backlog, 71);
+        let outstanding = HashMap::from([(RECEIPT, 100), (retry, 100)]);
+        backlog.apply_reconciliation(&outstanding);
+        assert_eq!(payment(&backlog).state, PaymentState::Unpaid);
+
+        assert_eq!(
+            backlog.abandon_older_than(Period::at_offset(82), FIRST_PERIOD_START_TIMESTAMP, 12),
+            0
+        );
+        assert_eq!(backlog.open(), 1);
+        assert_eq!(
+            backlog.abandon_older_than(Period::at_offset(83), FIRST_PERIOD_START_TIMESTAMP, 12),
+            1
+        );
+        assert_eq!(payment(&backlog).state, PaymentState::Abandoned);
+        assert_eq!(backlog.open(), 0);
+        assert!(backlog.expected_payments().is_empty());
+
+        // Abandoned receipts are not reopened or retried.
+        backlog.apply_reconciliation(&outstanding);
+        assert_eq!(payment(&backlog).state, PaymentState::Abandoned);
+        assert!(backlog
+            .retries(
+                Period::at_offset(83),
+                FIRST_PERIOD_START_TIMESTAMP,
+                &BTreeMap::new()
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn retry_without_original_only_checks_retry() {
+        let mut backlog = backlog(100);
+        let retry = retry(&mut backlog, 71);
+        // A backlog saved before the original receipt was tracked.
+        let mut json = serde_json::to_value(&backlog).unwrap();
+        let payment_json = json[hex::encode(RECEIPT)].as_object_mut().unwrap();
+        payment_json.remove("original_payout_address");
+        payment_json.remove("original_tft");
+        let mut backlog: Backlog = serde_json::from_value(json).unwrap();
+        assert_eq!(payment(&backlog).original_tft, 0);
+
+        assert_eq!(expected(&backlog), vec![(retry, "GB".to_string(), 100)]);
+        backlog.apply_reconciliation(&HashMap::from([(retry, 60)]));
+        assert_eq!(payment(&backlog).state, PaymentState::Unpaid);
+        assert_eq!(payment(&backlog).reward.tft, 60);
+        // An unpaid receipt is expected at its current address.
+        assert_eq!(expected(&backlog), vec![(RECEIPT, "GB".to_string(), 60)]);
+    }
+}
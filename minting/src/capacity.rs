@@ -0,0 +1,302 @@
+//! Capacity tracking for individual nodes.
+//!
+//! The capacity of a node can change during a period, when hardware is added, removed or breaks,
+//! and the node reports its new resources to the chain. Rather than rewarding the node for the
+//! capacity it reported last, the [`CapacityTimeline`] keeps every change as a segment starting at
+//! the time of the change, so rewards can be based on the time weighted average capacity over the
+//! period.
+//!
+//! Whether a change is actually applied depends on the [`CapacityPolicy`]. By default, decreases
+//! are applied immediately, while increases are ignored for the rest of the period.
+
+use crate::period::Period;
+use codec::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use tfchain_client::types::Resources;
+
+/// How a change of a single resource is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+#[serde(rename_all = "lowercase")]
+pub enum CapacityChange {
+    /// The new value is in effect from the moment it is reported.
+    Apply,
+    /// The new value is ignored, the previous value remains in effect.
+    Ignore,
+}
+
+impl fmt::Display for CapacityChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapacityChange::Apply => write!(f, "apply"),
+            CapacityChange::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+impl FromStr for CapacityChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "apply" => Ok(CapacityChange::Apply),
+            "ignore" => Ok(CapacityChange::Ignore),
+            _ => Err(format!(
+                "unknown capacity change policy {s}, expected apply or ignore"
+            )),
+        }
+    }
+}
+
+/// The handling of capacity changes reported during the period. Every resource is considered on
+/// its own, so a single update can both increase and decrease the capacity of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct CapacityPolicy {
+    /// Handling of a resource which is higher than before.
+    pub increase: CapacityChange,
+    /// Handling of a resource which is lower than before.
+    pub decrease: CapacityChange,
+}
+
+impl Default for CapacityPolicy {
+    /// Only lower resources in case of dead or removed hardware. Added hardware is not credited
+    /// in the period it is added.
+    fn default() -> Self {
+        CapacityPolicy {
+            increase: CapacityChange::Ignore,
+            decrease: CapacityChange::Apply,
+        }
+    }
+}
+
+impl CapacityPolicy {
+    /// The value of a resource after a change is reported.
+    fn apply(&self, current: u64, reported: u64) -> u64 {
+        let change = match reported.cmp(&current) {
+            std::cmp::Ordering::Greater => self.increase,
+            std::cmp::Ordering::Less => self.decrease,
+            std::cmp::Ordering::Equal => return current,
+        };
+        match change {
+            CapacityChange::Apply => reported,
+            CapacityChange::Ignore => current,
+        }
+    }
+}
+
+/// The capacity of a node over time. Every segment holds the resources in effect from its start
+/// timestamp until the start of the next segment.
+pub struct CapacityTimeline {
+    segments: Vec<(i64, Resources)>,
+}
+
+impl CapacityTimeline {
+    /// Create a new timeline, with the given resources in effect from the given timestamp.
+    pub fn new(since: i64, resources: Resources) -> Self {
+        CapacityTimeline {
+            segments: vec![(since, resources)],
+        }
+    }
+
+    /// The resources currently in effect.
+    pub fn current(&self) -> &Resources {
+        // There is always at least 1 segment.
+        &self.segments[self.segments.len() - 1].1
+    }
+
+    /// All segments in the timeline, as the start timestamp and the resources in effect.
+    pub fn segments(&self) -> &[(i64, Resources)] {
+        &self.segments
+    }
+
+    /// Process newly reported resources at the given timestamp. Returns true if the resources in
+    /// effect changed.
+    pub fn update(&mut self, ts: i64, reported: &Resources, policy: CapacityPolicy) -> bool {
+        let current = self.current();
+        let resources = Resources {
+            hru: policy.apply(current.hru, reported.hru),
+            sru: policy.apply(current.sru, reported.sru),
+            cru: policy.apply(current.cru, reported.cru),
+            mru: policy.apply(current.mru, reported.mru),
+        };
+        if resources.hru == current.hru
+            && resources.sru == current.sru
+            && resources.cru == current.cru
+            && resources.mru == current.mru
+        {
+            return false;
+        }
+        // Multiple updates at the same time only keep the last one.
+        if self.segments[self.segments.len() - 1].0 >= ts {
+            let last = self.segments.len() - 1;
+            self.segments[last].1 = resources;
+        } else {
+            self.segments.push((ts, resources));
+        }
+        true
+    }
+
+    /// The time weighted average of a value computed from the resources, over the part of the
+    /// period in which the timeline exists.
+    pub fn time_weighted(&self, period: Period, value: impl Fn(&Resources) -> u128) -> u128 {
+        self.time_weighted_between(period.start(), period.end(), value)
+    }
+
+    /// The time weighted average of a value computed from the resources, over the part of the
+    /// window from `from` to `to` in which the timeline exists.
+    pub fn time_weighted_between(
+        &self,
+        from: i64,
+        to: i64,
+        value: impl Fn(&Resources) -> u128,
+    ) -> u128 {
+        let mut total = 0;
+        let mut weighted = 0;
+        for (i, (start, resources)) in self.segments.iter().enumerate() {
+            let end = self
+                .segments
+                .get(i + 1)
+                .map_or(to, |(next, _)| *next)
+                .min(to);
+            let start = (*start).max(from);
+            if end <= start {
+                continue;
+            }
+            let duration = (end - start) as u128;
+            total += duration;
+            weighted += value(resources) * duration;
+        }
+        if total == 0 {
+            // The timeline only starts at the end of the window.
+            return value(self.current());
+        }
+        weighted / total
+    }
+
+    /// The time weighted average resources over the period.
+    pub fn average(&self, period: Period) -> Resources {
+        self.average_between(period.start(), period.end())
+    }
+
+    /// The time weighted average resources over the window from `from` to `to`.
+    pub fn average_between(&self, from: i64, to: i64) -> Resources {
+        let average = |value: fn(&Resources) -> u64| {
+            self.time_weighted_between(from, to, |r| value(r) as u128) as u64
+        };
+        Resources {
+            hru: average(|r| r.hru),
+            sru: average(|r| r.sru),
+            cru: average(|r| r.cru),
+            mru: average(|r| r.mru),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APPLY_ALL: CapacityPolicy = CapacityPolicy {
+        increase: CapacityChange::Apply,
+        decrease: CapacityChange::Apply,
+    };
+
+    fn resources(cru: u64, mru: u64) -> Resources {
+        Resources {
+            hru: 0,
+            sru: 1000,
+            cru,
+            mru,
+        }
+    }
+
+    fn cru_mru(resources: &Resources) -> (u64, u64) {
+        (resources.cru, resources.mru)
+    }
+
+    #[test]
+    fn default_policy_only_applies_decreases() {
+        let period = Period::at_offset(70);
+        let mut timeline = CapacityTimeline::new(period.start(), resources(8, 32));
+        // More cores are ignored, less memory is applied.
+        assert!(timeline.update(period.start() + 100, &resources(16, 16), Default::default()));
+        assert_eq!(cru_mru(timeline.current()), (8, 16));
+        // Only an increase, nothing changes.
+        assert!(!timeline.update(period.start() + 200, &resources(16, 32), Default::default()));
+        assert!(!timeline.update(period.start() + 300, &resources(8, 16), Default::default()));
+        assert_eq!(timeline.segments().len(), 2);
+    }
+
+    #[test]
+    fn change_mid_period_is_time_weighted() {
+        let period = Period::at_offset(70);
+        let half = period.start() + period.duration() as i64 / 2;
+        let mut timeline = CapacityTimeline::new(period.start(), resources(8, 32));
+        assert!(timeline.update(half, &resources(4, 64), APPLY_ALL));
+        assert_eq!(cru_mru(&timeline.average(period)), (6, 48));
+        assert_eq!(
+            timeline.time_weighted(period, |r| (r.cru * r.mru) as u128),
+            (8 * 32 + 4 * 64) / 2
+        );
+        // Only the segment in effect in a window counts.
+        assert_eq!(
+            cru_mru(&timeline.average_between(half, period.end())),
+            (4, 64)
+        );
+        assert_eq!(
+            cru_mru(&timeline.average_between(period.start(), half)),
+            (8, 32)
+        );
+    }
+
+    #[test]
+    fn updates_at_the_same_time_keep_the_last() {
+        let period = Period::at_offset(70);
+        let ts = period.start() + 100;
+        let mut timeline = CapacityTimeline::new(period.start(), resources(8, 32));
+        assert!(timeline.update(ts, &resources(4, 32), APPLY_ALL));
+        assert!(timeline.update(ts, &resources(2, 32), APPLY_ALL));
+        assert_eq!(timeline.segments().len(), 2);
+        assert_eq!(cru_mru(timeline.current()), (2, 32));
+    }
+
+    #[test]
+    fn node_created_in_period_is_averaged_from_creation() {
+        let period = Period::at_offset(70);
+        let created = period.start() + period.duration() as i64 / 2;
+        let mut timeline = CapacityTimeline::new(created, resources(8, 32));
+        let three_quarters = created + period.duration() as i64 / 4;
+        assert!(timeline.update(three_quarters, &resources(4, 32), APPLY_ALL));
+        assert_eq!(cru_mru(&timeline.average(period)), (6, 32));
+    }
+
+    #[test]
+    fn deleted_node_is_averaged_until_deletion() {
+        let period = Period::at_offset(70);
+        let quarter = period.duration() as i64 / 4;
+        let mut timeline = CapacityTimeline::new(period.start(), resources(8, 32));
+        assert!(timeline.update(period.start() + 2 * quarter, &resources(2, 32), APPLY_ALL));
+        // Deleted after three quarters of the period, the last segment only counts until then.
+        let deleted = period.start() + 3 * quarter;
+        assert_eq!(
+            timeline.time_weighted_between(period.start(), deleted, |r| r.cru as u128),
+            6
+        );
+        assert_eq!(cru_mru(&timeline.average(period)), (5, 32));
+    }
+
+    #[test]
+    fn timeline_after_window_uses_current_resources() {
+        let period = Period::at_offset(70);
+        let timeline = CapacityTimeline::new(period.end() + 100, resources(8, 32));
+        assert_eq!(cru_mru(&timeline.average(period)), (8, 32));
+    }
+
+    #[test]
+    fn change_policy_parses() {
+        assert_eq!("apply".parse(), Ok(CapacityChange::Apply));
+        assert_eq!("ignore".parse(), Ok(CapacityChange::Ignore));
+        assert!("keep".parse::<CapacityChange>().is_err());
+        assert_eq!(CapacityChange::Ignore.to_string(), "ignore");
+    }
+}
@@ -0,0 +1,1348 @@
+//! The minting engine, which calculates the rewards for a period from a stream of events.
+//!
+//! The engine starts from a [`Snapshot`] of the chain at the start of the period, and is then fed
+//! all blocks in the period, in order, followed by the blocks in the [`POST_PERIOD_BLOCKS`] after
+//! it. Once all blocks are processed, [`MintingEngine::finish`] produces the receipts for every
+//! node. The engine does not do any IO by itself, log lines are buffered and can be retrieved with
+//! [`MintingEngine::take_log`].
+
+use crate::{
+    capacity::CapacityPolicy,
+    limits,
+    node::{Contract, ContractStatus, MintingNode},
+    period::{Period, FIRST_PERIOD_START_TIMESTAMP},
+    pricing::{self, PriceOverrides, PriceSource},
+    receipt::MintingReceiptV2,
+    rules::{RuleSet, DEFAULT_NETWORK},
+    snapshot::Snapshot,
+    uptime::{TrackingMode, UptimeTracker},
+    violation::Violation,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+use tfchain_client::types::{
+    ContractData, ContractState, Farm, FarmPolicy, Resources, RuntimeEvents, Twin,
+};
+
+/// The amount of blocks expected in an hour.
+pub const BLOCKS_IN_HOUR: u32 = 10 * 60; // 10 blocks per minute
+/// The amount of blocks after the end of the period which are scanned for uptime events.
+pub const POST_PERIOD_BLOCKS: u32 = BLOCKS_IN_HOUR * 27;
+
+/// Calculates the minting of a single period.
+pub struct MintingEngine {
+    period: Period,
+    start_block: u32,
+    end_block: u32,
+    start_block_ts: i64,
+    nodes: BTreeMap<u32, MintingNode>,
+    contracts: BTreeMap<u64, Contract>,
+    farms: BTreeMap<u32, Farm>,
+    twins: BTreeMap<u32, Twin>,
+    payout_addresses: BTreeMap<u32, String>,
+    farming_policies: BTreeMap<u32, FarmPolicy>,
+    /// Nodes on a farming policy which is not known, with the id of that policy. Their reward
+    /// can't be calculated, so they fail the minting once it is finished.
+    unknown_policies: BTreeMap<u32, u32>,
+    capacity_policy: CapacityPolicy,
+    /// Connection price of the network in mUSD, used for nodes without a price of their own.
+    connection_price: u32,
+    price_overrides: PriceOverrides,
+    /// Timestamp of the start of the first period, to find the offset of the period.
+    period_epoch: i64,
+    rules: RuleSet,
+    mode: TrackingMode,
+    summary: Summary,
+    log: Vec<String>,
+}
+
+/// The result of minting a period.
+pub struct MintingOutcome {
+    pub period: Period,
+    /// Reports for every node known in the period, ordered by node id.
+    pub nodes: Vec<NodeReport>,
+    pub summary: Summary,
+}
+
+/// The minting result of a single node.
+pub struct NodeReport {
+    pub receipt: MintingReceiptV2,
+    pub violation: Violation,
+    pub virtualized: bool,
+    /// Timestamp at which the node was deleted, if it was deleted in the period.
+    pub deleted: Option<i64>,
+}
+
+/// Aggregated statistics of a minting run.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Summary {
+    /// Amount of blocks processed, including blocks after the period.
+    pub blocks: u32,
+    /// Amount of events processed, including events after the period.
+    pub events: usize,
+    pub nodes: usize,
+    /// Amount of nodes which reported uptime in the period.
+    pub nodes_online: usize,
+    pub nodes_with_violation: usize,
+    /// Amount of nodes which were deleted in the period.
+    pub nodes_deleted: usize,
+    /// Amount of nodes rolled back to the default farming policy by the limits of their farm.
+    pub nodes_policy_limited: usize,
+    /// Amount of nodes of which the farming policy, its rates or the certification changed in the
+    /// period.
+    pub nodes_policy_changed: usize,
+    /// Amount of receipts which need to be paid out.
+    pub payouts: usize,
+    /// Total reward in mUSD.
+    pub total_musd: u64,
+    /// Total reward in TFT units.
+    pub total_tft: u64,
+    /// Amount of NRU consumption reports for contracts which are not known, e.g. because they
+    /// were canceled before the period started.
+    pub nru_reports_unknown_contract: usize,
+    /// Amount of NRU consumption reports for contracts which were not active at all during the
+    /// reported window.
+    pub nru_reports_inactive_contract: usize,
+    /// Amount of NRU consumption reports which were only credited for the part of the reported
+    /// window in which the contract was active.
+    pub nru_reports_partially_active: usize,
+    /// Amount of NRU consumption reports which were older than the last report for the contract.
+    pub nru_reports_out_of_order: usize,
+    /// Amount of NRU consumption reports from before the start of the period.
+    pub nru_reports_before_period: usize,
+}
+
+/// An error which prevents the receipts of a period from being produced.
+#[derive(Debug)]
+pub enum MintingError {
+    /// The node has no connection price to convert its reward to TFT at.
+    MissingConnectionPrice { node_id: u32 },
+    /// The TFT reward on the receipt of the node is not the conversion of its mUSD reward.
+    InconsistentReceipt { node_id: u32, reason: String },
+    /// The node exceeds the farming policy limit of its farm, but there is no default farming
+    /// policy to roll it back to.
+    MissingDefaultPolicy { node_id: u32, farm_id: u32 },
+    /// The node is on a farming policy which is not known.
+    UnknownFarmingPolicy { node_id: u32, policy_id: u32 },
+}
+
+impl fmt::Display for MintingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MintingError::MissingConnectionPrice { node_id } => {
+                write!(f, "node {node_id} has no connection price")
+            }
+            MintingError::InconsistentReceipt { node_id, reason } => {
+                write!(f, "inconsistent receipt for node {node_id}: {reason}")
+            }
+            MintingError::MissingDefaultPolicy { node_id, farm_id } => write!(
+                f,
+                "node {node_id} exceeds the farming policy limit of farm {farm_id}, but there is no default farming policy to roll it back to"
+            ),
+            MintingError::UnknownFarmingPolicy { node_id, policy_id } => {
+                write!(f, "node {node_id} is on unknown farming policy {policy_id}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MintingError {}
+
+impl MintingEngine {
+    /// Create a new engine for the given period, starting from the chain state in the snapshot.
+    pub fn new(period: Period, snapshot: Snapshot) -> Self {
+        let Snapshot {
+            start_block,
+            end_block,
+            start_block_ts,
+            start_block_hash: _,
+            end_block_hash: _,
+            nodes,
+            mut power_states,
+            power_down_timestamps,
+            contracts,
+            farms,
+            twins,
+            payout_addresses,
+            farming_policies,
+            connection_price,
+        } = snapshot;
+
+        // Nodes which are currently power managed start out with the time they went down.
+        let mut unknown_policies = BTreeMap::new();
+        let nodes = nodes
+            .into_iter()
+            .filter_map(|node| {
+                let id = node.id;
+                let Some(policy) = farming_policies.get(&node.farming_policy_id).cloned() else {
+                    unknown_policies.insert(id, node.farming_policy_id);
+                    return None;
+                };
+                let uptime = match power_states.remove(&id) {
+                    Some(power) => UptimeTracker::with_power(
+                        id,
+                        period,
+                        start_block_ts,
+                        power,
+                        power_down_timestamps.get(&id).copied(),
+                    ),
+                    None => UptimeTracker::new(id, period, start_block_ts),
+                };
+                Some((
+                    id,
+                    MintingNode::new(node, start_block_ts, policy, connection_price, uptime),
+                ))
+            })
+            .collect();
+
+        let contracts = contracts
+            .into_iter()
+            .filter_map(|(contract, resources)| {
+                // Namecontract is actually billed once deployed through a node contract.
+                if let ContractData::NodeContract(nc) = contract.contract_type {
+                    // Contracts which are in grace period at the start of the period stay inactive
+                    // until the grace period ends.
+                    let status = match contract.state {
+                        ContractState::Created => ContractStatus::Active,
+                        ContractState::GracePeriod(_) => ContractStatus::GracePeriod,
+                        ContractState::Deleted(_) => ContractStatus::Canceled,
+                    };
+                    Some((
+                        contract.contract_id,
+                        Contract {
+                            contract_id: contract.contract_id,
+                            node_id: nc.node_id,
+                            // a report should pop up for this
+                            last_report_ts: 0,
+                            ips: nc.public_ips,
+                            resources,
+                            status: vec![(i64::MIN, status)],
+                        },
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        MintingEngine {
+            period,
+            start_block,
+            end_block,
+            start_block_ts,
+            nodes,
+            contracts,
+            farms,
+            twins,
+            payout_addresses,
+            farming_policies,
+            unknown_policies,
+            capacity_policy: CapacityPolicy::default(),
+            connection_price,
+            price_overrides: PriceOverrides::default(),
+            period_epoch: FIRST_PERIOD_START_TIMESTAMP,
+            rules: RuleSet::builtin(
+                DEFAULT_NETWORK,
+                period.offset_from(FIRST_PERIOD_START_TIMESTAMP),
+            ),
+            mode: TrackingMode::InPeriod,
+            summary: Summary::default(),
+            log: Vec::new(),
+        }
+    }
+
+    /// Set the policy for capacity changes reported during the period.
+    pub fn with_capacity_policy(mut self, policy: CapacityPolicy) -> Self {
+        self.capacity_policy = policy;
+        self
+    }
+
+    /// Set the tokenomics rule set used to calculate rewards. By default, the rule set shipped
+    /// with the binary for the default network is used.
+    pub fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Set the connection prices which override the prices on chain.
+    pub fn with_price_overrides(mut self, overrides: PriceOverrides) -> Self {
+        self.price_overrides = overrides;
+        self
+    }
+
+    /// Set the start of the first period of the network, which the period offsets in the price
+    /// overrides are relative to. By default, the first period of mainnet is used.
+    pub fn with_period_epoch(mut self, epoch: i64) -> Self {
+        self.period_epoch = epoch;
+        self
+    }
+
+    /// The period being minted.
+    pub fn period(&self) -> Period {
+        self.period
+    }
+
+    /// Height of the first block in the period.
+    pub fn start_block(&self) -> u32 {
+        self.start_block
+    }
+
+    /// Height of the last block in the period.
+    pub fn end_block(&self) -> u32 {
+        self.end_block
+    }
+
+    /// Height of the last block which needs to be processed by the engine.
+    pub fn last_block(&self) -> u32 {
+        self.end_block + POST_PERIOD_BLOCKS
+    }
+
+    /// Payout addresses of the farms at the end of the period.
+    pub fn payout_addresses(&self) -> &BTreeMap<u32, String> {
+        &self.payout_addresses
+    }
+
+    /// Take the log lines generated since the last call.
+    pub fn take_log(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// Feed an ordered stream of `(height, timestamp, events)` blocks to the engine, and produce
+    /// the outcome once the stream is exhausted.
+    pub fn run<I>(mut self, blocks: I) -> (Result<MintingOutcome, MintingError>, Vec<String>)
+    where
+        I: IntoIterator<Item = (u32, i64, Vec<RuntimeEvents>)>,
+    {
+        for (height, ts, events) in blocks {
+            if height > self.last_block() {
+                break;
+            }
+            self.process_block(height, ts, events);
+        }
+        let outcome = self.finish();
+        (outcome, self.take_log())
+    }
+
+    /// Process all events in a block. Blocks must be processed in order. Once a block past the end
+    /// of the period is processed, the engine switches to post period tracking.
+    pub fn process_block(&mut self, height: u32, ts: i64, events: Vec<RuntimeEvents>) {
+        self.summary.blocks += 1;
+        self.summary.events += events.len();
+        if height > self.end_block && self.mode == TrackingMode::InPeriod {
+            self.mode = TrackingMode::PostPeriod;
+            for node in self.nodes.values_mut() {
+                node.uptime.set_mode(TrackingMode::PostPeriod);
+            }
+        }
+        match self.mode {
+            TrackingMode::InPeriod => self.process_period_events(height, ts, events),
+            TrackingMode::PostPeriod => self.process_post_period_events(height, ts, events),
+        }
+    }
+
+    /// Finish the minting, and generate receipts for all nodes. Fails if the reward of a node
+    /// can't be calculated or converted to TFT, in which case no receipts can be trusted.
+    pub fn finish(&mut self) -> Result<MintingOutcome, MintingError> {
+        if let Some((&node_id, &policy_id)) = self.unknown_policies.iter().next() {
+            return Err(MintingError::UnknownFarmingPolicy { node_id, policy_id });
+        }
+        for node in self.nodes.values_mut() {
+            node.uptime.finalize(&mut self.log);
+        }
+        self.check_twins();
+        self.check_farms();
+        self.apply_price_overrides();
+        // Rewards are converted at the connection price, so every node needs one before any
+        // reward is calculated.
+        if let Some(node) = self.nodes.values().find(|node| node.connection_price == 0) {
+            return Err(MintingError::MissingConnectionPrice { node_id: node.id });
+        }
+        self.summary.nodes_policy_limited = limits::apply_farm_limits(
+            &mut self.nodes,
+            &self.farms,
+            &self.farming_policies,
+            self.period,
+            self.end_block,
+            &mut self.log,
+        )?;
+
+        let mut summary = self.summary.clone();
+        let mut reports = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.values() {
+            let receipt = node.receipt(
+                self.period,
+                &self.farms,
+                &self.payout_addresses,
+                &self.rules,
+            );
+            // A receipt must show the price its reward was converted at, anything else would
+            // make it impossible to verify. Every segment is converted on its own.
+            let rewards = if receipt.policy_segments.is_empty() {
+                vec![receipt.reward]
+            } else {
+                receipt.policy_segments.iter().map(|s| s.reward).collect()
+            };
+            for reward in rewards {
+                pricing::check_conversion(reward.musd, reward.tft, receipt.tft_connection_price)
+                    .map_err(|reason| MintingError::InconsistentReceipt {
+                        node_id: node.id,
+                        reason,
+                    })?;
+            }
+            summary.nodes += 1;
+            if node.uptime.measured_uptime().is_some() {
+                summary.nodes_online += 1;
+            }
+            if node.uptime.violation().is_some() {
+                summary.nodes_with_violation += 1;
+            }
+            if node.deleted.is_some() {
+                summary.nodes_deleted += 1;
+            }
+            if !receipt.policy_segments.is_empty() {
+                summary.nodes_policy_changed += 1;
+            }
+            if !receipt.stellar_payout_address.is_empty() && receipt.reward.tft != 0 {
+                summary.payouts += 1;
+                summary.total_musd += receipt.reward.musd;
+                summary.total_tft += receipt.reward.tft;
+            }
+            reports.push(NodeReport {
+                receipt,
+                violation: node.uptime.violation().clone(),
+                virtualized: node.virtualized,
+                deleted: node.deleted,
+            });
+        }
+
+        Ok(MintingOutcome {
+            period: self.period,
+            nodes: reports,
+            summary,
+        })
+    }
+
+    fn process_period_events(&mut self, height: u32, ts: i64, events: Vec<RuntimeEvents>) {
+        for evt in events.into_iter() {
+            match evt {
+                RuntimeEvents::NodeStoredEvent(node) => {
+                    let id = node.id;
+                    let Some(policy) = self.farming_policies.get(&node.farming_policy_id).cloned()
+                    else {
+                        self.log.push(format!(
+                            "New node {id} stored on unknown farming policy {}",
+                            node.farming_policy_id
+                        ));
+                        self.unknown_policies.insert(id, node.farming_policy_id);
+                        continue;
+                    };
+                    self.nodes.insert(
+                        id,
+                        MintingNode::new(
+                            node,
+                            ts,
+                            policy,
+                            self.connection_price,
+                            UptimeTracker::new(id, self.period, self.start_block_ts),
+                        ),
+                    );
+                    self.log.push(format!("New node stored with id {id}"));
+                }
+
+                RuntimeEvents::NodeUpdatedEvent(node) => {
+                    let Some(old_node) = self.nodes.get_mut(&node.id) else {
+                        self.log
+                            .push(format!("Ignoring update of unknown node {}", node.id));
+                        continue;
+                    };
+                    old_node.farm_id = node.farm_id;
+                    old_node.twin_id = node.twin_id;
+                    // Resources are tracked over time, the capacity policy decides which changes
+                    // take effect.
+                    if old_node
+                        .capacity
+                        .update(ts, &node.resources, self.capacity_policy)
+                    {
+                        self.log.push(format!(
+                            "Capacity of node {} changed to {:?}",
+                            node.id,
+                            old_node.capacity.current()
+                        ));
+                    }
+                    old_node.location = node.location;
+                    old_node.country = node.country;
+                    old_node.city = node.city;
+                    // Don't care about "create" as that should be fixed anyway
+                    // Certification and farming policy are tracked over time, so every part of
+                    // the period is rewarded with the policy in effect at that time.
+                    if old_node
+                        .policies
+                        .set_certification(ts, node.certification.clone())
+                    {
+                        self.log.push(format!(
+                            "Certification of node {} changed to {:?}",
+                            node.id, node.certification
+                        ));
+                    }
+                    if old_node.policies.current().policy.id != node.farming_policy_id {
+                        match self.farming_policies.get(&node.farming_policy_id) {
+                            Some(policy) => {
+                                self.log.push(format!(
+                                    "Farming policy of node {} changed to {}",
+                                    node.id, policy.id
+                                ));
+                                old_node.policies.set_policy(ts, policy.clone());
+                            }
+                            None => self.log.push(format!(
+                                "Ignoring change of node {} to unknown farming policy {}",
+                                node.id, node.farming_policy_id
+                            )),
+                        }
+                    }
+                    // Update connection price. This should not happen, but it is here in case
+                    // we modify the connection price of the node in place in the future and
+                    // emit this generic event when the 5 year fixed time is expired. Nodes
+                    // without a price of their own keep the price of the network they got.
+                    if node.connection_price != 0 {
+                        old_node.connection_price = node.connection_price;
+                        old_node.price_source = PriceSource::Node;
+                    }
+                    // Even though this likely means the node is rebooted, don't mess with
+                    // uptime_info. The reboot will be detected in the `NodeUptimeReported`
+                    // handler.
+                    // This does not change when the node was connected.
+                    //
+                    // Once a VM, always a VM
+                    if node.virtualized {
+                        old_node.virtualized = node.virtualized;
+                    }
+
+                    self.log.push(format!("Node updated with id {}", node.id));
+                }
+                RuntimeEvents::NodeUptimeReported(id, current_time, reported_uptime) => {
+                    let Some(node) = self.nodes.get_mut(&id) else {
+                        self.log
+                            .push(format!("Ignoring uptime report of unknown node {id}"));
+                        continue;
+                    };
+                    node.uptime.on_uptime_report(
+                        height,
+                        ts,
+                        current_time,
+                        reported_uptime,
+                        &mut self.log,
+                    );
+                }
+                RuntimeEvents::ContractUsedResourcesUpdated(data) => {
+                    let Some(contract) = self.contracts.get_mut(&data.contract_id) else {
+                        self.log.push(format!(
+                            "Ignoring used resources of unknown contract {}",
+                            data.contract_id
+                        ));
+                        continue;
+                    };
+                    contract.resources = data.used;
+                    self.log.push(format!(
+                        "Update used resources for contract {}",
+                        contract.contract_id
+                    ));
+                }
+                RuntimeEvents::NruConsumptionReceived(data) => {
+                    let contract = match self.contracts.get_mut(&data.contract_id) {
+                        Some(contract) => contract,
+                        // Canceled contracts are kept, so this is a contract which was already
+                        // gone before the period started.
+                        None => {
+                            self.summary.nru_reports_unknown_contract += 1;
+                            self.log.push(format!(
+                                "Ignoring NRU consumption report for unknown contract {}",
+                                data.contract_id
+                            ));
+                            continue;
+                        }
+                    };
+                    let Some(node) = self.nodes.get_mut(&contract.node_id) else {
+                        self.log.push(format!(
+                            "Ignoring NRU consumption report for contract {} on unknown node {}",
+                            contract.contract_id, contract.node_id
+                        ));
+                        continue;
+                    };
+                    // Just to make sure reports are ordered
+                    if ts <= contract.last_report_ts {
+                        // Silently ignore reports out of order, we already covered this in an
+                        // already processed consumption report. This can happen if the node pushes
+                        // a contract consumption report twice.
+                        self.summary.nru_reports_out_of_order += 1;
+                        self.log.push(format!("Ignoring out of order NRU consumption report for contract {} on node {}",
+                                    contract.contract_id,
+                                    node.id,
+                                ));
+                        continue;
+                    }
+
+                    // If report ts predates start we ignore it.
+                    if ts < self.period.start() {
+                        self.summary.nru_reports_before_period += 1;
+                        self.log.push(format!("Ignoring NRU consumption report for contract {} on node {} which predates the period start",
+                                    contract.contract_id,
+                                    node.id,
+                                ));
+                        continue;
+                    }
+
+                    // Reports can still arrive for contracts in grace period or which are
+                    // canceled, only credit the time the contract was actually active.
+                    let active = contract.active_seconds(ts, data.window);
+                    contract.last_report_ts = ts;
+                    if active == 0 {
+                        self.summary.nru_reports_inactive_contract += 1;
+                        self.log.push(format!(
+                            "Ignoring NRU consumption report for contract {} on node {} which is not active",
+                            contract.contract_id, node.id,
+                        ));
+                        continue;
+                    }
+                    let nru = if active < data.window {
+                        self.summary.nru_reports_partially_active += 1;
+                        self.log.push(format!(
+                            "Crediting {active} of {} seconds of NRU consumption report for contract {} on node {}",
+                            data.window, contract.contract_id, node.id,
+                        ));
+                        (data.nru as u128 * active as u128 / data.window as u128) as u64
+                    } else {
+                        data.nru
+                    };
+                    node.capacity_consumption.cru += (contract.resources.cru * active) as u128;
+                    node.capacity_consumption.mru += (contract.resources.mru * active) as u128;
+                    node.capacity_consumption.hru += (contract.resources.hru * active) as u128;
+                    node.capacity_consumption.sru += (contract.resources.sru * active) as u128;
+                    node.capacity_consumption.ips += contract.ips as u64 * active;
+                    node.capacity_consumption.nru += nru;
+                    self.log.push(format!(
+                        "Added NRU consumption report for contract {} on node {}",
+                        contract.contract_id, node.id,
+                    ));
+                }
+                RuntimeEvents::ContractCreated(contract) => {
+                    if let ContractData::NodeContract(nc) = &contract.contract_type {
+                        self.contracts.insert(
+                            contract.contract_id,
+                            Contract {
+                                contract_id: contract.contract_id,
+                                node_id: nc.node_id,
+                                last_report_ts: ts,
+                                ips: nc.public_ips,
+                                resources: Resources {
+                                    hru: 0,
+                                    sru: 0,
+                                    cru: 0,
+                                    mru: 0,
+                                },
+                                status: vec![(ts, ContractStatus::Active)],
+                            },
+                        );
+                        self.log.push(format!(
+                            "Created contract {} on node {}",
+                            contract.contract_id, nc.node_id,
+                        ));
+                    };
+                }
+                RuntimeEvents::PowerTargetChanged(ptc) => {
+                    let Some(node) = self.nodes.get_mut(&ptc.node_id) else {
+                        self.log.push(format!(
+                            "Ignoring power target change of unknown node {}",
+                            ptc.node_id
+                        ));
+                        continue;
+                    };
+                    node.uptime
+                        .on_power_target_changed(ts, ptc.power_target, &mut self.log);
+                }
+                RuntimeEvents::PowerStateChanged(psc) => {
+                    let Some(node) = self.nodes.get_mut(&psc.node_id) else {
+                        self.log.push(format!(
+                            "Ignoring power state change of unknown node {}",
+                            psc.node_id
+                        ));
+                        continue;
+                    };
+                    node.uptime
+                        .on_power_state_changed(ts, psc.power_state, &mut self.log);
+                }
+                RuntimeEvents::NodeContractCanceled(ncc) => {
+                    // Keep the contract, so reports sent after the cancellation can be recognized.
+                    match self.contracts.get_mut(&ncc.contract_id) {
+                        Some(contract) => {
+                            contract.set_status(ts, ContractStatus::Canceled);
+                            self.log.push(format!(
+                                "Canceled contract {} on node {}",
+                                ncc.contract_id, ncc.node_id
+                            ));
+                        }
+                        None => self.log.push(format!(
+                            "Ignoring cancellation of unknown contract {}",
+                            ncc.contract_id
+                        )),
+                    }
+                }
+                RuntimeEvents::RentContractCanceled(contract_id) => {
+                    // Rent contracts don't have utilization of their own, the node contracts
+                    // deployed on the rented node are canceled separately.
+                    self.log
+                        .push(format!("Canceled rent contract {contract_id}"));
+                }
+                RuntimeEvents::ContractGracePeriodStarted(gps) => {
+                    if let Some(contract) = self.contracts.get_mut(&gps.contract_id) {
+                        contract.set_status(ts, ContractStatus::GracePeriod);
+                        self.log.push(format!(
+                            "Contract {} on node {} entered grace period",
+                            gps.contract_id, gps.node_id
+                        ));
+                    }
+                }
+                RuntimeEvents::ContractGracePeriodEnded(gpe) => {
+                    if let Some(contract) = self.contracts.get_mut(&gpe.contract_id) {
+                        // A canceled contract stays canceled.
+                        if contract.current_status() == ContractStatus::GracePeriod {
+                            contract.set_status(ts, ContractStatus::Active);
+                        }
+                        self.log.push(format!(
+                            "Contract {} on node {} left grace period",
+                            gpe.contract_id, gpe.node_id
+                        ));
+                    }
+                }
+                RuntimeEvents::NodeCertificationSet(ncs) => {
+                    match self.nodes.get_mut(&ncs.node_id) {
+                        Some(node) => {
+                            if node
+                                .policies
+                                .set_certification(ts, ncs.certification.clone())
+                            {
+                                self.log.push(format!(
+                                    "Certification of node {} set to {:?}",
+                                    ncs.node_id, ncs.certification
+                                ));
+                            }
+                        }
+                        None => self.log.push(format!(
+                            "Ignoring certification of unknown node {}",
+                            ncs.node_id
+                        )),
+                    }
+                }
+                RuntimeEvents::FarmingPolicyStored(policy) => {
+                    self.log
+                        .push(format!("New farming policy stored with id {}", policy.id));
+                    self.farming_policies.insert(policy.id, policy);
+                }
+                RuntimeEvents::FarmingPolicyUpdated(policy) => {
+                    // Nodes on the policy get the new rates from now on.
+                    for node in self.nodes.values_mut() {
+                        if node.policies.current().policy.id == policy.id {
+                            node.policies.set_policy(ts, policy.clone());
+                        }
+                    }
+                    self.log
+                        .push(format!("Farming policy {} updated", policy.id));
+                    self.farming_policies.insert(policy.id, policy);
+                }
+                RuntimeEvents::FarmingPolicySet(fps) => {
+                    // The limits are enforced at the end of the period, and the farms are taken
+                    // at the end of the period, so they already have the last limit set.
+                    self.log
+                        .push(format!("Farming policy of farm {} set", fps.farm_id));
+                }
+                RuntimeEvents::ConnectionPriceSet(price) => {
+                    // Only affects nodes registered from now on, existing nodes without a price
+                    // of their own keep the price of the network at the start of the period.
+                    self.connection_price = price;
+                    self.log
+                        .push(format!("Network connection price set to {price} mUSD"));
+                }
+                RuntimeEvents::NodeDeleted(id) => {
+                    // Keep the node, so it still gets a receipt for the uptime it earned before
+                    // it was deleted.
+                    match self.nodes.get_mut(&id) {
+                        Some(node) => {
+                            node.deleted = Some(ts);
+                            self.log.push(format!("Node {id} deleted"));
+                        }
+                        None => self
+                            .log
+                            .push(format!("Ignoring deletion of unknown node {id}")),
+                    }
+                }
+                RuntimeEvents::FarmDeleted(id) => {
+                    // Farms are taken at the end of the period, so they should already be gone.
+                    // Nodes which are still in the farm get a violation once the period is
+                    // finished.
+                    self.farms.remove(&id);
+                    self.payout_addresses.remove(&id);
+                    self.log.push(format!("Farm {id} deleted"));
+                }
+                RuntimeEvents::TwinDeleted(id) => {
+                    // Same as farms, nodes referring to the twin get a violation once the period
+                    // is finished.
+                    self.twins.remove(&id);
+                    self.log.push(format!("Twin {id} deleted"));
+                }
+            }
+        }
+    }
+
+    fn process_post_period_events(&mut self, height: u32, ts: i64, events: Vec<RuntimeEvents>) {
+        for evt in events.into_iter() {
+            // Events for nodes which are not known are possible if the node came online after the
+            // period ended.
+            match evt {
+                RuntimeEvents::NodeUptimeReported(id, current_time, reported_uptime) => {
+                    if let Some(node) = self.nodes.get_mut(&id) {
+                        node.uptime.on_uptime_report(
+                            height,
+                            ts,
+                            current_time,
+                            reported_uptime,
+                            &mut self.log,
+                        );
+                    }
+                }
+                RuntimeEvents::PowerTargetChanged(ptc) => {
+                    if let Some(node) = self.nodes.get_mut(&ptc.node_id) {
+                        node.uptime
+                            .on_power_target_changed(ts, ptc.power_target, &mut self.log);
+                    }
+                }
+                RuntimeEvents::PowerStateChanged(psc) => {
+                    if let Some(node) = self.nodes.get_mut(&psc.node_id) {
+                        node.uptime
+                            .on_power_state_changed(ts, psc.power_state, &mut self.log);
+                    }
+                }
+                _ => {
+                    // Don't care here
+                }
+            }
+        }
+    }
+
+    /// Check twin relays and public keys.
+    fn check_twins(&mut self) {
+        for node in self.nodes.values_mut() {
+            // We only care for nodes which are online. The twin of a deleted node can be removed
+            // or changed after the node is gone, so don't hold that against the node.
+            if node.uptime.measured_uptime().is_none() || node.deleted.is_some() {
+                continue;
+            }
+            let twin = if let Some(twin) = self.twins.get(&node.twin_id) {
+                twin
+            } else {
+                // This should not happen, but still catch it
+                node.uptime.flag_violation(Violation::MissingTwin);
+                self.log
+                    .push(format!("Node {} ended period without twin", node.id));
+                continue;
+            };
+            let has_relay = match twin.relay {
+                None => false,
+                Some(ref s) if s.is_empty() => false,
+                _ => true,
+            };
+            if !has_relay && node.uptime.flag_violation(Violation::MissingRelay) {
+                self.log.push(format!(
+                    "Node {} ended period without twin relay set",
+                    node.id
+                ));
+            }
+            if let Some(ref pk) = twin.pk {
+                // Secp256k1 public key size is 33 bytes in compressed form
+                if pk.len() != 33 && node.uptime.flag_violation(Violation::InvalidPublicKey) {
+                    self.log.push(format!(
+                        "Node {} ended period with invalid public key set on twin",
+                        node.id
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Check that nodes with uptime still belong to a farm. Without farm, there is no payout
+    /// address to pay the node to.
+    fn check_farms(&mut self) {
+        for node in self.nodes.values_mut() {
+            // As for twins, the farm of a deleted node can be removed after the node is gone.
+            if node.uptime.measured_uptime().is_none()
+                || node.deleted.is_some()
+                || self.farms.contains_key(&node.farm_id)
+            {
+                continue;
+            }
+            if node.uptime.flag_violation(Violation::MissingFarm) {
+                self.log.push(format!(
+                    "Node {} ended period in farm {} which does not exist",
+                    node.id, node.farm_id
+                ));
+            }
+        }
+    }
+
+    /// Apply the price overrides for the period, and log the source of the price of every node
+    /// which doesn't use its own price.
+    fn apply_price_overrides(&mut self) {
+        let period = self.period.offset_from(self.period_epoch);
+        for node in self.nodes.values_mut() {
+            if let Some(price) = self.price_overrides.get(period, node.id) {
+                node.connection_price = price;
+                node.price_source = PriceSource::Override;
+            }
+            if node.price_source != PriceSource::Node {
+                self.log.push(format!(
+                    "Node {} uses connection price of {} mUSD from {:?}",
+                    node.id, node.connection_price, node.price_source
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::PolicyLimit;
+    use codec::Decode;
+    use tfchain_client::types::{
+        ContractResources, FarmCertification, FarmingPolicyLimit, Location, Node,
+        NodeCertification, Power, PowerState, PowerStateChanged, PowerTargetChanged,
+    };
+
+    const GIB: u64 = 1 << 30;
+
+    fn period() -> Period {
+        Period::at_offset(70)
+    }
+
+    /// Height of the block at the timestamp, with a block every 6 seconds from the start of the
+    /// period.
+    fn height(ts: i64) -> u32 {
+        1 + ((ts - period().start()) / 6) as u32
+    }
+
+    fn node(id: u32, cru: u64) -> Node {
+        Node {
+            version: 1,
+            id,
+            farm_id: 1,
+            twin_id: 1,
+            resources: Resources {
+                hru: 0,
+                sru: 1024 * GIB,
+                cru,
+                mru: 128 * GIB,
+            },
+            location: Location {
+                longitude: "0".to_string(),
+                latitude: "0".to_string(),
+            },
+            country: "Belgium".to_string(),
+            city: "Ghent".to_string(),
+            public_config: None,
+            created: 0,
+            farming_policy_id: 1,
+            interfaces: vec![],
+            certification: NodeCertification::Diy,
+            secure_boot: false,
+            virtualized: false,
+            serial_number: String::new(),
+            connection_price: 0,
+        }
+    }
+
+    fn policy(id: u32, default: bool) -> FarmPolicy {
+        FarmPolicy {
+            version: 1,
+            id,
+            name: format!("policy {id}"),
+            cu: 2400,
+            su: 1000,
+            nu: 30,
+            ipv4: 5,
+            minimal_uptime: 95,
+            policy_created: 0,
+            policy_end: 0,
+            immutable: false,
+            default,
+            node_certification: NodeCertification::Diy,
+            farm_certification: FarmCertification::NotCertified,
+        }
+    }
+
+    /// A snapshot with the nodes in farm 1, which is paid to `GFARM`, and farming policy 1 as the
+    /// only, default, policy.
+    fn snapshot(nodes: Vec<Node>) -> Snapshot {
+        let period = period();
+        let twin = Twin {
+            version: 1,
+            id: 1,
+            account_id: Decode::decode(&mut &[0; 32][..]).unwrap(),
+            relay: Some("relay.grid.tf".to_string()),
+            entities: vec![],
+            pk: None,
+        };
+        let farm = Farm {
+            version: 1,
+            id: 1,
+            name: "farm".to_string(),
+            twin_id: 1,
+            pricing_policy_id: 1,
+            certification: FarmCertification::NotCertified,
+            public_ips: vec![],
+            dedicated_farm: false,
+            farming_policy_limits: None,
+        };
+        Snapshot {
+            start_block: 1,
+            end_block: height(period.end() - 1),
+            start_block_hash: Default::default(),
+            end_block_hash: Default::default(),
+            start_block_ts: period.start(),
+            nodes,
+            power_states: BTreeMap::new(),
+            power_down_timestamps: BTreeMap::new(),
+            contracts: vec![],
+            connection_price: 80,
+            farms: BTreeMap::from([(1, farm)]),
+            twins: BTreeMap::from([(1, twin)]),
+            payout_addresses: BTreeMap::from([(1, "GFARM".to_string())]),
+            farming_policies: BTreeMap::from([(1, policy(1, true))]),
+        }
+    }
+
+    /// Report uptime every 40 minutes from `from` until `to`, for nodes which booted at the start
+    /// of the period.
+    fn report_uptime(engine: &mut MintingEngine, nodes: &[u32], from: i64, to: i64) {
+        let start = period().start();
+        let mut ts = from;
+        while ts <= to {
+            let events = nodes
+                .iter()
+                .map(|&id| RuntimeEvents::NodeUptimeReported(id, ts as u64, (ts - start) as u64))
+                .collect();
+            engine.process_block(height(ts), ts, events);
+            ts += 2400;
+        }
+    }
+
+    /// A snapshot with the nodes on farming policy 2, which farm 1 may use for a single node.
+    fn limited_snapshot(nodes: Vec<Node>) -> Snapshot {
+        let mut snapshot = snapshot(nodes);
+        for node in &mut snapshot.nodes {
+            node.farming_policy_id = 2;
+        }
+        snapshot.farming_policies.insert(2, policy(2, false));
+        snapshot.farms.get_mut(&1).unwrap().farming_policy_limits = Some(FarmingPolicyLimit {
+            farming_policy_id: 2,
+            cu: None,
+            su: None,
+            end: None,
+            node_count: Some(1),
+            node_certification: false,
+        });
+        snapshot
+    }
+
+    #[test]
+    fn offline_nodes_do_not_use_up_the_farm_limit() {
+        let period = period();
+        let snapshot = limited_snapshot(vec![node(1, 8), node(2, 8), node(3, 8)]);
+        let mut engine = MintingEngine::new(period, snapshot);
+        report_uptime(&mut engine, &[2, 3], period.start() + 60, period.end());
+
+        let outcome = engine.finish().unwrap();
+        assert_eq!(outcome.summary.nodes_policy_limited, 1);
+        let receipts: Vec<_> = outcome.nodes.iter().map(|report| &report.receipt).collect();
+        assert_eq!(receipts[0].farming_policy_id, 2);
+        assert_eq!(receipts[0].farming_policy_limit, None);
+        assert_eq!(receipts[1].farming_policy_id, 2);
+        assert_eq!(receipts[1].farming_policy_limit, None);
+        assert_eq!(receipts[2].farming_policy_id, 1);
+        assert_eq!(
+            receipts[2].farming_policy_limit,
+            Some(PolicyLimit::NodeCount)
+        );
+    }
+
+    #[test]
+    fn deleted_nodes_get_the_farm_limit_after_other_nodes() {
+        let period = period();
+        let snapshot = limited_snapshot(vec![node(1, 8), node(2, 8)]);
+        let mut engine = MintingEngine::new(period, snapshot);
+        let deleted = period.start() + period.duration() as i64 / 2;
+        report_uptime(&mut engine, &[1, 2], period.start() + 60, deleted - 1);
+        engine.process_block(
+            height(deleted),
+            deleted,
+            vec![RuntimeEvents::NodeDeleted(1)],
+        );
+        report_uptime(&mut engine, &[2], deleted + 60, period.end());
+
+        let outcome = engine.finish().unwrap();
+        assert_eq!(outcome.summary.nodes_policy_limited, 1);
+        assert_eq!(outcome.nodes[0].receipt.farming_policy_id, 1);
+        assert_eq!(
+            outcome.nodes[0].receipt.farming_policy_limit,
+            Some(PolicyLimit::NodeCount)
+        );
+        assert!(outcome.nodes[0].receipt.reward.tft > 0);
+        assert_eq!(outcome.nodes[1].receipt.farming_policy_id, 2);
+        assert_eq!(outcome.nodes[1].receipt.farming_policy_limit, None);
+    }
+
+    #[test]
+    fn exceeded_farm_limit_without_default_policy_fails_the_period() {
+        let period = period();
+        let mut snapshot = limited_snapshot(vec![node(1, 8), node(2, 8)]);
+        snapshot.farming_policies.insert(1, policy(1, false));
+        let mut engine = MintingEngine::new(period, snapshot);
+        report_uptime(&mut engine, &[1, 2], period.start() + 60, period.end());
+        assert!(matches!(
+            engine.finish(),
+            Err(MintingError::MissingDefaultPolicy {
+                node_id: 2,
+                farm_id: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn deleted_node_is_rewarded_for_its_capacity_until_deletion() {
+        let period = period();
+        let quarter = period.duration() as i64 / 4;
+        let mut engine = MintingEngine::new(period, snapshot(vec![node(1, 8)]));
+        let half = period.start() + 2 * quarter;
+        let deleted = period.start() + 3 * quarter;
+        report_uptime(&mut engine, &[1], period.start() + 60, half - 1);
+        engine.process_block(
+            height(half),
+            half,
+            vec![RuntimeEvents::NodeUpdatedEvent(node(1, 2))],
+        );
+        report_uptime(&mut engine, &[1], half + 60, deleted - 1);
+        engine.process_block(
+            height(deleted),
+            deleted,
+            vec![RuntimeEvents::NodeDeleted(1)],
+        );
+        report_uptime(&mut engine, &[], deleted + 60, period.end());
+
+        let outcome = engine.finish().unwrap();
+        assert_eq!(outcome.summary.nodes_deleted, 1);
+        let report = &outcome.nodes[0];
+        assert_eq!(report.deleted, Some(deleted));
+        // 8 cores for half of the period, then 2 cores until the node is deleted.
+        assert_eq!(report.receipt.resource_units.cru, 6);
+        assert_eq!(report.receipt.resource_units.mru, 128 * GIB);
+        assert_eq!(report.receipt.cloud_units.cu, 12_000_000);
+        assert!(report.receipt.reward.tft > 0);
+    }
+
+    /// Mint node 1, which is deleted at 3/4 of the period, with updates of farming policy 1 at
+    /// the given offsets in the period.
+    fn mint_deleted_node(updates: &[i64]) -> MintingReceiptV2 {
+        let period = period();
+        let mut engine = MintingEngine::new(period, snapshot(vec![node(1, 8)]));
+        let deleted = period.start() + 3 * (period.duration() as i64 / 4);
+        let mut blocks: Vec<(i64, RuntimeEvents)> = updates
+            .iter()
+            .map(|&offset| {
+                let mut policy = policy(1, true);
+                policy.cu = 4800;
+                (
+                    period.start() + offset,
+                    RuntimeEvents::FarmingPolicyUpdated(policy),
+                )
+            })
+            .collect();
+        blocks.push((deleted, RuntimeEvents::NodeDeleted(1)));
+        blocks.sort_by_key(|(ts, _)| *ts);
+        let mut from = period.start() + 60;
+        for (ts, event) in blocks {
+            report_uptime(&mut engine, &[1], from, (ts - 1).min(deleted - 1));
+            engine.process_block(height(ts), ts, vec![event]);
+            from = ts + 60;
+        }
+        report_uptime(&mut engine, &[], from, period.end());
+        let mut outcome = engine.finish().unwrap();
+        outcome.nodes.remove(0).receipt
+    }
+
+    #[test]
+    fn policy_changes_after_deletion_do_not_change_the_reward() {
+        let period = period();
+        let receipt = mint_deleted_node(&[]);
+        let updated = mint_deleted_node(&[period.duration() as i64 * 7 / 8]);
+        assert!(updated.policy_segments.is_empty());
+        assert_eq!(updated.reward.musd, receipt.reward.musd);
+        assert_eq!(updated.reward.tft, receipt.reward.tft);
+        assert_eq!(updated.resource_rewards.cu, 2400);
+    }
+
+    #[test]
+    fn policy_segments_end_when_the_node_is_deleted() {
+        let period = period();
+        let quarter = period.duration() as i64 / 4;
+        let receipt = mint_deleted_node(&[2 * quarter]);
+        let segments = &receipt.policy_segments;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].end, period.start() + 2 * quarter);
+        assert_eq!(segments[1].end, period.start() + 3 * quarter);
+        // The node existed twice as long at the old rates as at the new rates, which are twice
+        // as high for CU.
+        assert!(segments[1].reward.musd < segments[0].reward.musd);
+        assert_eq!(
+            receipt.reward.musd,
+            segments[0].reward.musd + segments[1].reward.musd
+        );
+        assert!(receipt.reward.musd > mint_deleted_node(&[]).reward.musd);
+    }
+
+    #[test]
+    fn node_deleted_before_its_farm_keeps_its_reward() {
+        let period = period();
+        let mut snapshot = snapshot(vec![node(1, 8), node(2, 8)]);
+        // The farm is gone at the end of the period.
+        snapshot.farms.clear();
+        snapshot.payout_addresses.clear();
+        let mut engine = MintingEngine::new(period, snapshot);
+        let deleted = period.start() + period.duration() as i64 / 2;
+        report_uptime(&mut engine, &[1, 2], period.start() + 60, deleted - 1);
+        engine.process_block(
+            height(deleted),
+            deleted,
+            vec![RuntimeEvents::NodeDeleted(1)],
+        );
+        engine.process_block(
+            height(deleted + 60),
+            deleted + 60,
+            vec![RuntimeEvents::FarmDeleted(1)],
+        );
+        report_uptime(&mut engine, &[2], deleted + 120, period.end());
+
+        let outcome = engine.finish().unwrap();
+        assert!(outcome.nodes[0].violation.is_none());
+        assert!(outcome.nodes[0].receipt.reward.tft > 0);
+        assert_eq!(outcome.nodes[0].receipt.stellar_payout_address, "");
+        // The node which is still there has lost its farm.
+        assert!(matches!(outcome.nodes[1].violation, Violation::MissingFarm));
+        assert_eq!(outcome.nodes[1].receipt.reward.tft, 0);
+    }
+
+    #[test]
+    fn node_without_connection_price_fails_the_period() {
+        let period = period();
+        let mut snapshot = snapshot(vec![node(1, 8), node(2, 8)]);
+        snapshot.connection_price = 0;
+        snapshot.nodes[0].connection_price = 90;
+        let mut engine = MintingEngine::new(period, snapshot);
+        report_uptime(&mut engine, &[1, 2], period.start() + 60, period.end());
+        assert!(matches!(
+            engine.finish(),
+            Err(MintingError::MissingConnectionPrice { node_id: 2 })
+        ));
+    }
+
+    #[test]
+    fn price_override_provides_missing_connection_price() {
+        let period = period();
+        let mut snapshot = snapshot(vec![node(1, 8)]);
+        snapshot.connection_price = 0;
+        let path = std::env::temp_dir().join(format!("price-overrides-{}", std::process::id()));
+        std::fs::write(&path, r#"[{"period": 70, "node_id": 1, "price": 50}]"#).unwrap();
+        let overrides = PriceOverrides::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut engine = MintingEngine::new(period, snapshot).with_price_overrides(overrides);
+        report_uptime(&mut engine, &[1], period.start() + 60, period.end());
+        let outcome = engine.finish().unwrap();
+        assert_eq!(outcome.nodes[0].receipt.tft_connection_price, 50);
+        assert!(outcome.nodes[0].receipt.reward.tft > 0);
+    }
+
+    #[test]
+    fn events_of_unknown_nodes_and_contracts_are_ignored() {
+        let period = period();
+        let mut engine = MintingEngine::new(period, snapshot(vec![node(1, 8)]));
+        let ts = period.start() + 60;
+        engine.process_block(
+            height(ts),
+            ts,
+            vec![
+                RuntimeEvents::NodeUptimeReported(9, ts as u64, 60),
+                RuntimeEvents::NodeUpdatedEvent(node(9, 8)),
+                RuntimeEvents::ContractUsedResourcesUpdated(ContractResources {
+                    contract_id: 9,
+                    used: Resources {
+                        hru: 0,
+                        sru: 0,
+                        cru: 1,
+                        mru: 0,
+                    },
+                }),
+                RuntimeEvents::PowerTargetChanged(PowerTargetChanged {
+                    farm_id: 1,
+                    node_id: 9,
+                    power_target: Power::Down,
+                }),
+                RuntimeEvents::PowerStateChanged(PowerStateChanged {
+                    farm_id: 1,
+                    node_id: 9,
+                    power_state: PowerState::Down(ts as u32),
+                }),
+            ],
+        );
+        report_uptime(&mut engine, &[1], ts, period.end());
+
+        let log = engine.take_log();
+        assert!(log.contains(&"Ignoring uptime report of unknown node 9".to_string()));
+        assert!(log.contains(&"Ignoring used resources of unknown contract 9".to_string()));
+        let outcome = engine.finish().unwrap();
+        assert_eq!(outcome.nodes.len(), 1);
+        assert!(outcome.nodes[0].receipt.reward.tft > 0);
+    }
+
+    #[test]
+    fn change_to_unknown_farming_policy_keeps_the_policy() {
+        let period = period();
+        let mut engine = MintingEngine::new(period, snapshot(vec![node(1, 8)]));
+        let mut update = node(1, 8);
+        update.farming_policy_id = 9;
+        let ts = period.start() + 60;
+        engine.process_block(
+            height(ts),
+            ts,
+            vec![RuntimeEvents::NodeUpdatedEvent(update)],
+        );
+        report_uptime(&mut engine, &[1], ts, period.end());
+
+        let outcome = engine.finish().unwrap();
+        assert_eq!(outcome.nodes[0].receipt.farming_policy_id, 1);
+        assert!(outcome.nodes[0].receipt.policy_segments.is_empty());
+    }
+
+    #[test]
+    fn node_on_unknown_farming_policy_fails_the_period() {
+        let period = period();
+        let mut engine = MintingEngine::new(period, snapshot(vec![node(1, 8)]));
+        let mut stored = node(2, 8);
+        stored.farming_policy_id = 9;
+        let ts = period.start() + 60;
+        engine.process_block(height(ts), ts, vec![RuntimeEvents::NodeStoredEvent(stored)]);
+        report_uptime(&mut engine, &[1, 2], ts, period.end());
+        assert!(matches!(
+            engine.finish(),
+            Err(MintingError::UnknownFarmingPolicy {
+                node_id: 2,
+                policy_id: 9
+            })
+        ));
+    }
+}
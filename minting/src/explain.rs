@@ -0,0 +1,149 @@
+//! Human readable explanations of the reward of a node.
+//!
+//! Receipts carry everything the reward was calculated from, but as integers in fixed units which
+//! are hard to read. An [`Explanation`] writes out the reward of a node step by step, together
+//! with the violation or deletion which affected it, so a farmer's question about a payout can be
+//! answered without reading the receipt fields by hand.
+
+use crate::{
+    engine::NodeReport,
+    receipt::{CloudUnitsV2, ResourceRewards, Reward, UNITS_PER_TFT},
+    violation::Violation,
+};
+use chrono::prelude::*;
+use std::fmt;
+
+/// The explanation of the minting result of a single node.
+pub struct Explanation<'a> {
+    report: &'a NodeReport,
+}
+
+impl<'a> Explanation<'a> {
+    /// Explain the minting result in the report.
+    pub fn new(report: &'a NodeReport) -> Self {
+        Explanation { report }
+    }
+}
+
+impl fmt::Display for Explanation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let receipt = &self.report.receipt;
+        let period = receipt.period;
+        writeln!(
+            f,
+            "Node {} of twin {} in farm {} ({})",
+            receipt.node_id, receipt.twin_id, receipt.farm_name, receipt.farm_id
+        )?;
+        writeln!(
+            f,
+            "  period: {} until {}",
+            timestamp(period.start()),
+            timestamp(period.end())
+        )?;
+        if let Some(deleted) = self.report.deleted {
+            writeln!(f, "  deleted: {}", timestamp(deleted))?;
+        }
+        writeln!(
+            f,
+            "  uptime: {} seconds, {}% of the period",
+            receipt.measured_uptime,
+            permill(receipt.measured_uptime * 1_000 / period.duration())
+        )?;
+        if self.report.virtualized {
+            writeln!(f, "  virtualized: the node runs in a virtual machine")?;
+        }
+        if !matches!(self.report.violation, Violation::None) {
+            writeln!(f, "  violation: {}", self.report.violation)?;
+        }
+        write!(
+            f,
+            "  farming policy: {}, {} node",
+            receipt.farming_policy_id, receipt.node_type
+        )?;
+        match receipt.farming_policy_limit {
+            Some(limit) => writeln!(f, ", rolled back by the {limit} limit of the farm")?,
+            None => writeln!(f)?,
+        }
+        if let Some(rule_set) = &receipt.rule_set {
+            writeln!(f, "  rule set: {} ({})", rule_set.name, rule_set.hash)?;
+        }
+        writeln!(
+            f,
+            "  connection price: {} USD per TFT",
+            musd(receipt.tft_connection_price)
+        )?;
+        writeln!(f, "  cloud units: {}", cloud_units(&receipt.cloud_units))?;
+        writeln!(f, "  rates: {}", rates(&receipt.resource_rewards))?;
+        for segment in &receipt.policy_segments {
+            writeln!(
+                f,
+                "  from {} until {}: farming policy {}, {} node",
+                timestamp(segment.start),
+                timestamp(segment.end),
+                segment.farming_policy_id,
+                segment.node_type
+            )?;
+            writeln!(f, "    cloud units: {}", cloud_units(&segment.cloud_units))?;
+            writeln!(f, "    rates: {}", rates(&segment.resource_rewards))?;
+            writeln!(f, "    reward: {}", reward(segment.reward))?;
+        }
+        if let Some(carbon_offset) = receipt.carbon_offset {
+            writeln!(f, "  carbon offset: {}", reward(carbon_offset))?;
+        }
+        writeln!(f, "  reward: {}", reward(receipt.reward))?;
+        if receipt.reward.tft == 0 {
+            writeln!(f, "  no payout")
+        } else if receipt.stellar_payout_address.is_empty() {
+            writeln!(f, "  no payout: the farm has no stellar payout address")
+        } else {
+            writeln!(
+                f,
+                "  paid to {} with memo {}",
+                receipt.stellar_payout_address,
+                hex::encode(receipt.hash())
+            )
+        }
+    }
+}
+
+fn timestamp(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822()
+}
+
+/// Format a permill value as a percentage.
+fn permill(value: u64) -> String {
+    format!("{}.{}", value / 10, value % 10)
+}
+
+/// Format a mUSD value in USD.
+fn musd(value: u64) -> String {
+    format!("{}.{:03}", value / 1_000, value % 1_000)
+}
+
+fn cloud_units(units: &CloudUnitsV2) -> String {
+    format!(
+        "CU {}.{:06}, SU {}.{:06}, NU {}.{:06}",
+        units.cu / 1_000_000,
+        units.cu % 1_000_000,
+        units.su / 1_000_000,
+        units.su % 1_000_000,
+        units.nu / 1_000_000,
+        units.nu % 1_000_000
+    )
+}
+
+fn rates(rewards: &ResourceRewards) -> String {
+    format!(
+        "CU {}, SU {}, NU {}, IPv4 {}",
+        rewards.cu, rewards.su, rewards.nu, rewards.ipv4
+    )
+}
+
+fn reward(reward: Reward) -> String {
+    format!(
+        "{} USD, {}.{:07} TFT",
+        musd(reward.musd),
+        reward.tft / UNITS_PER_TFT,
+        reward.tft % UNITS_PER_TFT
+    )
+}
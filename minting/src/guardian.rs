@@ -0,0 +1,354 @@
+//! Guardian approval of minting artifacts.
+//!
+//! Before a mint is executed, guardians check the execution and sign off on the result. A
+//! guardian signs the blake2b-256 hash of an artifact, typically the manifest of a period or the
+//! payout file, with a key from a local keystore file. This produces a detached
+//! [`GuardianSignature`]. The signatures of all guardians are then collected and checked against
+//! the configured [`GuardianSet`], which decides if enough guardians approved the artifact.
+
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use serde::{Deserialize, Serialize};
+use sp_core::{ed25519, sr25519, Pair};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fs,
+    path::Path,
+};
+
+type Blake2b256 = Blake2b<U32>;
+
+/// Signature scheme used by a guardian key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Ed25519,
+    Sr25519,
+}
+
+/// A keystore file, holding the key of a guardian.
+#[derive(Deserialize)]
+pub struct Keystore {
+    pub scheme: Scheme,
+    /// Secret of the key, either a hex encoded seed, a mnemonic or a secret URI.
+    pub secret: String,
+}
+
+/// The signing key of a guardian.
+pub enum GuardianKey {
+    Ed25519(ed25519::Pair),
+    Sr25519(sr25519::Pair),
+}
+
+impl GuardianKey {
+    /// Load a key from a keystore file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let keystore: Keystore = serde_json::from_slice(&fs::read(path)?)?;
+        Ok(match keystore.scheme {
+            Scheme::Ed25519 => GuardianKey::Ed25519(
+                ed25519::Pair::from_string(&keystore.secret, None)
+                    .map_err(|e| format!("invalid ed25519 secret: {e:?}"))?,
+            ),
+            Scheme::Sr25519 => GuardianKey::Sr25519(
+                sr25519::Pair::from_string(&keystore.secret, None)
+                    .map_err(|e| format!("invalid sr25519 secret: {e:?}"))?,
+            ),
+        })
+    }
+
+    /// The hex encoded public key.
+    pub fn public_key(&self) -> String {
+        match self {
+            GuardianKey::Ed25519(pair) => hex::encode(pair.public()),
+            GuardianKey::Sr25519(pair) => hex::encode(pair.public()),
+        }
+    }
+
+    /// Sign an artifact, producing a detached signature.
+    pub fn sign(&self, artifact: &[u8]) -> GuardianSignature {
+        let hash = artifact_hash(artifact);
+        let (scheme, signature) = match self {
+            GuardianKey::Ed25519(pair) => (Scheme::Ed25519, hex::encode(pair.sign(&hash))),
+            GuardianKey::Sr25519(pair) => (Scheme::Sr25519, hex::encode(pair.sign(&hash))),
+        };
+        GuardianSignature {
+            scheme,
+            public_key: self.public_key(),
+            artifact_hash: hex::encode(hash),
+            signature,
+        }
+    }
+}
+
+/// A detached signature of a guardian over an artifact.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GuardianSignature {
+    pub scheme: Scheme,
+    /// Hex encoded public key of the guardian.
+    pub public_key: String,
+    /// Hex encoded blake2b-256 hash of the signed artifact.
+    pub artifact_hash: String,
+    /// Hex encoded signature over the artifact hash.
+    pub signature: String,
+}
+
+impl GuardianSignature {
+    /// Check if this is a valid signature over the artifact with the given hash.
+    pub fn verify(&self, hash: &[u8; 32]) -> bool {
+        if self.artifact_hash != hex::encode(hash) {
+            return false;
+        }
+        let (Ok(public), Ok(signature)) =
+            (hex::decode(&self.public_key), hex::decode(&self.signature))
+        else {
+            return false;
+        };
+        let (Ok(public), Ok(signature)) = (
+            <[u8; 32]>::try_from(public),
+            <[u8; 64]>::try_from(signature),
+        ) else {
+            return false;
+        };
+        match self.scheme {
+            Scheme::Ed25519 => ed25519::Pair::verify(
+                &ed25519::Signature::from_raw(signature),
+                hash,
+                &ed25519::Public::from_raw(public),
+            ),
+            Scheme::Sr25519 => sr25519::Pair::verify(
+                &sr25519::Signature::from_raw(signature),
+                hash,
+                &sr25519::Public::from_raw(public),
+            ),
+        }
+    }
+}
+
+/// A guardian which is allowed to approve artifacts.
+#[derive(Serialize, Deserialize)]
+pub struct Guardian {
+    pub name: String,
+    pub scheme: Scheme,
+    /// Hex encoded public key of the guardian.
+    pub public_key: String,
+}
+
+/// The configured set of guardians, and the amount of them needed to approve an artifact.
+#[derive(Serialize, Deserialize)]
+pub struct GuardianSet {
+    pub threshold: usize,
+    pub guardians: Vec<Guardian>,
+}
+
+impl GuardianSet {
+    /// Load the guardian set from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let set: GuardianSet = serde_json::from_slice(&fs::read(path)?)?;
+        set.validate()?;
+        Ok(set)
+    }
+
+    /// Check that the threshold can be met, and that every guardian is listed once. A guardian
+    /// listed twice, under the same key or the same name, would count twice towards the
+    /// threshold.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threshold == 0 || self.threshold > self.guardians.len() {
+            return Err(format!(
+                "invalid threshold {} for {} guardians",
+                self.threshold,
+                self.guardians.len()
+            ));
+        }
+        let mut keys = BTreeSet::new();
+        let mut names = BTreeSet::new();
+        for guardian in &self.guardians {
+            if !keys.insert(guardian.public_key.to_ascii_lowercase()) {
+                return Err(format!("duplicate guardian key {}", guardian.public_key));
+            }
+            if !names.insert(guardian.name.as_str()) {
+                return Err(format!("duplicate guardian name {}", guardian.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the signatures over an artifact against the guardian set. Every guardian is counted
+    /// at most once, regardless of how many signatures it produced.
+    pub fn collect(
+        &self,
+        artifact: &[u8],
+        signatures: impl IntoIterator<Item = GuardianSignature>,
+    ) -> Approval {
+        let hash = artifact_hash(artifact);
+        let mut approval = Approval {
+            artifact_hash: hex::encode(hash),
+            threshold: self.threshold,
+            signatures: BTreeMap::new(),
+            rejected: Vec::new(),
+        };
+        for signature in signatures {
+            let Some(guardian) = self.guardians.iter().find(|g| {
+                g.public_key.eq_ignore_ascii_case(&signature.public_key)
+                    && g.scheme == signature.scheme
+            }) else {
+                approval
+                    .rejected
+                    .push(format!("{} is not a guardian", signature.public_key));
+                continue;
+            };
+            if !signature.verify(&hash) {
+                approval
+                    .rejected
+                    .push(format!("invalid signature from {}", guardian.name));
+                continue;
+            }
+            approval.signatures.insert(guardian.name.clone(), signature);
+        }
+        approval
+    }
+}
+
+/// The approval state of an artifact. This is written to disk as record of who approved it.
+#[derive(Serialize, Deserialize)]
+pub struct Approval {
+    /// Hex encoded blake2b-256 hash of the artifact.
+    pub artifact_hash: String,
+    pub threshold: usize,
+    /// Valid signatures, keyed by the name of the guardian.
+    pub signatures: BTreeMap<String, GuardianSignature>,
+    /// Reasons why signatures were not accepted.
+    pub rejected: Vec<String>,
+}
+
+impl Approval {
+    /// Whether enough guardians approved the artifact.
+    pub fn threshold_met(&self) -> bool {
+        self.signatures.len() >= self.threshold
+    }
+}
+
+/// Hash of an artifact, which is what guardians actually sign.
+pub fn artifact_hash(artifact: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2b256::new();
+    hasher.update(artifact);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARTIFACT: &[u8] = b"manifest";
+
+    fn keys() -> Vec<GuardianKey> {
+        vec![
+            GuardianKey::Ed25519(ed25519::Pair::from_seed(&[1; 32])),
+            GuardianKey::Sr25519(sr25519::Pair::from_seed(&[2; 32])),
+            GuardianKey::Ed25519(ed25519::Pair::from_seed(&[3; 32])),
+        ]
+    }
+
+    fn guardian(name: &str, key: &GuardianKey) -> Guardian {
+        Guardian {
+            name: name.to_string(),
+            scheme: match key {
+                GuardianKey::Ed25519(_) => Scheme::Ed25519,
+                GuardianKey::Sr25519(_) => Scheme::Sr25519,
+            },
+            public_key: key.public_key(),
+        }
+    }
+
+    /// A 2 of 3 guardian set of the given keys.
+    fn guardian_set(keys: &[GuardianKey]) -> GuardianSet {
+        GuardianSet {
+            threshold: 2,
+            guardians: keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| guardian(&format!("guardian {i}"), key))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn threshold() {
+        let keys = keys();
+        let set = guardian_set(&keys);
+        assert!(set.validate().is_ok());
+
+        let approval = set.collect(ARTIFACT, [keys[0].sign(ARTIFACT)]);
+        assert!(!approval.threshold_met());
+
+        let approval = set.collect(ARTIFACT, [keys[0].sign(ARTIFACT), keys[1].sign(ARTIFACT)]);
+        assert!(approval.threshold_met());
+        assert!(approval.rejected.is_empty());
+
+        let approval = set.collect(ARTIFACT, keys.iter().map(|key| key.sign(ARTIFACT)));
+        assert_eq!(approval.signatures.len(), 3);
+        assert!(approval.threshold_met());
+    }
+
+    #[test]
+    fn guardian_counts_once() {
+        let keys = keys();
+        let set = guardian_set(&keys);
+        let approval = set.collect(
+            ARTIFACT,
+            [
+                keys[0].sign(ARTIFACT),
+                keys[0].sign(ARTIFACT),
+                keys[0].sign(ARTIFACT),
+            ],
+        );
+        assert_eq!(approval.signatures.len(), 1);
+        assert!(!approval.threshold_met());
+    }
+
+    #[test]
+    fn invalid_signatures_are_rejected() {
+        let keys = keys();
+        let set = guardian_set(&keys);
+
+        // Signature over another artifact.
+        let other = keys[0].sign(b"other manifest");
+        // Signature claiming the wrong scheme for the key.
+        let mut wrong_scheme = keys[1].sign(ARTIFACT);
+        wrong_scheme.scheme = Scheme::Ed25519;
+        // Signature of a key which is not a guardian.
+        let stranger = GuardianKey::Ed25519(ed25519::Pair::from_seed(&[4; 32])).sign(ARTIFACT);
+        // Signature of another guardian, presented as the signature of a guardian.
+        let mut wrong_key = keys[2].sign(ARTIFACT);
+        wrong_key.public_key = keys[0].public_key();
+        // Signature which is not hex.
+        let mut garbage = keys[1].sign(ARTIFACT);
+        garbage.signature = "garbage".to_string();
+
+        let approval = set.collect(
+            ARTIFACT,
+            [other, wrong_scheme, stranger, wrong_key, garbage],
+        );
+        assert!(approval.signatures.is_empty());
+        assert_eq!(approval.rejected.len(), 5);
+        assert!(!approval.threshold_met());
+    }
+
+    #[test]
+    fn invalid_sets_are_rejected() {
+        let keys = keys();
+
+        let mut set = guardian_set(&keys);
+        set.threshold = 0;
+        assert!(set.validate().is_err());
+        set.threshold = 4;
+        assert!(set.validate().is_err());
+
+        let mut set = guardian_set(&keys);
+        set.guardians[2].public_key = set.guardians[0].public_key.to_uppercase();
+        assert!(set.validate().is_err());
+
+        let mut set = guardian_set(&keys);
+        set.guardians[2].name = set.guardians[0].name.clone();
+        assert!(set.validate().is_err());
+    }
+}
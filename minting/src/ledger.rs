@@ -0,0 +1,327 @@
+//! Access to the payments made by the TFT issuer.
+//!
+//! Payouts are linked to their receipt through the memo hash of the payment transaction. A
+//! [`PaymentLedger`] provides all transactions with a memo hash made by the TFT issuer, so the
+//! minting can find out which receipts have already been paid. The live ledger is
+//! [`Horizon`](crate::stellar::Horizon). [`FileLedger`] reads an export of the issuer transactions
+//! from disk, so a run can be done without network access, and [`MemoryLedger`] holds a fixed set
+//! of transactions.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, fs, io, path::Path};
+
+/// A transaction with a memo hash, made by the TFT issuer.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LedgerTransaction {
+    /// Hex encoded memo hash of the transaction.
+    pub memo: String,
+    /// TFT payments in the transaction. This is empty if the payments are not known, e.g. in an
+    /// export without them, or if the transaction is not a plain payment of the TFT issuer.
+    #[serde(default)]
+    pub payments: Vec<LedgerPayment>,
+}
+
+/// A single TFT payment.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LedgerPayment {
+    /// Stellar address of the receiver.
+    pub destination: String,
+    /// Amount of TFT units paid.
+    pub amount: u64,
+}
+
+/// Errors while reading a payment ledger.
+#[derive(Debug)]
+pub enum LedgerError {
+    /// The ledger could not be read.
+    Io(io::Error),
+    /// The ledger contains invalid data.
+    InvalidData(String),
+    /// The remote ledger could not be reached, after the given amount of attempts.
+    Unavailable { attempts: usize, reason: String },
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::Io(e) => write!(f, "could not read ledger: {e}"),
+            LedgerError::InvalidData(reason) => write!(f, "invalid ledger data: {reason}"),
+            LedgerError::Unavailable { attempts, reason } => {
+                write!(f, "ledger unavailable after {attempts} attempts: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl From<io::Error> for LedgerError {
+    fn from(e: io::Error) -> Self {
+        LedgerError::Io(e)
+    }
+}
+
+/// A source of payments made by the TFT issuer.
+#[async_trait]
+pub trait PaymentLedger: Send + Sync {
+    /// Get all transactions with a memo hash made by the TFT issuer.
+    async fn transactions(&self) -> Result<Vec<LedgerTransaction>, LedgerError>;
+}
+
+/// A ledger read from an export on disk.
+///
+/// The export is either a JSON array of [`LedgerTransaction`]s, or a CSV file with a
+/// `hash,memo,destination,amount` line per payment, in which case payments with the same
+/// transaction hash are grouped in a single transaction. Transactions with the same memo are kept
+/// apart, so a receipt which was paid twice can be detected. The format is selected by the file
+/// extension. Every memo must be a hex encoded hash.
+pub struct FileLedger {
+    transactions: Vec<LedgerTransaction>,
+}
+
+impl FileLedger {
+    /// Load the ledger from a file.
+    pub fn load(path: &Path) -> Result<Self, LedgerError> {
+        let data = fs::read_to_string(path)?;
+        let transactions = if path.extension().is_some_and(|ext| ext == "csv") {
+            parse_csv(&data)?
+        } else {
+            serde_json::from_str(&data).map_err(|e| LedgerError::InvalidData(e.to_string()))?
+        };
+        if let Some(tx) = transactions.iter().find(|tx| !is_hash(&tx.memo)) {
+            return Err(LedgerError::InvalidData(format!(
+                "memo {} is not a hex encoded hash",
+                tx.memo
+            )));
+        }
+        Ok(FileLedger { transactions })
+    }
+}
+
+#[async_trait]
+impl PaymentLedger for FileLedger {
+    async fn transactions(&self) -> Result<Vec<LedgerTransaction>, LedgerError> {
+        Ok(self.transactions.clone())
+    }
+}
+
+fn parse_csv(data: &str) -> Result<Vec<LedgerTransaction>, LedgerError> {
+    let mut transactions: Vec<LedgerTransaction> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.starts_with("hash")) {
+            continue;
+        }
+        let invalid = || LedgerError::InvalidData(format!("line {}: {line}", i + 1));
+        let mut fields = line.split(',');
+        let (Some(hash), Some(memo), Some(destination), Some(amount), None) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            return Err(invalid());
+        };
+        if !is_hash(hash) || !is_hash(memo) {
+            return Err(invalid());
+        }
+        let payment = LedgerPayment {
+            destination: destination.to_string(),
+            amount: amount.parse().map_err(|_| invalid())?,
+        };
+        match index.get(hash) {
+            // All payments of a transaction have the memo of the transaction.
+            Some(&i) if transactions[i].memo != memo => return Err(invalid()),
+            Some(&i) => transactions[i].payments.push(payment),
+            None => {
+                index.insert(hash.to_string(), transactions.len());
+                transactions.push(LedgerTransaction {
+                    memo: memo.to_string(),
+                    payments: vec![payment],
+                });
+            }
+        }
+    }
+    Ok(transactions)
+}
+
+/// Whether the value is a hex encoded 32 byte hash.
+fn is_hash(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// A ledger with a fixed set of transactions.
+#[derive(Default)]
+pub struct MemoryLedger {
+    transactions: Vec<LedgerTransaction>,
+}
+
+impl MemoryLedger {
+    /// Create a new ledger with the given transactions.
+    pub fn new(transactions: Vec<LedgerTransaction>) -> Self {
+        MemoryLedger { transactions }
+    }
+
+    /// Add a payment of a receipt to the ledger.
+    pub fn add_payment(&mut self, receipt: [u8; 32], destination: &str, amount: u64) {
+        self.transactions.push(LedgerTransaction {
+            memo: hex::encode(receipt),
+            payments: vec![LedgerPayment {
+                destination: destination.to_string(),
+                amount,
+            }],
+        });
+    }
+}
+
+#[async_trait]
+impl PaymentLedger for MemoryLedger {
+    async fn transactions(&self) -> Result<Vec<LedgerTransaction>, LedgerError> {
+        Ok(self.transactions.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reconcile::{reconcile, ExpectedPayment, PaymentStatus};
+
+    const MEMO_A: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+    const MEMO_B: &str = "0202020202020202020202020202020202020202020202020202020202020202";
+    const TX_1: &str = "a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1";
+    const TX_2: &str = "a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2";
+
+    /// Write the data to a file with the given extension, and load it as ledger.
+    fn load(data: &str, extension: &str) -> Result<FileLedger, LedgerError> {
+        let path = std::env::temp_dir().join(format!(
+            "ledger-test-{}-{extension}.{extension}",
+            std::process::id()
+        ));
+        fs::write(&path, data).unwrap();
+        let ledger = FileLedger::load(&path);
+        fs::remove_file(&path).unwrap();
+        ledger
+    }
+
+    #[tokio::test]
+    async fn csv_groups_payments_by_transaction() {
+        let csv = format!(
+            "hash,memo,destination,amount\n{TX_1},{MEMO_A},GA,10\n\n{TX_2},{MEMO_B},GB,20\n{TX_1},{MEMO_A},GC,30\n"
+        );
+        let transactions = load(&csv, "csv").unwrap().transactions().await.unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].memo, MEMO_A);
+        let payments: Vec<_> = transactions[0]
+            .payments
+            .iter()
+            .map(|p| (p.destination.as_str(), p.amount))
+            .collect();
+        assert_eq!(payments, vec![("GA", 10), ("GC", 30)]);
+        assert_eq!(transactions[1].memo, MEMO_B);
+        assert_eq!(transactions[1].payments.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn csv_keeps_transactions_with_the_same_memo_apart() {
+        let csv = format!("{TX_1},{MEMO_A},GA,10\n{TX_2},{MEMO_A},GA,10\n");
+        let transactions = load(&csv, "csv").unwrap().transactions().await.unwrap();
+        assert_eq!(transactions.len(), 2);
+
+        let expected = ExpectedPayment {
+            receipt: [1; 32],
+            destination: "GA".to_string(),
+            amount: 10,
+        };
+        let report = reconcile(&transactions, [expected]);
+        assert_eq!(
+            report.receipts[0].status,
+            PaymentStatus::PaidTwice {
+                transactions: 2,
+                paid: 20
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_csv_is_rejected() {
+        for csv in [
+            format!("{TX_1},{MEMO_A},GA"),
+            format!("{TX_1},{MEMO_A},GA,10,extra"),
+            format!("{TX_1},{MEMO_A},GA,ten"),
+            format!("{TX_1},{MEMO_A},GA,-10"),
+            format!("{MEMO_A},GA,10"),
+            format!("{TX_1},0101,GA,10"),
+            format!("{TX_1},{},GA,10", "z".repeat(64)),
+            format!("{TX_1},{MEMO_A},GA,10\n{TX_1},{MEMO_B},GB,20"),
+        ] {
+            assert!(matches!(
+                load(&csv, "csv"),
+                Err(LedgerError::InvalidData(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn json_export() {
+        let json = format!(
+            r#"[{{"memo":"{MEMO_A}","payments":[{{"destination":"GA","amount":10}}]}},{{"memo":"{MEMO_B}"}}]"#
+        );
+        let transactions = load(&json, "json").unwrap().transactions().await.unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].payments[0].amount, 10);
+        // Transactions which could not be decoded have no payments.
+        assert!(transactions[1].payments.is_empty());
+
+        assert!(matches!(
+            load("{}", "json"),
+            Err(LedgerError::InvalidData(_))
+        ));
+        assert!(matches!(
+            load(r#"[{"memo":"0101"}]"#, "json"),
+            Err(LedgerError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn missing_export() {
+        assert!(matches!(
+            FileLedger::load(Path::new("/nonexistent/ledger.csv")),
+            Err(LedgerError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reconcile_memory_ledger() {
+        let mut ledger = MemoryLedger::default();
+        ledger.add_payment([1; 32], "GA", 10);
+        ledger.add_payment([2; 32], "GB", 5);
+
+        let expected = [
+            ([1; 32], "GA", 10),
+            ([2; 32], "GB", 20),
+            ([3; 32], "GC", 30),
+        ]
+        .map(|(receipt, destination, amount)| ExpectedPayment {
+            receipt,
+            destination: destination.to_string(),
+            amount,
+        });
+        let report = reconcile(&ledger.transactions().await.unwrap(), expected);
+        let statuses: Vec<_> = report.receipts.iter().map(|r| r.status.clone()).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                PaymentStatus::Paid,
+                PaymentStatus::Underpaid { paid: 5 },
+                PaymentStatus::Unpaid
+            ]
+        );
+        let outstanding = report.outstanding();
+        assert_eq!(outstanding.len(), 2);
+        assert_eq!(outstanding[&[2; 32]], 15);
+        assert_eq!(outstanding[&[3; 32]], 30);
+    }
+}
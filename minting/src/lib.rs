@@ -0,0 +1,29 @@
+//! Minting for grid v3, using the v3 tokenomics.
+//!
+//! The [`engine::MintingEngine`] calculates the rewards of all nodes in a period, starting from a
+//! [`snapshot::Snapshot`] of the chain and an ordered stream of blocks. It produces receipts,
+//! violations and a summary as values, so the computation can be embedded by other tools.
+
+pub mod archive;
+pub mod backlog;
+pub mod capacity;
+pub mod engine;
+pub mod explain;
+pub mod guardian;
+pub mod ledger;
+pub mod limits;
+pub mod manifest;
+pub mod network;
+mod node;
+pub mod period;
+pub mod policy;
+pub mod pricing;
+pub mod receipt;
+pub mod reconcile;
+pub mod rules;
+pub mod snapshot;
+pub mod stellar;
+pub mod transaction;
+pub mod uptime;
+pub mod verify;
+pub mod violation;
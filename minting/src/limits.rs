@@ -0,0 +1,178 @@
+//! Enforcement of the farming policy limits of farms.
+//!
+//! A farm can have a custom farming policy attached with a [`FarmingPolicyLimit`], which limits
+//! the total CU and SU, and the amount of nodes which get the policy, until the limit ends. It can
+//! also restrict the policy to certified nodes. Nodes of the farm are assigned the policy in order
+//! of their id, so the oldest nodes keep the policy. Only nodes which earn a reward take up a part
+//! of the limit, and nodes which were deleted in the period only get what is left after the other
+//! nodes. Nodes which would exceed the limit are rolled back to the default farming policy, and
+//! their receipt records the [`PolicyLimit`] which caused it.
+
+use crate::{engine::MintingError, node::MintingNode, period::Period};
+use codec::Encode;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+use tfchain_client::types::{
+    Farm, FarmCertification, FarmPolicy, FarmingPolicyLimit, NodeCertification,
+};
+
+/// The part of a farming policy limit which caused a node to be rolled back to the default
+/// farming policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyLimit {
+    /// The node would exceed the total CU of the limit.
+    Cu,
+    /// The node would exceed the total SU of the limit.
+    Su,
+    /// The node would exceed the amount of nodes of the limit.
+    NodeCount,
+    /// The limit ended before the end of the period.
+    End,
+    /// The limit only applies to certified nodes, and the node is not certified.
+    NodeCertification,
+}
+
+impl fmt::Display for PolicyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyLimit::Cu => write!(f, "cu"),
+            PolicyLimit::Su => write!(f, "su"),
+            PolicyLimit::NodeCount => write!(f, "node count"),
+            PolicyLimit::End => write!(f, "end"),
+            PolicyLimit::NodeCertification => write!(f, "node certification"),
+        }
+    }
+}
+
+/// The usage of a limited policy within a farm, as nodes are assigned to it.
+#[derive(Default)]
+struct LimitUsage {
+    /// Total CU in millionths.
+    cu: u64,
+    /// Total SU in millionths.
+    su: u64,
+    nodes: u32,
+}
+
+/// Apply the farming policy limits of all farms to the nodes. Nodes which exceed the limit of
+/// their farm are rolled back to the default farming policy. A limit which ends before the last
+/// block of the period rolls back all nodes of the farm for the whole period. Returns the amount
+/// of nodes which were rolled back, or an error if there is no default policy to roll a node back
+/// to.
+pub(crate) fn apply_farm_limits(
+    nodes: &mut BTreeMap<u32, MintingNode>,
+    farms: &BTreeMap<u32, Farm>,
+    farming_policies: &BTreeMap<u32, FarmPolicy>,
+    period: Period,
+    end_block: u32,
+    log: &mut Vec<String>,
+) -> Result<usize, MintingError> {
+    let mut usage: BTreeMap<u32, LimitUsage> = BTreeMap::new();
+    let mut rolled_back = 0;
+    // Nodes are iterated in order of id, so older nodes get the policy first. Nodes without a
+    // reward are left alone, deleted nodes come after all others.
+    let (present, deleted): (Vec<_>, Vec<_>) = nodes
+        .values_mut()
+        .filter(|node| node.earns_reward())
+        .partition(|node| node.deleted.is_none());
+    for node in present.into_iter().chain(deleted) {
+        let (farm, limit) = match farms.get(&node.farm_id) {
+            Some(farm) => match &farm.farming_policy_limits {
+                Some(limit) if limit.farming_policy_id == node.policies.current().policy.id => {
+                    (farm, limit)
+                }
+                _ => continue,
+            },
+            None => continue,
+        };
+        let (cu, su, _) = node.cloud_units_permill(period);
+        let farm_usage = usage.entry(farm.id).or_default();
+        let reason = match exceeded_limit(limit, farm_usage, node, cu, su, end_block) {
+            Some(reason) => reason,
+            None => {
+                farm_usage.cu += cu;
+                farm_usage.su += su;
+                farm_usage.nodes += 1;
+                continue;
+            }
+        };
+        let certification = &node.policies.current().certification;
+        let Some(policy) = default_policy(farming_policies, certification, farm) else {
+            return Err(MintingError::MissingDefaultPolicy {
+                node_id: node.id,
+                farm_id: farm.id,
+            });
+        };
+        log.push(format!(
+            "Node {} exceeds the {reason} limit of farming policy {} in farm {}, rolled back to {}",
+            node.id, limit.farming_policy_id, farm.id, policy.id
+        ));
+        // The limit applies to the policy for the whole period, not just the current segment.
+        node.policies
+            .replace_policy(limit.farming_policy_id, policy);
+        node.policy_limit = Some(reason);
+        rolled_back += 1;
+    }
+    Ok(rolled_back)
+}
+
+/// The first part of the limit which would be exceeded by assigning the policy to the node, if
+/// any. Limits on cloud units are expressed in whole units.
+fn exceeded_limit(
+    limit: &FarmingPolicyLimit,
+    usage: &LimitUsage,
+    node: &MintingNode,
+    cu: u64,
+    su: u64,
+    end_block: u32,
+) -> Option<PolicyLimit> {
+    if matches!(limit.end, Some(end) if end < end_block as u64) {
+        return Some(PolicyLimit::End);
+    }
+    if limit.node_certification
+        && node.policies.current().certification != NodeCertification::Certified
+    {
+        return Some(PolicyLimit::NodeCertification);
+    }
+    if matches!(limit.node_count, Some(max) if usage.nodes >= max) {
+        return Some(PolicyLimit::NodeCount);
+    }
+    if matches!(limit.cu, Some(max) if usage.cu + cu > max * 1_000_000) {
+        return Some(PolicyLimit::Cu);
+    }
+    if matches!(limit.su, Some(max) if usage.su + su > max * 1_000_000) {
+        return Some(PolicyLimit::Su);
+    }
+    None
+}
+
+/// The default farming policy for a node with the given certification in the farm. Policies for
+/// the same node and farm certification are preferred, and newer policies are preferred over older
+/// ones.
+fn default_policy<'a>(
+    farming_policies: &'a BTreeMap<u32, FarmPolicy>,
+    certification: &NodeCertification,
+    farm: &Farm,
+) -> Option<&'a FarmPolicy> {
+    let certified_farm = matches!(farm.certification, FarmCertification::Gold);
+    farming_policies
+        .values()
+        .filter(|policy| policy.default)
+        // A policy for certified nodes or farms can't be applied to others.
+        .filter(|policy| {
+            matches!(policy.node_certification, NodeCertification::Diy)
+                || matches!(certification, NodeCertification::Certified)
+        })
+        .filter(|policy| {
+            matches!(policy.farm_certification, FarmCertification::NotCertified) || certified_farm
+        })
+        .max_by_key(|policy| {
+            (
+                matches!(policy.node_certification, NodeCertification::Certified)
+                    == matches!(certification, NodeCertification::Certified),
+                matches!(policy.farm_certification, FarmCertification::Gold) == certified_farm,
+                policy.id,
+            )
+        })
+}
@@ -1,1700 +1,608 @@
-use crate::{period::Period, violation::Violation};
 use chrono::prelude::*;
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+use codec::Encode;
 use indicatif::{ProgressBar, ProgressStyle};
-use receipt::{
-    CloudUnits, FixupReceipt, MintingReceipt, ResourceRewards, ResourceUnits, ResourceUtilization,
-    RetryPayoutReceipt, Reward,
+use minting_v3::{
+    archive::{Archive, ArchiveSettings, ArchiveWriter, BlockReader},
+    backlog::{Backlog, OutstandingPayment, ReceiptKind, DEFAULT_MAX_RETRY_AGE},
+    capacity::{CapacityChange, CapacityPolicy},
+    engine::{MintingEngine, MintingOutcome, POST_PERIOD_BLOCKS},
+    explain::Explanation,
+    guardian::{GuardianKey, GuardianSet, GuardianSignature},
+    ledger::{FileLedger, PaymentLedger},
+    manifest::{BlockRef, Manifest},
+    network::NetworkProfile,
+    period::Period,
+    pricing::PriceOverrides,
+    receipt::{
+        CloudUnitsV2, FixupReceipt, ResourceUtilizationV2, RetryPayoutReceipt, Reward,
+        VersionedReceipt, UNITS_PER_TFT,
+    },
+    reconcile::reconcile,
+    rules::{RuleSet, DEFAULT_NETWORK},
+    snapshot::Snapshot,
+    stellar,
+    transaction::{payout_transactions, Payout},
+    verify,
 };
 use std::{
     collections::{BTreeMap, HashMap},
-    fs,
-    io::Write,
+    fmt, fs,
+    io::{self, Write},
     os::unix::prelude::OsStrExt,
-    path,
+    path::{self, PathBuf},
+    sync::Arc,
 };
-use tfchain_client::dynamic::DynamicClient;
 use tfchain_client::{
     client::{height_at_timestamp, RuntimeClient},
-    types::{
-        Contract as ChainContract, ContractData, Farm, FarmPolicy, Location, Node,
-        NodeCertification, NodePower, Power, PowerState, Resources, RuntimeEvents, Twin,
-    },
+    error::ClientError,
+    multi::MultiClient,
+    retry::{Backoff, RetryClient},
+    stream::{BlockEvents, EventStream, StreamOptions},
 };
 use tokio::{io::AsyncWriteExt, sync::mpsc};
 
-mod period;
-mod receipt;
-mod stellar;
-mod types;
-mod violation;
-
+const GIB: f64 = (1024 * 1024 * 1024) as f64;
 const RPC_THREADS: usize = 24;
 const PRE_FETCH: usize = 5;
-/// Currently on TFchain, the weight limit of a block is `2_000_000_000_000`. The vast majority of
-/// calls are `UptimeReported` calls and `billContractForBlock` calls. The former has an associated
-/// weight of `446_058_000`, while the latter has an associated weight of `780_660_000`. Given that
-/// a node sends an uptime report every 40 minutes, the expected amount of uptime reports is (nr
-/// nodes * 1.5) per hour, while the expected billContractForBlock call amount per hour is simply
-/// nr contracts. Additionally, as part of billing we also have `addNruReports`, which is called
-/// once per hour for every contract (technically nodes with contracts call this once per hour and
-/// it contains multiple contracts, but the weight scales roughly linearly with the amount of
-/// contracts). The weight of one call is `473_727_000`. For calculating the density of these
-/// calls, we assume all nodes are up. This gives about 5500 nodes. We also assume 750 contracts
-/// (values are taken at the time of writing).
-///
-/// Since uptime reporting depends on boot time, and contract billing depends on contract creation
-/// time, we can use the law of large numbers to model these events as evenly distributed across
-/// the available blocks. With a block time of 6 seconds, there are assumed to be 600 blocks per
-/// hour for a fully functioning chain. Additionally, if a block is missed, the calls for that
-/// block will be added to the next block, recursively, until a block is created. This means that,
-/// a block contains all calls for itself and all previously missed blocks.
-///
-/// For a fully functioning chain, the used weight is 5500/600*780_660_000 +
-/// 750/600*(446_058_000+473_727_000). This amounts to 7_156_050_000 + 1_149_731_250 =
-/// 8_305_892_250. Considering the previously established block allowed weight of 2E12, that equals
-/// to an average of 0.415% of a block. Even if we are pessimistic and assume the actual weight of
-/// calls should be double, AND the chain increases 10 times in size of both nodes and contracts,
-/// we are only at 8.30% weight, which should not be a problem at all. Considering there are 10
-/// block creators, this means we could loose all of them but one and still not have a problem for
-/// nodes to push uptimes (note the chain requires more than 2/3 i.e. at least 7 block creators
-/// before finalization stalled, so we can assume at most 3 nodes are down at once).
-///
-/// Considering these constraints, and the invariant that nodes send an uptime report every 40
-/// minutes, 1 minute is a sufficiently large grace period: this allows for nodes to _still_ get
-/// their uptime reports in the chain even if all nodes on the chain are down except one (which as
-/// discussed above is a critical chain situation).
-///
-/// Numbers are accurate as of 2023-05-08.
-const UPTIME_GRACE_PERIOD_SECONDS: i64 = 60; // 1 Minute
-/// The maximum allowed clock drift while measuring. Ideally this should be less and this should
-/// probably be constrained in the future. We take twice the amount of UPTIME_GRACE_PERIOD_SECONDS
-/// for now because we consider that a node can have a skew in one direction of up to this amount,
-/// and it would technically be possible to have the same skew in the opposing direction without
-/// being considered a validation.
-///
-/// In practice, a skew which is allowed by the above will happen in one direction and then be
-/// fixed later, so technically speaking a copy of the above should be sufficient. To be validated.
-// FIXME: This check is faulty as it is way to broad in it's current form, and malfunctioning nodes
-// might not be detected.
-const CLOCK_SKEW_INTERVAL: i64 = 2 * UPTIME_GRACE_PERIOD_SECONDS;
-const NODE_UPTIME_REPORT_INTERVAL_SECONDS: i64 = 60 * 40; // 40 minutes
-const GIB: u128 = 1024 * 1024 * 1024;
-const ONE_MILL: u128 = 1_000_000;
-/// The amount of "units" that make 1 TFT.
-const UNITS_PER_TFT: u64 = 10_000_000;
-/// The amount of blocks expected in an hour.
-const BLOCKS_IN_HOUR: u32 = 10 * 60; // 10 blocks per minute
-/// Address of horizon server to use
-//const HORIZON_URL: &str = "https://stellar-mainnet.grid.tf";
-const HORIZON_URL: &str = "https://horizon.stellar.org";
-/// Maximum amount of seconds a node can be offline because of the power managment feature while
-/// still getting rewards.
-const MAX_POWER_MANAGER_DOWNTIME: u64 = 60 * 60 * 24;
-/// Maximum amount of seconds a node has before it needs to be booted as result of a farmer bot
-/// power up request.
-const MAX_POWER_MANAGER_BOOT_TIME: i64 = 60 * 30;
-/// The maximum amount of boot violations (for not booting fast enough) we allow for power managed
-/// nodes before striking a violation.
-const MAX_ALLOWED_BOOT_VIOLATIONS: usize = 1;
 
-#[tokio::main]
-async fn main() {
-    let mut args = std::env::args();
-    // ignore binary name
-    args.next();
+/// Minting of grid v3 rewards.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-    let mut log_file = tokio::fs::File::create("minting_log.txt").await.unwrap();
+#[derive(Subcommand)]
+enum Command {
+    /// Mint a period, and write the receipts and payouts.
+    Mint(MintArgs),
+    /// Recompute the receipts of a period, and compare them with a submitted set of receipts.
+    Verify(VerifyArgs),
+    /// Recompute a period, and explain the rewards of the selected nodes.
+    Explain(ExplainArgs),
+    /// Compare 2 sets of receipts.
+    Diff(DiffArgs),
+    /// Reconcile the unpaid receipts with the payments of the TFT issuer.
+    Reconcile(ReconcileArgs),
+    /// Record the chain data of a period in an archive, to mint it offline later.
+    Archive(ArchiveArgs),
+    /// Sign an artifact with a guardian key.
+    Sign(SignArgs),
+    /// Check if enough guardians signed an artifact.
+    Approve(ApproveArgs),
+}
 
-    let period_offset = args.next().unwrap().parse().unwrap();
-    let period = Period::at_offset(period_offset);
-    let start_ts: i64 = period.start();
-    let end_ts: i64 = period.end();
-    let wss_url = args.next().unwrap();
+/// Selection of the network to mint.
+#[derive(Args)]
+struct NetworkArgs {
+    /// Network to mint, one of mainnet, testnet, qanet or devnet.
+    #[arg(long, default_value = DEFAULT_NETWORK)]
+    network: String,
+    /// Directory with the receipts, manifests and backlog, instead of the one of the network.
+    #[arg(long)]
+    receipt_root: Option<PathBuf>,
+    /// Rules file with the tokenomics rules, instead of the rules of the network.
+    #[arg(long)]
+    rules: Option<PathBuf>,
+    /// Account which issues TFT, instead of the issuer of the network.
+    #[arg(long)]
+    stellar_issuer: Option<String>,
+}
 
-    log_file
-        .write_all(
-            format!(
-                "Start minting for period {period_offset} starting at {} and ending at {}\n",
-                Utc.timestamp_opt(start_ts, 0).unwrap().to_rfc2822(),
-                Utc.timestamp_opt(end_ts, 0).unwrap().to_rfc2822(),
-            )
-            .as_bytes(),
-        )
-        .await
-        .unwrap();
+impl NetworkArgs {
+    /// The profile of the selected network.
+    fn profile(&self) -> NetworkProfile {
+        let mut profile = NetworkProfile::by_name(&self.network)
+            .unwrap_or_else(|e| Cli::command().error(ErrorKind::InvalidValue, e).exit());
+        if let Some(root) = &self.receipt_root {
+            profile.receipt_root = root.clone();
+        }
+        if let Some(rules) = &self.rules {
+            profile.rules = Some(rules.clone());
+        }
+        if let Some(issuer) = &self.stellar_issuer {
+            profile.stellar_issuer = Some(issuer.clone());
+        }
+        println!(
+            "Minting network {}, receipts in {}",
+            profile.name,
+            profile.receipt_root.display()
+        );
+        profile
+    }
+}
 
-    // load previous receipts
-    let previous_period_offset = period_offset - 1;
-    println!("Loading receipts from period {}", previous_period_offset);
-    let mut previous_receipt_dir = path::PathBuf::new();
-    previous_receipt_dir.push("receipts");
-    previous_receipt_dir.push(previous_period_offset.to_string());
+/// The chain nodes to import the chain data from.
+#[derive(Args)]
+struct ChainNodeArgs {
+    /// Websocket urls of the chain nodes to import the chain data from, instead of the chain of
+    /// the network. Requests are spread over all nodes.
+    #[arg(value_name = "URL")]
+    urls: Vec<String>,
+    /// Compare block hashes between the chain nodes, to detect a node on a different fork.
+    #[arg(long)]
+    cross_check: bool,
+}
 
-    let mut previous_receipts = HashMap::new();
+impl ChainNodeArgs {
+    /// The given chain nodes, or the chain nodes of the network if none are given.
+    fn nodes(&self, profile: &NetworkProfile) -> ChainNodes {
+        let urls = if self.urls.is_empty() {
+            profile.chain_urls.clone()
+        } else {
+            self.urls.clone()
+        };
+        ChainNodes {
+            urls,
+            cross_check: self.cross_check,
+        }
+    }
+}
 
-    match std::fs::read_dir(&previous_receipt_dir) {
-        Err(_) => println!("Previous receipt dir does not exist, skip loading receipts"),
-        Ok(dir_iter) => {
-            for file in dir_iter {
-                let file = file.unwrap();
-                if !file.file_type().unwrap().is_file() {
-                    continue;
-                }
-                let mut hash = [0; 32];
-                hex::decode_to_slice(file.file_name().as_bytes(), &mut hash).unwrap();
-                let mut path = previous_receipt_dir.clone();
-                path.push(file.file_name());
-                let data = fs::read_to_string(path).unwrap();
-                let receipt: MintingReceipt = serde_json::from_str(&data).unwrap();
-                previous_receipts.insert(hash, receipt);
-            }
+/// The source of the chain data of a period.
+#[derive(Args)]
+struct ChainArgs {
+    #[command(flatten)]
+    nodes: ChainNodeArgs,
+    /// Load the chain data from a previously recorded archive, instead of the chain.
+    #[arg(long, conflicts_with_all = ["urls", "cross_check"])]
+    from_archive: Option<PathBuf>,
+}
+
+impl ChainArgs {
+    /// The chain nodes to import the chain data from.
+    fn nodes(&self, profile: &NetworkProfile) -> ChainNodes {
+        self.nodes.nodes(profile)
+    }
+
+    /// The archive to replay, if any. Exits if the archive can't be opened, or if it was recorded
+    /// for another period or with other settings.
+    fn replay(&self, period: Period, settings: &ArchiveSettings) -> Option<Archive> {
+        let dir = self.from_archive.as_ref()?;
+        let archive = Archive::open(dir).unwrap_or_else(|e| {
+            eprintln!("Can't open archive {}: {e}", dir.display());
+            std::process::exit(1);
+        });
+        if archive.header().period != period {
+            eprintln!(
+                "Can't replay archive: it was recorded for the period starting at {}",
+                archive.header().period.start()
+            );
+            std::process::exit(1);
+        }
+        if let Err(e) = archive.check_settings(settings) {
+            eprintln!("Can't replay archive: {e}");
+            std::process::exit(1);
         }
+        Some(archive)
     }
+}
 
-    log_file
-        .write_all(
-            format!(
-                "Loaded {} receipts from previous period\n",
-                previous_receipts.len()
-            )
-            .as_bytes(),
-        )
-        .await
-        .unwrap();
+/// The chain nodes to import the chain data from.
+struct ChainNodes {
+    urls: Vec<String>,
+    cross_check: bool,
+}
 
-    // load previous fixup receipts
-    println!(
-        "Loading fixup receipts from period {}",
-        previous_period_offset
-    );
-    let mut previous_fixup_receipt_dir = path::PathBuf::new();
-    previous_fixup_receipt_dir.push("receipts");
-    previous_fixup_receipt_dir.push("fixed");
-    previous_fixup_receipt_dir.push(previous_period_offset.to_string());
+/// Settings of the reward calculation.
+#[derive(Args)]
+struct EngineArgs {
+    /// Handling of capacity increases reported during the period, apply or ignore.
+    #[arg(long)]
+    capacity_increase: Option<CapacityChange>,
+    /// Handling of capacity decreases reported during the period, apply or ignore.
+    #[arg(long)]
+    capacity_decrease: Option<CapacityChange>,
+    /// JSON file with connection prices which override the prices on chain.
+    #[arg(long)]
+    price_overrides: Option<PathBuf>,
+}
 
-    let mut previous_fixup_receipts = HashMap::new();
+impl EngineArgs {
+    /// Create the engine for the period on the network.
+    fn engine(
+        &self,
+        profile: &NetworkProfile,
+        period_offset: i64,
+        snapshot: Snapshot,
+    ) -> MintingEngine {
+        MintingEngine::new(profile.period(period_offset), snapshot)
+            .with_capacity_policy(self.capacity_policy())
+            .with_price_overrides(self.price_overrides())
+            .with_period_epoch(profile.period_epoch)
+            .with_rules(rule_set(profile, period_offset))
+    }
 
-    match std::fs::read_dir(&previous_fixup_receipt_dir) {
-        Err(_) => {
-            println!("Previous fixup receipt dir does not exist, skip loading fixup receipts")
+    /// The settings of the engine for the period on the network, as recorded in an archive.
+    fn settings(&self, profile: &NetworkProfile, period_offset: i64) -> ArchiveSettings {
+        ArchiveSettings::new(
+            &profile.name,
+            &profile.rule_set(period_offset).unwrap(),
+            &self.price_overrides(),
+            self.capacity_policy(),
+            period_offset,
+        )
+    }
+
+    fn capacity_policy(&self) -> CapacityPolicy {
+        let mut capacity_policy = CapacityPolicy::default();
+        if let Some(increase) = self.capacity_increase {
+            capacity_policy.increase = increase;
         }
-        Ok(dir_iter) => {
-            for file in dir_iter {
-                let file = file.unwrap();
-                if !file.file_type().unwrap().is_file() {
-                    continue;
-                }
-                let mut hash = [0; 32];
-                hex::decode_to_slice(file.file_name().as_bytes(), &mut hash).unwrap();
-                let mut path = previous_fixup_receipt_dir.clone();
-                path.push(file.file_name());
-                let data = fs::read_to_string(path).unwrap();
-                let receipt: FixupReceipt = serde_json::from_str(&data).unwrap();
-                previous_fixup_receipts.insert(hash, receipt);
-            }
+        if let Some(decrease) = self.capacity_decrease {
+            capacity_policy.decrease = decrease;
         }
+        capacity_policy
     }
 
-    log_file
-        .write_all(
-            format!(
-                "Loaded {} fixup receipts from previous period\n",
-                previous_fixup_receipts.len()
-            )
-            .as_bytes(),
-        )
-        .await
-        .unwrap();
+    fn price_overrides(&self) -> PriceOverrides {
+        self.price_overrides
+            .as_ref()
+            .map(|file| PriceOverrides::load(file).unwrap())
+            .unwrap_or_default()
+    }
+}
 
-    if !previous_receipts.is_empty() || !previous_fixup_receipts.is_empty() {
-        println!(
-            "Filtering receipts, pre filter length {}",
-            previous_receipts.len() + previous_fixup_receipts.len()
-        );
-        let horizon = stellar::Horizon::new(HORIZON_URL);
-        horizon
-            .filter_previous_mints(&mut previous_receipts, &mut previous_fixup_receipts)
-            .await;
-        println!(
-            "Done filtering receipts, post filter length {}",
-            previous_receipts.len() + previous_fixup_receipts.len()
-        );
+/// Selection of nodes. Without any node or farm, all nodes are selected.
+#[derive(Args)]
+struct FilterArgs {
+    /// Only include the node with this id, can be repeated.
+    #[arg(long = "node", value_name = "NODE_ID")]
+    nodes: Vec<u32>,
+    /// Only include the nodes in the farm with this id, can be repeated.
+    #[arg(long = "farm", value_name = "FARM_ID")]
+    farms: Vec<u32>,
+}
+
+impl FilterArgs {
+    /// Whether all nodes are selected.
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.farms.is_empty()
     }
 
-    log_file
-        .write_all(
-            format!(
-                "Filtered paid receipts, {} (fixup) receipts from previous period not paid yet\n",
-                previous_receipts.len() + previous_fixup_receipts.len()
-            )
-            .as_bytes(),
-        )
-        .await
-        .unwrap();
+    /// Whether the node is selected. Receipts which are not for a single node, like retries, are
+    /// only selected by their farm.
+    fn matches(&self, node_id: Option<u32>, farm_id: u32) -> bool {
+        self.is_empty()
+            || node_id.is_some_and(|id| self.nodes.contains(&id))
+            || self.farms.contains(&farm_id)
+    }
 
-    let client = DynamicClient::new(&wss_url).await.unwrap();
+    /// Only keep the selected receipts.
+    fn retain(&self, receipts: &mut BTreeMap<[u8; 32], VersionedReceipt>) {
+        receipts.retain(|_, receipt| self.matches(Some(receipt.node_id()), receipt.farm_id()));
+    }
+}
 
-    println!("Finding start block");
-    let start_block = height_at_timestamp(&client, start_ts).await.unwrap();
-    println!("Finding end block");
-    let end_block = height_at_timestamp(&client, end_ts).await.unwrap();
+#[derive(Args)]
+struct MintArgs {
+    /// Offset of the period to mint.
+    period: i64,
+    #[command(flatten)]
+    chain: ChainArgs,
+    #[command(flatten)]
+    network: NetworkArgs,
+    #[command(flatten)]
+    engine: EngineArgs,
+    // Only the selected nodes are reported. A filtered run is always a dry run, as the receipts of
+    // the period would be incomplete.
+    #[command(flatten)]
+    filter: FilterArgs,
+    /// Directory to write the log and the reports to.
+    #[arg(long, default_value = ".")]
+    output: PathBuf,
+    /// Record the chain data used in this run in an archive.
+    #[arg(long)]
+    archive: Option<PathBuf>,
+    /// Read the payments of the TFT issuer from an export, instead of horizon.
+    #[arg(long)]
+    ledger: Option<PathBuf>,
+    /// Generate the payout transactions, starting after this sequence number of the issuer.
+    #[arg(long)]
+    sequence: Option<i64>,
+    /// Abandon receipts which are still unpaid after this many periods.
+    #[arg(long, default_value_t = DEFAULT_MAX_RETRY_AGE)]
+    max_retry_age: i64,
+    /// Calculate and report the period, without writing receipts or updating the backlog.
+    #[arg(long)]
+    dry_run: bool,
+    /// Write the receipts of the period, even if receipts were already written for it.
+    #[arg(long)]
+    force: bool,
+}
 
-    log_file
-        .write_all(
-            format!("Period runs from block {start_block} to block {end_block}\n",).as_bytes(),
-        )
-        .await
-        .unwrap();
+#[derive(Args)]
+struct VerifyArgs {
+    /// Offset of the period to verify.
+    period: i64,
+    #[command(flatten)]
+    chain: ChainArgs,
+    #[command(flatten)]
+    network: NetworkArgs,
+    #[command(flatten)]
+    engine: EngineArgs,
+    #[command(flatten)]
+    filter: FilterArgs,
+    /// Directory with the submitted receipts, by default the receipts of the period in the
+    /// receipt root of the network.
+    #[arg(long)]
+    receipts: Option<PathBuf>,
+    /// Directory to write the log to.
+    #[arg(long, default_value = ".")]
+    output: PathBuf,
+}
 
-    // Grab existing nodes
-    let mut nodes: BTreeMap<_, _> = get_nodes(&client, start_block)
-        .await
-        .unwrap()
-        .into_iter()
-        .map(|node| {
-            (
-                node.id,
-                MintingNode {
-                    id: node.id,
-                    farm_id: node.farm_id,
-                    twin_id: node.twin_id,
-                    resources: node.resources,
-                    location: node.location,
-                    country: node.country,
-                    city: node.city,
-                    _created: node.created,
-                    certification_type: node.certification,
-                    uptime_info: None,
-                    boot_time: None,
-                    violation: Violation::None,
-                    connection_price: node.connection_price,
-                    capacity_consumption: TotalConsumption::default(),
-                    virtualized: node.virtualized,
-                    farming_policy_id: node.farming_policy_id,
-                    power_managed: None,
-                    power_manage_boot: None,
-                    boot_duration_violations: 0,
-                },
-            )
-        })
-        .collect();
-    println!("Found {} existing nodes", nodes.len());
+#[derive(Args)]
+struct ExplainArgs {
+    /// Offset of the period to explain.
+    period: i64,
+    #[command(flatten)]
+    chain: ChainArgs,
+    #[command(flatten)]
+    network: NetworkArgs,
+    #[command(flatten)]
+    engine: EngineArgs,
+    // The nodes to explain, at least 1 node or farm is required.
+    #[command(flatten)]
+    filter: FilterArgs,
+    /// Directory to write the log to.
+    #[arg(long, default_value = ".")]
+    output: PathBuf,
+}
 
-    log_file
-        .write_all(format!("Loaded {} existing nodes\n", nodes.len()).as_bytes())
-        .await
-        .unwrap();
+#[derive(Args)]
+struct DiffArgs {
+    /// Directory with the expected receipts.
+    expected: PathBuf,
+    /// Directory with the receipts to compare to the expected receipts.
+    submitted: PathBuf,
+    #[command(flatten)]
+    filter: FilterArgs,
+}
 
-    let mut power_states: BTreeMap<_, _> = get_power_states(&client, start_block)
-        .await
-        .unwrap()
-        .into_iter()
-        .collect();
-    println!("Found {} power states", power_states.len());
+#[derive(Args)]
+struct ReconcileArgs {
+    /// Offset of the period which is about to be minted. The receipts of the previous period are
+    /// added to the backlog.
+    period: i64,
+    #[command(flatten)]
+    network: NetworkArgs,
+    /// Directory to write the log and the reconciliation report to.
+    #[arg(long, default_value = ".")]
+    output: PathBuf,
+    /// Read the payments of the TFT issuer from an export, instead of horizon.
+    #[arg(long)]
+    ledger: Option<PathBuf>,
+    /// Abandon receipts which are still unpaid after this many periods.
+    #[arg(long, default_value_t = DEFAULT_MAX_RETRY_AGE)]
+    max_retry_age: i64,
+    /// Report the reconciliation, without updating the backlog.
+    #[arg(long)]
+    dry_run: bool,
+}
 
-    log_file
-        .write_all(format!("Loaded {} power states\n", power_states.len()).as_bytes())
-        .await
-        .unwrap();
+#[derive(Args)]
+struct ArchiveArgs {
+    /// Offset of the period to record.
+    period: i64,
+    /// Directory to record the archive in.
+    dir: PathBuf,
+    #[command(flatten)]
+    chain: ChainNodeArgs,
+    #[command(flatten)]
+    network: NetworkArgs,
+    // The settings the archive is replayed with, a replay with other settings is refused.
+    #[command(flatten)]
+    engine: EngineArgs,
+    /// Directory to write the log and the reconciliation report to.
+    #[arg(long, default_value = ".")]
+    output: PathBuf,
+    /// Read the payments of the TFT issuer from an export, instead of horizon.
+    #[arg(long)]
+    ledger: Option<PathBuf>,
+    /// Abandon receipts which are still unpaid after this many periods.
+    #[arg(long, default_value_t = DEFAULT_MAX_RETRY_AGE)]
+    max_retry_age: i64,
+}
 
-    let start_block_hash = client.hash_at_height(Some(start_block)).await.unwrap();
-    let start_block_ts = client.timestamp(start_block_hash).await.unwrap() / 1000;
-
-    // Insert missing entries for power state, and update node power managed if it currently is
-    // power managed.
-    for (node_id, node) in nodes.iter_mut() {
-        match power_states.get(node_id) {
-            None => {
-                power_states.insert(
-                    *node_id,
-                    NodePower {
-                        state: PowerState::Up,
-                        target: Power::Up,
-                    },
-                );
-            }
-            Some(NodePower {
-                state: PowerState::Down(block),
-                target,
-            }) => {
-                let hash = client.hash_at_height(Some(*block)).await.unwrap();
-                let ts = client.timestamp(hash).await.unwrap() / 1000;
-                node.power_managed = Some(ts as i64);
-                if let Power::Up = target {
-                    // Set the powerup request as start timestamp. Technically this is wrong,
-                    // however this will be validated properly in the previous period in the
-                    // post period checks.
-                    node.power_manage_boot = Some(start_block_ts as i64);
-                }
-            }
-            _ => {}
-        }
-    }
+#[derive(Args)]
+struct SignArgs {
+    /// The artifact to sign, e.g. a manifest or payout file.
+    artifact: PathBuf,
+    /// Keystore with the guardian key.
+    keystore: PathBuf,
+}
 
-    // Load farms at the end of the period. This means we don't have to parse individual farm
-    // events, as we can just fetch the last known state.
-    let farms: BTreeMap<_, _> = get_farms(&client, end_block)
-        .await
-        .unwrap()
-        .into_iter()
-        .map(|farm| (farm.id, farm))
-        .collect();
-    println!("Found {} existing farms", farms.len());
+#[derive(Args)]
+struct ApproveArgs {
+    /// The artifact which was signed.
+    artifact: PathBuf,
+    /// The set of guardians and the approval threshold.
+    guardians: PathBuf,
+    /// Detached signatures of the artifact.
+    signatures: Vec<PathBuf>,
+}
 
-    log_file
-        .write_all(format!("Loaded {} farms, at the end of the period\n", farms.len()).as_bytes())
-        .await
-        .unwrap();
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    // Before there were subcommands, a period was minted by passing only its offset.
+    if args.get(1).is_some_and(|arg| arg.parse::<i64>().is_ok()) {
+        args.insert(1, "mint".to_string());
+    }
 
-    let twins: BTreeMap<_, _> = get_twins(&client, end_block)
-        .await
-        .unwrap()
-        .into_iter()
-        .map(|twin| (twin.id, twin))
-        .collect();
-    println!("Found {} existing twins", twins.len());
+    match Cli::parse_from(args).command {
+        Command::Mint(args) => mint(args).await,
+        Command::Verify(args) => verify_period(args).await,
+        Command::Explain(args) => explain(args).await,
+        Command::Diff(args) => diff(args),
+        Command::Reconcile(args) => reconcile_period(args).await,
+        Command::Archive(args) => archive(args).await,
+        Command::Sign(args) => sign(args),
+        Command::Approve(args) => approve(args),
+    }
+}
 
-    log_file
-        .write_all(format!("Loaded {} twins, at the end of the period\n", twins.len()).as_bytes())
-        .await
-        .unwrap();
+/// Mint a period, and write the receipts and payouts.
+async fn mint(args: MintArgs) {
+    let period_offset = args.period;
+    let profile = args.network.profile();
+    let period = profile.period(period_offset);
+    let start_ts: i64 = period.start();
+    let end_ts: i64 = period.end();
+    let settings = args.engine.settings(&profile, period_offset);
+    let replay = args.chain.replay(period, &settings);
+    let dry_run = args.dry_run || !args.filter.is_empty();
+    if dry_run {
+        println!("Dry run, no receipts are written and the backlog is not updated");
+    }
+    let output = args.output.as_path();
+    fs::create_dir_all(output).unwrap();
+
+    // Receipts are published once they are paid, so they are never replaced by accident.
+    let receipt_dir = profile.receipt_dir(period_offset.to_string());
+    if !dry_run && !args.force && receipt_dir.exists() {
+        eprintln!(
+            "Receipts of period {period_offset} already exist in {}, use --force to write them anyway",
+            receipt_dir.display()
+        );
+        std::process::exit(1);
+    }
 
-    let payout_addresses: BTreeMap<_, _> = get_payout_addresses(&client, &farms, end_block)
+    let mut log_file = tokio::fs::File::create(output.join("minting_log.txt"))
         .await
         .unwrap();
-
     log_file
         .write_all(
             format!(
-                "Loaded {} payout addresses, at the end of the period\n",
-                payout_addresses.len()
+                "Start minting for period {period_offset} starting at {} and ending at {}\n",
+                Utc.timestamp_opt(start_ts, 0).unwrap().to_rfc2822(),
+                Utc.timestamp_opt(end_ts, 0).unwrap().to_rfc2822(),
             )
             .as_bytes(),
         )
         .await
         .unwrap();
 
-    // Grab existing contracts
-    let mut contracts: BTreeMap<_, _> = get_contracts(&client, start_block)
-        .await
-        .unwrap()
-        .into_iter()
-        .filter_map(|(contract, resources)| {
-            // Namecontract is actually billed once deployed through a node contract.
-            if let ContractData::NodeContract(nc) = contract.contract_type {
-                Some((
-                    contract.contract_id,
-                    Contract {
-                        _contract_id: contract.contract_id,
-                        node_id: nc.node_id,
-                        // a report should pop up for this
-                        last_report_ts: 0,
-                        ips: nc.public_ips,
-                        resources,
-                    },
-                ))
-            } else {
-                None
+    let backlog_path = profile.backlog_file();
+    let mut backlog = match &replay {
+        // Use the payment state at the time the archive was recorded, so the run is reproducible.
+        // The saved backlog must still be in that state, as it is replaced by the backlog of the
+        // replay, which would undo any change made since.
+        Some(archive) => {
+            let backlog = archive.header().backlog.clone();
+            if !dry_run && Backlog::load(&backlog_path).unwrap().encode() != backlog.encode() {
+                eprintln!(
+                    "The backlog in {} changed since the archive was recorded, use --dry-run to replay it",
+                    backlog_path.display()
+                );
+                std::process::exit(1);
             }
-        })
-        .collect();
-    println!("Found {} existing contracts", contracts.len());
+            backlog
+        }
+        None => {
+            reconcile_backlog(
+                &profile,
+                period_offset,
+                args.ledger.as_deref(),
+                args.max_retry_age,
+                output,
+                &mut log_file,
+            )
+            .await
+        }
+    };
+
+    let (snapshot, import_queue) = imported(
+        load_chain(period, args.chain.nodes(&profile), replay).await,
+        period_offset,
+    );
+    let start_block = snapshot.start_block;
+    let end_block = snapshot.end_block;
+    let start_block_ref = BlockRef {
+        height: start_block,
+        hash: hex::encode(snapshot.start_block_hash),
+    };
+    let end_block_ref = BlockRef {
+        height: end_block,
+        hash: hex::encode(snapshot.end_block_hash),
+    };
 
     log_file
-        .write_all(format!("Loaded {} existing contracts\n", contracts.len()).as_bytes())
+        .write_all(
+            format!("Period runs from block {start_block} to block {end_block}\n",).as_bytes(),
+        )
         .await
         .unwrap();
 
-    // Get farming policies
-    let farming_policies: BTreeMap<_, _> = get_farming_policies(&client, end_block)
-        .await
-        .unwrap()
-        .into_iter()
-        .map(|policy| (policy.id, policy))
-        .collect();
-    println!("Found {} farming policies", farming_policies.len());
+    println!("Found {} existing nodes", snapshot.nodes.len());
+    println!("Found {} power states", snapshot.power_states.len());
+    println!("Found {} existing farms", snapshot.farms.len());
+    println!("Found {} existing twins", snapshot.twins.len());
+    println!("Found {} existing contracts", snapshot.contracts.len());
+    println!("Found {} farming policies", snapshot.farming_policies.len());
 
     log_file
         .write_all(
             format!(
-                "Loaded {} farming policies, at the end of the period\n",
-                farming_policies.len()
+                "Loaded {} existing nodes\n\
+                 Loaded {} power states\n\
+                 Loaded {} farms, at the end of the period\n\
+                 Loaded {} twins, at the end of the period\n\
+                 Loaded {} payout addresses, at the end of the period\n\
+                 Loaded {} existing contracts\n\
+                 Loaded {} farming policies, at the start of the period\n",
+                snapshot.nodes.len(),
+                snapshot.power_states.len(),
+                snapshot.farms.len(),
+                snapshot.twins.len(),
+                snapshot.payout_addresses.len(),
+                snapshot.contracts.len(),
+                snapshot.farming_policies.len(),
             )
             .as_bytes(),
         )
         .await
         .unwrap();
 
-    println!("Setup block import pipeline");
-    let blocks = end_block - start_block + 1;
+    let archive = args
+        .archive
+        .as_ref()
+        .map(|dir| ArchiveWriter::create(dir, period, &settings, &snapshot, &backlog).unwrap());
 
-    let bar = ProgressBar::new(blocks as u64);
-    bar.set_style(
-        ProgressStyle::default_bar()
-            .template("[Time on chain: {msg}] {wide_bar} {pos:>6}/{len:>6} (ETA: {eta_precise})")
-            .expect("Can set valid template"),
+    let mut engine = args.engine.engine(&profile, period_offset, snapshot);
+    imported(
+        process_blocks(&mut engine, import_queue, archive, &mut log_file).await,
+        period_offset,
     );
-    let mut height = start_block;
-    let mut import_queue = block_import(&wss_url, height as usize, end_block as usize).await;
-    loop {
-        let (block_height, ts, evts) =
-            if let Some((block_height, ts, evts)) = import_queue.recv().await {
-                (block_height, ts, evts)
-            } else {
-                panic!("Block import exitted too early");
-            };
-
-        log_file
-            .write_all(
-                format!(
-                    "Loaded block {} ({}) containing {} events\n",
-                    block_height,
-                    Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822(),
-                    evts.len()
-                )
-                .as_bytes(),
-            )
-            .await
-            .unwrap();
 
-        for evt in evts.into_iter() {
-            match evt {
-                RuntimeEvents::NodeStoredEvent(node) => {
-                    nodes.insert(
-                        node.id,
-                        MintingNode {
-                            id: node.id,
-                            farm_id: node.farm_id,
-                            twin_id: node.twin_id,
-                            resources: node.resources,
-                            location: node.location,
-                            country: node.country,
-                            city: node.city,
-                            _created: node.created,
-                            certification_type: node.certification.clone(),
-                            uptime_info: None,
-                            boot_time: None,
-                            violation: Violation::None,
-                            connection_price: node.connection_price,
-                            capacity_consumption: TotalConsumption::default(),
-                            virtualized: node.virtualized,
-                            farming_policy_id: node.farming_policy_id,
-                            power_managed: None,
-                            power_manage_boot: None,
-                            boot_duration_violations: 0,
-                        },
-                    );
-                    power_states.insert(
-                        node.id,
-                        NodePower {
-                            state: PowerState::Up,
-                            target: Power::Up,
-                        },
-                    );
-                    log_file
-                        .write_all(format!("New node stored with id {}\n", node.id).as_bytes())
-                        .await
-                        .unwrap();
-                }
-                RuntimeEvents::NodeUpdatedEvent(node) => {
-                    let old_node = nodes
-                        .get_mut(&node.id)
-                        .expect("node update of unknown node");
-                    old_node.farm_id = node.farm_id;
-                    old_node.twin_id = node.twin_id;
-                    // update resources, but only lower them in case of dead or removed
-                    // hardware. Do not update in case of added hardware as this is currently
-                    // unresolved.
-                    old_node.resources.cru =
-                        std::cmp::min(old_node.resources.cru, node.resources.cru);
-                    old_node.resources.mru =
-                        std::cmp::min(old_node.resources.mru, node.resources.mru);
-                    old_node.resources.hru =
-                        std::cmp::min(old_node.resources.hru, node.resources.hru);
-                    old_node.resources.sru =
-                        std::cmp::min(old_node.resources.sru, node.resources.sru);
-                    old_node.location = node.location;
-                    old_node.resources = node.resources;
-                    old_node.country = node.country;
-                    old_node.city = node.city;
-                    // Don't care about "create" as that should be fixed anyway
-                    // Update certification type. It's technically possible for a node to jump
-                    // from DIY to certified and back in the same period, but practically that
-                    // should not happen.
-                    old_node.certification_type = node.certification.clone();
-                    // It is possible that this also causes a node to get a different farming
-                    // policy ID.
-                    old_node.farming_policy_id = node.farming_policy_id;
-                    // Update connection price. This should not happen, but it is here in case
-                    // we modify the connection price of the node in place in the future and
-                    // emit this generic event when the 5 year fixed time is expired.
-                    old_node.connection_price = node.connection_price;
-                    // Even though this likely means the node is rebooted, don't mess with
-                    // uptime_info. The reboot will be detected in the `NodeUptimeReported`
-                    // handler.
-                    // This does not change when the node was connected.
-                    //
-                    // Once a VM, always a VM
-                    if node.virtualized {
-                        old_node.virtualized = node.virtualized;
-                    }
+    let outcome = engine.finish();
+    write_log(&mut log_file, engine.take_log()).await;
+    let outcome = outcome.unwrap_or_else(|e| {
+        eprintln!("Can't mint period {period_offset}: {e}");
+        std::process::exit(1);
+    });
+    let payout_addresses = engine.payout_addresses();
+    std::fs::write(
+        output.join("summary.json"),
+        serde_json::to_vec_pretty(&outcome.summary).unwrap(),
+    )
+    .unwrap();
 
-                    log_file
-                        .write_all(format!("Node updated with id {}\n", node.id).as_bytes())
-                        .await
-                        .unwrap();
-                }
-                RuntimeEvents::NodeUptimeReported(id, current_time, reported_uptime) => {
-                    let node = match nodes.get_mut(&id) {
-                        Some(node) => node,
-                        None => panic!(
-                            "can't report uptime for unknown node {} in block {}",
-                            id, height
-                        ),
-                    };
-
-                    // We are power managed and got a request to wake up.
-                    match (node.power_managed, node.power_manage_boot) {
-                        (Some(time_set_down), Some(boot_request)) => {
-                            // Ignore the event if it is sent after the node is supposed to go down,
-                            // this will be accounted for once the node starts up again.
-                            // For the node to have been properly power managed, it must be booted
-                            // after it was set to down.
-                            if (current_time - reported_uptime) as i64 > time_set_down {
-                                // node got power managed to down
-                                let time_delta = current_time as i64 - time_set_down;
-                                assert!(time_delta >= 0, "uptime events can't travel back in time");
-                                let (_, _, mut total_uptime) = node.uptime_info.unwrap_or_default();
-                                // Only add uptime if node boot did not violate any constraints.
-                                let mut credit_uptime = true;
-                                if time_delta as u64 > MAX_POWER_MANAGER_DOWNTIME {
-                                    credit_uptime = false;
-                                    log_file
-                                        .write_all(format!("Refusing to credit uptime for power managed node {} as the last boot was {time_delta} seconds ago, more than the allowed 24 hours\n", node.id).as_bytes())
-                                        .await
-                                        .unwrap();
-                                }
-                                if (current_time - reported_uptime) as i64 - boot_request
-                                    > MAX_POWER_MANAGER_BOOT_TIME
-                                {
-                                    credit_uptime = false;
-                                    // Mark a violation on the node
-                                    node.boot_duration_violations += 1;
-                                    log_file
-                                            .write_all(format!("Detected farmer bot boot violation for node {}, request was done at {} but node only came online at {}\n",
-                                                node.id,
-                                                Utc.timestamp_opt(boot_request, 0).unwrap().to_rfc2822(),
-                                                Utc.timestamp_opt((current_time - reported_uptime) as i64, 0).unwrap().to_rfc2822()
-                                            ).as_bytes())
-                                            .await
-                                            .unwrap();
-                                }
-                                if credit_uptime {
-                                    // Check and scale to match the actual period start if needed
-                                    if time_set_down < start_ts {
-                                        total_uptime += (current_time as i64 - start_ts) as u64;
-                                        log_file
-                                            .write_all(
-                                                format!(
-                                                    "Added {} seconds of uptime for node {}, scaled in period start\n",
-                                                    current_time as i64 - start_ts,
-                                                    node.id
-                                                )
-                                                .as_bytes(),
-                                            )
-                                            .await
-                                            .unwrap();
-                                    } else {
-                                        total_uptime += time_delta as u64;
-                                        log_file
-                                            .write_all(
-                                                format!(
-                                                    "Added {time_delta} seconds of uptime for node {}\n",
-                                                    node.id
-                                                )
-                                                .as_bytes(),
-                                            )
-                                            .await
-                                            .unwrap();
-                                    }
-                                }
-                                // Clear the fact that we got power managed, if it is still the case, it
-                                // will be set again in the proper event handler.
-                                node.power_managed = None;
-                                node.power_manage_boot = None;
-                                node.uptime_info =
-                                    Some((current_time as i64, reported_uptime, total_uptime));
-                                // Also mark a boot
-                                node.boot_time = Some((
-                                    (current_time - reported_uptime) as i64,
-                                    current_time as i64,
-                                ));
-                            } else {
-                                log_file
-                                .write_all(format!("Ignoring uptime event for node {} as it happened before the node powered down after being requested to do so\n", node.id).as_bytes())
-                                .await
-                                .unwrap();
-                            }
-                        }
-                        // We are power managed but woke up without boot request. We explicitly ignore this: being
-                        // put to sleep by the farmer bot requires a wakeup from the farmer bot. This case also
-                        // means nodes just go to sleep anyhow.
-                        (Some(_), None) => {
-                            log_file
-                                .write_all(
-                                    format!("Ignoring boot for node {} which is power managed, but did not get a boot request from the farmer bot\n", node.id)
-                                        .as_bytes(),
-                                )
-                                .await
-                                .unwrap();
-                        }
-                        // We got a wakeup request from farmer bot but we are not sleeping due to
-                        // the farmer bot. This should not happen.
-                        (None, Some(_)) => {
-                            log_file
-                                .write_all(
-                                    format!("Ignoring uptime for node {} after farmer bot asked for a boot while the node was not sleeping as a result of farmer bot\n", node.id)
-                                        .as_bytes(),
-                                )
-                                .await
-                                .unwrap();
-                        }
-                        (None, None) => {
-                            if let Some((
-                                last_reported_at,
-                                last_reported_uptime,
-                                mut total_uptime,
-                            )) = node.uptime_info
-                            {
-                                let report_delta = current_time as i64 - last_reported_at;
-                                let uptime_delta =
-                                    reported_uptime as i64 - last_reported_uptime as i64;
-                                // There are quite some situations here. Notice that due to the
-                                // blockchain only producing blocks every 6 seconds, and network delay
-                                // + a host of other issues, we will allow a node to report uptime with
-                                // "grace period" of a minute or so in either direction.
-                                //
-                                // 1. uptime_delta > report_delta + GRACE_PERIOD. Node is talking
-                                //    rubish.
-                                if uptime_delta > report_delta + UPTIME_GRACE_PERIOD_SECONDS {
-                                    if node.violation.is_none() {
-                                        node.violation = Violation::UptimeTooHigh {
-                                            previous_uptime: last_reported_uptime,
-                                            previous_timestamp: last_reported_at,
-                                            reported_uptime,
-                                            reported_timestamp: ts,
-                                            block_reported: height,
-                                        };
-                                    }
-                                    node.uptime_info =
-                                        Some((current_time as i64, reported_uptime, total_uptime));
-
-                                    log_file
-                                    .write_all(format!("Node {} reported an uptime increase of {uptime_delta} seconds, while reports are {report_delta} seconds appart\n",node.id,).as_bytes())
-                                    .await
-                                    .unwrap();
-                                    continue;
-                                }
-                                // 2. The difference in uptime is within reason of the difference in
-                                //    report times, i.e. the node is properly reporting.
-                                if uptime_delta <= report_delta + UPTIME_GRACE_PERIOD_SECONDS
-                                    && uptime_delta >= report_delta - UPTIME_GRACE_PERIOD_SECONDS
-                                {
-                                    // check skew
-                                    if let Some((boot, detected)) = node.boot_time {
-                                        let new_boot = (current_time - reported_uptime) as i64;
-                                        if (new_boot - boot).abs() >= CLOCK_SKEW_INTERVAL {
-                                            // This is a violation
-                                            if node.violation.is_none() {
-                                                node.violation = Violation::ClockSkew {
-                                                    original_boot: boot,
-                                                    current_boot: new_boot,
-                                                    previous_timestamp: detected,
-                                                    reported_timestamp: current_time as i64,
-                                                };
-                                            }
-
-                                            log_file
-                                            .write_all(format!("Node {} has a detected clock skew of {} seconds, more than the allowed {CLOCK_SKEW_INTERVAL} seconds\n",node.id, (new_boot - boot).abs()).as_bytes())
-                                            .await
-                                            .unwrap();
-                                        }
-                                    } else {
-                                        panic!("node does not have boot time but does have uptime")
-                                    }
-
-                                    // It is technically possible for the delta to be less than 0 and
-                                    // within the expected time frame. If nodes boot, send uptime, then
-                                    // immediately reboot that is possible. In those cases, handle that
-                                    // below, as that is the reboot detection.
-                                    if uptime_delta > 0 {
-                                        // Simply add the uptime delta. If this is too large or low by a
-                                        // couple of seconds it will be corrected by the next pings anyhow.
-                                        // That being said, we also limit the amount of uptime credit
-                                        // to the uptime report interval + grace period, as healthy
-                                        // nodes _must_ ping every interval amount of time
-                                        let credit = u64::min(
-                                            uptime_delta as u64,
-                                            (NODE_UPTIME_REPORT_INTERVAL_SECONDS
-                                                + UPTIME_GRACE_PERIOD_SECONDS)
-                                                as u64,
-                                        );
-                                        total_uptime += credit;
-                                        if credit != uptime_delta as u64 {
-                                            log_file
-                                            .write_all(format!("credited node {} with {credit} seconds of uptime, less than the reported {uptime_delta} seconds as the gap is too big\n", node.id).as_bytes())
-                                            .await
-                                            .unwrap();
-                                        } else {
-                                            log_file
-                                            .write_all(format!("credited node {} with {credit} seconds of reported uptime\n", node.id).as_bytes())
-                                            .await
-                                            .unwrap();
-                                        }
-                                        node.uptime_info = Some((
-                                            current_time as i64,
-                                            reported_uptime,
-                                            total_uptime,
-                                        ));
-                                        continue;
-                                    }
-                                }
-                                // 3. The difference in uptime is too low. Again there are multiple
-                                //    scenarios. Either way we consider the node rebooted. Depending on
-                                //    the reported uptime, the node reports legit uptime, or it reports
-                                //    an uptime which is too high.
-                                //
-                                //    1. Uptime is within bounds.
-                                if reported_uptime as i64 <= report_delta {
-                                    let credit = u64::min(
-                                        reported_uptime,
-                                        (NODE_UPTIME_REPORT_INTERVAL_SECONDS
-                                            + UPTIME_GRACE_PERIOD_SECONDS)
-                                            as u64,
-                                    );
-                                    total_uptime += credit;
-                                    if reported_uptime != credit {
-                                        log_file
-                                        .write_all(format!("credited node {} with {credit} seconds of uptime after a reboot, less than the reported {reported_uptime} seconds as the gap is too big\n", node.id).as_bytes())
-                                        .await
-                                        .unwrap();
-                                    } else {
-                                        log_file
-                                        .write_all(format!("credited node {} with {credit} seconds of reported uptime after a reboot\n", node.id).as_bytes())
-                                        .await
-                                        .unwrap();
-                                    }
-                                    node.uptime_info =
-                                        Some((current_time as i64, reported_uptime, total_uptime));
-                                    node.boot_time = Some((
-                                        (current_time - reported_uptime) as i64,
-                                        current_time as i64,
-                                    ));
-                                    continue;
-                                }
-                                //    2. Uptime is actually higher than difference in timestamp, but
-                                //       not high enough to be valid. This means the node was
-                                //       supposedly rebooted _before_ the previous uptime report,
-                                //       meaning either that report is invalid or this report is
-                                //       invalid.
-                                if reported_uptime > last_reported_uptime {
-                                    if node.violation.is_none() {
-                                        node.violation = Violation::UptimeTooLow {
-                                            previous_uptime: last_reported_uptime,
-                                            previous_timestamp: last_reported_at,
-                                            reported_uptime,
-                                            reported_timestamp: ts,
-                                            block_reported: height,
-                                        };
-                                        log_file
-                                        .write_all(format!("Node {} reported uptime of {reported_uptime} seconds, so time would have advanced slower on the node than in the universe\n", node.id).as_bytes())
-                                        .await
-                                        .unwrap();
-                                    }
-                                    continue;
-                                }
-                                //    3. Uptime is too high, this is garbage
-                                if node.violation.is_none() {
-                                    node.violation = Violation::InvalidReboot {
-                                        previous_uptime: last_reported_uptime,
-                                        previous_timestamp: last_reported_at,
-                                        reported_uptime,
-                                        reported_timestamp: ts,
-                                        block_reported: height,
-                                    };
-                                    log_file
-                                    .write_all(format!("Node {} reported uptime of {reported_uptime} seconds, so time would have advanced faster on the node than in the universe\n", node.id).as_bytes())
-                                    .await
-                                    .unwrap();
-                                }
-                                continue;
-                            } else {
-                                let period_duration = current_time as i64 - start_ts;
-                                // Make sure we don't give more credit than the current length of the
-                                // period.
-                                // Account for uptime period
-                                let up_in_period = u64::min(
-                                    std::cmp::min(period_duration as u64, reported_uptime),
-                                    (NODE_UPTIME_REPORT_INTERVAL_SECONDS
-                                        + UPTIME_GRACE_PERIOD_SECONDS)
-                                        as u64,
-                                );
-                                log_file
-                                .write_all(format!("Node {} reported uptime of {reported_uptime} seconds, scaled to {up_in_period} seconds\n", node.id).as_bytes())
-                                .await
-                                .unwrap();
-                                // Save uptime info
-                                node.uptime_info =
-                                    Some((current_time as i64, reported_uptime, up_in_period));
-                                node.boot_time = Some((
-                                    (current_time - reported_uptime) as i64,
-                                    current_time as i64,
-                                ));
-                            }
-                        }
-                    };
-                }
-                RuntimeEvents::ContractUsedResourcesUpdated(data) => {
-                    let contract = match contracts.get_mut(&data.contract_id) {
-                        Some(contract) => contract,
-                        // Contract needs to exist.
-                        None => {
-                            panic!("Can't set used resources for contract {} which does not exist in block {}", data.contract_id, height);
-                        }
-                    };
-                    contract.resources = data.used;
-                    log_file
-                        .write_all(
-                            format!(
-                                "Update used resources for contract {}\n",
-                                contract._contract_id
-                            )
-                            .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                }
-                RuntimeEvents::NruConsumptionReceived(data) => {
-                    let contract = match contracts.get_mut(&data.contract_id) {
-                        Some(contract) => contract,
-                        // Contract needs to exist.
-                        None => {
-                            // If a contract is in grace period, there seem to still be NRU
-                            // reports. These may or may not be legit, depending on how zos is set
-                            // up. This warrants further investigation, as it would indicate the
-                            // network is not properly disconnected, but at this point in time we
-                            // will ignore this.
-                            continue;
-                            // panic!("Can't set used resources for contract {} which does not exist in block {}", data.contract_id, height);
-                        }
-                    };
-                    let node = match nodes.get_mut(&contract.node_id) {
-                        Some(node) => node,
-                        None => {
-                            panic!(
-                                "can't process consumption for unknown node {} in block {}",
-                                contract.node_id, height
-                            )
-                        }
-                    };
-                    // Just to make sure reports are ordered
-                    if ts <= contract.last_report_ts {
-                        // Silently ignore reports out of order, we already covered this in an
-                        // already processed consumption report. This can happen if the node pushes
-                        // a contract consumption report twice.
-                        log_file
-                            .write_all(
-                                format!(
-                                    "Ignoring out of order NRU consumption report for contract {} on node {}\n",
-                                    contract._contract_id,
-                                    node.id,
-                                )
-                                .as_bytes(),
-                            )
-                            .await
-                            .unwrap();
-                        continue;
-                    }
+    let mut receipts = BTreeMap::new();
+    let mut payout_file = std::fs::File::create(output.join("payouts.csv")).unwrap();
+    let mut overview_file = std::fs::File::create(output.join("overview.csv")).unwrap();
+    let mut retry_file = std::fs::File::create(output.join("retries.csv")).unwrap();
+    let mut payouts = Vec::new();
 
-                    // If report ts predates start we ignore it.
-                    if ts < start_ts {
-                        log_file
-                            .write_all(
-                                format!(
-                                    "Ignoring NRU consumption report for contract {} on node {} which predates the period start\n",
-                                    contract._contract_id,
-                                    node.id,
-                                )
-                                .as_bytes(),
-                            )
-                            .await
-                            .unwrap();
-                        continue;
-                    }
-                    node.capacity_consumption.cru += (contract.resources.cru * data.window) as u128;
-                    node.capacity_consumption.mru += (contract.resources.mru * data.window) as u128;
-                    node.capacity_consumption.hru += (contract.resources.hru * data.window) as u128;
-                    node.capacity_consumption.sru += (contract.resources.sru * data.window) as u128;
-                    node.capacity_consumption.ips += contract.ips as u64 * data.window;
-                    node.capacity_consumption.nru += data.nru;
-                    contract.last_report_ts = ts;
-                    log_file
-                        .write_all(
-                            format!(
-                                "Added NRU consumption report for contract {} on node {}\n",
-                                contract._contract_id, node.id,
-                            )
-                            .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                }
-                RuntimeEvents::ContractCreated(contract) => {
-                    if let ContractData::NodeContract(nc) = &contract.contract_type {
-                        contracts.insert(
-                            contract.contract_id,
-                            Contract {
-                                _contract_id: contract.contract_id,
-                                node_id: nc.node_id,
-                                last_report_ts: ts,
-                                ips: nc.public_ips,
-                                resources: Resources {
-                                    hru: 0,
-                                    sru: 0,
-                                    cru: 0,
-                                    mru: 0,
-                                },
-                            },
-                        );
-                        log_file
-                            .write_all(
-                                format!(
-                                    "Created contract {} on node {}\n",
-                                    contract.contract_id, nc.node_id,
-                                )
-                                .as_bytes(),
-                            )
-                            .await
-                            .unwrap();
-                    };
-                }
-                RuntimeEvents::PowerTargetChanged(ptc) => {
-                    let node_power = match power_states.get_mut(&ptc.node_id) {
-                        Some(ps) => ps,
-                        None => panic!(
-                            "can't change power target for unknown node {} in block {}",
-                            ptc.node_id, height
-                        ),
-                    };
-                    log_file
-                        .write_all(
-                            format!(
-                                "Power target changed for node {} from {:?} to {:?}\n",
-                                ptc.node_id, node_power.target, ptc.power_target,
-                            )
-                            .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                    // Remember a rising edge here to validate node actually boots.
-                    // This is cleared when a node sends an uptime report of a _reboot_. It is
-                    // allowed for this to happen if a rising edge is not consumed yet, in which
-                    // case the new event is ignored, as we want to measure time from the first
-                    // event and it is actually a good idea to send multiple of these if the node
-                    // does not react. Of course, we also only want to track this if the node is
-                    // currently power managed. While we shouldn't try to boot an online node,
-                    // there is no _real_ harm in doing it anyway.
-                    if ptc.power_target == Power::Up
-                        && matches!(node_power.state, PowerState::Down(_))
-                    {
-                        if let Some(node) = nodes.get_mut(&ptc.node_id) {
-                            // Only remember the first boot request.
-                            if node.power_manage_boot.is_none() {
-                                node.power_manage_boot = Some(ts);
-                                log_file
-                                    .write_all(
-                                        format!(
-                                            "Remembered boot request time for node {}\n",
-                                            node.id
-                                        )
-                                        .as_bytes(),
-                                    )
-                                    .await
-                                    .unwrap();
-                            }
-                        } else {
-                            panic!(
-                                "can't change power target for unknown node {} in block {}",
-                                ptc.node_id, height
-                            );
-                        }
-                    }
-                    node_power.target = ptc.power_target;
-                }
-                RuntimeEvents::PowerStateChanged(psc) => {
-                    let node_power = match power_states.get_mut(&psc.node_id) {
-                        Some(ps) => ps,
-                        None => panic!(
-                            "can't change power state for unknown node {} in block {}",
-                            psc.node_id, height
-                        ),
-                    };
-                    log_file
-                        .write_all(
-                            format!(
-                                "Power state changed for node {} from {:?} to {:?}\n",
-                                psc.node_id, node_power.state, psc.power_state,
-                            )
-                            .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                    // Add exception to allow node 1 uptime ping once it gets back on which
-                    // indicates a reboot.
-                    // Also, we only allow this if the target is down as well.
-                    if node_power.target == Power::Down {
-                        // Only on state transition
-                        if node_power.state == PowerState::Up
-                            && matches!(psc.power_state, PowerState::Down(_))
-                        {
-                            // Keep track of this timestamp
-                            let node = match nodes.get_mut(&psc.node_id) {
-                                Some(node) => node,
-                                None => {
-                                    panic!("Node must be registered if its power target changes")
-                                }
-                            };
-                            // Either this is Some(timestamp), indicating a previous state
-                            // transition which was not followed by an uptime ping once the node
-                            // came online. In this case, we ignore that here. This would mean the
-                            // node did not come up again.
-                            // Otherwise, if None, set the current timestamp as time of going down.
-                            if node.power_managed.is_none() {
-                                // Also add an implicit uptime.
-                                node.power_managed = Some(ts);
-                                // While we are at it, credit uptime since last uptime event as
-                                // well, as we will use this timestamp as the base for future
-                                // uptime calculations.
-                                // We don't have to overwrite this since future calculations will
-                                // first work on the saved power_managed variable, and will have a
-                                // reboot either way.
-                                if let Some((last_reported_at, _, mut total_uptime)) =
-                                    node.uptime_info
-                                {
-                                    let delta = ts - last_reported_at;
-                                    assert!(
-                                        delta >= 0,
-                                        "Power state changes can't travel back in time"
-                                    );
-                                    total_uptime += delta as u64;
-                                    // We can set uptime to 0, node will reboot anyway.
-                                    node.uptime_info = Some((ts, 0, total_uptime));
-                                }
-                                log_file
-                                    .write_all(
-                                        format!(
-                                            "Remembered farmer bot shutdown for node {}\n",
-                                            node.id
-                                        )
-                                        .as_bytes(),
-                                    )
-                                    .await
-                                    .unwrap();
-                            }
-                        }
-                    }
-                    node_power.state = psc.power_state;
-                }
-            }
+    writeln!(overview_file,"node id,twin id,farm name (farm id),period start,period end,measured uptime,CU,SU,NU,USD reward,TFT reward,TFT price on connect,carbon offset USD generated,carbon offset TFT generated,cru,cru used,mru,mru used,hru,hru used,sru,sru used,IP used,DIY state,Virtualized,violation,stellar address").unwrap();
+    for report in outcome.nodes {
+        let receipt = report.receipt;
+        if !args.filter.matches(Some(receipt.node_id), receipt.farm_id) {
+            continue;
         }
-
-        // finally update progress bar
-        bar.set_message(Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822());
-        bar.inc(1);
-
-        height += 1;
-
-        if height > end_block {
-            break;
-        }
-    }
-
-    bar.finish();
-
-    println!("Getting uptime info from post period");
-    let bar = ProgressBar::new(BLOCKS_IN_HOUR as u64 * 27);
-    bar.set_style(
-        ProgressStyle::default_bar()
-            .template("[Time on chain: {msg}] {wide_bar} {pos:>6}/{len:>6} (ETA: {eta_precise})")
-            .expect("Can set valid template"),
-    );
-
-    // Collect post-period uptime events. Violations don't matter here, those will be handled next
-    // period.
-    let mut import_queue = block_import(
-        &wss_url,
-        height as usize,
-        end_block as usize + BLOCKS_IN_HOUR as usize * 27,
-    )
-    .await;
-    loop {
-        let (block_height, ts, evts) =
-            if let Some((block_height, ts, evts)) = import_queue.recv().await {
-                (block_height, ts, evts)
-            } else {
-                panic!("Block import exited too early");
-            };
-        log_file
-            .write_all(
-                format!(
-                    "Loaded block {} ({}) containing {} events\n",
-                    block_height,
-                    Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822(),
-                    evts.len()
-                )
-                .as_bytes(),
-            )
-            .await
-            .unwrap();
-        for evt in evts.into_iter() {
-            match evt {
-                RuntimeEvents::NodeUptimeReported(id, current_time, reported_uptime) => {
-                    let node = match nodes.get_mut(&id) {
-                        Some(node) => node,
-                        // This is possible if its an uptime report for a node which came online after
-                        // the period ended
-                        None => continue,
-                    };
-
-                    match (node.power_managed, node.power_manage_boot) {
-                        (Some(time_set_down), Some(boot_request)) => {
-                            // node got power managed to down
-                            let time_delta = current_time as i64 - time_set_down;
-                            assert!(time_delta >= 0, "uptime events can't travel back in time");
-                            let mut total_uptime = if let Some((
-                                last_reported_at,
-                                _,
-                                total_uptime,
-                            )) = node.uptime_info
-                            {
-                                if last_reported_at > end_ts {
-                                    log_file.write_all(
-                                        format!(
-                                            "Ignoring more than 1 farmer bot uptime event after period for node {}\n",
-                                            node.id,
-                                        ).as_bytes()
-                                    ).await.unwrap();
-                                    continue;
-                                }
-                                total_uptime
-                            } else {
-                                0
-                            };
-
-                            // Verify farmer bot boot constraints
-                            let mut credit_uptime = true;
-                            if time_delta as u64 > MAX_POWER_MANAGER_DOWNTIME {
-                                credit_uptime = false;
-                                log_file
-                                    .write_all(format!("Refusing to credit uptime for power managed node {} post period as the last boot was {time_delta} seconds ago, more than the allowed 24 hours\n", node.id).as_bytes())
-                                    .await
-                                    .unwrap();
-                            }
-                            if (current_time - reported_uptime) as i64 - boot_request
-                                > MAX_POWER_MANAGER_BOOT_TIME
-                            {
-                                credit_uptime = false;
-                                // Mark a violation on the node.
-                                node.boot_duration_violations += 1;
-                                log_file
-                                        .write_all(format!("Detected farmer bot boot violation for node {} post period, request was done at {} but node only came online at {}\n",
-                                            node.id,
-                                            Utc.timestamp_opt(boot_request, 0).unwrap().to_rfc2822(),
-                                            Utc.timestamp_opt((current_time - reported_uptime) as i64, 0).unwrap().to_rfc2822()
-                                        ).as_bytes())
-                                        .await
-                                        .unwrap();
-                            }
-                            // All good, at uptime in period.
-                            if credit_uptime {
-                                let uptime_diff = end_ts - i64::max(start_ts, time_set_down);
-                                if uptime_diff < 0 {
-                                    log_file.write_all(format!(
-                                        "Ignoring farmer bot wakeup for node {} which went down after the period ended\n", node.id
-                                    ).as_bytes()).await.unwrap();
-                                } else {
-                                    total_uptime += uptime_diff as u64;
-                                    log_file
-                                        .write_all(
-                                            format!(
-                                                "Added {uptime_diff} seconds of uptime for node {}, for farmer bot boot post period\n",
-                                                node.id
-                                            )
-                                            .as_bytes(),
-                                        )
-                                        .await
-                                        .unwrap();
-                                }
-                            }
-
-                            // Clear the fact that we got power managed, if it is still the case, it
-                            // will be set again in the proper event handler.
-                            node.power_managed = None;
-                            node.power_manage_boot = None;
-                            node.uptime_info =
-                                Some((current_time as i64, reported_uptime, total_uptime));
-                            // Also mark a boot
-                            node.boot_time = Some((
-                                (current_time - reported_uptime) as i64,
-                                current_time as i64,
-                            ));
-                        }
-                        // We are power managed but woke up without boot request. We explicitly ignore this: being
-                        // put to sleep by the farmer bot requires a wakeup from the farmer bot. This case also
-                        // means nodes just go to sleep anyhow.
-                        (Some(_), None) => {
-                            log_file
-                                .write_all(
-                                    format!("Ignoring boot for node {} which is power managed, but did not get a boot request from the farmer bot in the period\n", node.id)
-                                        .as_bytes(),
-                                )
-                                .await
-                                .unwrap();
-                        }
-                        // We got a wakeup request from farmer bot but we are not sleeping due to
-                        // the farmer bot. This should not happen.
-                        (None, Some(_)) => {
-                            log_file
-                                .write_all(
-                                    format!("Ignoring uptime for node {} after farmer bot asked for a boot while the node was not sleeping as a result of farmer bot\n", node.id)
-                                        .as_bytes(),
-                                )
-                                .await
-                                .unwrap();
-                        }
-                        (None, None) => {
-                            if let Some((
-                                last_reported_at,
-                                last_reported_uptime,
-                                mut total_uptime,
-                            )) = node.uptime_info
-                            {
-                                // only collect 1 uptime event after the period ended
-                                if last_reported_at >= end_ts {
-                                    continue;
-                                }
-                                let report_delta = current_time as i64 - last_reported_at;
-                                let uptime_delta =
-                                    reported_uptime as i64 - last_reported_uptime as i64;
-                                let delta_in_period = end_ts - last_reported_at;
-                                // There are quite some situations here. Notice that due to the
-                                // blockchain only producing blocks every 6 seconds, and network delay
-                                // + a host of other issues, we will allow a node to report uptime with
-                                // "grace period" of a minute or so in either direction.
-                                //
-                                // 1. uptime_delta > report_delta + GRACE_PERIOD. Node is talking
-                                //    rubish.
-                                if uptime_delta > report_delta + UPTIME_GRACE_PERIOD_SECONDS {
-                                    // We need to register the violation here as we won't be able to
-                                    // next period (since we don't scrape points from before the period
-                                    // atm).
-                                    if node.violation.is_none() {
-                                        node.violation = Violation::UptimeTooHigh {
-                                            previous_uptime: last_reported_uptime,
-                                            previous_timestamp: last_reported_at,
-                                            reported_uptime,
-                                            reported_timestamp: ts,
-                                            block_reported: height,
-                                        };
-                                    }
-                                    node.uptime_info =
-                                        Some((current_time as i64, reported_uptime, total_uptime));
-                                    log_file
-                                    .write_all(format!("Node {} reported an uptime increase of {uptime_delta} seconds, while reports are {report_delta} seconds appart\n",node.id,).as_bytes())
-                                    .await
-                                    .unwrap();
-                                    continue;
-                                }
-                                // 2. The difference in uptime is within reason of the difference in
-                                //    report times, i.e. the node is properly reporting.
-                                if uptime_delta <= report_delta + UPTIME_GRACE_PERIOD_SECONDS
-                                    && uptime_delta >= report_delta - UPTIME_GRACE_PERIOD_SECONDS
-                                {
-                                    // check skew
-                                    if let Some((boot, detected)) = node.boot_time {
-                                        let new_boot = (current_time - reported_uptime) as i64;
-                                        if (new_boot - boot).abs() >= CLOCK_SKEW_INTERVAL {
-                                            // This is a violation
-                                            if node.violation.is_none() {
-                                                node.violation = Violation::ClockSkew {
-                                                    original_boot: boot,
-                                                    current_boot: new_boot,
-                                                    previous_timestamp: detected,
-                                                    reported_timestamp: current_time as i64,
-                                                };
-                                            }
-                                            log_file
-                                            .write_all(format!("Node {} has a detected clock skew of {} seconds, more than the allowed {CLOCK_SKEW_INTERVAL} seconds\n",node.id, (new_boot - boot).abs()).as_bytes())
-                                            .await
-                                            .unwrap();
-                                        }
-                                    } else {
-                                        panic!("node does not have boot time but does have uptime")
-                                    }
-
-                                    // It is technically possible for the delta to be less than 0 and
-                                    // within the expected time frame. If nodes boot, send uptime, then
-                                    // immediately reboot that is possible. In those cases, handle that
-                                    // below, as that is the reboot detection.
-                                    if uptime_delta > 0 {
-                                        // Simply add the uptime delta. If this is too large or low by a
-                                        // couple of seconds it will be corrected by the next pings anyhow.
-                                        //
-                                        // Make sure we don't add too much based on the period.
-                                        let credit = u64::min(
-                                            delta_in_period as u64,
-                                            (NODE_UPTIME_REPORT_INTERVAL_SECONDS
-                                                + UPTIME_GRACE_PERIOD_SECONDS)
-                                                as u64,
-                                        );
-                                        total_uptime += credit;
-                                        if credit != delta_in_period as u64 {
-                                            log_file
-                                            .write_all(format!("credited node {} with {credit} seconds of uptime, less than the reported {delta_in_period} seconds as the gap is too big\n", node.id).as_bytes())
-                                            .await
-                                            .unwrap();
-                                        } else {
-                                            log_file
-                                            .write_all(format!("credited node {} with {credit} seconds of reported uptime\n", node.id).as_bytes())
-                                            .await
-                                            .unwrap();
-                                        }
-                                        node.uptime_info = Some((
-                                            current_time as i64,
-                                            reported_uptime,
-                                            total_uptime,
-                                        ));
-                                        continue;
-                                    }
-                                }
-                                // 3. The difference in uptime is too low. Again there are multiple
-                                //    scenarios. Either way we consider the node rebooted. Depending on
-                                //    the reported uptime, the node reports legit uptime, or it reports
-                                //    an uptime which is too high.
-                                //
-                                //    1. Uptime is within bounds.
-                                if reported_uptime as i64 <= report_delta {
-                                    // Account for the fact that we are actually out of the period
-                                    let out_of_period = current_time - end_ts as u64;
-                                    if out_of_period < reported_uptime {
-                                        let credit = u64::min(
-                                            reported_uptime - out_of_period,
-                                            (NODE_UPTIME_REPORT_INTERVAL_SECONDS
-                                                + UPTIME_GRACE_PERIOD_SECONDS)
-                                                as u64,
-                                        );
-                                        total_uptime += credit;
-                                        if (reported_uptime - out_of_period) != credit {
-                                            log_file
-                                        .write_all(format!("credited node {} with {credit} seconds of uptime after a reboot, less than the reported {} seconds as the gap is too big\n", node.id, reported_uptime - out_of_period).as_bytes())
-                                        .await
-                                        .unwrap();
-                                        } else {
-                                            log_file
-                                        .write_all(format!("credited node {} with {credit} seconds of reported uptime after a reboot\n", node.id).as_bytes())
-                                        .await
-                                        .unwrap();
-                                        }
-                                    }
-                                    node.uptime_info =
-                                        Some((current_time as i64, reported_uptime, total_uptime));
-                                    node.boot_time = Some((
-                                        (current_time - reported_uptime) as i64,
-                                        current_time as i64,
-                                    ));
-                                    continue;
-                                }
-                                //    2. Uptime is actually higher than difference in timestamp, but
-                                //       not high enough to be valid. This means the node was
-                                //       supposedly rebooted _before_ the previous uptime report,
-                                //       meaning either that report is invalid or this report is
-                                //       invalid.
-                                if reported_uptime > last_reported_uptime {
-                                    if node.violation.is_none() {
-                                        node.violation = Violation::UptimeTooLow {
-                                            previous_uptime: last_reported_uptime,
-                                            previous_timestamp: last_reported_at,
-                                            reported_uptime,
-                                            reported_timestamp: ts,
-                                            block_reported: height,
-                                        };
-                                        log_file
-                                        .write_all(format!("Node {} reported uptime of {reported_uptime} seconds, so time would have advanced slower on the node than in the universe\n", node.id).as_bytes())
-                                        .await
-                                        .unwrap();
-                                    }
-                                    continue;
-                                }
-                                //    3. Uptime is too high, this is garbage
-                                if node.violation.is_none() {
-                                    node.violation = Violation::InvalidReboot {
-                                        previous_uptime: last_reported_uptime,
-                                        previous_timestamp: last_reported_at,
-                                        reported_uptime,
-                                        reported_timestamp: ts,
-                                        block_reported: height,
-                                    };
-                                    log_file
-                                    .write_all(format!("Node {} reported uptime of {reported_uptime} seconds, so time would have advanced faster on the node than in the universe\n", node.id).as_bytes())
-                                    .await
-                                    .unwrap();
-                                    continue;
-                                }
-
-                                // We should have handled all cases. Make this explicit here.
-                                unreachable!();
-                            }
-                        }
-                    }
-                }
-                RuntimeEvents::PowerTargetChanged(ptc) => {
-                    let node_power = match power_states.get_mut(&ptc.node_id) {
-                        Some(ps) => ps,
-                        // This could happen if a node is added post period and becomes power
-                        // managed.
-                        None => continue,
-                    };
-                    log_file
-                        .write_all(
-                            format!(
-                                "Power target changed for node {} from {:?} to {:?}\n",
-                                ptc.node_id, node_power.target, ptc.power_target,
-                            )
-                            .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                    // Remember a rising edge here to validate node actually boots.
-                    // This is cleared when a node sends an uptime report of a _reboot_. It is
-                    // allowed for this to happen if a rising edge is not consumed yet, in which
-                    // case the new event is ignored, as we want to measure time from the first
-                    // event and it is actually a good idea to send multiple of these if the node
-                    // does not react. Of course, we also only want to track this if the node is
-                    // currently power managed. While we shouldn't try to boot an online node,
-                    // there is no _real_ harm in doing it anyway.
-                    if ptc.power_target == Power::Up
-                        && matches!(node_power.state, PowerState::Down(_))
-                    {
-                        if let Some(node) = nodes.get_mut(&ptc.node_id) {
-                            // Only remember the first boot request.
-                            if node.power_manage_boot.is_none() {
-                                node.power_manage_boot = Some(ts);
-                                log_file
-                                    .write_all(
-                                        format!(
-                                            "Remembered boot request time for node {}\n",
-                                            node.id
-                                        )
-                                        .as_bytes(),
-                                    )
-                                    .await
-                                    .unwrap();
-                            }
-                        } else {
-                            panic!(
-                                "can't change power target for unknown node {} in block {}",
-                                ptc.node_id, height
-                            );
-                        }
-                    }
-                    node_power.target = ptc.power_target;
-                }
-                // Technically this is not needed since we don't care for actual state changes
-                // after the period. After all, we only use this to arm a trigger to catch
-                // farmerbot wakes up. This trigger is set when the node goes from up to down, and
-                // in doing so it also sends an uptime report to chain. Since we are post period
-                // now, if the node goes to sleep now its sleep time won't influence the current
-                // period. Regardless add this code here so we can keep track of state changes and
-                // rely on the fact that we only allow 1 uptime post period to do the proper thing.
-                RuntimeEvents::PowerStateChanged(psc) => {
-                    let node_power = match power_states.get_mut(&psc.node_id) {
-                        Some(ps) => ps,
-                        None => continue,
-                    };
-                    log_file
-                        .write_all(
-                            format!(
-                                "Power state changed for node {} from {:?} to {:?}\n",
-                                psc.node_id, node_power.state, psc.power_state,
-                            )
-                            .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                    // Add exception to allow node 1 uptime ping once it gets back on which
-                    // indicates a reboot.
-                    // Also, we only allow this if the target is down as well.
-                    if node_power.target == Power::Down {
-                        // Only on state transition
-                        if node_power.state == PowerState::Up
-                            && matches!(psc.power_state, PowerState::Down(_))
-                        {
-                            // Keep track of this timestamp
-                            let node = match nodes.get_mut(&psc.node_id) {
-                                Some(node) => node,
-                                None => {
-                                    panic!("Node must be registered if its power target changes")
-                                }
-                            };
-                            // Either this is Some(timestamp), indicating a previous state
-                            // transition which was not followed by an uptime ping once the node
-                            // came online. In this case, we ignore that here. This would mean the
-                            // node did not come up again.
-                            // Otherwise, if None, set the current timestamp as time of going down.
-                            if node.power_managed.is_none() {
-                                // Also add an implicit uptime.
-                                node.power_managed = Some(ts);
-                                log_file
-                                    .write_all(
-                                        format!(
-                                            "Remembered farmer bot shutdown for node {}\n",
-                                            node.id
-                                        )
-                                        .as_bytes(),
-                                    )
-                                    .await
-                                    .unwrap();
-                            }
-                        }
-                    }
-                    node_power.state = psc.power_state;
-                }
-                _ => {
-                    // Don't care here
-                }
-            }
-        }
-
-        bar.set_message(Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822());
-        bar.inc(1);
-
-        height += 1;
-
-        if height > end_block + BLOCKS_IN_HOUR * 27 {
-            break;
-        }
-    }
-    bar.finish_and_clear();
-
-    // At this point we are done fetching events. Note that for the case of power manager boot
-    // requests, we haven't checked the case where the node does not respond at all. We already
-    // fetched a days worth of blocks after the period ended, and don't keep track of power on
-    // requests there. So any leftover requests here are already a day old, which is way too much.
-    // So if any node has an outstanding power on request here, mark a boot failure.
-    //
-    // On top of this, if a node has more than the allowed amount of boot failures, stick a
-    // violation on them if there isn't anohter one already.
-    for (_, node) in nodes.iter_mut() {
-        // First see if we need to mark another failure to boot in time.
-        if let Some(boot_request) = node.power_manage_boot {
-            // Ignore if this is the same as start, no need to slap a violation on what is likely a
-            // dead node.
-            if boot_request == start_block_ts as i64 {
-                log_file
-                    .write_all(format!("Not giving node {} a slow boot violation since it never tried to boot in the first place\n",
-                        node.id,
-                    ).as_bytes())
-                    .await
-                    .unwrap();
-            } else if boot_request > end_ts {
-                // Boot request (and possible failure) is entirely past the current period so we
-                // reserve the violation for next minting.
-                log_file
-                    .write_all(format!("Not giving node {} a slow boot violation since the wakup request happened post period\n",
-                        node.id,
-                    ).as_bytes())
-                    .await
-                    .unwrap();
-            } else {
-                node.boot_duration_violations += 1;
-                log_file
-                    .write_all(format!("Detected farmer bot boot violation for node {}, request was done at {} but node never booted\n",
-                        node.id,
-                        Utc.timestamp_opt(boot_request, 0).unwrap().to_rfc2822(),
-                    ).as_bytes())
-                    .await
-                    .unwrap();
-            }
-        }
-
-        // Then slap on a violation if needed
-        if node.violation.is_none() && node.boot_duration_violations > MAX_ALLOWED_BOOT_VIOLATIONS {
-            node.violation = Violation::BootRequestExpired {
-                failed_boots: node.boot_duration_violations,
-            };
-        }
-    }
-
-    // Check twin relays and public keys
-    for (_, node) in nodes.iter_mut() {
-        // We only care for nodes which are online.
-        if node.uptime_info.is_none() {
-            continue;
-        }
-        let twin = if let Some(twin) = twins.get(&node.twin_id) {
-            twin
-        } else {
-            // This should not happen, but still catch it
-            if node.violation.is_none() {
-                node.violation = Violation::MissingTwin;
-            }
-            log_file
-                .write_all(format!("Node {} ended period without twin\n", node.id,).as_bytes())
-                .await
-                .unwrap();
-            continue;
-        };
-        let has_relay = match twin.relay {
-            None => false,
-            Some(ref s) if s.is_empty() => false,
-            _ => true,
-        };
-        if !has_relay && node.violation.is_none() {
-            node.violation = Violation::MissingRelay;
-            log_file
-                .write_all(
-                    format!("Node {} ended period without twin relay set\n", node.id,).as_bytes(),
-                )
-                .await
-                .unwrap();
-        }
-        if let Some(ref pk) = twin.pk {
-            // Secp256k1 public key size is 33 bytes in compressed form
-            if pk.len() != 33 && node.violation.is_none() {
-                node.violation = Violation::InvalidPublicKey;
-                log_file
-                    .write_all(
-                        format!(
-                            "Node {} ended period with invalid public key set on twin\n",
-                            node.id,
-                        )
-                        .as_bytes(),
-                    )
-                    .await
-                    .unwrap();
-            }
-        }
-    }
-
-    let mut receipts = BTreeMap::new();
-    let mut payout_file = std::fs::File::create("payouts.csv").unwrap();
-    let mut overview_file = std::fs::File::create("overview.csv").unwrap();
-    let mut retry_file = std::fs::File::create("retries.csv").unwrap();
-
-    writeln!(overview_file,"node id,twin id,farm name (farm id),period start,period end,measured uptime,CU,SU,NU,USD reward,TFT reward,TFT price on connect,carbon offset USD generated,carbon offset TFT generated,cru,cru used,mru,mru used,hru,hru used,sru,sru used,IP used,DIY state,Virtualized,violation,stellar address").unwrap();
-    for (_, node) in nodes {
-        // generate receipt
-        let receipt = node.receipt(period, &farms, &payout_addresses, &farming_policies);
         if !receipt.stellar_payout_address.is_empty() && receipt.reward.tft != 0 {
             writeln!(
                 payout_file,
@@ -1705,49 +613,38 @@ async fn main() {
                 hex::encode(receipt.hash()),
             )
             .unwrap();
+            payouts.push(Payout {
+                destination: receipt.stellar_payout_address.clone(),
+                amount: receipt.reward.tft,
+                receipt: receipt.hash(),
+            });
         }
 
-        //let node_period = node.real_period(period);
         let node_period = receipt.period;
         let node_start = Utc.timestamp_opt(node_period.start(), 0).unwrap();
         let node_end = Utc.timestamp_opt(node_period.end(), 0).unwrap();
-        let CloudUnits { cu, su, nu } = receipt.cloud_units;
+        let CloudUnitsV2 { cu, su, nu } = receipt.cloud_units;
         let Reward { musd, tft } = receipt.reward;
         let (co_musd, co_tft) = receipt.carbon_offset.map_or((0, 0), |r| (r.musd, r.tft));
-        let ResourceUtilization {
+        let ResourceUtilizationV2 {
             cru: cru_used,
             mru: mru_used,
             hru: hru_used,
             sru: sru_used,
             ip: ip_used,
         } = receipt.resource_utilization;
-        let farm = if let Some(farm) = farms.get(&receipt.farm_id) {
-            farm
-        } else {
-            println!(
-                "node {} is in farm {} which does not exist anymore",
-                receipt.node_id, receipt.farm_id
-            );
-            continue;
-        };
-        let stellar_address = if let Some(stellar_address) = payout_addresses.get(&receipt.farm_id)
-        {
-            stellar_address
-        } else {
-            ""
-        };
         writeln!(overview_file,
             "{},{},{} ({}),{},{},{},{},{},{},{} $,{},{} $,{} $,{} TFT,{},{:.2}%,{},{:.2}%,{},{:.2}%,{},{:.2}%,{:.2} hours,{},{},{},{}",
-            node.id,
-            node.twin_id,
-            farm.name,
-            node.farm_id,
+            receipt.node_id,
+            receipt.twin_id,
+            receipt.farm_name,
+            receipt.farm_id,
             node_start,
             node_end,
             receipt.measured_uptime,
-            format_args!("{:.6}", cu ),
-            format_args!("{:.6}", su ),
-            format_args!("{:.6}", nu ),
+            format_args!("{}.{:06}", cu / 1_000_000, cu % 1_000_000),
+            format_args!("{}.{:06}", su / 1_000_000, su % 1_000_000),
+            format_args!("{}.{:06}", nu / 1_000_000, nu % 1_000_000),
             format_args!("{}.{:03}", musd / 1_000, musd % 1_000),
             format_args!("{}.{:07}", tft / UNITS_PER_TFT, tft % UNITS_PER_TFT),
             format_args!(
@@ -1758,45 +655,32 @@ async fn main() {
             format_args!("{}.{:03}", co_musd / 1_000, co_musd % 1_000),
             format_args!("{}.{:07}", co_tft / UNITS_PER_TFT, co_tft % UNITS_PER_TFT),
             receipt.resource_units.cru,
-            cru_used,
-            receipt.resource_units.mru,
-            mru_used,
-            receipt.resource_units.hru,
-            hru_used,
-            receipt.resource_units.sru,
-            sru_used,
-            ip_used,
+            cru_used as f64 / 10_000.,
+            receipt.resource_units.mru as f64 / GIB,
+            mru_used as f64 / 10_000.,
+            receipt.resource_units.hru as f64 / GIB,
+            hru_used as f64 / 10_000.,
+            receipt.resource_units.sru as f64 / GIB,
+            sru_used as f64 / 10_000.,
+            ip_used as f64 / 3600.,
             receipt.node_type,
-            node.virtualized,
-            node.violation,
-            stellar_address,
+            report.virtualized,
+            report.violation,
+            receipt.stellar_payout_address,
         ).unwrap();
 
         receipts.insert(receipt.hash(), receipt);
     }
 
-    // Retry payments once
-    let mut retry_receipts = HashMap::new();
-    for (hash, failed_receipt) in previous_receipts {
-        // no point in doing this
-        if failed_receipt.reward.tft == 0 {
+    // Retry all unpaid receipts from the backlog
+    let mut retry_receipts = BTreeMap::new();
+    for retry_receipt in backlog.retries(period, profile.period_epoch, payout_addresses) {
+        if !args.filter.matches(None, retry_receipt.farm_id) {
             continue;
         }
-        let retry_receipt = RetryPayoutReceipt {
-            failed_payout_period: failed_receipt.period,
-            retry_period: period,
-            farm_id: failed_receipt.farm_id,
-            previous_stellar_payout_address: failed_receipt.stellar_payout_address,
-            stellar_payout_address: payout_addresses
-                .get(&failed_receipt.farm_id)
-                .cloned()
-                .unwrap_or("".to_string()),
-            retry_for_receipt: hex::encode(hash),
-            reward: failed_receipt.reward,
-        };
         let retry_hash = hex::encode(retry_receipt.hash());
 
-        if !retry_receipt.stellar_payout_address.is_empty() && retry_receipt.reward.tft != 0 {
+        if !retry_receipt.stellar_payout_address.is_empty() {
             writeln!(
                 payout_file,
                 "{},{}.{:07},{}",
@@ -1806,70 +690,88 @@ async fn main() {
                 retry_hash,
             )
             .unwrap();
+            payouts.push(Payout {
+                destination: retry_receipt.stellar_payout_address.clone(),
+                amount: retry_receipt.reward.tft,
+                receipt: retry_receipt.hash(),
+            });
         }
 
         retry_receipts.insert(retry_hash, retry_receipt);
     }
 
-    let mut retry_fixed_receipts = HashMap::new();
-    for (hash, failed_receipt) in previous_fixup_receipts {
-        // no point in doing this
-        if failed_receipt.fixup_reward.tft == 0 {
-            continue;
+    if let Some(account_sequence) = args.sequence {
+        let (transactions, failed) =
+            payout_transactions(stellar_issuer(&profile), payouts, account_sequence);
+        for (payout, e) in failed {
+            log_file
+                .write_all(
+                    format!(
+                        "Can't create transaction for receipt {}: {e}\n",
+                        hex::encode(payout.receipt)
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
         }
-        let retry_receipt = RetryPayoutReceipt {
-            failed_payout_period: failed_receipt.period,
-            retry_period: period,
-            farm_id: failed_receipt.farm_id,
-            previous_stellar_payout_address: failed_receipt.stellar_payout_address,
-            stellar_payout_address: payout_addresses
-                .get(&failed_receipt.farm_id)
-                .cloned()
-                .unwrap_or("".to_string()),
-            retry_for_receipt: hex::encode(hash),
-            reward: failed_receipt.fixup_reward,
-        };
-        let retry_hash = hex::encode(retry_receipt.hash());
-
-        if !retry_receipt.stellar_payout_address.is_empty() && retry_receipt.reward.tft != 0 {
+        let mut transaction_file = std::fs::File::create(output.join("transactions.csv")).unwrap();
+        writeln!(transaction_file, "sequence,memo,hash,envelope").unwrap();
+        for tx in transactions {
             writeln!(
-                payout_file,
-                "{},{}.{:07},{}",
-                retry_receipt.stellar_payout_address,
-                retry_receipt.reward.tft / UNITS_PER_TFT,
-                retry_receipt.reward.tft % UNITS_PER_TFT,
-                retry_hash,
+                transaction_file,
+                "{},{},{},{}",
+                tx.sequence(),
+                hex::encode(tx.memo()),
+                hex::encode(tx.hash(&profile.stellar_passphrase)),
+                tx.envelope_base64(),
             )
             .unwrap();
         }
-
-        retry_fixed_receipts.insert(retry_hash, retry_receipt);
     }
 
-    // Write generated receipts
-    let mut receipt_dir = path::PathBuf::new();
-    receipt_dir.push("receipts");
-    receipt_dir.push(period_offset.to_string());
-    std::fs::create_dir_all(&receipt_dir).unwrap();
-    for (hash, receipt) in receipts {
-        let mut path = receipt_dir.clone();
-        path.push(hex::encode(hash));
-        std::fs::write(path, serde_json::to_vec(&receipt).unwrap()).unwrap();
+    // Fixup receipts for this period are created separately, include them if they exist.
+    let fixup_receipt_dir = profile.receipt_dir("fixed").join(period_offset.to_string());
+    let mut fixup_receipts = Vec::new();
+    if let Ok(dir_iter) = std::fs::read_dir(&fixup_receipt_dir) {
+        for file in dir_iter {
+            let file = file.unwrap();
+            if !file.file_type().unwrap().is_file() {
+                continue;
+            }
+            let data = fs::read_to_string(file.path()).unwrap();
+            let receipt: FixupReceipt = serde_json::from_str(&data).unwrap();
+            fixup_receipts.push(receipt);
+        }
     }
 
-    // Write retry receipts
+    let manifest = Manifest::new(
+        period,
+        start_block_ref,
+        end_block_ref,
+        receipts.values(),
+        retry_receipts.values(),
+        &fixup_receipts,
+    );
+    let tree = manifest.tree().unwrap();
+    log_file
+        .write_all(
+            format!(
+                "Manifest root {} over {} receipts\n",
+                manifest.root,
+                manifest.receipts.len()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
     writeln!(
         retry_file,
         "farm_id,previous_stellar_address,new_stellar_address,amount TFT,retry_for",
     )
     .unwrap();
-
-    let mut retry_receipt_dir = path::PathBuf::new();
-    retry_receipt_dir.push("receipts");
-    retry_receipt_dir.push("retries");
-    retry_receipt_dir.push(period_offset.to_string());
-    std::fs::create_dir_all(&retry_receipt_dir).unwrap();
-    for (hash, receipt) in retry_receipts {
+    for receipt in retry_receipts.values() {
         writeln!(
             retry_file,
             "{},{},{},{}.{:07},{}",
@@ -1881,469 +783,724 @@ async fn main() {
             receipt.retry_for_receipt,
         )
         .unwrap();
-        let mut path = retry_receipt_dir.clone();
-        path.push(hash);
+    }
+
+    if dry_run {
+        return;
+    }
+
+    // Write manifest and inclusion proofs
+    let mut manifest_dir = profile.receipt_dir("manifests");
+    std::fs::create_dir_all(&manifest_dir).unwrap();
+    manifest_dir.push(period_offset.to_string());
+    std::fs::write(manifest_dir, serde_json::to_vec(&manifest).unwrap()).unwrap();
+
+    let proof_dir = profile
+        .receipt_dir("proofs")
+        .join(period_offset.to_string());
+    std::fs::create_dir_all(&proof_dir).unwrap();
+    for hash in tree.leaves() {
+        let mut path = proof_dir.clone();
+        path.push(hex::encode(hash));
+        let proof = tree.proof(hash).unwrap();
+        std::fs::write(path, serde_json::to_vec(&proof).unwrap()).unwrap();
+    }
+
+    // Write generated receipts
+    std::fs::create_dir_all(&receipt_dir).unwrap();
+    for (hash, receipt) in receipts {
+        let mut path = receipt_dir.clone();
+        path.push(hex::encode(hash));
         std::fs::write(path, serde_json::to_vec(&receipt).unwrap()).unwrap();
     }
-    for (hash, receipt) in retry_fixed_receipts {
-        writeln!(
-            retry_file,
-            "{},{},{},{}.{:07},{}",
-            receipt.farm_id,
-            receipt.previous_stellar_payout_address,
-            receipt.stellar_payout_address,
-            receipt.reward.tft / UNITS_PER_TFT,
-            receipt.reward.tft % UNITS_PER_TFT,
-            receipt.retry_for_receipt,
-        )
-        .unwrap();
+
+    // Write retry receipts
+    let retry_receipt_dir = profile
+        .receipt_dir("retries")
+        .join(period_offset.to_string());
+    std::fs::create_dir_all(&retry_receipt_dir).unwrap();
+    for (hash, receipt) in retry_receipts {
         let mut path = retry_receipt_dir.clone();
         path.push(hash);
         std::fs::write(path, serde_json::to_vec(&receipt).unwrap()).unwrap();
     }
+
+    backlog.save(&backlog_path).unwrap();
 }
 
-struct MintingNode {
-    id: u32,
-    farm_id: u32,
-    twin_id: u32,
-    resources: Resources,
-    location: Location,
-    country: String,
-    city: String,
-    _created: u64,
-    certification_type: NodeCertification,
-    // (last ping, last reported uptime, total uptime).
-    uptime_info: Option<(i64, u64, u64)>,
-    // (boot time, original boot time record).
-    boot_time: Option<(i64, i64)>,
-    violation: Violation,
-    // TFT price expressed in USD at time of connection. Price is expressed in mUSD (3 digits
-    // precision). I.e. 1 USD => 1000.
-    connection_price: u32,
-    // capacity consumed by workloads over a period.
-    capacity_consumption: TotalConsumption,
-    virtualized: bool,
-    farming_policy_id: u32,
-    // Timestamp the node changed powerstate to down, before a new uptime was posted.
-    power_managed: Option<i64>,
-    /// Time the last power manage target changed to up. We keep track of this to make sure we
-    /// always have a node go up after target is set to up (i.e. farmerbot powers on a node).
-    /// Cleared when node boots.
-    power_manage_boot: Option<i64>,
-    /// Track the amount of times a farmerbot managed node failed to wake up within 30 minutes.
-    boot_duration_violations: usize,
+/// Recompute the receipts of a period, and compare them with a submitted set of receipts. Exits
+/// with a nonzero status code if the sets don't match.
+async fn verify_period(args: VerifyArgs) {
+    let profile = args.network.profile();
+    fs::create_dir_all(&args.output).unwrap();
+    let mut log_file = tokio::fs::File::create(args.output.join("verify_log.txt"))
+        .await
+        .unwrap();
+
+    let receipt_dir = args
+        .receipts
+        .clone()
+        .unwrap_or_else(|| profile.receipt_dir(args.period.to_string()));
+    println!("Loading submitted receipts from {}", receipt_dir.display());
+    let mut submitted = verify::load_receipts(&receipt_dir).unwrap();
+    args.filter.retain(&mut submitted);
+
+    let outcome = recompute(
+        args.period,
+        &profile,
+        &args.chain,
+        &args.engine,
+        &mut log_file,
+    )
+    .await;
+    let mut expected: BTreeMap<_, _> = outcome
+        .nodes
+        .into_iter()
+        .map(|report| (report.receipt.hash(), VersionedReceipt::V2(report.receipt)))
+        .collect();
+    args.filter.retain(&mut expected);
+
+    let report = verify::compare(expected, submitted);
+    println!("{report}");
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
 }
 
-impl MintingNode {
-    /// Compute the CU, SU and NU for the node. The result is expressed in a "permill" way. So the
-    /// actual CU, SU and NU are obtained by dividing the results by 1_000_000.
-    ///
-    /// In order for this to be accurate, the data about network and IP usage needs to already have
-    /// been aggregated on the node object.
-    ///
-    /// Calculation taken from [the
-    /// wiki](https://library.threefold.me/info/threefold#/tfgrid/farming/threefold__resource_units_calc_cloudunits)
-    /// on 31-01-2022 as follows:
-    ///   CU: MIN(cru * 4 / 2, (mru - 1) / 4, sru / 50)
-    ///   SU: hru / 1200 + sru * 0.8 / 200
-    ///   NU: gigabytes of public traffic reported
-    fn cloud_units_permill(&self) -> (u64, u64, u64) {
-        // Calculate CU first. Mru and sru are in bytes, but are expressed in GB in the formula.
-        // Rather than dividing first, we multiply cru first, then take the MIN, and finally
-        // divide. This eliminates the issue of rounding errors _BEFORE_ the MIN. Also multiply by
-        // 1000000 so we have precision without working with floats.
-        //
-        // MIN is associative.
-        #[allow(clippy::identity_op)] // Allow explicit multiplication by 1 to make it clear we use
-        // 1 GiB here.
-        let cu_intermediate = std::cmp::min(
-            self.resources.cru as u128 * 2 * GIB * ONE_MILL,
-            (self.resources.mru as u128 - 1 * GIB) * ONE_MILL / 4,
-        );
-        let cu = std::cmp::min(cu_intermediate, self.resources.sru as u128 * ONE_MILL / 50);
-        let su = self.resources.hru as u128 * ONE_MILL / 1200
-            + self.resources.sru as u128 * ONE_MILL / 250;
-        let nu = self.capacity_consumption.nru as u128 * ONE_MILL;
-        ((cu / GIB) as u64, (su / GIB) as u64, (nu / GIB) as u64)
+/// Recompute a period, and explain the rewards of the selected nodes.
+async fn explain(args: ExplainArgs) {
+    if args.filter.is_empty() {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "explain requires at least 1 --node or --farm",
+            )
+            .exit();
     }
+    let profile = args.network.profile();
+    fs::create_dir_all(&args.output).unwrap();
+    let mut log_file = tokio::fs::File::create(args.output.join("explain_log.txt"))
+        .await
+        .unwrap();
 
-    /// Calculate the USD payout of the node in a minting period based on its cloud units. The
-    /// payout is expressed in mUSD, i.e. 1 USD == 1000.
-    ///
-    /// In order for this to be accurate, the data about network and IP usage needs to already have
-    /// been aggregated on the node object.
-    ///
-    /// Payout =
-    ///     CU * CU_REWARD
-    ///     + SU * SU_REWARD
-    ///     + NU used * NU REWARD
-    ///     + IP used * IP REWARD
-    ///
-    /// Additionally, "certified" nodes get 25% extra.
-    ///
-    /// A virtualized node (i.e. zos running in VM) won't get anything.
-    fn node_payout_musd(&self, farming_policies: &BTreeMap<u32, FarmPolicy>) -> u64 {
-        if self.virtualized || self.violation.is_some() {
-            return 0;
-        }
-        let policy = farming_policies.get(&self.farming_policy_id).unwrap();
-        let (cu, su, nu) = self.cloud_units_permill();
-        let cu_reward = cu * policy.cu as u64;
-        let su_reward = su * policy.su as u64;
-        let nu_reward = nu * policy.nu as u64;
-        // Recall that IP usage is actually in seconds. Multiply the seconds of IP usage with the
-        // hourly reward, then divide by 3600 seconds/hour. This prevents issues with low usage.
-        let ip_reward = self.capacity_consumption.ips * policy.ipv4 as u64 / 3600;
-        let base_payout = (cu_reward + su_reward + nu_reward) / ONE_MILL as u64 + ip_reward;
-        // TODO: remove once Titans have policy id 2
-        if matches!(self.certification_type, NodeCertification::Certified)
-            && self.farming_policy_id == 1
+    let outcome = recompute(
+        args.period,
+        &profile,
+        &args.chain,
+        &args.engine,
+        &mut log_file,
+    )
+    .await;
+    for report in &outcome.nodes {
+        if args
+            .filter
+            .matches(Some(report.receipt.node_id), report.receipt.farm_id)
         {
-            base_payout * 5 / 4
-        } else {
-            base_payout
+            println!("{}", Explanation::new(report));
         }
     }
+}
 
-    /// Calculate the TFT payout of the node in the minting period based on its cloud units and
-    /// connection price. The payout is expressed in "units", where 1 TFT == 10_000_000 units.
-    /// This also acconts for measured NU and Ip usage.
-    fn node_payout_tft_units(&self, farming_policies: &BTreeMap<u32, FarmPolicy>) -> u64 {
-        // connection price is in mUSD.
-        self.node_payout_musd(farming_policies) * UNITS_PER_TFT / self.connection_price as u64
+/// Compare 2 sets of receipts, e.g. of 2 runs with different settings. Exits with a nonzero
+/// status code if the sets don't match.
+fn diff(args: DiffArgs) {
+    let mut expected = verify::load_receipts(&args.expected).unwrap();
+    let mut submitted = verify::load_receipts(&args.submitted).unwrap();
+    args.filter.retain(&mut expected);
+    args.filter.retain(&mut submitted);
+
+    let report = verify::compare(expected, submitted);
+    println!("{report}");
+    if !report.is_ok() {
+        std::process::exit(1);
     }
+}
+
+/// Reconcile the backlog before a period is minted, and save it unless it is a dry run.
+async fn reconcile_period(args: ReconcileArgs) {
+    let profile = args.network.profile();
+    let output = args.output.as_path();
+    fs::create_dir_all(output).unwrap();
+    let mut log_file = tokio::fs::File::create(output.join("reconcile_log.txt"))
+        .await
+        .unwrap();
 
-    /// Get the real period for the node given an observed period.
-    fn real_period(&self, observed_period: Period) -> Period {
-        observed_period
+    let backlog = reconcile_backlog(
+        &profile,
+        args.period,
+        args.ledger.as_deref(),
+        args.max_retry_age,
+        output,
+        &mut log_file,
+    )
+    .await;
+    if !args.dry_run {
+        backlog.save(&profile.backlog_file()).unwrap();
     }
+}
 
-    fn receipt(
-        &self,
-        period: Period,
-        farms: &BTreeMap<u32, Farm>,
-        payout_addresses: &BTreeMap<u32, String>,
-        farming_policies: &BTreeMap<u32, FarmPolicy>,
-    ) -> MintingReceipt {
-        let (_, _, uptime) = self.uptime_info.unwrap_or_default();
-        let farm = farms.get(&self.farm_id).unwrap();
-        let (cu, su, nu) = self.cloud_units_permill();
-        let cu = cu as f64 / ONE_MILL as f64;
-        let su = su as f64 / ONE_MILL as f64;
-        let nu = nu as f64 / ONE_MILL as f64;
-        let cru_used = (self.capacity_consumption.cru / period.duration() as u128) as u64;
-        let mru_used = (self.capacity_consumption.mru / period.duration() as u128) as u64;
-        let hru_used = (self.capacity_consumption.hru / period.duration() as u128) as u64;
-        let sru_used = (self.capacity_consumption.sru / period.duration() as u128) as u64;
-        let (musd, tft) = self.scaled_payout(period, farming_policies);
-        let payout_address = match payout_addresses.get(&self.farm_id) {
-            Some(address) => address,
-            None => "",
-        };
-        let policy = farming_policies.get(&self.farming_policy_id).unwrap();
-        MintingReceipt {
-            period: self.real_period(period),
-            node_id: self.id,
-            twin_id: self.twin_id,
-            farm_id: self.farm_id,
-            farm_name: farm.name.clone(),
-            stellar_payout_address: payout_address.to_string(),
-            measured_uptime: uptime,
-            // TODO: revert once fixed on chain (period 54 presumably)
-            // tft_connection_price: self.connection_price as u64,
-            tft_connection_price: 80,
-            cloud_units: CloudUnits { cu, su, nu },
-            resource_units: ResourceUnits {
-                cru: self.resources.cru as f64,
-                mru: self.resources.mru as f64 / GIB as f64,
-                sru: self.resources.sru as f64 / GIB as f64,
-                hru: self.resources.hru as f64 / GIB as f64,
-            },
-            resource_utilization: ResourceUtilization {
-                cru: if self.resources.cru > 0 {
-                    cru_used as f64 * 100. / self.resources.cru as f64
-                } else {
-                    0.
-                },
-                mru: if self.resources.mru > 0 {
-                    mru_used as f64 * 100. / self.resources.mru as f64
-                } else {
-                    0.
-                },
-                sru: if self.resources.sru > 0 {
-                    sru_used as f64 * 100. / self.resources.sru as f64
-                } else {
-                    0.
-                },
-                hru: if self.resources.hru > 0 {
-                    hru_used as f64 * 100. / self.resources.hru as f64
-                } else {
-                    0.
-                },
-                ip: self.capacity_consumption.ips as f64 / 3600.,
-            },
-            reward: Reward { musd, tft },
-            carbon_offset: None, // Set to None for new receipts
-            node_type: match self.certification_type {
-                NodeCertification::Diy => "DIY".into(),
-                NodeCertification::Certified => "CERTIFIED".into(),
-            },
-            farming_policy_id: self.farming_policy_id,
-            resource_rewards: ResourceRewards {
-                cu: policy.cu as u64,
-                su: policy.su as u64,
-                nu: policy.nu as u64,
-                ipv4: policy.ipv4 as u64,
-            },
-        }
+/// Record the chain data of a period in an archive, together with the reconciled backlog, so the
+/// period can be minted offline with `--from-archive`. The reconciled backlog is saved as well,
+/// as the replay continues from it.
+async fn archive(args: ArchiveArgs) {
+    let profile = args.network.profile();
+    let period = profile.period(args.period);
+    let output = args.output.as_path();
+    fs::create_dir_all(output).unwrap();
+    let mut log_file = tokio::fs::File::create(output.join("archive_log.txt"))
+        .await
+        .unwrap();
+
+    let backlog = reconcile_backlog(
+        &profile,
+        args.period,
+        args.ledger.as_deref(),
+        args.max_retry_age,
+        output,
+        &mut log_file,
+    )
+    .await;
+    let (snapshot, import_queue) = imported(
+        load_chain(period, args.chain.nodes(&profile), None).await,
+        args.period,
+    );
+    let settings = args.engine.settings(&profile, args.period);
+    let writer = ArchiveWriter::create(&args.dir, period, &settings, &snapshot, &backlog).unwrap();
+    // The engine only drives the import, the period is not minted.
+    let mut engine = args.engine.engine(&profile, args.period, snapshot);
+    imported(
+        process_blocks(&mut engine, import_queue, Some(writer), &mut log_file).await,
+        args.period,
+    );
+    backlog.save(&profile.backlog_file()).unwrap();
+    println!("Recorded period {} in {}", args.period, args.dir.display());
+}
+
+/// Sign an artifact, e.g. a manifest or payout file, with a guardian key. The detached signature
+/// is written next to the artifact.
+fn sign(args: SignArgs) {
+    let key = GuardianKey::load(&args.keystore).unwrap();
+    let artifact = fs::read(&args.artifact).unwrap();
+    let signature = key.sign(&artifact);
+
+    let mut signature_path = args.artifact.into_os_string();
+    signature_path.push(format!(".{}.sig", signature.public_key));
+    std::fs::write(&signature_path, serde_json::to_vec(&signature).unwrap()).unwrap();
+    println!(
+        "Signed artifact {} as {}",
+        signature.artifact_hash, signature.public_key
+    );
+}
+
+/// Collect the detached guardian signatures of an artifact, and check if enough guardians approved
+/// it. The approval is written next to the artifact. Exits with a nonzero status code if the
+/// threshold is not met.
+fn approve(args: ApproveArgs) {
+    let guardians = GuardianSet::load(&args.guardians).unwrap();
+    let artifact = fs::read(&args.artifact).unwrap();
+    let signatures = args.signatures.iter().map(|path| {
+        let data = fs::read(path).unwrap();
+        serde_json::from_slice::<GuardianSignature>(&data).unwrap()
+    });
+    let approval = guardians.collect(&artifact, signatures);
+
+    for reason in &approval.rejected {
+        println!("Rejected: {reason}");
+    }
+    for name in approval.signatures.keys() {
+        println!("Approved by {name}");
     }
+    println!(
+        "{} of {} required approvals",
+        approval.signatures.len(),
+        approval.threshold
+    );
 
-    /// Get the payout for a node in mUSD and units TFT for a period. This accounts for scaled
-    /// period due to connection time, and SLA.
-    ///
-    /// Payout is linear to node uptime in the period.
-    fn scaled_payout(
-        &self,
-        period: Period,
-        farming_policies: &BTreeMap<u32, FarmPolicy>,
-    ) -> (u64, u64) {
-        if let Some((_, _, uptime)) = self.uptime_info {
-            // Calculate uptime with 0.001% precision by upscaling with factor 1_000.
-            let mut uptime_percentage = uptime * 1_000 / period.duration();
-            // Sanity check
-            if uptime_percentage > 1_000 {
-                uptime_percentage = 1_000;
-            }
+    let mut approval_path = args.artifact.into_os_string();
+    approval_path.push(".approval");
+    std::fs::write(&approval_path, serde_json::to_vec(&approval).unwrap()).unwrap();
+
+    if !approval.threshold_met() {
+        std::process::exit(1);
+    }
+}
+
+/// Track the unpaid receipts of the period before the given period in the backlog, and reconcile
+/// all open receipts with the payments of the TFT issuer. Receipts which are still unpaid after
+/// `max_retry_age` periods are abandoned.
+async fn reconcile_backlog(
+    profile: &NetworkProfile,
+    period_offset: i64,
+    ledger_file: Option<&path::Path>,
+    max_retry_age: i64,
+    output: &path::Path,
+    log_file: &mut tokio::fs::File,
+) -> Backlog {
+    let period = profile.period(period_offset);
+
+    // load previous receipts
+    let previous_period_offset = period_offset - 1;
+    println!("Loading receipts from period {}", previous_period_offset);
+    let previous_receipt_dir = profile.receipt_dir(previous_period_offset.to_string());
+
+    let mut previous_receipts = HashMap::new();
 
-            // Scale payouts for now, remember to divide by the upscale.
-            if self.farming_policy_id == 1
-                || self.farming_policy_id == 2
-                    // Convoluted check because new nodes on testnet seem to get farming policy 3,
-                    // without having to add explicit network selectors.
-                || (self.farming_policy_id == 3
-                    && if let Some(policy) = farming_policies.get(&3) {
-                        !policy.default && !policy.immutable && policy.minimal_uptime == 95
-                    } else {
-                        false
-                    })
-            {
-                // Enforce minimal uptimes now. For now validate this manually
-                // 95% for DIY
-                // 98% for Certified
-                if (matches!(self.certification_type, NodeCertification::Diy)
-                    && uptime_percentage < 950)
-                    || (matches!(self.certification_type, NodeCertification::Certified)
-                        && uptime_percentage < 980)
-                {
-                    return (0, 0);
+    match std::fs::read_dir(&previous_receipt_dir) {
+        Err(_) => println!("Previous receipt dir does not exist, skip loading receipts"),
+        Ok(dir_iter) => {
+            for file in dir_iter {
+                let file = file.unwrap();
+                if !file.file_type().unwrap().is_file() {
+                    continue;
                 }
-                (
-                    self.node_payout_musd(farming_policies) * uptime_percentage / 1_000,
-                    self.node_payout_tft_units(farming_policies) * uptime_percentage / 1_000,
-                )
-            } else {
-                // Not the default policy, enforce the minimal uptime
-                let policy = farming_policies.get(&self.farming_policy_id).unwrap();
-                if uptime_percentage < policy.minimal_uptime as u64 {
-                    (0, 0)
-                } else {
-                    (
-                        self.node_payout_musd(farming_policies),
-                        self.node_payout_tft_units(farming_policies),
-                    )
+                // Receipts of all versions are accepted, as long as they match their hash. Files
+                // which are not a valid receipt are skipped, so they can be looked into without
+                // blocking the minting.
+                match VersionedReceipt::load(&file.path()) {
+                    Ok((hash, receipt)) => {
+                        previous_receipts.insert(hash, receipt);
+                    }
+                    Err(e) => {
+                        let msg = format!(
+                            "Skipping invalid receipt file {}: {e}",
+                            file.path().display()
+                        );
+                        eprintln!("{msg}");
+                        log_file
+                            .write_all(format!("{msg}\n").as_bytes())
+                            .await
+                            .unwrap();
+                    }
                 }
             }
-        } else {
-            (0, 0)
         }
     }
-}
 
-struct Contract {
-    _contract_id: u64,
-    node_id: u32,
-    // timestamp of last report. If not set, time when the contract was created.
-    last_report_ts: i64,
-    ips: u32,
-    // Resources set on chain
-    resources: Resources,
-}
+    log_file
+        .write_all(
+            format!(
+                "Loaded {} receipts from previous period\n",
+                previous_receipts.len()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
 
-#[derive(Default)]
-struct TotalConsumption {
-    // cru mru hru sru and ips is value * time i.e. unit seconds
-    cru: u128,
-    sru: u128,
-    hru: u128,
-    mru: u128,
-    ips: u64,
-    nru: u64,
-}
+    // load previous fixup receipts
+    println!(
+        "Loading fixup receipts from period {}",
+        previous_period_offset
+    );
+    let previous_fixup_receipt_dir = profile
+        .receipt_dir("fixed")
+        .join(previous_period_offset.to_string());
 
-pub async fn get_nodes(
-    client: &dyn RuntimeClient,
-    block: u32,
-) -> Result<Vec<Node>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let node_count = client.node_count(hash).await?;
-    let mut nodes = Vec::new();
-    for i in 1..=node_count {
-        if let Some(node) = client.node(i, hash).await? {
-            nodes.push(node);
+    let mut previous_fixup_receipts = HashMap::new();
+
+    match std::fs::read_dir(&previous_fixup_receipt_dir) {
+        Err(_) => {
+            println!("Previous fixup receipt dir does not exist, skip loading fixup receipts")
+        }
+        Ok(dir_iter) => {
+            for file in dir_iter {
+                let file = file.unwrap();
+                if !file.file_type().unwrap().is_file() {
+                    continue;
+                }
+                let mut hash = [0; 32];
+                hex::decode_to_slice(file.file_name().as_bytes(), &mut hash).unwrap();
+                let mut path = previous_fixup_receipt_dir.clone();
+                path.push(file.file_name());
+                let data = fs::read_to_string(path).unwrap();
+                let receipt: FixupReceipt = serde_json::from_str(&data).unwrap();
+                previous_fixup_receipts.insert(hash, receipt);
+            }
         }
     }
-    Ok(nodes)
-}
 
-pub async fn get_twins(
-    client: &dyn RuntimeClient,
-    block: u32,
-) -> Result<Vec<Twin>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let twin_count = client.twin_count(hash).await?;
-    let mut twins = Vec::new();
-    for i in 1..=twin_count {
-        if let Some(twin) = client.twin(i, hash).await? {
-            twins.push(twin);
+    log_file
+        .write_all(
+            format!(
+                "Loaded {} fixup receipts from previous period\n",
+                previous_fixup_receipts.len()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    let backlog_path = profile.backlog_file();
+    // Before the backlog existed, retries were not tracked at all. Start tracking the retries
+    // of the previous period once, later retries are created from the backlog itself.
+    let bootstrap = !backlog_path.exists();
+    let mut backlog = Backlog::load(&backlog_path).unwrap();
+    for (hash, receipt) in previous_receipts {
+        backlog.track(
+            hash,
+            OutstandingPayment::unpaid(
+                ReceiptKind::Minting,
+                receipt.period(),
+                receipt.farm_id(),
+                receipt.stellar_payout_address().to_string(),
+                receipt.reward(),
+            ),
+        );
+    }
+    for (hash, receipt) in previous_fixup_receipts {
+        backlog.track(
+            hash,
+            OutstandingPayment::unpaid(
+                ReceiptKind::Fixup,
+                receipt.period,
+                receipt.farm_id,
+                receipt.stellar_payout_address,
+                receipt.fixup_reward,
+            ),
+        );
+    }
+    if bootstrap {
+        let retry_receipt_dir = profile
+            .receipt_dir("retries")
+            .join(previous_period_offset.to_string());
+        if let Ok(dir_iter) = std::fs::read_dir(&retry_receipt_dir) {
+            for file in dir_iter {
+                let file = file.unwrap();
+                if !file.file_type().unwrap().is_file() {
+                    continue;
+                }
+                let mut hash = [0; 32];
+                hex::decode_to_slice(file.file_name().as_bytes(), &mut hash).unwrap();
+                let data = fs::read_to_string(file.path()).unwrap();
+                let receipt: RetryPayoutReceipt = serde_json::from_str(&data).unwrap();
+                backlog.track(
+                    hash,
+                    OutstandingPayment::unpaid(
+                        ReceiptKind::Retry,
+                        receipt.failed_payout_period,
+                        receipt.farm_id,
+                        receipt.stellar_payout_address,
+                        receipt.reward,
+                    ),
+                );
+            }
         }
     }
-    Ok(twins)
-}
 
-pub async fn get_farms(
-    client: &dyn RuntimeClient,
-    block: u32,
-) -> Result<Vec<Farm>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let farm_count = client.farm_count(hash).await?;
-    let mut farms = Vec::new();
-    for i in 1..=farm_count {
-        if let Some(farm) = client.farm(i, hash).await? {
-            farms.push(farm);
+    let expected = backlog.expected_payments();
+    if !expected.is_empty() {
+        println!("Reconciling {} open receipts", expected.len());
+        let ledger: Box<dyn PaymentLedger> = match ledger_file {
+            Some(path) => Box::new(FileLedger::load(path).unwrap()),
+            None => Box::new(stellar::Horizon::new(
+                &profile.horizon_url,
+                stellar_issuer(profile),
+            )),
+        };
+        let transactions = ledger.transactions().await.unwrap();
+        // Transactions which are not plain payments of the issuer never count as payout.
+        for tx in transactions.iter().filter(|tx| tx.payments.is_empty()) {
+            log_file
+                .write_all(
+                    format!("Transaction with memo {} has no known payments\n", tx.memo).as_bytes(),
+                )
+                .await
+                .unwrap();
         }
+        let report = reconcile(&transactions, expected);
+        println!("{report}");
+        let mut reconciliation_file =
+            std::fs::File::create(output.join("reconciliation.csv")).unwrap();
+        report.write_csv(&mut reconciliation_file).unwrap();
+        backlog.apply_reconciliation(&report.outstanding());
     }
-    Ok(farms)
+
+    let abandoned = backlog.abandon_older_than(period, profile.period_epoch, max_retry_age);
+    log_file
+        .write_all(
+            format!("Abandoned {abandoned} receipts older than {max_retry_age} periods\n")
+                .as_bytes(),
+        )
+        .await
+        .unwrap();
+    println!(
+        "Done reconciling receipts, {} open receipts",
+        backlog.open()
+    );
+
+    log_file
+        .write_all(
+            format!(
+                "Reconciled backlog, {} receipts not paid yet\n",
+                backlog.open()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    backlog
 }
 
-pub async fn get_payout_addresses(
-    client: &dyn RuntimeClient,
-    farms: &BTreeMap<u32, Farm>,
-    block: u32,
-) -> Result<BTreeMap<u32, String>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let mut addresses = BTreeMap::new();
-    for &id in farms.keys() {
-        match client.farm_payout_address(id, hash).await? {
-            Some(a) => {
-                addresses.insert(id, a);
-            }
-            None => continue,
+/// Recompute a period, from the chain or from an archive.
+async fn recompute(
+    period_offset: i64,
+    profile: &NetworkProfile,
+    chain: &ChainArgs,
+    engine: &EngineArgs,
+    log_file: &mut tokio::fs::File,
+) -> MintingOutcome {
+    let period = profile.period(period_offset);
+    let replay = chain.replay(period, &engine.settings(profile, period_offset));
+    let (snapshot, import_queue) = imported(
+        load_chain(period, chain.nodes(profile), replay).await,
+        period_offset,
+    );
+    let mut engine = engine.engine(profile, period_offset, snapshot);
+    imported(
+        process_blocks(&mut engine, import_queue, None, log_file).await,
+        period_offset,
+    );
+    let outcome = engine.finish();
+    write_log(log_file, engine.take_log()).await;
+    outcome.unwrap_or_else(|e| {
+        eprintln!("Can't recompute period {period_offset}: {e}");
+        std::process::exit(1);
+    })
+}
+
+/// The tokenomics rule set of the network for the period.
+fn rule_set(profile: &NetworkProfile, period_offset: i64) -> RuleSet {
+    let rule_set = profile.rule_set(period_offset).unwrap();
+    println!(
+        "Using tokenomics rule set {} ({})",
+        rule_set.name,
+        hex::encode(rule_set.hash())
+    );
+    rule_set
+}
+
+/// The account which issues TFT on the network, which is needed to pay out and reconcile.
+fn stellar_issuer(profile: &NetworkProfile) -> &str {
+    profile.issuer().unwrap_or_else(|e| {
+        eprintln!("Can't pay out or reconcile: {e}, set one with --stellar-issuer");
+        std::process::exit(1);
+    })
+}
+
+/// Load the chain state at the start of the period, and start importing the blocks of the period,
+/// either from the chain or from an archive.
+async fn load_chain(
+    period: Period,
+    nodes: ChainNodes,
+    replay: Option<Archive>,
+) -> Result<(Snapshot, BlockSource), ClientError> {
+    Ok(match replay {
+        Some(archive) => {
+            println!("Loading chain state from archive");
+            let (header, blocks) = archive.into_parts().unwrap();
+            (
+                header.snapshot,
+                BlockSource::Archive(archive_import(blocks)),
+            )
         }
-    }
-    Ok(addresses)
+        None => {
+            let client = connect(&nodes).await?;
+
+            println!("Finding start block");
+            let start_block = height_at_timestamp(&client, period.start()).await?;
+            println!("Finding end block");
+            let end_block = height_at_timestamp(&client, period.end()).await?;
+
+            println!("Loading chain state");
+            let snapshot = Snapshot::fetch(&client, start_block, end_block).await?;
+
+            println!("Setup block import pipeline");
+            let import_queue =
+                block_import(&nodes, start_block, end_block + POST_PERIOD_BLOCKS).await?;
+            (snapshot, BlockSource::Chain(import_queue))
+        }
+    })
+}
+
+/// The result of importing the chain data of a period. Exits if the import failed.
+fn imported<T, E: fmt::Display>(result: Result<T, E>, period_offset: i64) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Can't import period {period_offset}: {e}");
+        std::process::exit(1);
+    })
 }
 
-pub async fn get_contracts(
-    client: &dyn RuntimeClient,
-    block: u32,
-) -> Result<Vec<(ChainContract, Resources)>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let contract_count = client.contract_count(hash).await?;
-    let mut contracts = Vec::new();
-    for i in 1..=contract_count {
-        if let Some(contract) = client.contract(i, hash).await? {
-            if let Some(contract_resources) = client.contract_resources(i, hash).await? {
-                contracts.push((contract, contract_resources.used));
-            } else {
-                contracts.push((
-                    contract,
-                    Resources {
-                        hru: 0,
-                        sru: 0,
-                        cru: 0,
-                        mru: 0,
-                    },
-                ));
+/// An error while importing the blocks of a period.
+enum ImportError {
+    /// A block could not be imported from the chain.
+    Chain(ClientError),
+    /// A block could not be read from the archive.
+    Archive(io::Error),
+    /// The import ended before the block at the height.
+    Incomplete(u32),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Chain(e) => write!(f, "{e}"),
+            ImportError::Archive(e) => write!(f, "could not read archive: {e}"),
+            ImportError::Incomplete(height) => {
+                write!(f, "import ended before block {height}")
             }
         }
     }
-    Ok(contracts)
 }
 
-pub async fn get_farming_policies(
-    client: &dyn RuntimeClient,
-    block: u32,
-) -> Result<Vec<FarmPolicy>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let policy_count = client.farming_policy_count(hash).await?;
-    let mut policies = Vec::new();
-    for i in 1..=policy_count {
-        if let Some(farm_policy) = client.farming_policy(i, hash).await? {
-            policies.push(farm_policy);
+/// Feed all blocks of the period and the post period to the engine, optionally recording them in
+/// an archive. Returns an error if a block can't be imported.
+async fn process_blocks(
+    engine: &mut MintingEngine,
+    mut import_queue: BlockSource,
+    mut archive: Option<ArchiveWriter>,
+    log_file: &mut tokio::fs::File,
+) -> Result<(), ImportError> {
+    let start_block = engine.start_block();
+    let end_block = engine.end_block();
+    let blocks = end_block - start_block + 1;
+
+    let mut bar = progress_bar(blocks);
+    for height in start_block..=engine.last_block() {
+        // Uptime info from the post period is only used to complete the last uptime report.
+        if height == end_block + 1 {
+            bar.finish();
+            println!("Getting uptime info from post period");
+            bar = progress_bar(POST_PERIOD_BLOCKS);
         }
-    }
-    Ok(policies)
-}
 
-pub async fn get_power_states(
-    client: &dyn RuntimeClient,
-    block: u32,
-) -> Result<Vec<(u32, NodePower)>, Box<dyn std::error::Error>> {
-    let hash = client.hash_at_height(Some(block)).await?;
-    let node_count = client.node_count(hash).await?;
-    let mut power_states = Vec::new();
-    for i in 1..=node_count {
-        if let Some(ps) = client.node_power(i, hash).await? {
-            power_states.push((i, ps));
+        let (block_height, hash, ts, evts) = match import_queue.next().await {
+            Some(block) => block?,
+            None => return Err(ImportError::Incomplete(height)),
+        };
+
+        if let Some(archive) = &mut archive {
+            archive.write_block(block_height, hash, ts, &evts).unwrap();
         }
+
+        log_file
+            .write_all(
+                format!(
+                    "Loaded block {} ({}) containing {} events\n",
+                    block_height,
+                    Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822(),
+                    evts.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        engine.process_block(block_height, ts, evts);
+        write_log(log_file, engine.take_log()).await;
+
+        // finally update progress bar
+        bar.set_message(Utc.timestamp_opt(ts, 0).unwrap().to_rfc2822());
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+    if let Some(archive) = archive {
+        archive.finish().unwrap();
     }
-    Ok(power_states)
+    Ok(())
 }
 
+fn progress_bar(blocks: u32) -> ProgressBar {
+    let bar = ProgressBar::new(blocks as u64);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[Time on chain: {msg}] {wide_bar} {pos:>6}/{len:>6} (ETA: {eta_precise})")
+            .expect("Can set valid template"),
+    );
+    bar
+}
+
+/// Connect to the chain nodes. Requests which fail because of a flaky connection are sent to
+/// another node, or retried, so a single dropped request or stalled node does not abort the
+/// import of a period.
+async fn connect(nodes: &ChainNodes) -> Result<RetryClient<MultiClient>, ClientError> {
+    Ok(RetryClient::new(
+        connect_nodes(nodes).await?,
+        Backoff::default(),
+    ))
+}
+
+async fn connect_nodes(nodes: &ChainNodes) -> Result<MultiClient, ClientError> {
+    Ok(MultiClient::new(&nodes.urls)
+        .await?
+        .with_cross_check(nodes.cross_check))
+}
+
+/// Stream the blocks in the range from the chain, with a separate connection to the chain nodes
+/// for every concurrent fetch.
 async fn block_import(
-    wss_url: &str,
-    start: usize,
-    end: usize,
-) -> mpsc::Receiver<(u32, i64, Vec<RuntimeEvents>)> {
-    let mut t_rec = Vec::with_capacity(RPC_THREADS);
-    for i in 0..RPC_THREADS {
-        let client = DynamicClient::new(wss_url).await.unwrap();
-        let (tx, rx) = mpsc::channel(PRE_FETCH);
-        let mut height = start + i;
-        tokio::task::spawn(async move {
-            loop {
-                let hash = client.hash_at_height(Some(height as u32)).await.unwrap();
-                let evts = client.events(hash).await.unwrap();
-                let ts = client.timestamp(hash).await.unwrap() / 1000;
-                if let Err(e) = tx.send((height as u32, ts as i64, evts)).await {
-                    panic!("{e}");
-                }
-                height += RPC_THREADS;
+    nodes: &ChainNodes,
+    start: u32,
+    end: u32,
+) -> Result<EventStream, ClientError> {
+    let mut clients: Vec<Arc<dyn RuntimeClient + Send + Sync>> = Vec::with_capacity(RPC_THREADS);
+    for _ in 0..RPC_THREADS {
+        clients.push(Arc::new(connect_nodes(nodes).await?));
+    }
+    Ok(EventStream::new(
+        clients,
+        start..=end,
+        StreamOptions {
+            concurrency: RPC_THREADS,
+            prefetch: PRE_FETCH,
+            backoff: Backoff::default(),
+        },
+    ))
+}
 
-                if height > end {
-                    break;
-                }
+/// The blocks of a period, imported from the chain or from an archive.
+enum BlockSource {
+    Chain(EventStream),
+    Archive(mpsc::Receiver<io::Result<BlockEvents>>),
+}
+
+impl BlockSource {
+    /// The next block, or `None` if there are no more blocks. Returns an error if the block
+    /// can't be imported.
+    async fn next(&mut self) -> Option<Result<BlockEvents, ImportError>> {
+        match self {
+            BlockSource::Chain(stream) => {
+                stream.next().await.map(|b| b.map_err(ImportError::Chain))
             }
-        });
-        t_rec.push(rx);
+            BlockSource::Archive(blocks) => {
+                blocks.recv().await.map(|b| b.map_err(ImportError::Archive))
+            }
+        }
     }
+}
 
-    let (tx, rx) = mpsc::channel(5);
-    tokio::task::spawn(async move {
-        let mut i = 0;
-        let l = t_rec.len();
-        loop {
-            if let Some(r) = t_rec[i % l].recv().await {
-                if let Err(e) = tx.send(r).await {
-                    panic!("{e}");
-                }
-            } else {
+/// Write buffered log lines to the log file.
+async fn write_log(log_file: &mut tokio::fs::File, log: Vec<String>) {
+    for line in log {
+        log_file
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+    }
+}
+
+/// Feed the blocks from an archive in the same way as they are imported from the chain. The import
+/// stops at the first block which can't be read.
+fn archive_import(blocks: BlockReader) -> mpsc::Receiver<io::Result<BlockEvents>> {
+    let (tx, rx) = mpsc::channel(PRE_FETCH);
+    tokio::task::spawn_blocking(move || {
+        for block in blocks {
+            let failed = block.is_err();
+            let block = block.map(|b| (b.height, b.hash, b.timestamp, b.events));
+            if tx.blocking_send(block).is_err() || failed {
                 break;
             }
-            i += 1;
         }
     });
-
     rx
 }
@@ -0,0 +1,368 @@
+//! A single commitment to all receipts of a minting period.
+//!
+//! The manifest of a period contains a Merkle root over the hashes of all minting, retry and fixup
+//! receipts of the period. Guardians sign this root, rather than the individual receipts. Every
+//! receipt gets an [`InclusionProof`], which can be used to show that it is part of the approved
+//! set without needing the full list of receipts.
+//!
+//! The tree is built over the sorted and deduplicated receipt hashes. Leaves and inner nodes are
+//! hashed with a different prefix, so an inner node can't be passed off as a receipt:
+//!
+//! - leaf: `blake2b256(0x00 || receipt hash)`
+//! - node: `blake2b256(0x01 || left || right)`
+//!
+//! If a level has an odd amount of nodes, the last node is moved up to the next level as is.
+
+use crate::{
+    period::Period,
+    receipt::{FixupReceipt, MintingReceiptV2, RetryPayoutReceipt},
+};
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use serde::{Deserialize, Serialize};
+
+type Blake2b256 = Blake2b<U32>;
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// A block on chain, identified by its height and hash.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub height: u32,
+    /// Hex encoded hash of the block.
+    pub hash: String,
+}
+
+/// Totals of all receipts in a manifest.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ManifestTotals {
+    pub receipts: u64,
+    pub retries: u64,
+    pub fixups: u64,
+    /// Reward of all minting receipts in milli USD.
+    pub minted_musd: u64,
+    /// Reward of all minting receipts in TFT units.
+    pub minted_tft: u64,
+    /// Reward of all retry receipts in TFT units.
+    pub retried_tft: u64,
+    /// Reward of all fixup receipts in TFT units.
+    pub fixup_tft: u64,
+}
+
+/// The manifest of a minting period.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub period: Period,
+    pub start_block: BlockRef,
+    pub end_block: BlockRef,
+    pub totals: ManifestTotals,
+    /// Hex encoded hashes of all receipts in the period, sorted.
+    pub receipts: Vec<String>,
+    /// Hex encoded Merkle root over all receipts.
+    pub root: String,
+}
+
+impl Manifest {
+    /// Create the manifest for a period from all receipts in the period.
+    pub fn new<'a>(
+        period: Period,
+        start_block: BlockRef,
+        end_block: BlockRef,
+        receipts: impl IntoIterator<Item = &'a MintingReceiptV2>,
+        retries: impl IntoIterator<Item = &'a RetryPayoutReceipt>,
+        fixups: impl IntoIterator<Item = &'a FixupReceipt>,
+    ) -> Self {
+        let mut totals = ManifestTotals::default();
+        let mut hashes = Vec::new();
+        for receipt in receipts {
+            totals.receipts += 1;
+            totals.minted_musd += receipt.reward.musd;
+            totals.minted_tft += receipt.reward.tft;
+            hashes.push(receipt.hash());
+        }
+        for receipt in retries {
+            totals.retries += 1;
+            totals.retried_tft += receipt.reward.tft;
+            hashes.push(receipt.hash());
+        }
+        for receipt in fixups {
+            totals.fixups += 1;
+            totals.fixup_tft += receipt.fixup_reward.tft;
+            hashes.push(receipt.hash());
+        }
+        let tree = MerkleTree::new(hashes);
+
+        Manifest {
+            period,
+            start_block,
+            end_block,
+            totals,
+            receipts: tree.leaves().iter().map(hex::encode).collect(),
+            root: hex::encode(tree.root()),
+        }
+    }
+
+    /// Rebuild the Merkle tree of the manifest.
+    pub fn tree(&self) -> Result<MerkleTree, hex::FromHexError> {
+        let mut hashes = Vec::with_capacity(self.receipts.len());
+        for receipt in &self.receipts {
+            let mut hash = [0; 32];
+            hex::decode_to_slice(receipt, &mut hash)?;
+            hashes.push(hash);
+        }
+        Ok(MerkleTree::new(hashes))
+    }
+}
+
+/// A Merkle tree over a set of receipt hashes.
+pub struct MerkleTree {
+    /// All levels of the tree, starting with the hashed leaves. The last level contains only the
+    /// root.
+    levels: Vec<Vec<[u8; 32]>>,
+    /// The sorted receipt hashes.
+    leaves: Vec<[u8; 32]>,
+}
+
+impl MerkleTree {
+    /// Build a tree over the given receipt hashes. The hashes don't need to be sorted, and
+    /// duplicates are removed.
+    pub fn new(mut hashes: Vec<[u8; 32]>) -> Self {
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        let mut levels = vec![hashes.iter().map(hash_leaf).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_node(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        MerkleTree {
+            levels,
+            leaves: hashes,
+        }
+    }
+
+    /// The sorted receipt hashes in the tree.
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.leaves
+    }
+
+    /// The root of the tree. The root of an empty tree is all zeroes.
+    pub fn root(&self) -> [u8; 32] {
+        self.levels
+            .last()
+            .unwrap()
+            .first()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the inclusion proof for a receipt hash, if the receipt is part of the tree.
+    pub fn proof(&self, receipt: &[u8; 32]) -> Option<InclusionProof> {
+        let mut index = self.leaves.binary_search(receipt).ok()?;
+        let mut path = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if index % 2 == 1 {
+                path.push(ProofStep {
+                    side: Side::Left,
+                    hash: hex::encode(level[index - 1]),
+                });
+            } else if index + 1 < level.len() {
+                path.push(ProofStep {
+                    side: Side::Right,
+                    hash: hex::encode(level[index + 1]),
+                });
+            }
+            index /= 2;
+        }
+        Some(InclusionProof {
+            receipt: hex::encode(receipt),
+            path,
+        })
+    }
+}
+
+/// The side of a sibling in a proof step.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A sibling on the path from a leaf to the root.
+#[derive(Serialize, Deserialize)]
+pub struct ProofStep {
+    pub side: Side,
+    /// Hex encoded hash of the sibling.
+    pub hash: String,
+}
+
+/// Proof that a receipt is included in the manifest of a period.
+#[derive(Serialize, Deserialize)]
+pub struct InclusionProof {
+    /// Hex encoded hash of the receipt.
+    pub receipt: String,
+    /// Siblings from the leaf up to the root.
+    pub path: Vec<ProofStep>,
+}
+
+impl InclusionProof {
+    /// Check if the proof leads to the given (hex encoded) Merkle root.
+    pub fn verify(&self, root: &str) -> bool {
+        let mut current = [0; 32];
+        if hex::decode_to_slice(&self.receipt, &mut current).is_err() {
+            return false;
+        }
+        current = hash_leaf(&current);
+        for step in &self.path {
+            let mut sibling = [0; 32];
+            if hex::decode_to_slice(&step.hash, &mut sibling).is_err() {
+                return false;
+            }
+            current = match step.side {
+                Side::Left => hash_node(&sibling, &current),
+                Side::Right => hash_node(&current, &sibling),
+            };
+        }
+        hex::encode(current) == root
+    }
+}
+
+fn hash_leaf(hash: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Blake2b256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(hash);
+    hasher.finalize().into()
+}
+
+fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Blake2b256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(count: u8) -> Vec<[u8; 32]> {
+        // Reversed, so the tree has to sort them.
+        (0..count).rev().map(|i| [i; 32]).collect()
+    }
+
+    /// Check that every leaf of a tree over `count` hashes has a proof which leads to the root.
+    fn assert_round_trip(count: u8) {
+        let tree = MerkleTree::new(hashes(count));
+        let root = hex::encode(tree.root());
+        assert_eq!(tree.leaves().len(), count as usize);
+        for leaf in tree.leaves() {
+            let proof = tree.proof(leaf).unwrap();
+            assert_eq!(proof.receipt, hex::encode(leaf));
+            assert!(proof.verify(&root), "proof of leaf {leaf:?} of {count}");
+        }
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree = MerkleTree::new(vec![]);
+        assert_eq!(tree.root(), [0; 32]);
+        assert!(tree.leaves().is_empty());
+        assert!(tree.proof(&[0; 32]).is_none());
+    }
+
+    #[test]
+    fn single_leaf() {
+        let tree = MerkleTree::new(hashes(1));
+        assert_eq!(tree.root(), hash_leaf(&[0; 32]));
+        let proof = tree.proof(&[0; 32]).unwrap();
+        assert!(proof.path.is_empty());
+        assert!(proof.verify(&hex::encode(tree.root())));
+    }
+
+    #[test]
+    fn two_leaves() {
+        let tree = MerkleTree::new(hashes(2));
+        assert_eq!(
+            tree.root(),
+            hash_node(&hash_leaf(&[0; 32]), &hash_leaf(&[1; 32]))
+        );
+        assert_round_trip(2);
+    }
+
+    #[test]
+    fn odd_leaf_is_moved_up() {
+        let tree = MerkleTree::new(hashes(3));
+        let left = hash_node(&hash_leaf(&[0; 32]), &hash_leaf(&[1; 32]));
+        assert_eq!(tree.root(), hash_node(&left, &hash_leaf(&[2; 32])));
+        // The last leaf has no sibling on the first level.
+        assert_eq!(tree.proof(&[2; 32]).unwrap().path.len(), 1);
+        assert_round_trip(3);
+    }
+
+    #[test]
+    fn round_trip() {
+        for count in [4, 5, 7, 8, 9, 16, 33] {
+            assert_round_trip(count);
+        }
+    }
+
+    #[test]
+    fn duplicates_are_removed() {
+        let mut duplicated = hashes(5);
+        duplicated.extend(hashes(5));
+        let tree = MerkleTree::new(duplicated);
+        assert_eq!(tree.leaves(), MerkleTree::new(hashes(5)).leaves());
+        assert_eq!(tree.root(), MerkleTree::new(hashes(5)).root());
+    }
+
+    #[test]
+    fn unknown_receipt_has_no_proof() {
+        let tree = MerkleTree::new(hashes(4));
+        assert!(tree.proof(&[9; 32]).is_none());
+    }
+
+    #[test]
+    fn tampered_proof_is_rejected() {
+        let tree = MerkleTree::new(hashes(6));
+        let root = hex::encode(tree.root());
+
+        let mut proof = tree.proof(&[2; 32]).unwrap();
+        proof.path[1].hash = hex::encode([0xff; 32]);
+        assert!(!proof.verify(&root));
+
+        let mut proof = tree.proof(&[2; 32]).unwrap();
+        proof.path[0].side = match proof.path[0].side {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        };
+        assert!(!proof.verify(&root));
+
+        let mut proof = tree.proof(&[2; 32]).unwrap();
+        proof.receipt = hex::encode([3; 32]);
+        assert!(!proof.verify(&root));
+
+        let mut proof = tree.proof(&[2; 32]).unwrap();
+        proof.path[0].hash = "not hex".to_string();
+        assert!(!proof.verify(&root));
+
+        // An inner node can't be passed off as a receipt.
+        let inner = hash_node(&hash_leaf(&[0; 32]), &hash_leaf(&[1; 32]));
+        let proof = InclusionProof {
+            receipt: hex::encode(inner),
+            path: tree.proof(&[0; 32]).unwrap().path.split_off(1),
+        };
+        assert!(!proof.verify(&root));
+    }
+}
@@ -0,0 +1,249 @@
+//! Profiles of the networks which can be minted.
+//!
+//! Every grid network has its own chain, its own TFT issuer on Stellar, and keeps its receipts
+//! apart from the other networks. A [`NetworkProfile`] bundles these settings, so the same binary
+//! can mint mainnet and rehearse on testnet, qanet or devnet. Profiles are selected by name.
+
+use crate::{
+    period::{Period, FIRST_PERIOD_START_TIMESTAMP},
+    rules::{RuleSet, RulesError, TokenomicsRules},
+    stellar::TFT_ISSUER,
+    transaction::PUBLIC_NETWORK_PASSPHRASE,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Names of the networks with a builtin profile.
+pub const NETWORKS: [&str; 4] = ["mainnet", "testnet", "qanet", "devnet"];
+
+/// Passphrase of the Stellar test network.
+const TEST_NETWORK_PASSPHRASE: &str = "Test SDF Network ; September 2015";
+/// Account which issues TFT on the Stellar test network for testnet.
+const TESTNET_TFT_ISSUER: &str = "GA47YZA3PKFUZMPLQ3B5F2E3CJIB57TGGU7SPCQT2WAEYKN766PWIMB3";
+const PUBLIC_HORIZON_URL: &str = "https://horizon.stellar.org";
+const TEST_HORIZON_URL: &str = "https://horizon-testnet.stellar.org";
+
+/// The builtin settings of a network.
+struct Builtin {
+    name: &'static str,
+    chain_url: &'static str,
+    /// Whether the network pays out on the Stellar test network.
+    test: bool,
+    /// Account which issues TFT for the network, if it has one of its own.
+    stellar_issuer: Option<&'static str>,
+    period_epoch: i64,
+}
+
+/// The settings of the networks with a builtin profile. The test networks follow the period
+/// schedule of mainnet. Qanet and devnet don't have a TFT issuer of their own, so they can only
+/// pay out with an explicitly configured issuer, and never with the one of testnet.
+const BUILTINS: [Builtin; 4] = [
+    Builtin {
+        name: "mainnet",
+        chain_url: "wss://tfchain.grid.tf:443",
+        test: false,
+        stellar_issuer: Some(TFT_ISSUER),
+        period_epoch: FIRST_PERIOD_START_TIMESTAMP,
+    },
+    Builtin {
+        name: "testnet",
+        chain_url: "wss://tfchain.test.grid.tf:443",
+        test: true,
+        stellar_issuer: Some(TESTNET_TFT_ISSUER),
+        period_epoch: FIRST_PERIOD_START_TIMESTAMP,
+    },
+    Builtin {
+        name: "qanet",
+        chain_url: "wss://tfchain.qa.grid.tf:443",
+        test: true,
+        stellar_issuer: None,
+        period_epoch: FIRST_PERIOD_START_TIMESTAMP,
+    },
+    Builtin {
+        name: "devnet",
+        chain_url: "wss://tfchain.dev.grid.tf:443",
+        test: true,
+        stellar_issuer: None,
+        period_epoch: FIRST_PERIOD_START_TIMESTAMP,
+    },
+];
+/// File with the receipts which are not fully paid yet, across all periods, in the receipt root.
+const BACKLOG_FILE: &str = "outstanding.json";
+
+/// The settings to mint a network.
+#[derive(Debug, Clone)]
+pub struct NetworkProfile {
+    /// Name of the network, which also selects the tokenomics rule sets of the network.
+    pub name: String,
+    /// Websocket urls of the chain nodes to import the chain data from.
+    pub chain_urls: Vec<String>,
+    /// Account which issues TFT, and which pays out the minting. Networks without an issuer of
+    /// their own can't be paid out or reconciled until one is configured.
+    pub stellar_issuer: Option<String>,
+    /// Address of the horizon server to find previous payments.
+    pub horizon_url: String,
+    /// Passphrase of the Stellar network the payouts are made on.
+    pub stellar_passphrase: String,
+    /// Timestamp of the start of the first period.
+    pub period_epoch: i64,
+    /// Directory with the receipts, manifests and backlog of the network.
+    pub receipt_root: PathBuf,
+    /// Rules file with the tokenomics rules of the network. If not set, the rules shipped with the
+    /// binary are used.
+    pub rules: Option<PathBuf>,
+}
+
+impl NetworkProfile {
+    /// The builtin profile of the network with the given name.
+    pub fn by_name(name: &str) -> Result<Self, UnknownNetwork> {
+        let Some(builtin) = BUILTINS.iter().find(|b| b.name == name) else {
+            return Err(UnknownNetwork(name.to_string()));
+        };
+        let (horizon_url, stellar_passphrase) = if builtin.test {
+            (TEST_HORIZON_URL, TEST_NETWORK_PASSPHRASE)
+        } else {
+            (PUBLIC_HORIZON_URL, PUBLIC_NETWORK_PASSPHRASE)
+        };
+        // Mainnet keeps its receipts in the root, as it did before there were profiles.
+        let receipt_root = if builtin.test {
+            Path::new("receipts").join(name)
+        } else {
+            PathBuf::from("receipts")
+        };
+        Ok(NetworkProfile {
+            name: name.to_string(),
+            chain_urls: vec![builtin.chain_url.to_string()],
+            stellar_issuer: builtin.stellar_issuer.map(str::to_string),
+            horizon_url: horizon_url.to_string(),
+            stellar_passphrase: stellar_passphrase.to_string(),
+            period_epoch: builtin.period_epoch,
+            receipt_root,
+            rules: None,
+        })
+    }
+
+    /// The account which issues TFT on the network.
+    pub fn issuer(&self) -> Result<&str, MissingIssuer> {
+        self.stellar_issuer
+            .as_deref()
+            .ok_or_else(|| MissingIssuer(self.name.clone()))
+    }
+
+    /// The period with the given offset on the network.
+    pub fn period(&self, offset: i64) -> Period {
+        Period::at_offset_from(self.period_epoch, offset)
+    }
+
+    /// The receipt directory with the given path in the receipt root.
+    pub fn receipt_dir(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.receipt_root.join(path)
+    }
+
+    /// The file with the outstanding payments of the network.
+    pub fn backlog_file(&self) -> PathBuf {
+        self.receipt_root.join(BACKLOG_FILE)
+    }
+
+    /// The tokenomics rule set of the network for the period with the given offset.
+    pub fn rule_set(&self, period: i64) -> Result<RuleSet, RulesError> {
+        let rules = match &self.rules {
+            Some(path) => TokenomicsRules::load(path)?,
+            None => TokenomicsRules::builtin(),
+        };
+        rules.select(&self.name, period)
+    }
+}
+
+/// A network without a profile.
+#[derive(Debug)]
+pub struct UnknownNetwork(pub String);
+
+impl fmt::Display for UnknownNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown network {}, expected one of {}",
+            self.0,
+            NETWORKS.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownNetwork {}
+
+/// A network without a TFT issuer.
+#[derive(Debug)]
+pub struct MissingIssuer(pub String);
+
+impl fmt::Display for MissingIssuer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "network {} has no TFT issuer", self.0)
+    }
+}
+
+impl std::error::Error for MissingIssuer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfchain_client::types::{FarmCertification, FarmPolicy, NodeCertification};
+
+    #[test]
+    fn networks_have_their_own_settings() {
+        let mainnet = NetworkProfile::by_name("mainnet").unwrap();
+        assert_eq!(mainnet.issuer().unwrap(), TFT_ISSUER);
+        assert_eq!(mainnet.stellar_passphrase, PUBLIC_NETWORK_PASSPHRASE);
+        assert_eq!(mainnet.period_epoch, FIRST_PERIOD_START_TIMESTAMP);
+        assert_eq!(mainnet.receipt_root, PathBuf::from("receipts"));
+
+        let testnet = NetworkProfile::by_name("testnet").unwrap();
+        assert_eq!(testnet.issuer().unwrap(), TESTNET_TFT_ISSUER);
+        assert_eq!(testnet.stellar_passphrase, TEST_NETWORK_PASSPHRASE);
+        assert_eq!(testnet.receipt_root, Path::new("receipts").join("testnet"));
+
+        for name in ["qanet", "devnet"] {
+            let profile = NetworkProfile::by_name(name).unwrap();
+            assert!(profile.issuer().is_err());
+            assert_eq!(profile.receipt_root, Path::new("receipts").join(name));
+        }
+        assert!(NetworkProfile::by_name("localnet").is_err());
+    }
+
+    #[test]
+    fn every_network_has_a_profile_and_rules() {
+        for name in NETWORKS {
+            let profile = NetworkProfile::by_name(name).unwrap();
+            assert_eq!(profile.name, name);
+            assert!(profile.rule_set(0).is_ok());
+        }
+    }
+
+    #[test]
+    fn policy_3_is_only_uptime_scaled_on_testnet() {
+        let policy = FarmPolicy {
+            version: 1,
+            id: 3,
+            name: "policy 3".to_string(),
+            cu: 0,
+            su: 0,
+            nu: 0,
+            ipv4: 0,
+            minimal_uptime: 95,
+            policy_created: 0,
+            policy_end: 0,
+            immutable: false,
+            default: false,
+            node_certification: NodeCertification::Diy,
+            farm_certification: FarmCertification::NotCertified,
+        };
+        for name in NETWORKS {
+            let rules = NetworkProfile::by_name(name)
+                .unwrap()
+                .rule_set(100)
+                .unwrap();
+            assert_eq!(rules.uptime_scaled(&policy), name == "testnet", "{name}");
+        }
+    }
+}
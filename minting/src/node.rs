@@ -0,0 +1,486 @@
+//! Nodes as tracked during the minting of a period, and the reward calculations for them.
+
+use crate::{
+    capacity::CapacityTimeline,
+    limits::PolicyLimit,
+    period::Period,
+    policy::{PolicySegment, PolicyTimeline},
+    pricing::PriceSource,
+    receipt::{
+        CloudUnitsV2, MintingReceiptV2, PolicySegmentReward, ResourceRewards, ResourceUnitsV2,
+        ResourceUtilizationV2, Reward, RECEIPT_V2, UNITS_PER_TFT,
+    },
+    rules::RuleSet,
+    uptime::UptimeTracker,
+};
+use std::collections::BTreeMap;
+use tfchain_client::types::{Farm, FarmPolicy, Location, Node, NodeCertification, Resources};
+
+const GIB: u128 = 1024 * 1024 * 1024;
+const ONE_MILL: u128 = 1_000_000;
+
+pub(crate) struct MintingNode {
+    pub(crate) id: u32,
+    pub(crate) farm_id: u32,
+    pub(crate) twin_id: u32,
+    pub(crate) capacity: CapacityTimeline,
+    pub(crate) location: Location,
+    pub(crate) country: String,
+    pub(crate) city: String,
+    pub(crate) _created: u64,
+    // Farming policy and certification of the node over the period.
+    pub(crate) policies: PolicyTimeline,
+    pub(crate) uptime: UptimeTracker,
+    // TFT price expressed in USD at time of connection. Price is expressed in mUSD (3 digits
+    // precision). I.e. 1 USD => 1000.
+    pub(crate) connection_price: u32,
+    pub(crate) price_source: PriceSource,
+    // capacity consumed by workloads over a period.
+    pub(crate) capacity_consumption: TotalConsumption,
+    pub(crate) virtualized: bool,
+    // Limit of the farm which rolled the node back to the default farming policy, if any.
+    pub(crate) policy_limit: Option<PolicyLimit>,
+    // Timestamp at which the node was deleted, if it was deleted in the period.
+    pub(crate) deleted: Option<i64>,
+}
+
+impl MintingNode {
+    /// Create a new minting node from a node on chain, with its resources in effect from the given
+    /// timestamp, as is the given farming policy of the node. Uptime of the node is tracked by the
+    /// given tracker. If the node has no connection price, the given price of the network is used.
+    pub(crate) fn new(
+        node: Node,
+        since: i64,
+        policy: FarmPolicy,
+        network_price: u32,
+        uptime: UptimeTracker,
+    ) -> Self {
+        let (connection_price, price_source) = if node.connection_price != 0 {
+            (node.connection_price, PriceSource::Node)
+        } else {
+            (network_price, PriceSource::Network)
+        };
+        MintingNode {
+            id: node.id,
+            farm_id: node.farm_id,
+            twin_id: node.twin_id,
+            capacity: CapacityTimeline::new(since, node.resources),
+            location: node.location,
+            country: node.country,
+            city: node.city,
+            _created: node.created,
+            policies: PolicyTimeline::new(since, policy, node.certification),
+            uptime,
+            connection_price,
+            price_source,
+            capacity_consumption: TotalConsumption::default(),
+            virtualized: node.virtualized,
+            policy_limit: None,
+            deleted: None,
+        }
+    }
+
+    /// Whether the node can earn a reward in the period, i.e. it was online, and it has no
+    /// violation.
+    pub(crate) fn earns_reward(&self) -> bool {
+        !self.virtualized
+            && self.uptime.violation().is_none()
+            && self
+                .uptime
+                .measured_uptime()
+                .is_some_and(|uptime| uptime > 0)
+    }
+
+    /// Compute the CU, SU and NU for the node. The result is expressed in a "permill" way. So the
+    /// actual CU, SU and NU are obtained by dividing the results by 1_000_000.
+    ///
+    /// In order for this to be accurate, the data about network and IP usage needs to already have
+    /// been aggregated on the node object.
+    ///
+    /// If the capacity of the node changed during the period, CU and SU are the time weighted
+    /// average of the CU and SU of every capacity segment, up to the deletion of the node.
+    ///
+    /// Calculation taken from [the
+    /// wiki](https://library.threefold.me/info/threefold#/tfgrid/farming/threefold__resource_units_calc_cloudunits)
+    /// on 31-01-2022 as follows:
+    ///   CU: MIN(cru * 4 / 2, (mru - 1) / 4, sru / 50)
+    ///   SU: hru / 1200 + sru * 0.8 / 200
+    ///   NU: gigabytes of public traffic reported
+    pub(crate) fn cloud_units_permill(&self, period: Period) -> (u64, u64, u64) {
+        let (cu, su) = self.cloud_units_between(period.start(), period.end());
+        let nu = self.capacity_consumption.nru as u128 * ONE_MILL;
+        (cu, su, (nu / GIB) as u64)
+    }
+
+    /// Compute the CU and SU for the node between 2 timestamps, as the time weighted average
+    /// over the capacity segments. The result is expressed in a "permill" way, like
+    /// [`Self::cloud_units_permill`].
+    fn cloud_units_between(&self, start: i64, end: i64) -> (u64, u64) {
+        // The capacity a node had before it was deleted is what it was rewarded for.
+        let end = self.deleted.map_or(end, |deleted| end.min(deleted));
+        let cu = self
+            .capacity
+            .time_weighted_between(start, end, cu_permill_bytes);
+        let su = self
+            .capacity
+            .time_weighted_between(start, end, su_permill_bytes);
+        ((cu / GIB) as u64, (su / GIB) as u64)
+    }
+
+    /// Calculate the USD payout of the node for a full minting period at the rates of a policy
+    /// segment, based on the given cloud units. The payout is expressed in mUSD, i.e. 1 USD ==
+    /// 1000.
+    ///
+    /// In order for this to be accurate, the data about network and IP usage needs to already have
+    /// been aggregated on the node object.
+    ///
+    /// Payout =
+    ///     CU * CU_REWARD
+    ///     + SU * SU_REWARD
+    ///     + NU used * NU REWARD
+    ///     + IP used * IP REWARD
+    ///
+    /// Additionally, "certified" nodes can get a bonus on some policies, as set in the rules.
+    ///
+    /// A virtualized node (i.e. zos running in VM) won't get anything.
+    fn node_payout_musd(
+        &self,
+        segment: &PolicySegment,
+        (cu, su, nu): (u64, u64, u64),
+        rules: &RuleSet,
+    ) -> u64 {
+        if self.virtualized || self.uptime.violation().is_some() {
+            return 0;
+        }
+        let policy = &segment.policy;
+        let cu_reward = cu * policy.cu as u64;
+        let su_reward = su * policy.su as u64;
+        let nu_reward = nu * policy.nu as u64;
+        // Recall that IP usage is actually in seconds. Multiply the seconds of IP usage with the
+        // hourly reward, then divide by 3600 seconds/hour. This prevents issues with low usage.
+        let ip_reward = self.capacity_consumption.ips * policy.ipv4 as u64 / 3600;
+        let base_payout = (cu_reward + su_reward + nu_reward) / ONE_MILL as u64 + ip_reward;
+        rules.apply_certified_bonus(policy.id, &segment.certification, base_payout)
+    }
+
+    /// Calculate the TFT payout of the node for a full minting period at the rates of a policy
+    /// segment, based on the given cloud units and the connection price. The payout is expressed
+    /// in "units", where 1 TFT == 10_000_000 units. This also acconts for measured NU and Ip
+    /// usage.
+    fn node_payout_tft_units(
+        &self,
+        segment: &PolicySegment,
+        units: (u64, u64, u64),
+        rules: &RuleSet,
+    ) -> u64 {
+        // connection price is in mUSD.
+        self.node_payout_musd(segment, units, rules) * UNITS_PER_TFT / self.connection_price as u64
+    }
+
+    /// The amount of seconds the node existed in the period. Nodes deleted in the period are only
+    /// expected to be up until they are deleted.
+    fn active_duration(&self, period: Period) -> u64 {
+        match self.deleted {
+            Some(ts) => (ts - period.start()).clamp(1, period.duration() as i64) as u64,
+            None => period.duration(),
+        }
+    }
+
+    /// Get the real period for the node given an observed period.
+    fn real_period(&self, observed_period: Period) -> Period {
+        observed_period
+    }
+
+    pub(crate) fn receipt(
+        &self,
+        period: Period,
+        farms: &BTreeMap<u32, Farm>,
+        payout_addresses: &BTreeMap<u32, String>,
+        rules: &RuleSet,
+    ) -> MintingReceiptV2 {
+        let uptime = self.uptime.measured_uptime().unwrap_or_default();
+        // A node without farm has a violation, so there is no reward to pay anyway, unless the node
+        // was deleted before its farm. Its receipt then has no farm name and payout address.
+        let farm_name = farms
+            .get(&self.farm_id)
+            .map_or_else(String::new, |farm| farm.name.clone());
+        let (cu, su, nu) = self.cloud_units_permill(period);
+        let resources = match self.deleted {
+            Some(deleted) => self
+                .capacity
+                .average_between(period.start(), deleted.min(period.end())),
+            None => self.capacity.average(period),
+        };
+        let segments = self.segment_rewards(period, rules);
+        let reward = Reward {
+            musd: segments.iter().map(|s| s.reward.musd).sum(),
+            tft: segments.iter().map(|s| s.reward.tft).sum(),
+        };
+        let payout_address = match payout_addresses.get(&self.farm_id) {
+            Some(address) => address,
+            None => "",
+        };
+        // The policy of a deleted node is the one it had when it was deleted.
+        let windows = self.active_windows(period);
+        let current = windows
+            .last()
+            .map_or_else(|| self.policies.current(), |(_, _, segment)| *segment);
+        MintingReceiptV2 {
+            version: RECEIPT_V2,
+            period: self.real_period(period),
+            node_id: self.id,
+            twin_id: self.twin_id,
+            farm_id: self.farm_id,
+            farm_name,
+            stellar_payout_address: payout_address.to_string(),
+            measured_uptime: uptime,
+            tft_connection_price: self.connection_price as u64,
+            cloud_units: CloudUnitsV2 { cu, su, nu },
+            resource_units: ResourceUnitsV2 {
+                cru: resources.cru,
+                mru: resources.mru,
+                sru: resources.sru,
+                hru: resources.hru,
+            },
+            resource_utilization: ResourceUtilizationV2 {
+                cru: utilization(self.capacity_consumption.cru, resources.cru, period),
+                mru: utilization(self.capacity_consumption.mru, resources.mru, period),
+                sru: utilization(self.capacity_consumption.sru, resources.sru, period),
+                hru: utilization(self.capacity_consumption.hru, resources.hru, period),
+                ip: self.capacity_consumption.ips,
+            },
+            reward,
+            carbon_offset: None, // Set to None for new receipts
+            node_type: node_type(&current.certification),
+            farming_policy_id: current.policy.id,
+            resource_rewards: resource_rewards(&current.policy),
+            farming_policy_limit: self.policy_limit,
+            // A node which kept the same policy and certification for the whole period has no
+            // breakdown, the receipt itself holds the only segment.
+            policy_segments: if segments.len() > 1 {
+                segments
+            } else {
+                Vec::new()
+            },
+            rule_set: Some(rules.reference()),
+        }
+    }
+
+    /// The policy windows of the period, clipped to the part of the period the node existed.
+    fn active_windows(&self, period: Period) -> Vec<(i64, i64, &PolicySegment)> {
+        let active_end = period.start() + self.active_duration(period) as i64;
+        self.policies
+            .windows(period)
+            .into_iter()
+            .filter_map(|(start, end, segment)| {
+                let end = end.min(active_end);
+                (end > start).then_some((start, end, segment))
+            })
+            .collect()
+    }
+
+    /// The reward of the node for every policy segment in the part of the period the node existed,
+    /// so a node deleted in the period is only rewarded at the policies in effect before it was
+    /// deleted. CU and SU are the averages over the segment, NU and IP usage are attributed to the
+    /// segments in proportion to their duration.
+    fn segment_rewards(&self, period: Period, rules: &RuleSet) -> Vec<PolicySegmentReward> {
+        let nu = self.cloud_units_permill(period).2;
+        let active = self.active_duration(period);
+        self.active_windows(period)
+            .into_iter()
+            .map(|(start, end, segment)| {
+                let (cu, su) = self.cloud_units_between(start, end);
+                let duration = (end - start) as u64;
+                let (musd, tft) = self.scaled_payout(period, segment, (cu, su, nu), rules);
+                PolicySegmentReward {
+                    start,
+                    end,
+                    farming_policy_id: segment.policy.id,
+                    node_type: node_type(&segment.certification),
+                    cloud_units: CloudUnitsV2 {
+                        cu,
+                        su,
+                        nu: nu * duration / active,
+                    },
+                    resource_rewards: resource_rewards(&segment.policy),
+                    reward: Reward {
+                        musd: musd * duration / active,
+                        tft: tft * duration / active,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Get the payout for a node in mUSD and units TFT for a period, at the rates and minimal
+    /// uptime of a policy segment. This accounts for scaled period due to connection time, and
+    /// SLA. The payout is for the full period, it still needs to be scaled to the part of the
+    /// period covered by the segment.
+    ///
+    /// Payout is linear to node uptime in the period. The minimal uptime is checked against the
+    /// time the node existed in the period, so a node deleted in the period still gets the
+    /// uptime it earned before it was deleted. As uptime is measured over the whole period, the
+    /// minimal uptime of every segment is checked against the uptime in the whole period.
+    fn scaled_payout(
+        &self,
+        period: Period,
+        segment: &PolicySegment,
+        units: (u64, u64, u64),
+        rules: &RuleSet,
+    ) -> (u64, u64) {
+        if let Some(uptime) = self.uptime.measured_uptime() {
+            // Calculate uptime with 0.001% precision by upscaling with factor 1_000.
+            let mut uptime_percentage = uptime * 1_000 / period.duration();
+            // Sanity check
+            if uptime_percentage > 1_000 {
+                uptime_percentage = 1_000;
+            }
+            let sla_percentage =
+                std::cmp::min(uptime * 1_000 / self.active_duration(period), 1_000);
+
+            let policy = &segment.policy;
+            // Scale payouts for now, remember to divide by the upscale.
+            if rules.uptime_scaled(policy) {
+                // Enforce the minimal SLA of the certification.
+                if sla_percentage < rules.minimal_sla(&segment.certification) {
+                    return (0, 0);
+                }
+                (
+                    self.node_payout_musd(segment, units, rules) * uptime_percentage / 1_000,
+                    self.node_payout_tft_units(segment, units, rules) * uptime_percentage / 1_000,
+                )
+            } else {
+                // Not an uptime scaled policy, enforce the minimal uptime
+                if sla_percentage < policy.minimal_uptime as u64 {
+                    (0, 0)
+                } else {
+                    // Only pay for the part of the period the node existed.
+                    let active = self.active_duration(period);
+                    (
+                        self.node_payout_musd(segment, units, rules) * active / period.duration(),
+                        self.node_payout_tft_units(segment, units, rules) * active
+                            / period.duration(),
+                    )
+                }
+            }
+        } else {
+            (0, 0)
+        }
+    }
+}
+
+/// The node type of a certification, as used in receipts.
+fn node_type(certification: &NodeCertification) -> String {
+    match certification {
+        NodeCertification::Diy => "DIY".into(),
+        NodeCertification::Certified => "CERTIFIED".into(),
+    }
+}
+
+/// The rewards of a farming policy, as used in receipts.
+fn resource_rewards(policy: &FarmPolicy) -> ResourceRewards {
+    ResourceRewards {
+        cu: policy.cu as u64,
+        su: policy.su as u64,
+        nu: policy.nu as u64,
+        ipv4: policy.ipv4 as u64,
+    }
+}
+
+/// CU of a set of resources in millionths, multiplied by the size of a GiB.
+fn cu_permill_bytes(resources: &Resources) -> u128 {
+    // Mru and sru are in bytes, but are expressed in GB in the formula. Rather than dividing
+    // first, we multiply cru first, then take the MIN, and finally divide. This eliminates the
+    // issue of rounding errors _BEFORE_ the MIN. Also multiply by 1000000 so we have precision
+    // without working with floats.
+    //
+    // MIN is associative.
+    #[allow(clippy::identity_op)] // Allow explicit multiplication by 1 to make it clear we use
+    // 1 GiB here.
+    let cu_intermediate = std::cmp::min(
+        resources.cru as u128 * 2 * GIB * ONE_MILL,
+        (resources.mru as u128 - 1 * GIB) * ONE_MILL / 4,
+    );
+    std::cmp::min(cu_intermediate, resources.sru as u128 * ONE_MILL / 50)
+}
+
+/// SU of a set of resources in millionths, multiplied by the size of a GiB.
+fn su_permill_bytes(resources: &Resources) -> u128 {
+    resources.hru as u128 * ONE_MILL / 1200 + resources.sru as u128 * ONE_MILL / 250
+}
+
+/// Average utilization of a resource over a period, in millionths of the capacity. The consumption
+/// is the amount of the resource used multiplied by the time it was used.
+fn utilization(consumption: u128, capacity: u64, period: Period) -> u64 {
+    if capacity == 0 {
+        return 0;
+    }
+    let used = consumption / period.duration() as u128;
+    (used * ONE_MILL / capacity as u128) as u64
+}
+
+pub(crate) struct Contract {
+    pub(crate) contract_id: u64,
+    pub(crate) node_id: u32,
+    // timestamp of last report. If not set, time when the contract was created.
+    pub(crate) last_report_ts: i64,
+    pub(crate) ips: u32,
+    // Resources set on chain
+    pub(crate) resources: Resources,
+    // State changes of the contract, as the timestamp of the change and the new status.
+    pub(crate) status: Vec<(i64, ContractStatus)>,
+}
+
+/// The status of a contract, as far as utilization is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ContractStatus {
+    /// The contract is active, and utilization on it is credited to the node.
+    Active,
+    /// The contract is in grace period because its owner ran out of funds. The workloads are not
+    /// reachable, so utilization is not credited.
+    GracePeriod,
+    /// The contract is canceled, any report for it is not credited.
+    Canceled,
+}
+
+impl Contract {
+    /// The current status of the contract.
+    pub(crate) fn current_status(&self) -> ContractStatus {
+        self.status
+            .last()
+            .map_or(ContractStatus::Active, |(_, s)| *s)
+    }
+
+    /// Record a status change of the contract at the given timestamp.
+    pub(crate) fn set_status(&mut self, ts: i64, status: ContractStatus) {
+        self.status.push((ts, status));
+    }
+
+    /// The amount of seconds the contract was active in the `window` seconds before `ts`.
+    pub(crate) fn active_seconds(&self, ts: i64, window: u64) -> u64 {
+        let window_start = ts - window as i64;
+        let mut active = 0;
+        for (i, (start, status)) in self.status.iter().enumerate() {
+            if *status != ContractStatus::Active {
+                continue;
+            }
+            let end = self.status.get(i + 1).map_or(ts, |(next, _)| *next).min(ts);
+            let start = (*start).max(window_start);
+            if end > start {
+                active += (end - start) as u64;
+            }
+        }
+        active
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct TotalConsumption {
+    // cru mru hru sru and ips is value * time i.e. unit seconds
+    pub(crate) cru: u128,
+    pub(crate) sru: u128,
+    pub(crate) hru: u128,
+    pub(crate) mru: u128,
+    pub(crate) ips: u64,
+    pub(crate) nru: u64,
+}
@@ -4,17 +4,18 @@
 //! the v1 minting, such that there were exactly 60 periods in the 5 years a node would receive
 //! tokens.
 
+use codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
-/// Timestamp of the start of the first period.
-const FIRST_PERIOD_START_TIMESTAMP: i64 = 1522501000;
+/// Timestamp of the start of the first period on mainnet.
+pub const FIRST_PERIOD_START_TIMESTAMP: i64 = 1522501000;
 /// The duration of a standard period, as used by the minting payouts, in seconds.
 const STANDARD_PERIOD_DURATION: u64 = 24 * 60 * 60 * (365 * 3 + 366 * 2) / 60;
 
 /// A period represents a timestamp used by the minting process.
 ///
 /// Periods are defined such that there are roughly 12 periods per year.
-#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
 pub struct Period {
     start: i64,
     end: i64,
@@ -38,12 +39,22 @@ impl Period {
 
     /// Get the period with the given offset from the start.
     pub fn at_offset(offset: i64) -> Self {
+        Self::at_offset_from(FIRST_PERIOD_START_TIMESTAMP, offset)
+    }
+
+    /// Get the period with the given offset from a first period starting at `epoch`.
+    pub fn at_offset_from(epoch: i64, offset: i64) -> Self {
         Period {
-            start: FIRST_PERIOD_START_TIMESTAMP + STANDARD_PERIOD_DURATION as i64 * offset,
-            end: FIRST_PERIOD_START_TIMESTAMP + STANDARD_PERIOD_DURATION as i64 * (offset + 1),
+            start: epoch + STANDARD_PERIOD_DURATION as i64 * offset,
+            end: epoch + STANDARD_PERIOD_DURATION as i64 * (offset + 1),
         }
     }
 
+    /// The offset of the period from a first period starting at `epoch`.
+    pub fn offset_from(&self, epoch: i64) -> i64 {
+        (self.start - epoch) / STANDARD_PERIOD_DURATION as i64
+    }
+
     /// Start timestamp of the period.
     pub fn start(&self) -> i64 {
         self.start
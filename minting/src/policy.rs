@@ -0,0 +1,245 @@
+//! Farming policy and certification tracking for individual nodes.
+//!
+//! The farming policy of a node, the rates of that policy, and the certification of the node can
+//! all change during a period. The [`PolicyTimeline`] keeps every change as a segment starting at
+//! the time of the change, so every part of the period can be rewarded at the rates and with the
+//! minimal uptime which were in effect at that time.
+
+use crate::period::Period;
+use tfchain_client::types::{FarmPolicy, NodeCertification};
+
+/// The farming policy and certification of a node from a point in time.
+pub struct PolicySegment {
+    /// Timestamp from which the segment is in effect.
+    pub start: i64,
+    /// The farming policy of the node, with the rates in effect during the segment.
+    pub policy: FarmPolicy,
+    pub certification: NodeCertification,
+}
+
+/// The farming policy and certification of a node over time.
+pub struct PolicyTimeline {
+    segments: Vec<PolicySegment>,
+}
+
+impl PolicyTimeline {
+    /// Create a new timeline, with the given policy and certification in effect from the given
+    /// timestamp.
+    pub fn new(since: i64, policy: FarmPolicy, certification: NodeCertification) -> Self {
+        PolicyTimeline {
+            segments: vec![PolicySegment {
+                start: since,
+                policy,
+                certification,
+            }],
+        }
+    }
+
+    /// The segment currently in effect.
+    pub fn current(&self) -> &PolicySegment {
+        // There is always at least 1 segment.
+        &self.segments[self.segments.len() - 1]
+    }
+
+    /// All segments in the timeline.
+    pub fn segments(&self) -> &[PolicySegment] {
+        &self.segments
+    }
+
+    /// Set the farming policy of the node at the given timestamp. This is also used when the rates
+    /// of the current policy change. Returns true if a new segment was started.
+    pub fn set_policy(&mut self, ts: i64, policy: FarmPolicy) -> bool {
+        let certification = self.current().certification.clone();
+        self.push(ts, policy, certification)
+    }
+
+    /// Set the certification of the node at the given timestamp. Returns true if the certification
+    /// changed.
+    pub fn set_certification(&mut self, ts: i64, certification: NodeCertification) -> bool {
+        if self.current().certification == certification {
+            return false;
+        }
+        let policy = self.current().policy.clone();
+        self.push(ts, policy, certification)
+    }
+
+    /// Replace the farming policy with the given id by another policy, in every segment.
+    pub fn replace_policy(&mut self, policy_id: u32, policy: &FarmPolicy) {
+        for segment in self.segments.iter_mut() {
+            if segment.policy.id == policy_id {
+                segment.policy = policy.clone();
+            }
+        }
+    }
+
+    /// The segments which are in effect during the period, with the start and end timestamp of
+    /// the part of the period they cover. The first segment is extended to the start of the
+    /// period, so the segments always cover the full period.
+    pub fn windows(&self, period: Period) -> Vec<(i64, i64, &PolicySegment)> {
+        let mut windows = Vec::with_capacity(self.segments.len());
+        for (i, segment) in self.segments.iter().enumerate() {
+            let start = if i == 0 {
+                period.start()
+            } else {
+                segment.start.max(period.start())
+            };
+            let end = self
+                .segments
+                .get(i + 1)
+                .map_or(period.end(), |next| next.start)
+                .min(period.end());
+            if end > start {
+                windows.push((start, end, segment));
+            }
+        }
+        windows
+    }
+
+    fn push(&mut self, ts: i64, policy: FarmPolicy, certification: NodeCertification) -> bool {
+        let segment = PolicySegment {
+            start: ts,
+            policy,
+            certification,
+        };
+        // Multiple updates at the same time only keep the last one.
+        let last = self.segments.len() - 1;
+        if self.segments[last].start >= ts {
+            self.segments[last] = segment;
+            false
+        } else {
+            self.segments.push(segment);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tfchain_client::types::FarmCertification;
+
+    fn policy(id: u32, cu: u32) -> FarmPolicy {
+        FarmPolicy {
+            version: 1,
+            id,
+            name: format!("policy {id}"),
+            cu,
+            su: 1000,
+            nu: 30,
+            ipv4: 5,
+            minimal_uptime: 95,
+            policy_created: 0,
+            policy_end: 0,
+            immutable: false,
+            default: true,
+            node_certification: NodeCertification::Diy,
+            farm_certification: FarmCertification::NotCertified,
+        }
+    }
+
+    /// The start, end, policy id, cu rate and certification of every window in the period.
+    fn windows(
+        timeline: &PolicyTimeline,
+        period: Period,
+    ) -> Vec<(i64, i64, u32, u32, NodeCertification)> {
+        timeline
+            .windows(period)
+            .into_iter()
+            .map(|(start, end, segment)| {
+                (
+                    start,
+                    end,
+                    segment.policy.id,
+                    segment.policy.cu,
+                    segment.certification.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn policy_and_certification_changes_start_segments() {
+        let period = Period::at_offset(70);
+        let (start, end) = (period.start(), period.end());
+        let mut timeline =
+            PolicyTimeline::new(start - 100, policy(1, 2400), NodeCertification::Diy);
+        assert!(timeline.set_policy(start + 100, policy(2, 2400)));
+        assert!(timeline.set_certification(start + 200, NodeCertification::Certified));
+        // The rates of the current policy change.
+        assert!(timeline.set_policy(start + 300, policy(2, 3000)));
+
+        assert_eq!(timeline.segments().len(), 4);
+        assert_eq!(timeline.current().policy.cu, 3000);
+        assert_eq!(
+            windows(&timeline, period),
+            vec![
+                (start, start + 100, 1, 2400, NodeCertification::Diy),
+                (start + 100, start + 200, 2, 2400, NodeCertification::Diy),
+                (
+                    start + 200,
+                    start + 300,
+                    2,
+                    2400,
+                    NodeCertification::Certified
+                ),
+                (start + 300, end, 2, 3000, NodeCertification::Certified),
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_certification_keeps_the_segment() {
+        let mut timeline = PolicyTimeline::new(0, policy(1, 2400), NodeCertification::Diy);
+        assert!(!timeline.set_certification(100, NodeCertification::Diy));
+        assert_eq!(timeline.segments().len(), 1);
+    }
+
+    #[test]
+    fn changes_at_the_same_time_keep_the_last_one() {
+        let mut timeline = PolicyTimeline::new(0, policy(1, 2400), NodeCertification::Diy);
+        assert!(timeline.set_policy(100, policy(2, 2400)));
+        assert!(!timeline.set_certification(100, NodeCertification::Certified));
+        assert!(!timeline.set_policy(100, policy(3, 2400)));
+
+        assert_eq!(timeline.segments().len(), 2);
+        let current = timeline.current();
+        assert_eq!(current.start, 100);
+        assert_eq!(current.policy.id, 3);
+        assert_eq!(current.certification, NodeCertification::Certified);
+    }
+
+    #[test]
+    fn windows_are_clipped_to_the_period() {
+        let period = Period::at_offset(70);
+        let (start, end) = (period.start(), period.end());
+        let mut timeline =
+            PolicyTimeline::new(start + 500, policy(1, 2400), NodeCertification::Diy);
+        // The first segment is extended to the start of the period, changes after the end of the
+        // period are not part of it.
+        assert!(timeline.set_policy(start + 1000, policy(2, 2400)));
+        assert!(timeline.set_policy(end + 100, policy(3, 2400)));
+
+        assert_eq!(
+            windows(&timeline, period),
+            vec![
+                (start, start + 1000, 1, 2400, NodeCertification::Diy),
+                (start + 1000, end, 2, 2400, NodeCertification::Diy),
+            ]
+        );
+    }
+
+    #[test]
+    fn replaced_policy_applies_to_every_segment() {
+        let mut timeline = PolicyTimeline::new(0, policy(2, 2400), NodeCertification::Diy);
+        assert!(timeline.set_policy(100, policy(3, 2400)));
+        assert!(timeline.set_policy(200, policy(2, 3000)));
+        timeline.replace_policy(2, &policy(1, 1800));
+
+        let ids: Vec<_> = timeline
+            .segments()
+            .iter()
+            .map(|segment| (segment.start, segment.policy.id, segment.policy.cu))
+            .collect();
+        assert_eq!(ids, vec![(0, 1, 1800), (100, 3, 2400), (200, 1, 1800)]);
+    }
+}
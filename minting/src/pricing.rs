@@ -0,0 +1,174 @@
+//! Resolution of the TFT connection price of nodes.
+//!
+//! The TFT reward of a node is its USD reward converted at the TFT price at the time the node
+//! connected to the grid. The price of a node is resolved in order from:
+//!
+//! - an explicit override for the node in the minted period, from the [`PriceOverrides`] table.
+//!   This is used to correct prices which are known to be wrong on chain.
+//! - the connection price stored on chain for the node.
+//! - the connection price of the network, for nodes which don't have a connection price of their
+//!   own. Nodes which exist at the start of the period get the price at the start of the period,
+//!   nodes registered during the period get the price at the time they are registered.
+
+use crate::receipt::UNITS_PER_TFT;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// Where the connection price of a node comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceSource {
+    /// The price is overridden for the period.
+    Override,
+    /// The price is set on the node on chain.
+    Node,
+    /// The node has no price, the price of the network is used: the price at the start of the
+    /// period for nodes which existed then, or the price at registration for nodes registered in
+    /// the period.
+    Network,
+}
+
+/// A single override of the connection price of a node in a period.
+#[derive(Serialize, Deserialize)]
+pub struct PriceOverride {
+    /// Offset of the period for which the override applies.
+    pub period: i64,
+    pub node_id: u32,
+    /// Connection price in mUSD.
+    pub price: u32,
+}
+
+/// Connection prices to use instead of the price on chain, keyed by period offset and node id.
+#[derive(Default)]
+pub struct PriceOverrides {
+    prices: BTreeMap<(i64, u32), u32>,
+}
+
+impl PriceOverrides {
+    /// Load the overrides from a JSON file with a list of [`PriceOverride`]s.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let overrides: Vec<PriceOverride> = serde_json::from_slice(&data)?;
+        let mut prices = BTreeMap::new();
+        for o in overrides {
+            if o.price == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("price override for node {} is 0", o.node_id),
+                ));
+            }
+            if prices.insert((o.period, o.node_id), o.price).is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "duplicate price override for node {} in period {}",
+                        o.node_id, o.period
+                    ),
+                ));
+            }
+        }
+        Ok(PriceOverrides { prices })
+    }
+
+    /// The overridden price of a node in a period, if any.
+    pub fn get(&self, period: i64, node_id: u32) -> Option<u32> {
+        self.prices.get(&(period, node_id)).copied()
+    }
+
+    /// All overridden prices in a period, as node id and price, ordered by node id.
+    pub fn in_period(&self, period: i64) -> Vec<(u32, u32)> {
+        self.prices
+            .range((period, 0)..=(period, u32::MAX))
+            .map(|(&(_, node_id), &price)| (node_id, price))
+            .collect()
+    }
+}
+
+/// Check that a TFT reward is the conversion of a mUSD reward at the given connection price. As
+/// the mUSD and TFT rewards are scaled separately, to the uptime of the node and to the part of
+/// the period a policy was in effect, they can each be rounded down twice, so a difference of up
+/// to 2 mUSD worth of TFT units is allowed.
+pub fn check_conversion(musd: u64, tft: u64, price: u64) -> Result<(), String> {
+    if price == 0 {
+        return if musd == 0 && tft == 0 {
+            Ok(())
+        } else {
+            Err("reward converted at a connection price of 0".into())
+        };
+    }
+    let expected = musd as u128 * UNITS_PER_TFT as u128 / price as u128;
+    let tolerance = 2 * UNITS_PER_TFT as u128 / price as u128 + 3;
+    if (tft as u128).abs_diff(expected) > tolerance {
+        return Err(format!(
+            "reward of {musd} mUSD converts to {expected} TFT units at a connection price of {price} mUSD, but is {tft} TFT units"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write the data to a file, and load it as price overrides.
+    fn load(name: &str, data: &str) -> io::Result<PriceOverrides> {
+        let path =
+            std::env::temp_dir().join(format!("price-overrides-{}-{name}", std::process::id()));
+        fs::write(&path, data).unwrap();
+        let overrides = PriceOverrides::load(&path);
+        fs::remove_file(&path).unwrap();
+        overrides
+    }
+
+    #[test]
+    fn overrides_are_looked_up_by_period_and_node() {
+        let overrides = load(
+            "valid",
+            r#"[
+                {"period": 70, "node_id": 2, "price": 50},
+                {"period": 70, "node_id": 1, "price": 40},
+                {"period": 71, "node_id": 1, "price": 60}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(overrides.get(70, 1), Some(40));
+        assert_eq!(overrides.get(71, 1), Some(60));
+        assert_eq!(overrides.get(71, 2), None);
+        assert_eq!(overrides.in_period(70), vec![(1, 40), (2, 50)]);
+        assert!(overrides.in_period(72).is_empty());
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        for (name, data) in [
+            ("zero", r#"[{"period": 70, "node_id": 1, "price": 0}]"#),
+            (
+                "duplicate",
+                r#"[{"period": 70, "node_id": 1, "price": 40}, {"period": 70, "node_id": 1, "price": 50}]"#,
+            ),
+            ("malformed", r#"{"period": 70}"#),
+        ] {
+            assert_eq!(
+                load(name, data).err().unwrap().kind(),
+                io::ErrorKind::InvalidData,
+                "{name}"
+            );
+        }
+        assert_eq!(
+            PriceOverrides::load(Path::new("/nonexistent/prices.json"))
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn conversion_is_checked_within_rounding() {
+        // 80 mUSD buys 1 TFT.
+        check_conversion(80, UNITS_PER_TFT, 80).unwrap();
+        check_conversion(80, UNITS_PER_TFT - 1, 80).unwrap();
+        assert!(check_conversion(80, UNITS_PER_TFT / 2, 80).is_err());
+        check_conversion(0, 0, 0).unwrap();
+        assert!(check_conversion(80, UNITS_PER_TFT, 0).is_err());
+    }
+}
@@ -1,10 +1,16 @@
-use crate::period::Period;
+use crate::{limits::PolicyLimit, period::Period};
 use blake2::{digest::consts::U32, Blake2b, Digest};
+use codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
-use std::ops::Sub;
+use std::{io, ops::Sub, os::unix::prelude::OsStrExt, path::Path};
 
 type Blake2b256 = Blake2b<U32>;
 
+/// The amount of "units" that make 1 TFT.
+pub const UNITS_PER_TFT: u64 = 10_000_000;
+/// The version of [`MintingReceiptV2`].
+pub const RECEIPT_V2: u8 = 2;
+
 #[derive(Serialize, Deserialize)]
 /// A receipt which will be stored to validate the payout of a node. This will then be hashed to
 /// create the payment memo.
@@ -39,7 +45,7 @@ const fn default_farming_policy_id() -> u32 {
     1
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Encode)]
 pub struct ResourceRewards {
     pub cu: u64,
     pub su: u64,
@@ -69,6 +75,215 @@ impl MintingReceipt {
     }
 }
 
+#[derive(Serialize, Deserialize, Encode)]
+/// A receipt which will be stored to validate the payout of a node, without floating point values.
+/// This will then be hashed to create the payment memo.
+///
+/// The hash of the receipt is the blake2b-256 hash of its canonical encoding. The canonical
+/// encoding is the [SCALE](https://docs.substrate.io/reference/scale-codec/) encoding of the
+/// fields, in the order in which they are declared here:
+///
+/// - integers are encoded as fixed width little endian values,
+/// - strings are encoded as their compact encoded length in bytes, followed by the UTF-8 bytes,
+/// - an optional value is encoded as `0x00` if it is absent, or `0x01` followed by the value,
+/// - a period is encoded as its start and end timestamps, as `i64`,
+/// - nested structs are encoded as the concatenation of their fields,
+/// - enums are encoded as the index of their variant, in the order in which they are declared,
+/// - lists are encoded as their compact encoded length, followed by the encoded items.
+///
+/// All fields are part of the encoding, including the optional farming policy limit, the list of
+/// policy segments, and the optional rule set, which are encoded as `0x00` when absent or empty.
+///
+/// Quantities which are fractional in [`MintingReceipt`] are expressed as integers in a fixed
+/// unit, documented on the field.
+pub struct MintingReceiptV2 {
+    /// Version of the receipt, always [`RECEIPT_V2`].
+    pub version: u8,
+    pub period: Period,
+    pub node_id: u32,
+    pub twin_id: u32,
+    pub farm_id: u32,
+    pub farm_name: String,
+    pub stellar_payout_address: String,
+    pub measured_uptime: u64,
+    /// TFT price on connection in milli USD.
+    pub tft_connection_price: u64,
+    pub cloud_units: CloudUnitsV2,
+    pub resource_units: ResourceUnitsV2,
+    pub resource_utilization: ResourceUtilizationV2,
+    pub reward: Reward,
+    pub carbon_offset: Option<Reward>,
+    /// Certification type of the node, "CERTIFIED" or "DIY".
+    pub node_type: String,
+    pub farming_policy_id: u32,
+    pub resource_rewards: ResourceRewards,
+    /// The limit of the farm which rolled the node back from its farming policy to the default
+    /// farming policy, if any. `farming_policy_id` is the default policy in that case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub farming_policy_limit: Option<PolicyLimit>,
+    /// Breakdown of the reward for every part of the period with a different farming policy,
+    /// policy rates or certification. Empty if these did not change during the period, in which
+    /// case `farming_policy_id`, `node_type` and `resource_rewards` apply to the whole period.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policy_segments: Vec<PolicySegmentReward>,
+    /// The tokenomics rule set the reward was calculated with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_set: Option<RuleSetRef>,
+}
+
+impl MintingReceiptV2 {
+    /// The canonical encoding of the receipt, which is used to calculate the hash.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.encode()
+    }
+
+    /// Get the hash of the receipt.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Blake2b256::new();
+        hasher.update(self.canonical_bytes());
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Encode)]
+/// Cloud units for a node, in millionths of a unit.
+pub struct CloudUnitsV2 {
+    pub cu: u64,
+    pub su: u64,
+    pub nu: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Encode)]
+/// A reference to the tokenomics rule set a reward was calculated with.
+pub struct RuleSetRef {
+    pub name: String,
+    /// Hex encoded hash of the rule set.
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Encode)]
+/// The reward of a node for the part of a period in which its farming policy, the rates of that
+/// policy and its certification did not change.
+pub struct PolicySegmentReward {
+    /// Start timestamp of the segment.
+    pub start: i64,
+    /// End timestamp of the segment.
+    pub end: i64,
+    pub farming_policy_id: u32,
+    /// Certification type of the node, "CERTIFIED" or "DIY".
+    pub node_type: String,
+    /// Cloud units of the node during the segment.
+    pub cloud_units: CloudUnitsV2,
+    pub resource_rewards: ResourceRewards,
+    pub reward: Reward,
+}
+
+#[derive(Serialize, Deserialize, Encode)]
+/// Resource units as reported by the node. Cru is the amount of logical cores, the other units are
+/// expressed in bytes.
+pub struct ResourceUnitsV2 {
+    pub cru: u64,
+    pub mru: u64,
+    pub hru: u64,
+    pub sru: u64,
+}
+
+#[derive(Serialize, Deserialize, Encode)]
+/// Utilization of resources on a node as measured through capacity reports on the chain. The
+/// utilization of cru, mru, hru and sru is expressed in millionths of the capacity of the node,
+/// the utilization of public IPs is expressed in seconds.
+pub struct ResourceUtilizationV2 {
+    pub cru: u64,
+    pub mru: u64,
+    pub hru: u64,
+    pub sru: u64,
+    pub ip: u64,
+}
+
+/// A minting receipt of any version, as found in the receipt directories.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum VersionedReceipt {
+    V1(MintingReceipt),
+    V2(MintingReceiptV2),
+}
+
+impl VersionedReceipt {
+    /// Parse a receipt from JSON. Receipts without a version field are v1 receipts.
+    pub fn from_json(data: &[u8]) -> serde_json::Result<Self> {
+        let value: serde_json::Value = serde_json::from_slice(data)?;
+        match value.get("version").map(serde_json::Value::as_u64) {
+            None => Ok(VersionedReceipt::V1(serde_json::from_value(value)?)),
+            Some(Some(v)) if v == RECEIPT_V2 as u64 => {
+                Ok(VersionedReceipt::V2(serde_json::from_value(value)?))
+            }
+            Some(v) => Err(serde::de::Error::custom(format!(
+                "unsupported receipt version {v:?}"
+            ))),
+        }
+    }
+
+    /// Load a receipt from a file, which is named after the hex encoded hash of the receipt. The
+    /// hash of the receipt is checked against the name of the file.
+    pub fn load(path: &Path) -> io::Result<([u8; 32], Self)> {
+        let mut hash = [0; 32];
+        let name = path.file_name().unwrap_or_default();
+        hex::decode_to_slice(name.as_bytes(), &mut hash)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let receipt = VersionedReceipt::from_json(&std::fs::read(path)?)?;
+        if receipt.hash() != hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("receipt hash does not match file {}", path.display()),
+            ));
+        }
+        Ok((hash, receipt))
+    }
+
+    /// Get the hash of the receipt.
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            VersionedReceipt::V1(receipt) => receipt.hash(),
+            VersionedReceipt::V2(receipt) => receipt.hash(),
+        }
+    }
+
+    pub fn period(&self) -> Period {
+        match self {
+            VersionedReceipt::V1(receipt) => receipt.period,
+            VersionedReceipt::V2(receipt) => receipt.period,
+        }
+    }
+
+    pub fn node_id(&self) -> u32 {
+        match self {
+            VersionedReceipt::V1(receipt) => receipt.node_id,
+            VersionedReceipt::V2(receipt) => receipt.node_id,
+        }
+    }
+
+    pub fn farm_id(&self) -> u32 {
+        match self {
+            VersionedReceipt::V1(receipt) => receipt.farm_id,
+            VersionedReceipt::V2(receipt) => receipt.farm_id,
+        }
+    }
+
+    pub fn stellar_payout_address(&self) -> &str {
+        match self {
+            VersionedReceipt::V1(receipt) => &receipt.stellar_payout_address,
+            VersionedReceipt::V2(receipt) => &receipt.stellar_payout_address,
+        }
+    }
+
+    pub fn reward(&self) -> Reward {
+        match self {
+            VersionedReceipt::V1(receipt) => receipt.reward,
+            VersionedReceipt::V2(receipt) => receipt.reward,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Serialize, Deserialize)]
 /// Cloud units for a node.
 pub struct CloudUnits {
@@ -88,7 +303,7 @@ impl Sub for CloudUnits {
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Serialize, Deserialize, Encode, Decode)]
 /// Payout for a node.
 pub struct Reward {
     /// Reward in milli USD.
@@ -102,16 +317,8 @@ impl Sub for Reward {
     fn sub(self, rhs: Self) -> Self::Output {
         // If we would end up with a negative, set to 0
         Self {
-            musd: if self.musd >= rhs.musd {
-                self.musd - rhs.musd
-            } else {
-                0
-            },
-            tft: if self.tft >= rhs.tft {
-                self.tft - rhs.tft
-            } else {
-                0
-            },
+            musd: self.musd.saturating_sub(rhs.musd),
+            tft: self.tft.saturating_sub(rhs.tft),
         }
     }
 }
@@ -190,3 +397,136 @@ impl FixupReceipt {
         hasher.finalize().into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    /// A v1 receipt, as written by the v1 minting.
+    const V1_RECEIPT: &str = r#"{"period":{"start":1700000000,"end":1702629440},"node_id":42,"twin_id":7,"farm_id":3,"farm_name":"farm","stellar_payout_address":"GADDRESS","measured_uptime":2600000,"tft_connection_price":80,"cloud_units":{"cu":1.5,"su":2.25,"nu":0.0},"resource_units":{"cru":4.0,"mru":16.0,"hru":1000.0,"sru":500.0},"resource_utilization":{"cru":0.5,"mru":0.25,"hru":0.0,"sru":0.125,"ip":0.0},"reward":{"musd":12345,"tft":1543125000},"carbon_offset":null,"node_type":"DIY","farming_policy_id":1,"resource_rewards":{"cu":2400,"su":1000,"nu":30,"ipv4":5}}"#;
+    const V1_HASH: &str = "60df65e9c0dc698e89844bef13bd947e8fd32fb9121586dd58ee483d7765f191";
+
+    const V2_BYTES: &str = "0200f1536500000000e0157c65000000002a0000000700000003000000106661726d20474144445245535340ac270000000000500000000000000060e3160000000000105522000000000000000000000000000400000000000000000000000400000000000000fa000000000000007d00000020a107000000000090d0030000000000000000000000000048e8010000000000100e00000000000039300000000000000838fa5b00000000000c444959010000006009000000000000e8030000000000001e000000000000000500000000000000000000";
+    const V2_HASH: &str = "4f4da616bece549d6d244d3492f86960fce09c5698ccdf0981312a7786253294";
+    /// Encoding of the v2 receipt with a farming policy limit, a policy segment and a rule set.
+    const V2_EXTENDED_BYTES: &str = "0200f1536500000000e0157c65000000002a0000000700000003000000106661726d20474144445245535340ac270000000000500000000000000060e3160000000000105522000000000000000000000000000400000000000000000000000400000000000000fa000000000000007d00000020a107000000000090d0030000000000000000000000000048e8010000000000100e00000000000039300000000000000838fa5b00000000000c444959010000006009000000000000e8030000000000001e00000000000000050000000000000001020400f15365000000004033636500000000010000000c44495960e3160000000000105522000000000000000000000000006009000000000000e8030000000000001e000000000000000500000000000000881300000000000040be40250000000001087633806162616261626162616261626162616261626162616261626162616261626162";
+    const V2_EXTENDED_HASH: &str =
+        "81360d1a71f11e8d549e10c241716d3cae8965f44ff976d988eaa7fef10a08f3";
+
+    fn v2_receipt() -> MintingReceiptV2 {
+        MintingReceiptV2 {
+            version: RECEIPT_V2,
+            period: Period::at_offset_from(1700000000, 0),
+            node_id: 42,
+            twin_id: 7,
+            farm_id: 3,
+            farm_name: "farm".to_string(),
+            stellar_payout_address: "GADDRESS".to_string(),
+            measured_uptime: 2600000,
+            tft_connection_price: 80,
+            cloud_units: CloudUnitsV2 {
+                cu: 1_500_000,
+                su: 2_250_000,
+                nu: 0,
+            },
+            resource_units: ResourceUnitsV2 {
+                cru: 4,
+                mru: 16 * GIB,
+                hru: 1000 * GIB,
+                sru: 500 * GIB,
+            },
+            resource_utilization: ResourceUtilizationV2 {
+                cru: 500_000,
+                mru: 250_000,
+                hru: 0,
+                sru: 125_000,
+                ip: 3600,
+            },
+            reward: Reward {
+                musd: 12345,
+                tft: 1543125000,
+            },
+            carbon_offset: None,
+            node_type: "DIY".to_string(),
+            farming_policy_id: 1,
+            resource_rewards: ResourceRewards::default(),
+            farming_policy_limit: None,
+            policy_segments: vec![],
+            rule_set: None,
+        }
+    }
+
+    #[test]
+    fn v2_canonical_encoding_is_stable() {
+        let receipt = v2_receipt();
+        assert_eq!(hex::encode(receipt.canonical_bytes()), V2_BYTES);
+        assert_eq!(hex::encode(receipt.hash()), V2_HASH);
+    }
+
+    #[test]
+    fn v2_optional_fields_are_encoded() {
+        let mut receipt = v2_receipt();
+        receipt.farming_policy_limit = Some(PolicyLimit::NodeCount);
+        receipt.policy_segments = vec![PolicySegmentReward {
+            start: 1700000000,
+            end: 1701000000,
+            farming_policy_id: 1,
+            node_type: "DIY".to_string(),
+            cloud_units: receipt.cloud_units,
+            resource_rewards: ResourceRewards::default(),
+            reward: Reward {
+                musd: 5000,
+                tft: 625000000,
+            },
+        }];
+        receipt.rule_set = Some(RuleSetRef {
+            name: "v3".to_string(),
+            hash: "ab".repeat(16),
+        });
+        assert_eq!(hex::encode(receipt.canonical_bytes()), V2_EXTENDED_BYTES);
+        assert_eq!(hex::encode(receipt.hash()), V2_EXTENDED_HASH);
+    }
+
+    #[test]
+    fn v2_json_round_trip_keeps_hash() {
+        let data = serde_json::to_vec(&v2_receipt()).unwrap();
+        let receipt = VersionedReceipt::from_json(&data).unwrap();
+        assert!(matches!(receipt, VersionedReceipt::V2(_)));
+        assert_eq!(hex::encode(receipt.hash()), V2_HASH);
+    }
+
+    #[test]
+    fn v1_hash_is_accepted() {
+        let receipt = VersionedReceipt::from_json(V1_RECEIPT.as_bytes()).unwrap();
+        assert!(matches!(receipt, VersionedReceipt::V1(_)));
+        assert_eq!(hex::encode(receipt.hash()), V1_HASH);
+        assert_eq!(receipt.node_id(), 42);
+        assert_eq!(receipt.reward().tft, 1543125000);
+    }
+
+    #[test]
+    fn load_checks_hash() {
+        let dir = std::env::temp_dir().join(format!("receipt-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(V1_HASH);
+        std::fs::write(&path, V1_RECEIPT).unwrap();
+        let (hash, receipt) = VersionedReceipt::load(&path).unwrap();
+        assert_eq!(hex::encode(hash), V1_HASH);
+        assert!(matches!(receipt, VersionedReceipt::V1(_)));
+
+        let path = dir.join(V2_HASH);
+        std::fs::write(&path, V1_RECEIPT).unwrap();
+        let err = VersionedReceipt::load(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        assert!(VersionedReceipt::from_json(br#"{"version":3}"#).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Reconciliation of receipts with the payments made for them.
+//!
+//! A receipt is paid by a transaction with the hash of the receipt as memo. Finding such a
+//! transaction does not mean the receipt is paid correctly, so the payments in the transactions
+//! are compared with the payout address and reward of the receipt. Receipts which are not fully
+//! paid have an outstanding amount, which is retried in the next period.
+
+use crate::ledger::LedgerTransaction;
+use std::{collections::HashMap, fmt};
+
+/// A payment which is expected for a receipt.
+pub struct ExpectedPayment {
+    /// Hash of the receipt, which is used as memo.
+    pub receipt: [u8; 32],
+    /// Stellar address which should receive the payment.
+    pub destination: String,
+    /// Amount of TFT units which should be paid.
+    pub amount: u64,
+}
+
+/// The payment state of a receipt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentStatus {
+    /// The full amount was paid to the right address, in a single transaction.
+    Paid,
+    /// Less than the full amount was paid to the right address.
+    Underpaid { paid: u64 },
+    /// More than the full amount was paid to the right address.
+    Overpaid { paid: u64 },
+    /// Nothing was paid to the right address, but payments were made to other addresses.
+    WrongAddress { destinations: Vec<String> },
+    /// Multiple transactions were made with the hash of the receipt.
+    PaidTwice { transactions: usize, paid: u64 },
+    /// No transaction was made with the hash of the receipt.
+    Unpaid,
+    /// A transaction was made with the hash of the receipt, but its payments are not known. As
+    /// the payment can't be verified, the full amount is considered outstanding.
+    Unverified,
+}
+
+impl fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentStatus::Paid => write!(f, "paid"),
+            PaymentStatus::Underpaid { .. } => write!(f, "underpaid"),
+            PaymentStatus::Overpaid { .. } => write!(f, "overpaid"),
+            PaymentStatus::WrongAddress { .. } => write!(f, "wrong address"),
+            PaymentStatus::PaidTwice { .. } => write!(f, "paid twice"),
+            PaymentStatus::Unpaid => write!(f, "unpaid"),
+            PaymentStatus::Unverified => write!(f, "unverified"),
+        }
+    }
+}
+
+/// The reconciliation of a single receipt.
+pub struct ReceiptReconciliation {
+    pub expected: ExpectedPayment,
+    pub status: PaymentStatus,
+}
+
+impl ReceiptReconciliation {
+    /// The amount of TFT units which still needs to be paid for the receipt.
+    pub fn outstanding(&self) -> u64 {
+        match self.status {
+            PaymentStatus::Unpaid
+            | PaymentStatus::WrongAddress { .. }
+            | PaymentStatus::Unverified => self.expected.amount,
+            PaymentStatus::Underpaid { paid } | PaymentStatus::PaidTwice { paid, .. } => {
+                self.expected.amount.saturating_sub(paid)
+            }
+            PaymentStatus::Paid | PaymentStatus::Overpaid { .. } => 0,
+        }
+    }
+
+    /// The amount of TFT units paid to the right address.
+    pub fn paid(&self) -> u64 {
+        match self.status {
+            PaymentStatus::Paid => self.expected.amount,
+            PaymentStatus::Underpaid { paid }
+            | PaymentStatus::Overpaid { paid }
+            | PaymentStatus::PaidTwice { paid, .. } => paid,
+            PaymentStatus::WrongAddress { .. }
+            | PaymentStatus::Unpaid
+            | PaymentStatus::Unverified => 0,
+        }
+    }
+}
+
+/// Reconciliation of a set of receipts.
+#[derive(Default)]
+pub struct ReconciliationReport {
+    pub receipts: Vec<ReceiptReconciliation>,
+}
+
+impl ReconciliationReport {
+    /// The outstanding amount for every receipt which is not fully paid.
+    pub fn outstanding(&self) -> HashMap<[u8; 32], u64> {
+        self.receipts
+            .iter()
+            .filter(|r| r.outstanding() > 0)
+            .map(|r| (r.expected.receipt, r.outstanding()))
+            .collect()
+    }
+
+    /// Write the report as CSV.
+    pub fn write_csv(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(
+            w,
+            "receipt,destination,expected,paid,outstanding,status,details"
+        )?;
+        for r in &self.receipts {
+            let details = match &r.status {
+                PaymentStatus::WrongAddress { destinations } => destinations.join(" "),
+                PaymentStatus::PaidTwice { transactions, .. } => {
+                    format!("{transactions} transactions")
+                }
+                _ => String::new(),
+            };
+            writeln!(
+                w,
+                "{},{},{},{},{},{},{}",
+                hex::encode(r.expected.receipt),
+                r.expected.destination,
+                r.expected.amount,
+                r.paid(),
+                r.outstanding(),
+                r.status,
+                details,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ReconciliationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for r in &self.receipts {
+            let status = r.status.to_string();
+            match counts.iter_mut().find(|(s, _)| *s == status) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((status, 1)),
+            }
+        }
+        let counts: Vec<_> = counts
+            .into_iter()
+            .map(|(status, count)| format!("{count} {status}"))
+            .collect();
+        write!(
+            f,
+            "Reconciled {} receipts: {}",
+            self.receipts.len(),
+            counts.join(", ")
+        )
+    }
+}
+
+/// Reconcile the expected payments of receipts with the transactions in the ledger.
+pub fn reconcile(
+    transactions: &[LedgerTransaction],
+    expected: impl IntoIterator<Item = ExpectedPayment>,
+) -> ReconciliationReport {
+    let mut by_memo: HashMap<&str, Vec<&LedgerTransaction>> = HashMap::new();
+    for tx in transactions {
+        by_memo.entry(tx.memo.as_str()).or_default().push(tx);
+    }
+
+    let mut report = ReconciliationReport::default();
+    for expected in expected {
+        let txs = by_memo
+            .get(hex::encode(expected.receipt).as_str())
+            .cloned()
+            .unwrap_or_default();
+        let status = status(&expected, &txs);
+        report
+            .receipts
+            .push(ReceiptReconciliation { expected, status });
+    }
+    report
+}
+
+fn status(expected: &ExpectedPayment, txs: &[&LedgerTransaction]) -> PaymentStatus {
+    if txs.is_empty() {
+        return PaymentStatus::Unpaid;
+    }
+    if txs.iter().all(|tx| tx.payments.is_empty()) {
+        return PaymentStatus::Unverified;
+    }
+    let payments = txs.iter().flat_map(|tx| tx.payments.iter());
+    let paid: u64 = payments
+        .clone()
+        .filter(|p| p.destination == expected.destination)
+        .map(|p| p.amount)
+        .sum();
+    if txs.len() > 1 {
+        return PaymentStatus::PaidTwice {
+            transactions: txs.len(),
+            paid,
+        };
+    }
+    if paid == 0 {
+        return PaymentStatus::WrongAddress {
+            destinations: payments.map(|p| p.destination.clone()).collect(),
+        };
+    }
+    match paid.cmp(&expected.amount) {
+        std::cmp::Ordering::Less => PaymentStatus::Underpaid { paid },
+        std::cmp::Ordering::Equal => PaymentStatus::Paid,
+        std::cmp::Ordering::Greater => PaymentStatus::Overpaid { paid },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::LedgerPayment;
+
+    fn expected(receipt: u8, amount: u64) -> ExpectedPayment {
+        ExpectedPayment {
+            receipt: [receipt; 32],
+            destination: "GA".to_string(),
+            amount,
+        }
+    }
+
+    fn tx(receipt: u8, payments: &[(&str, u64)]) -> LedgerTransaction {
+        LedgerTransaction {
+            memo: hex::encode([receipt; 32]),
+            payments: payments
+                .iter()
+                .map(|(destination, amount)| LedgerPayment {
+                    destination: destination.to_string(),
+                    amount: *amount,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn statuses_and_outstanding_amounts() {
+        let transactions = [
+            tx(1, &[("GA", 100)]),
+            tx(2, &[("GA", 40)]),
+            tx(3, &[("GA", 150)]),
+            tx(4, &[("GB", 100)]),
+            tx(5, &[("GA", 30)]),
+            tx(5, &[("GA", 20), ("GB", 50)]),
+            tx(6, &[("GA", 100)]),
+            tx(6, &[("GA", 100)]),
+            tx(8, &[]),
+        ];
+        let report = reconcile(&transactions, (1..=8).map(|receipt| expected(receipt, 100)));
+        let results: Vec<_> = report
+            .receipts
+            .iter()
+            .map(|r| (r.status.clone(), r.paid(), r.outstanding()))
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                (PaymentStatus::Paid, 100, 0),
+                (PaymentStatus::Underpaid { paid: 40 }, 40, 60),
+                (PaymentStatus::Overpaid { paid: 150 }, 150, 0),
+                (
+                    PaymentStatus::WrongAddress {
+                        destinations: vec!["GB".to_string()]
+                    },
+                    0,
+                    100
+                ),
+                (
+                    PaymentStatus::PaidTwice {
+                        transactions: 2,
+                        paid: 50
+                    },
+                    50,
+                    50
+                ),
+                (
+                    PaymentStatus::PaidTwice {
+                        transactions: 2,
+                        paid: 200
+                    },
+                    200,
+                    0
+                ),
+                (PaymentStatus::Unpaid, 0, 100),
+                (PaymentStatus::Unverified, 0, 100),
+            ]
+        );
+        let outstanding = report.outstanding();
+        assert_eq!(outstanding.len(), 5);
+        assert_eq!(outstanding[&[5; 32]], 50);
+        assert_eq!(outstanding[&[8; 32]], 100);
+    }
+}
@@ -0,0 +1,465 @@
+//! Tokenomics rules which are not part of the farming policies on chain.
+//!
+//! Some parts of the reward calculation depend on rules which were agreed upon outside of the
+//! chain, such as which farming policies are paid linear to uptime, the minimal SLA of nodes on
+//! those policies, and bonuses for certified nodes. These rules are kept in a versioned JSON file,
+//! with rule sets for a range of periods on a set of networks. The rule set in effect for the
+//! minted period is recorded in every receipt by name and hash, so the rules a reward was
+//! calculated with can always be found back.
+//!
+//! The rules shipped with the binary are in `rules.json`, a different file can be loaded to
+//! rehearse rule changes before they are released.
+
+use crate::receipt::RuleSetRef;
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::Path};
+use tfchain_client::types::{FarmPolicy, NodeCertification};
+
+type Blake2b256 = Blake2b<U32>;
+
+/// The version of the rules file format.
+pub const RULES_VERSION: u32 = 1;
+/// The network which is minted if no network is selected.
+pub const DEFAULT_NETWORK: &str = "mainnet";
+
+/// The rules shipped with the binary.
+const BUILTIN_RULES: &str = include_str!("../rules.json");
+
+/// A farming policy on chain, identified by its id and optionally some of its properties. Testnet
+/// assigns policy 3 to new nodes without it being a default policy, so it is matched on its
+/// properties as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyMatcher {
+    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub immutable: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimal_uptime: Option<u16>,
+}
+
+impl PolicyMatcher {
+    /// Whether the policy matches.
+    pub fn matches(&self, policy: &FarmPolicy) -> bool {
+        self.id == policy.id
+            && self.default.is_none_or(|d| d == policy.default)
+            && self.immutable.is_none_or(|i| i == policy.immutable)
+            && self
+                .minimal_uptime
+                .is_none_or(|u| u == policy.minimal_uptime)
+    }
+}
+
+/// The minimal SLA of nodes on an uptime scaled policy, in permill of the time the node existed
+/// in the period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinimalSla {
+    pub diy: u64,
+    pub certified: u64,
+}
+
+/// A bonus for certified nodes on a farming policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertifiedBonus {
+    pub policy_id: u32,
+    /// The reward of certified nodes in permill of the base reward, i.e. 1250 is a 25% bonus.
+    pub permill: u64,
+}
+
+/// The rules for a range of periods on a set of networks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleSet {
+    /// Unique name of the rule set.
+    pub name: String,
+    pub networks: Vec<String>,
+    /// Offset of the first period the rule set applies to.
+    pub first_period: i64,
+    /// Offset of the last period the rule set applies to, if it is not open ended.
+    pub last_period: Option<i64>,
+    /// Policies on which nodes are paid linear to their uptime, as long as they reach the
+    /// minimal SLA. Nodes on other policies have to reach the minimal uptime of their policy, and
+    /// are paid for the part of the period they existed.
+    pub uptime_scaled_policies: Vec<PolicyMatcher>,
+    pub minimal_sla: MinimalSla,
+    pub certified_bonus: Vec<CertifiedBonus>,
+}
+
+impl RuleSet {
+    /// The rule set shipped with the binary for the network in the given period.
+    pub fn builtin(network: &str, period: i64) -> Self {
+        TokenomicsRules::builtin()
+            .select(network, period)
+            .expect("builtin rules cover all periods")
+    }
+
+    /// Whether the rule set applies to the period on the network.
+    pub fn applies_to(&self, network: &str, period: i64) -> bool {
+        self.networks.iter().any(|n| n == network)
+            && period >= self.first_period
+            && self.last_period.is_none_or(|last| period <= last)
+    }
+
+    /// Whether nodes on the policy are paid linear to their uptime.
+    pub fn uptime_scaled(&self, policy: &FarmPolicy) -> bool {
+        self.uptime_scaled_policies
+            .iter()
+            .any(|m| m.matches(policy))
+    }
+
+    /// The minimal SLA for a node with the given certification on an uptime scaled policy.
+    pub fn minimal_sla(&self, certification: &NodeCertification) -> u64 {
+        match certification {
+            NodeCertification::Diy => self.minimal_sla.diy,
+            NodeCertification::Certified => self.minimal_sla.certified,
+        }
+    }
+
+    /// Apply the bonus for certified nodes on the policy, if any, to a reward.
+    pub fn apply_certified_bonus(
+        &self,
+        policy_id: u32,
+        certification: &NodeCertification,
+        reward: u64,
+    ) -> u64 {
+        if *certification != NodeCertification::Certified {
+            return reward;
+        }
+        match self
+            .certified_bonus
+            .iter()
+            .find(|b| b.policy_id == policy_id)
+        {
+            Some(bonus) => reward * bonus.permill / 1_000,
+            None => reward,
+        }
+    }
+
+    /// Hash of the rule set, as the blake2b-256 hash of its JSON encoding.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Blake2b256::new();
+        hasher.update(serde_json::to_vec(self).unwrap());
+        hasher.finalize().into()
+    }
+
+    /// The reference to the rule set which is recorded in receipts.
+    pub fn reference(&self) -> RuleSetRef {
+        RuleSetRef {
+            name: self.name.clone(),
+            hash: hex::encode(self.hash()),
+        }
+    }
+}
+
+/// An error in a rules file.
+#[derive(Debug)]
+pub enum RulesError {
+    /// The file could not be read or parsed.
+    Io(io::Error),
+    /// The file has a version which is not supported.
+    UnsupportedVersion(u32),
+    /// A rule set is not valid.
+    InvalidRuleSet { name: String, reason: String },
+    /// Multiple rule sets apply to the same period on a network.
+    Overlap {
+        first: String,
+        second: String,
+        network: String,
+    },
+    /// No rule set applies to the period on the network.
+    NoRuleSet { network: String, period: i64 },
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RulesError::Io(e) => write!(f, "could not load rules: {e}"),
+            RulesError::UnsupportedVersion(v) => write!(f, "unsupported rules version {v}"),
+            RulesError::InvalidRuleSet { name, reason } => {
+                write!(f, "invalid rule set {name}: {reason}")
+            }
+            RulesError::Overlap {
+                first,
+                second,
+                network,
+            } => write!(f, "rule sets {first} and {second} overlap on {network}"),
+            RulesError::NoRuleSet { network, period } => {
+                write!(f, "no rule set for period {period} on {network}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RulesError {}
+
+impl From<io::Error> for RulesError {
+    fn from(e: io::Error) -> Self {
+        RulesError::Io(e)
+    }
+}
+
+/// All tokenomics rules, as loaded from a rules file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenomicsRules {
+    pub version: u32,
+    pub rule_sets: Vec<RuleSet>,
+}
+
+impl TokenomicsRules {
+    /// The rules shipped with the binary.
+    pub fn builtin() -> Self {
+        let rules: TokenomicsRules =
+            serde_json::from_str(BUILTIN_RULES).expect("builtin rules are valid JSON");
+        rules.validate().expect("builtin rules are valid");
+        rules
+    }
+
+    /// Load and validate the rules from a JSON file.
+    pub fn load(path: &Path) -> Result<Self, RulesError> {
+        let data = fs::read(path)?;
+        let rules: TokenomicsRules = serde_json::from_slice(&data).map_err(io::Error::from)?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    /// Check that the rules are supported and consistent.
+    pub fn validate(&self) -> Result<(), RulesError> {
+        if self.version != RULES_VERSION {
+            return Err(RulesError::UnsupportedVersion(self.version));
+        }
+        for (i, set) in self.rule_sets.iter().enumerate() {
+            let invalid = |reason: &str| RulesError::InvalidRuleSet {
+                name: set.name.clone(),
+                reason: reason.into(),
+            };
+            if set.name.is_empty() {
+                return Err(invalid("empty name"));
+            }
+            if set.networks.is_empty() {
+                return Err(invalid("no networks"));
+            }
+            if matches!(set.last_period, Some(last) if last < set.first_period) {
+                return Err(invalid("last period before first period"));
+            }
+            if set.minimal_sla.diy > 1_000 || set.minimal_sla.certified > 1_000 {
+                return Err(invalid("minimal SLA above 1000 permill"));
+            }
+            if set.certified_bonus.iter().any(|b| b.permill < 1_000) {
+                return Err(invalid("certified bonus below 1000 permill"));
+            }
+            for other in &self.rule_sets[..i] {
+                if other.name == set.name {
+                    return Err(invalid("duplicate name"));
+                }
+                let overlaps = other.first_period <= set.last_period.unwrap_or(i64::MAX)
+                    && set.first_period <= other.last_period.unwrap_or(i64::MAX);
+                if !overlaps {
+                    continue;
+                }
+                if let Some(network) = set.networks.iter().find(|n| other.networks.contains(n)) {
+                    return Err(RulesError::Overlap {
+                        first: other.name.clone(),
+                        second: set.name.clone(),
+                        network: network.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The rule set which applies to the period on the network.
+    pub fn select(&self, network: &str, period: i64) -> Result<RuleSet, RulesError> {
+        self.rule_sets
+            .iter()
+            .find(|set| set.applies_to(network, period))
+            .cloned()
+            .ok_or_else(|| RulesError::NoRuleSet {
+                network: network.into(),
+                period,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A rule set for the networks, starting at the given period.
+    fn rule_set(name: &str, networks: &[&str], first_period: i64, last: Option<i64>) -> RuleSet {
+        RuleSet {
+            name: name.to_string(),
+            networks: networks.iter().map(|n| n.to_string()).collect(),
+            first_period,
+            last_period: last,
+            uptime_scaled_policies: vec![PolicyMatcher {
+                id: 1,
+                default: None,
+                immutable: None,
+                minimal_uptime: None,
+            }],
+            minimal_sla: MinimalSla {
+                diy: 950,
+                certified: 980,
+            },
+            certified_bonus: vec![CertifiedBonus {
+                policy_id: 1,
+                permill: 1250,
+            }],
+        }
+    }
+
+    fn rules(rule_sets: Vec<RuleSet>) -> TokenomicsRules {
+        TokenomicsRules {
+            version: RULES_VERSION,
+            rule_sets,
+        }
+    }
+
+    #[test]
+    fn rule_set_is_selected_by_period() {
+        let rules = rules(vec![
+            rule_set("old", &["mainnet"], 0, Some(99)),
+            rule_set("new", &["mainnet"], 100, None),
+        ]);
+        rules.validate().unwrap();
+        assert_eq!(rules.select("mainnet", 99).unwrap().name, "old");
+        assert_eq!(rules.select("mainnet", 100).unwrap().name, "new");
+        assert_eq!(rules.select("mainnet", 1_000).unwrap().name, "new");
+        assert!(matches!(
+            rules.select("testnet", 100),
+            Err(RulesError::NoRuleSet { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let mut unsupported = rules(vec![rule_set("v3", &["mainnet"], 0, None)]);
+        unsupported.version = RULES_VERSION + 1;
+        assert!(matches!(
+            unsupported.validate(),
+            Err(RulesError::UnsupportedVersion(_))
+        ));
+
+        let mut high_sla = rule_set("v3", &["mainnet"], 0, None);
+        high_sla.minimal_sla.certified = 1_001;
+        let mut low_bonus = rule_set("v3", &["mainnet"], 0, None);
+        low_bonus.certified_bonus[0].permill = 999;
+        for set in [
+            rule_set("", &["mainnet"], 0, None),
+            rule_set("v3", &[], 0, None),
+            rule_set("v3", &["mainnet"], 10, Some(9)),
+            high_sla,
+            low_bonus,
+        ] {
+            assert!(matches!(
+                rules(vec![set]).validate(),
+                Err(RulesError::InvalidRuleSet { .. })
+            ));
+        }
+
+        let duplicate = rules(vec![
+            rule_set("v3", &["mainnet"], 0, Some(9)),
+            rule_set("v3", &["mainnet"], 10, None),
+        ]);
+        assert!(matches!(
+            duplicate.validate(),
+            Err(RulesError::InvalidRuleSet { .. })
+        ));
+    }
+
+    #[test]
+    fn overlapping_rule_sets_are_rejected() {
+        let overlap = rules(vec![
+            rule_set("old", &["mainnet", "testnet"], 0, Some(100)),
+            rule_set("new", &["testnet"], 100, None),
+        ]);
+        assert!(matches!(
+            overlap.validate(),
+            Err(RulesError::Overlap { network, .. }) if network == "testnet"
+        ));
+        // The same periods on other networks don't overlap.
+        rules(vec![
+            rule_set("old", &["mainnet"], 0, Some(100)),
+            rule_set("new", &["testnet"], 100, None),
+        ])
+        .validate()
+        .unwrap();
+    }
+
+    #[test]
+    fn policies_are_matched_on_their_properties() {
+        let mut policy = FarmPolicy {
+            version: 1,
+            id: 3,
+            name: "policy".to_string(),
+            cu: 0,
+            su: 0,
+            nu: 0,
+            ipv4: 0,
+            minimal_uptime: 95,
+            policy_created: 0,
+            policy_end: 0,
+            immutable: false,
+            default: false,
+            node_certification: NodeCertification::Diy,
+            farm_certification: tfchain_client::types::FarmCertification::NotCertified,
+        };
+        let matcher = PolicyMatcher {
+            id: 3,
+            default: Some(false),
+            immutable: None,
+            minimal_uptime: Some(95),
+        };
+        assert!(matcher.matches(&policy));
+        policy.default = true;
+        assert!(!matcher.matches(&policy));
+        policy.default = false;
+        policy.minimal_uptime = 90;
+        assert!(!matcher.matches(&policy));
+        policy.minimal_uptime = 95;
+        policy.id = 4;
+        assert!(!matcher.matches(&policy));
+    }
+
+    #[test]
+    fn certified_bonus_only_applies_to_certified_nodes() {
+        let set = rule_set("v3", &["mainnet"], 0, None);
+        assert_eq!(
+            set.apply_certified_bonus(1, &NodeCertification::Certified, 1_000),
+            1_250
+        );
+        assert_eq!(
+            set.apply_certified_bonus(1, &NodeCertification::Diy, 1_000),
+            1_000
+        );
+        assert_eq!(
+            set.apply_certified_bonus(2, &NodeCertification::Certified, 1_000),
+            1_000
+        );
+        assert_eq!(set.minimal_sla(&NodeCertification::Certified), 980);
+    }
+
+    #[test]
+    fn hash_changes_with_the_rules() {
+        let set = rule_set("v3", &["mainnet"], 0, None);
+        let mut other = set.clone();
+        other.minimal_sla.diy = 900;
+        assert_ne!(set.hash(), other.hash());
+        assert_eq!(set.reference().hash, hex::encode(set.hash()));
+    }
+
+    #[test]
+    fn builtin_rules_are_selected_per_network() {
+        let rules = TokenomicsRules::builtin();
+        assert_eq!(rules.select("mainnet", 100).unwrap().name, "v3");
+        assert_eq!(rules.select("qanet", 100).unwrap().name, "v3");
+        assert_eq!(rules.select("devnet", 100).unwrap().name, "v3");
+        assert_eq!(rules.select("testnet", 100).unwrap().name, "v3-testnet");
+        assert!(matches!(
+            rules.select("localnet", 100),
+            Err(RulesError::NoRuleSet { .. })
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! Snapshots of the chain state used as starting point for minting a period.
+
+use codec::{Decode, Encode};
+use std::collections::BTreeMap;
+use tfchain_client::{
+    client::RuntimeClient,
+    error::ClientError,
+    types::{Contract, Farm, FarmPolicy, Hash, Node, NodePower, PowerState, Resources, Twin},
+};
+
+/// The chain state needed to mint a period.
+///
+/// Nodes, power states, contracts and farming policies are taken at the start of the period, as
+/// changes to them are tracked through events. Farms, twins and payout addresses are taken at the
+/// end of the period. This means we don't have to parse individual events for these, as we can
+/// just use the last known state.
+#[derive(Encode, Decode)]
+pub struct Snapshot {
+    /// Height of the first block in the period.
+    pub start_block: u32,
+    /// Height of the last block in the period.
+    pub end_block: u32,
+    /// Hash of the first block in the period.
+    pub start_block_hash: Hash,
+    /// Hash of the last block in the period.
+    pub end_block_hash: Hash,
+    /// Timestamp of the first block in the period, in seconds since the UNIX epoch.
+    pub start_block_ts: i64,
+    pub nodes: Vec<Node>,
+    pub power_states: BTreeMap<u32, NodePower>,
+    /// Timestamp, in seconds, at which nodes which are powered down at the start of the period
+    /// went down, keyed by node id.
+    pub power_down_timestamps: BTreeMap<u32, i64>,
+    /// Contracts with the resources they use at the start of the period.
+    pub contracts: Vec<(Contract, Resources)>,
+    /// Connection price for new nodes at the start of the period, in mUSD.
+    pub connection_price: u32,
+    pub farms: BTreeMap<u32, Farm>,
+    pub twins: BTreeMap<u32, Twin>,
+    pub payout_addresses: BTreeMap<u32, String>,
+    pub farming_policies: BTreeMap<u32, FarmPolicy>,
+}
+
+impl Snapshot {
+    /// Load a snapshot for the period running from `start_block` to `end_block` from the chain.
+    pub async fn fetch(
+        client: &dyn RuntimeClient,
+        start_block: u32,
+        end_block: u32,
+    ) -> Result<Self, ClientError> {
+        let start_block_hash = client.hash_at_height(Some(start_block)).await?;
+        let start_block_ts = client.timestamp(start_block_hash).await? as i64 / 1000;
+        let end_block_hash = client.hash_at_height(Some(end_block)).await?;
+
+        let nodes = get_nodes(client, start_block).await?;
+        let power_states: BTreeMap<_, _> = get_power_states(client, start_block)
+            .await?
+            .into_iter()
+            .collect();
+        let mut power_down_timestamps = BTreeMap::new();
+        for (node_id, power) in power_states.iter() {
+            if let PowerState::Down(block) = power.state {
+                let hash = client.hash_at_height(Some(block)).await?;
+                let ts = client.timestamp(hash).await? as i64 / 1000;
+                power_down_timestamps.insert(*node_id, ts);
+            }
+        }
+        let contracts = get_contracts(client, start_block).await?;
+        let connection_price = client.connection_price(start_block_hash).await?;
+
+        let farms: BTreeMap<_, _> = get_farms(client, end_block)
+            .await?
+            .into_iter()
+            .map(|farm| (farm.id, farm))
+            .collect();
+        let twins = get_twins(client, end_block)
+            .await?
+            .into_iter()
+            .map(|twin| (twin.id, twin))
+            .collect();
+        let payout_addresses = get_payout_addresses(client, &farms, end_block).await?;
+        // Policies are taken at the start of the period, changes during the period are applied
+        // by the engine as they happen.
+        let farming_policies = get_farming_policies(client, start_block)
+            .await?
+            .into_iter()
+            .map(|policy| (policy.id, policy))
+            .collect();
+
+        Ok(Snapshot {
+            start_block,
+            end_block,
+            start_block_hash: start_block_hash.ok_or(ClientError::MissingBlock)?,
+            end_block_hash: end_block_hash.ok_or(ClientError::MissingBlock)?,
+            start_block_ts,
+            nodes,
+            power_states,
+            power_down_timestamps,
+            contracts,
+            connection_price,
+            farms,
+            twins,
+            payout_addresses,
+            farming_policies,
+        })
+    }
+}
+
+pub async fn get_nodes(client: &dyn RuntimeClient, block: u32) -> Result<Vec<Node>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let node_count = client.node_count(hash).await?;
+    let mut nodes = Vec::new();
+    for i in 1..=node_count {
+        if let Some(node) = client.node(i, hash).await? {
+            nodes.push(node);
+        }
+    }
+    Ok(nodes)
+}
+
+pub async fn get_twins(client: &dyn RuntimeClient, block: u32) -> Result<Vec<Twin>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let twin_count = client.twin_count(hash).await?;
+    let mut twins = Vec::new();
+    for i in 1..=twin_count {
+        if let Some(twin) = client.twin(i, hash).await? {
+            twins.push(twin);
+        }
+    }
+    Ok(twins)
+}
+
+pub async fn get_farms(client: &dyn RuntimeClient, block: u32) -> Result<Vec<Farm>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let farm_count = client.farm_count(hash).await?;
+    let mut farms = Vec::new();
+    for i in 1..=farm_count {
+        if let Some(farm) = client.farm(i, hash).await? {
+            farms.push(farm);
+        }
+    }
+    Ok(farms)
+}
+
+pub async fn get_payout_addresses(
+    client: &dyn RuntimeClient,
+    farms: &BTreeMap<u32, Farm>,
+    block: u32,
+) -> Result<BTreeMap<u32, String>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let mut addresses = BTreeMap::new();
+    for &id in farms.keys() {
+        match client.farm_payout_address(id, hash).await? {
+            Some(a) => {
+                addresses.insert(id, a);
+            }
+            None => continue,
+        }
+    }
+    Ok(addresses)
+}
+
+pub async fn get_contracts(
+    client: &dyn RuntimeClient,
+    block: u32,
+) -> Result<Vec<(Contract, Resources)>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let contract_count = client.contract_count(hash).await?;
+    let mut contracts = Vec::new();
+    for i in 1..=contract_count {
+        if let Some(contract) = client.contract(i, hash).await? {
+            if let Some(contract_resources) = client.contract_resources(i, hash).await? {
+                contracts.push((contract, contract_resources.used));
+            } else {
+                contracts.push((
+                    contract,
+                    Resources {
+                        hru: 0,
+                        sru: 0,
+                        cru: 0,
+                        mru: 0,
+                    },
+                ));
+            }
+        }
+    }
+    Ok(contracts)
+}
+
+pub async fn get_farming_policies(
+    client: &dyn RuntimeClient,
+    block: u32,
+) -> Result<Vec<FarmPolicy>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let policy_count = client.farming_policy_count(hash).await?;
+    let mut policies = Vec::new();
+    for i in 1..=policy_count {
+        if let Some(farm_policy) = client.farming_policy(i, hash).await? {
+            policies.push(farm_policy);
+        }
+    }
+    Ok(policies)
+}
+
+pub async fn get_power_states(
+    client: &dyn RuntimeClient,
+    block: u32,
+) -> Result<Vec<(u32, NodePower)>, ClientError> {
+    let hash = client.hash_at_height(Some(block)).await?;
+    let node_count = client.node_count(hash).await?;
+    let mut power_states = Vec::new();
+    for i in 1..=node_count {
+        if let Some(ps) = client.node_power(i, hash).await? {
+            power_states.push((i, ps));
+        }
+    }
+    Ok(power_states)
+}
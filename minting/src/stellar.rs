@@ -1,43 +1,64 @@
-use std::collections::HashMap;
+use crate::{
+    ledger::{LedgerError, LedgerPayment, LedgerTransaction, PaymentLedger},
+    transaction::decode_payments,
+};
+use async_trait::async_trait;
 use stellar_rs::horizon_client::HorizonClient;
 use stellar_rs::models::Order;
 use stellar_rs::transactions::prelude::TransactionsForAccountRequest;
 
-const TFT_ISSUER: &str = "GBOVQKJYHXRR3DX6NOX2RRYFRCUMSADGDESTDNBDS6CDVLGVESRTAC47";
+/// Account which issues TFT on mainnet, and which pays out the minting.
+pub const TFT_ISSUER: &str = "GBOVQKJYHXRR3DX6NOX2RRYFRCUMSADGDESTDNBDS6CDVLGVESRTAC47";
 const PAGE_LIMIT: u8 = 100;
+/// The amount of times a request to horizon is attempted before giving up.
+const MAX_ATTEMPTS: usize = 5;
+/// Time to wait between attempts of a request to horizon.
+const RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(2);
 
 pub struct Horizon {
     client: HorizonClient,
+    issuer: String,
 }
 
 impl Horizon {
-    /// Create a new horizon instance.
-    pub fn new(url: &str) -> Self {
+    /// Create a new horizon instance, for the payments of the given TFT issuer.
+    pub fn new(url: &str, issuer: &str) -> Self {
         Self {
             client: HorizonClient::new(url).unwrap(),
+            issuer: issuer.to_string(),
         }
     }
+}
 
-    pub async fn filter_previous_mints<V, W>(
-        &self,
-        mint_map: &mut HashMap<[u8; 32], V>,
-        other_mint_map: &mut HashMap<[u8; 32], W>,
-    ) {
+#[async_trait]
+impl PaymentLedger for Horizon {
+    async fn transactions(&self) -> Result<Vec<LedgerTransaction>, LedgerError> {
+        let mut transactions = Vec::new();
         let mut transactions_request = TransactionsForAccountRequest::new()
             .set_order(Order::Asc)
             .expect("Can set order")
             .set_limit(PAGE_LIMIT)
             .expect("Can set limit")
-            .set_account_id(TFT_ISSUER)
+            .set_account_id(&self.issuer)
             .expect("Can set acccount");
         loop {
-            let Ok(transactions_response) = self
-                .client
-                .get_transactions_for_account(&transactions_request)
-                .await
-            else {
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                continue;
+            let mut attempts = 0;
+            let transactions_response = loop {
+                attempts += 1;
+                match self
+                    .client
+                    .get_transactions_for_account(&transactions_request)
+                    .await
+                {
+                    Ok(response) => break response,
+                    Err(e) if attempts >= MAX_ATTEMPTS => {
+                        return Err(LedgerError::Unavailable {
+                            attempts,
+                            reason: e.to_string(),
+                        })
+                    }
+                    Err(_) => tokio::time::sleep(RETRY_DELAY).await,
+                }
             };
 
             for tx in transactions_response.embedded().records() {
@@ -45,10 +66,7 @@ impl Horizon {
                     continue;
                 }
                 if let Some(ref memo) = tx.memo() {
-                    let mut hash = [0; 32];
-                    base64::decode_config_slice(memo, base64::STANDARD, &mut hash).unwrap();
-                    mint_map.remove(&hash);
-                    other_mint_map.remove(&hash);
+                    transactions.push(ledger_transaction(memo, tx.envelope_xdr(), &self.issuer)?);
                 }
             }
 
@@ -65,5 +83,79 @@ impl Horizon {
                 )
                 .expect("Can set cursor");
         }
+        Ok(transactions)
+    }
+}
+
+/// The ledger transaction for a transaction with the base64 encoded memo hash and envelope. Anyone
+/// can send a transaction with a memo hash to the issuer, so a transaction which is not a plain
+/// payment of the issuer is recorded without payments, which never counts as a payout.
+fn ledger_transaction(
+    memo: &str,
+    envelope: &str,
+    issuer: &str,
+) -> Result<LedgerTransaction, LedgerError> {
+    let mut hash = [0; 32];
+    base64::decode_config_slice(memo, base64::STANDARD, &mut hash)
+        .map_err(|e| LedgerError::InvalidData(format!("memo {memo}: {e}")))?;
+    let payments = decode_payments(envelope, issuer)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(destination, amount)| LedgerPayment {
+            destination,
+            amount,
+        })
+        .collect();
+    Ok(LedgerTransaction {
+        memo: hex::encode(hash),
+        payments,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Transaction;
+
+    const DESTINATION: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+    fn memo() -> String {
+        base64::encode([3; 32])
+    }
+
+    #[test]
+    fn payments_of_the_issuer_are_recorded() {
+        let mut tx = Transaction::new(TFT_ISSUER, 7, [3; 32]).unwrap();
+        tx.add_payment(DESTINATION, 10).unwrap();
+        let transaction = ledger_transaction(&memo(), &tx.envelope_base64(), TFT_ISSUER).unwrap();
+        assert_eq!(transaction.memo, hex::encode([3; 32]));
+        let payments: Vec<_> = transaction
+            .payments
+            .iter()
+            .map(|p| (p.destination.as_str(), p.amount))
+            .collect();
+        assert_eq!(payments, vec![(DESTINATION, 10)]);
+    }
+
+    #[test]
+    fn other_transactions_are_recorded_without_payments() {
+        let mut tx = Transaction::new(TFT_ISSUER, 7, [3; 32]).unwrap();
+        tx.add_payment(DESTINATION, 10).unwrap();
+        // Preconditions which can't be decoded, e.g. of a PRECOND_V2 transaction.
+        let mut envelope = tx.envelope_xdr();
+        envelope[52..56].copy_from_slice(&2i32.to_be_bytes());
+        // A transaction of another account with the memo of a receipt.
+        let mut other = Transaction::new(DESTINATION, 7, [3; 32]).unwrap();
+        other.add_payment(DESTINATION, 10).unwrap();
+
+        for envelope in [
+            base64::encode(&envelope),
+            other.envelope_base64(),
+            "not an envelope".to_string(),
+        ] {
+            let transaction = ledger_transaction(&memo(), &envelope, TFT_ISSUER).unwrap();
+            assert_eq!(transaction.memo, hex::encode([3; 32]));
+            assert!(transaction.payments.is_empty());
+        }
     }
 }
@@ -0,0 +1,581 @@
+//! Unsigned Stellar payment transactions for the payouts of a period.
+//!
+//! Payouts are minted by the TFT issuer account. Every receipt is paid in a separate transaction,
+//! with a `MEMO_HASH` memo set to the hash of the receipt. Stellar only has a memo per
+//! transaction, not per operation, so payments for different receipts can't share a transaction
+//! without losing the link to their receipt. [`reconcile`] relies on this link to find which
+//! receipts have been paid.
+//!
+//! Transactions are encoded as XDR `TransactionEnvelope`s without signatures. The encoding is done
+//! by hand, as only a small part of the XDR definitions is needed here.
+//!
+//! [`reconcile`]: crate::reconcile::reconcile
+
+use std::fmt;
+
+/// Passphrase of the Stellar public network.
+pub const PUBLIC_NETWORK_PASSPHRASE: &str = "Public Global Stellar Network ; September 2015";
+/// Fee per operation, in stroops.
+pub const BASE_FEE: u32 = 100;
+
+const ASSET_CODE: &[u8; 4] = b"TFT\0";
+
+/// Version byte of an ed25519 public key in strkey encoding (`G...` addresses).
+const STRKEY_ED25519_PUBLIC: u8 = 6 << 3;
+
+// XDR discriminants.
+const ENVELOPE_TYPE_TX_V0: i32 = 0;
+const ENVELOPE_TYPE_TX: i32 = 2;
+const KEY_TYPE_ED25519: i32 = 0;
+const KEY_TYPE_MUXED_ED25519: i32 = 0x100;
+const PRECOND_NONE: i32 = 0;
+const PRECOND_TIME: i32 = 1;
+const MEMO_NONE: i32 = 0;
+const MEMO_TEXT: i32 = 1;
+const MEMO_ID: i32 = 2;
+const MEMO_HASH: i32 = 3;
+const MEMO_RETURN: i32 = 4;
+const OPERATION_PAYMENT: i32 = 1;
+const ASSET_TYPE_NATIVE: i32 = 0;
+const ASSET_TYPE_CREDIT_ALPHANUM4: i32 = 1;
+const ASSET_TYPE_CREDIT_ALPHANUM12: i32 = 2;
+
+/// Errors while building a transaction.
+#[derive(Debug)]
+pub enum TransactionError {
+    /// The address is not a valid Stellar account address.
+    InvalidAddress(String),
+    /// The amount can't be paid in a single operation.
+    InvalidAmount(u64),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::InvalidAddress(address) => {
+                write!(f, "invalid stellar address {address}")
+            }
+            TransactionError::InvalidAmount(amount) => write!(f, "invalid amount {amount}"),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// A payout which needs to be paid.
+pub struct Payout {
+    /// Stellar address of the receiver.
+    pub destination: String,
+    /// Amount of TFT units to pay. A TFT unit is exactly a stroop.
+    pub amount: u64,
+    /// Hash of the receipt which is paid.
+    pub receipt: [u8; 32],
+}
+
+struct PaymentOp {
+    destination: [u8; 32],
+    amount: i64,
+}
+
+/// An unsigned payment transaction from the TFT issuer.
+pub struct Transaction {
+    source: [u8; 32],
+    sequence: i64,
+    memo: [u8; 32],
+    operations: Vec<PaymentOp>,
+}
+
+impl Transaction {
+    /// Create a new transaction from the given TFT issuer without operations.
+    pub fn new(issuer: &str, sequence: i64, memo: [u8; 32]) -> Result<Self, TransactionError> {
+        Ok(Transaction {
+            source: decode_address(issuer)?,
+            sequence,
+            memo,
+            operations: Vec::new(),
+        })
+    }
+
+    /// The sequence number of the transaction.
+    pub fn sequence(&self) -> i64 {
+        self.sequence
+    }
+
+    /// The memo hash of the transaction.
+    pub fn memo(&self) -> [u8; 32] {
+        self.memo
+    }
+
+    /// Add a TFT payment to the transaction. The amount is expressed in TFT units.
+    pub fn add_payment(&mut self, destination: &str, amount: u64) -> Result<(), TransactionError> {
+        let destination = decode_address(destination)?;
+        if amount == 0 || amount > i64::MAX as u64 {
+            return Err(TransactionError::InvalidAmount(amount));
+        }
+        self.operations.push(PaymentOp {
+            destination,
+            amount: amount as i64,
+        });
+        Ok(())
+    }
+
+    /// Encode the transaction as XDR `Transaction`.
+    fn transaction_xdr(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        // sourceAccount: MuxedAccount
+        put_i32(&mut buf, KEY_TYPE_ED25519);
+        buf.extend_from_slice(&self.source);
+        // fee
+        put_u32(&mut buf, BASE_FEE * self.operations.len() as u32);
+        // seqNum
+        put_i64(&mut buf, self.sequence);
+        // cond: Preconditions
+        put_i32(&mut buf, PRECOND_NONE);
+        // memo: Memo
+        put_i32(&mut buf, MEMO_HASH);
+        buf.extend_from_slice(&self.memo);
+        // operations
+        put_u32(&mut buf, self.operations.len() as u32);
+        for op in &self.operations {
+            // sourceAccount: optional MuxedAccount, not set
+            put_u32(&mut buf, 0);
+            put_i32(&mut buf, OPERATION_PAYMENT);
+            // destination: MuxedAccount
+            put_i32(&mut buf, KEY_TYPE_ED25519);
+            buf.extend_from_slice(&op.destination);
+            // asset: Asset
+            put_i32(&mut buf, ASSET_TYPE_CREDIT_ALPHANUM4);
+            buf.extend_from_slice(ASSET_CODE);
+            put_i32(&mut buf, KEY_TYPE_ED25519);
+            buf.extend_from_slice(&self.source);
+            // amount
+            put_i64(&mut buf, op.amount);
+        }
+        // ext
+        put_i32(&mut buf, 0);
+        buf
+    }
+
+    /// Encode the transaction as XDR `TransactionEnvelope`, without signatures.
+    pub fn envelope_xdr(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_i32(&mut buf, ENVELOPE_TYPE_TX);
+        buf.extend(self.transaction_xdr());
+        // signatures
+        put_u32(&mut buf, 0);
+        buf
+    }
+
+    /// The base64 encoded unsigned transaction envelope.
+    pub fn envelope_base64(&self) -> String {
+        base64::encode(self.envelope_xdr())
+    }
+
+    /// The hash of the transaction on the given network. This is the payload which is signed.
+    pub fn hash(&self, network_passphrase: &str) -> [u8; 32] {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&sp_core::hashing::sha2_256(network_passphrase.as_bytes()));
+        put_i32(&mut payload, ENVELOPE_TYPE_TX);
+        payload.extend(self.transaction_xdr());
+        sp_core::hashing::sha2_256(&payload)
+    }
+}
+
+/// Build the transactions for a set of payouts, with a single payment per transaction. The
+/// transactions get consecutive sequence numbers, starting after the current sequence number of
+/// the TFT issuer account. Payouts which can't be paid are returned with the reason, and don't
+/// consume a sequence number.
+pub fn payout_transactions(
+    issuer: &str,
+    payouts: Vec<Payout>,
+    account_sequence: i64,
+) -> (Vec<Transaction>, Vec<(Payout, TransactionError)>) {
+    let mut transactions = Vec::with_capacity(payouts.len());
+    let mut failed = Vec::new();
+    let mut sequence = account_sequence;
+    for payout in payouts {
+        let mut tx = Transaction::new(issuer, sequence + 1, payout.receipt)
+            .expect("TFT issuer is a valid address");
+        match tx.add_payment(&payout.destination, payout.amount) {
+            Ok(()) => {
+                sequence += 1;
+                transactions.push(tx);
+            }
+            Err(e) => failed.push((payout, e)),
+        }
+    }
+    (transactions, failed)
+}
+
+/// Decode the TFT payments made by the issuer in a base64 encoded transaction envelope, as
+/// (destination, amount) pairs. Only transactions of the issuer which consist solely of payments
+/// can be decoded, `None` is returned for other transactions. Payments with another source than
+/// the issuer are not payouts, and are left out. TFT is identified by the given issuer.
+pub(crate) fn decode_payments(envelope: &str, issuer: &str) -> Option<Vec<(String, u64)>> {
+    let data = base64::decode(envelope).ok()?;
+    let mut r = XdrReader { data: &data };
+    let tft_issuer = decode_address(issuer).ok()?;
+
+    let source = match r.i32()? {
+        ENVELOPE_TYPE_TX_V0 => {
+            let source = r.key()?;
+            // fee, seqNum
+            r.take(4 + 8)?;
+            // timeBounds: optional
+            if r.u32()? == 1 {
+                r.take(16)?;
+            }
+            source
+        }
+        ENVELOPE_TYPE_TX => {
+            let source = r.muxed_account()?;
+            // fee, seqNum
+            r.take(4 + 8)?;
+            match r.i32()? {
+                PRECOND_NONE => {}
+                PRECOND_TIME => {
+                    r.take(16)?;
+                }
+                _ => return None,
+            }
+            source
+        }
+        _ => return None,
+    };
+    if source != tft_issuer {
+        return None;
+    }
+
+    match r.i32()? {
+        MEMO_NONE => {}
+        MEMO_TEXT => {
+            let len = r.u32()? as usize;
+            r.take(len.div_ceil(4) * 4)?;
+        }
+        MEMO_ID => {
+            r.take(8)?;
+        }
+        MEMO_HASH | MEMO_RETURN => {
+            r.take(32)?;
+        }
+        _ => return None,
+    }
+
+    let mut payments = Vec::new();
+    for _ in 0..r.u32()? {
+        let from_issuer = r.u32()? == 0 || r.muxed_account()? == tft_issuer;
+        if r.i32()? != OPERATION_PAYMENT {
+            return None;
+        }
+        let destination = r.muxed_account()?;
+        let is_tft = match r.i32()? {
+            ASSET_TYPE_NATIVE => false,
+            ASSET_TYPE_CREDIT_ALPHANUM4 => {
+                let code = r.take(4)?;
+                code == ASSET_CODE && r.account_id()? == tft_issuer
+            }
+            ASSET_TYPE_CREDIT_ALPHANUM12 => {
+                r.take(12)?;
+                r.account_id()?;
+                false
+            }
+            _ => return None,
+        };
+        let amount = r.i64()?;
+        if is_tft && from_issuer {
+            payments.push((encode_address(&destination), amount as u64));
+        }
+    }
+    Some(payments)
+}
+
+/// Minimal reader for XDR encoded data.
+struct XdrReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> XdrReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Some(head)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn key(&mut self) -> Option<[u8; 32]> {
+        self.take(32)?.try_into().ok()
+    }
+
+    /// Read an `AccountID`, returning the ed25519 key.
+    fn account_id(&mut self) -> Option<[u8; 32]> {
+        if self.i32()? != KEY_TYPE_ED25519 {
+            return None;
+        }
+        self.key()
+    }
+
+    /// Read a `MuxedAccount`, returning the ed25519 key.
+    fn muxed_account(&mut self) -> Option<[u8; 32]> {
+        match self.i32()? {
+            KEY_TYPE_ED25519 => self.key(),
+            KEY_TYPE_MUXED_ED25519 => {
+                self.take(8)?;
+                self.key()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Encode a raw ed25519 public key as strkey (`G...` address).
+pub(crate) fn encode_address(key: &[u8; 32]) -> String {
+    let mut data = Vec::with_capacity(35);
+    data.push(STRKEY_ED25519_PUBLIC);
+    data.extend_from_slice(key);
+    let checksum = crc16_xmodem(&data);
+    data.extend_from_slice(&checksum.to_le_bytes());
+    base32_encode(&data)
+}
+
+/// Decode a strkey encoded ed25519 public key (`G...` address) to the raw key.
+fn decode_address(address: &str) -> Result<[u8; 32], TransactionError> {
+    let invalid = || TransactionError::InvalidAddress(address.to_string());
+    let data = base32_decode(address).ok_or_else(invalid)?;
+    if data.len() != 35 || data[0] != STRKEY_ED25519_PUBLIC {
+        return Err(invalid());
+    }
+    let checksum = u16::from_le_bytes([data[33], data[34]]);
+    if crc16_xmodem(&data[..33]) != checksum {
+        return Err(invalid());
+    }
+    let mut key = [0; 32];
+    key.copy_from_slice(&data[1..33]);
+    Ok(key)
+}
+
+/// Decode unpadded RFC 4648 base32.
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in input.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+/// Encode data as unpadded RFC 4648 base32.
+fn base32_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// CRC16-XModem checksum, as used by strkey.
+fn crc16_xmodem(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn put_i32(buf: &mut Vec<u8>, value: i32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_i64(buf: &mut Vec<u8>, value: i64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stellar::TFT_ISSUER;
+
+    /// The address of the all zero key.
+    const ZERO_ADDRESS: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+    #[test]
+    fn address_round_trip() {
+        assert_eq!(decode_address(ZERO_ADDRESS).unwrap(), [0; 32]);
+        assert_eq!(encode_address(&[0; 32]), ZERO_ADDRESS);
+
+        let issuer = decode_address(TFT_ISSUER).unwrap();
+        assert_eq!(encode_address(&issuer), TFT_ISSUER);
+
+        for key in [[1; 32], [0xff; 32], *b"0123456789abcdef0123456789abcdef"] {
+            assert_eq!(decode_address(&encode_address(&key)).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        // Wrong checksum.
+        let mut address = TFT_ISSUER.to_string();
+        address.replace_range(10..11, "A");
+        assert!(decode_address(&address).is_err());
+        // Not base32.
+        assert!(decode_address(&TFT_ISSUER.to_lowercase()).is_err());
+        // Too short.
+        assert!(decode_address(&TFT_ISSUER[..40]).is_err());
+        // A secret seed rather than a public key.
+        let mut seed = vec![18 << 3];
+        seed.extend_from_slice(&[0; 32]);
+        let checksum = crc16_xmodem(&seed);
+        seed.extend_from_slice(&checksum.to_le_bytes());
+        assert!(decode_address(&base32_encode(&seed)).is_err());
+    }
+
+    #[test]
+    fn payments_round_trip() {
+        let mut tx = Transaction::new(TFT_ISSUER, 7, [3; 32]).unwrap();
+        tx.add_payment(ZERO_ADDRESS, 15_000_000).unwrap();
+        tx.add_payment(&encode_address(&[1; 32]), 1).unwrap();
+        let envelope = tx.envelope_base64();
+
+        assert_eq!(
+            decode_payments(&envelope, TFT_ISSUER).unwrap(),
+            vec![
+                (ZERO_ADDRESS.to_string(), 15_000_000),
+                (encode_address(&[1; 32]), 1)
+            ]
+        );
+        // TFT of another issuer is not TFT.
+        let mut envelope = tx.envelope_xdr();
+        envelope[152..184].copy_from_slice(&[0; 32]);
+        assert_eq!(
+            decode_payments(&base64::encode(&envelope), TFT_ISSUER).unwrap(),
+            vec![(encode_address(&[1; 32]), 1)]
+        );
+    }
+
+    #[test]
+    fn only_payments_of_the_issuer_are_decoded() {
+        // A transaction of another account is not a payout, even if it pays TFT.
+        let mut tx = Transaction::new(ZERO_ADDRESS, 7, [3; 32]).unwrap();
+        tx.add_payment(ZERO_ADDRESS, 10).unwrap();
+        assert!(decode_payments(&tx.envelope_base64(), TFT_ISSUER).is_none());
+
+        // A payment operation of the issuer's transaction with another source is not counted.
+        let mut tx = Transaction::new(TFT_ISSUER, 7, [3; 32]).unwrap();
+        tx.add_payment(ZERO_ADDRESS, 10).unwrap();
+        let mut envelope = tx.envelope_xdr();
+        let source_account = [&1u32.to_be_bytes()[..], &[0; 4], &[1; 32]].concat();
+        envelope.splice(96..100, source_account);
+        assert_eq!(
+            decode_payments(&base64::encode(&envelope), TFT_ISSUER).unwrap(),
+            vec![]
+        );
+        // Unless the source of the operation is the issuer itself.
+        let mut envelope = tx.envelope_xdr();
+        let source_account = [
+            &1u32.to_be_bytes()[..],
+            &[0; 4],
+            &decode_address(TFT_ISSUER).unwrap(),
+        ]
+        .concat();
+        envelope.splice(96..100, source_account);
+        assert_eq!(
+            decode_payments(&base64::encode(&envelope), TFT_ISSUER).unwrap(),
+            vec![(ZERO_ADDRESS.to_string(), 10)]
+        );
+    }
+
+    #[test]
+    fn undecodable_envelopes() {
+        let mut tx = Transaction::new(TFT_ISSUER, 7, [3; 32]).unwrap();
+        assert_eq!(
+            decode_payments(&tx.envelope_base64(), TFT_ISSUER).unwrap(),
+            vec![]
+        );
+        // Truncated in the middle of a payment.
+        tx.add_payment(ZERO_ADDRESS, 10).unwrap();
+        let mut envelope = tx.envelope_xdr();
+        envelope.truncate(envelope.len() - 20);
+        assert!(decode_payments(&base64::encode(&envelope), TFT_ISSUER).is_none());
+        // Not base64.
+        assert!(decode_payments("not an envelope", TFT_ISSUER).is_none());
+    }
+
+    #[test]
+    fn invalid_payouts_keep_sequence() {
+        let payouts = vec![
+            Payout {
+                destination: ZERO_ADDRESS.to_string(),
+                amount: 10,
+                receipt: [1; 32],
+            },
+            Payout {
+                destination: "GINVALID".to_string(),
+                amount: 10,
+                receipt: [2; 32],
+            },
+            Payout {
+                destination: ZERO_ADDRESS.to_string(),
+                amount: 0,
+                receipt: [3; 32],
+            },
+            Payout {
+                destination: ZERO_ADDRESS.to_string(),
+                amount: 20,
+                receipt: [4; 32],
+            },
+        ];
+        let (transactions, failed) = payout_transactions(TFT_ISSUER, payouts, 100);
+        let sequences: Vec<_> = transactions.iter().map(Transaction::sequence).collect();
+        assert_eq!(sequences, vec![101, 102]);
+        assert_eq!(transactions[1].memo(), [4; 32]);
+        assert_eq!(failed.len(), 2);
+    }
+}
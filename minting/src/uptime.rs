@@ -0,0 +1,1135 @@
+//! Uptime tracking for individual nodes.
+//!
+//! Nodes periodically report their uptime to the chain, and farmer bot managed nodes additionally
+//! have their power state and power target changed through events. The [`UptimeTracker`] consumes
+//! these events for a single node, and keeps track of the uptime which can be credited to the node
+//! in the period, as well as any violation the node commits while doing so.
+//!
+//! Events are processed in one of two [`TrackingMode`]s. While in the period, every uptime report
+//! is evaluated. After the period ended, only a single uptime report is considered, which is used
+//! to credit the uptime between the last report in the period and the end of the period.
+
+use crate::{period::Period, violation::Violation};
+use chrono::prelude::*;
+use tfchain_client::types::{NodePower, Power, PowerState};
+
+/// Currently on TFchain, the weight limit of a block is `2_000_000_000_000`. The vast majority of
+/// calls are `UptimeReported` calls and `billContractForBlock` calls. The former has an associated
+/// weight of `446_058_000`, while the latter has an associated weight of `780_660_000`. Given that
+/// a node sends an uptime report every 40 minutes, the expected amount of uptime reports is (nr
+/// nodes * 1.5) per hour, while the expected billContractForBlock call amount per hour is simply
+/// nr contracts. Additionally, as part of billing we also have `addNruReports`, which is called
+/// once per hour for every contract (technically nodes with contracts call this once per hour and
+/// it contains multiple contracts, but the weight scales roughly linearly with the amount of
+/// contracts). The weight of one call is `473_727_000`. For calculating the density of these
+/// calls, we assume all nodes are up. This gives about 5500 nodes. We also assume 750 contracts
+/// (values are taken at the time of writing).
+///
+/// Since uptime reporting depends on boot time, and contract billing depends on contract creation
+/// time, we can use the law of large numbers to model these events as evenly distributed across
+/// the available blocks. With a block time of 6 seconds, there are assumed to be 600 blocks per
+/// hour for a fully functioning chain. Additionally, if a block is missed, the calls for that
+/// block will be added to the next block, recursively, until a block is created. This means that,
+/// a block contains all calls for itself and all previously missed blocks.
+///
+/// For a fully functioning chain, the used weight is 5500/600*780_660_000 +
+/// 750/600*(446_058_000+473_727_000). This amounts to 7_156_050_000 + 1_149_731_250 =
+/// 8_305_892_250. Considering the previously established block allowed weight of 2E12, that equals
+/// to an average of 0.415% of a block. Even if we are pessimistic and assume the actual weight of
+/// calls should be double, AND the chain increases 10 times in size of both nodes and contracts,
+/// we are only at 8.30% weight, which should not be a problem at all. Considering there are 10
+/// block creators, this means we could loose all of them but one and still not have a problem for
+/// nodes to push uptimes (note the chain requires more than 2/3 i.e. at least 7 block creators
+/// before finalization stalled, so we can assume at most 3 nodes are down at once).
+///
+/// Considering these constraints, and the invariant that nodes send an uptime report every 40
+/// minutes, 1 minute is a sufficiently large grace period: this allows for nodes to _still_ get
+/// their uptime reports in the chain even if all nodes on the chain are down except one (which as
+/// discussed above is a critical chain situation).
+///
+/// Numbers are accurate as of 2023-05-08.
+pub const UPTIME_GRACE_PERIOD_SECONDS: i64 = 60; // 1 Minute
+/// The maximum allowed clock drift while measuring. Ideally this should be less and this should
+/// probably be constrained in the future. We take twice the amount of UPTIME_GRACE_PERIOD_SECONDS
+/// for now because we consider that a node can have a skew in one direction of up to this amount,
+/// and it would technically be possible to have the same skew in the opposing direction without
+/// being considered a validation.
+///
+/// In practice, a skew which is allowed by the above will happen in one direction and then be
+/// fixed later, so technically speaking a copy of the above should be sufficient. To be validated.
+// FIXME: This check is faulty as it is way to broad in it's current form, and malfunctioning nodes
+// might not be detected.
+pub const CLOCK_SKEW_INTERVAL: i64 = 2 * UPTIME_GRACE_PERIOD_SECONDS;
+pub const NODE_UPTIME_REPORT_INTERVAL_SECONDS: i64 = 60 * 40; // 40 minutes
+/// Maximum amount of seconds a node can be offline because of the power managment feature while
+/// still getting rewards.
+pub const MAX_POWER_MANAGER_DOWNTIME: u64 = 60 * 60 * 24;
+/// Maximum amount of seconds a node has before it needs to be booted as result of a farmer bot
+/// power up request.
+pub const MAX_POWER_MANAGER_BOOT_TIME: i64 = 60 * 30;
+/// The maximum amount of boot violations (for not booting fast enough) we allow for power managed
+/// nodes before striking a violation.
+pub const MAX_ALLOWED_BOOT_VIOLATIONS: usize = 1;
+
+/// The way events are interpreted by an [`UptimeTracker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackingMode {
+    /// Events happen inside the period. Every uptime report is evaluated, and violations are
+    /// recorded.
+    InPeriod,
+    /// Events happen after the period ended. Only the first uptime report is used, to credit the
+    /// time between the last report in the period and the end of the period.
+    PostPeriod,
+}
+
+/// Uptime state of a single node over a period.
+pub struct UptimeTracker {
+    node_id: u32,
+    period: Period,
+    /// Timestamp of the first block in the period.
+    start_block_ts: i64,
+    mode: TrackingMode,
+    power: NodePower,
+    // (last ping, last reported uptime, total uptime).
+    uptime_info: Option<(i64, u64, u64)>,
+    // (boot time, original boot time record).
+    boot_time: Option<(i64, i64)>,
+    violation: Violation,
+    // Timestamp the node changed powerstate to down, before a new uptime was posted.
+    power_managed: Option<i64>,
+    /// Time the last power manage target changed to up. We keep track of this to make sure we
+    /// always have a node go up after target is set to up (i.e. farmerbot powers on a node).
+    /// Cleared when node boots.
+    power_manage_boot: Option<i64>,
+    /// Track the amount of times a farmerbot managed node failed to wake up within 30 minutes.
+    boot_duration_violations: usize,
+}
+
+impl UptimeTracker {
+    /// Create a new tracker for a node which is up, and not managed by the farmer bot.
+    pub fn new(node_id: u32, period: Period, start_block_ts: i64) -> Self {
+        UptimeTracker {
+            node_id,
+            period,
+            start_block_ts,
+            mode: TrackingMode::InPeriod,
+            power: NodePower {
+                state: PowerState::Up,
+                target: Power::Up,
+            },
+            uptime_info: None,
+            boot_time: None,
+            violation: Violation::None,
+            power_managed: None,
+            power_manage_boot: None,
+            boot_duration_violations: 0,
+        }
+    }
+
+    /// Create a new tracker for a node with a known power state at the start of the period.
+    /// `powered_down_at` is the timestamp of the block in which the node went down, if the power
+    /// state is [`PowerState::Down`].
+    pub fn with_power(
+        node_id: u32,
+        period: Period,
+        start_block_ts: i64,
+        power: NodePower,
+        powered_down_at: Option<i64>,
+    ) -> Self {
+        let mut tracker = Self::new(node_id, period, start_block_ts);
+        if let (PowerState::Down(_), Some(ts)) = (&power.state, powered_down_at) {
+            tracker.power_managed = Some(ts);
+            if let Power::Up = power.target {
+                // Set the powerup request as start timestamp. Technically this is wrong,
+                // however this will be validated properly in the previous period in the
+                // post period checks.
+                tracker.power_manage_boot = Some(start_block_ts);
+            }
+        }
+        tracker.power = power;
+        tracker
+    }
+
+    /// Switch the way subsequent events are interpreted.
+    pub fn set_mode(&mut self, mode: TrackingMode) {
+        self.mode = mode;
+    }
+
+    /// The total uptime credited to the node, if it reported uptime at all.
+    pub fn measured_uptime(&self) -> Option<u64> {
+        self.uptime_info.map(|(_, _, total_uptime)| total_uptime)
+    }
+
+    /// The violation the node commited, if any.
+    pub fn violation(&self) -> &Violation {
+        &self.violation
+    }
+
+    /// Record a violation for the node. Only the first violation is kept. Returns true if the
+    /// violation was recorded.
+    pub fn flag_violation(&mut self, violation: Violation) -> bool {
+        if self.violation.is_none() {
+            self.violation = violation;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Process an uptime report of the node, sent in block `height` with timestamp `ts`.
+    pub fn on_uptime_report(
+        &mut self,
+        height: u32,
+        ts: i64,
+        current_time: u64,
+        reported_uptime: u64,
+        log: &mut Vec<String>,
+    ) {
+        match self.mode {
+            TrackingMode::InPeriod => {
+                self.uptime_in_period(height, ts, current_time, reported_uptime, log)
+            }
+            TrackingMode::PostPeriod => {
+                self.uptime_post_period(height, ts, current_time, reported_uptime, log)
+            }
+        }
+    }
+
+    /// Process a change of the power target of the node.
+    pub fn on_power_target_changed(&mut self, ts: i64, target: Power, log: &mut Vec<String>) {
+        log.push(format!(
+            "Power target changed for node {} from {:?} to {:?}",
+            self.node_id, self.power.target, target,
+        ));
+        // Remember a rising edge here to validate node actually boots.
+        // This is cleared when a node sends an uptime report of a _reboot_. It is
+        // allowed for this to happen if a rising edge is not consumed yet, in which
+        // case the new event is ignored, as we want to measure time from the first
+        // event and it is actually a good idea to send multiple of these if the node
+        // does not react. Of course, we also only want to track this if the node is
+        // currently power managed. While we shouldn't try to boot an online node,
+        // there is no _real_ harm in doing it anyway.
+        if target == Power::Up && matches!(self.power.state, PowerState::Down(_)) {
+            // Only remember the first boot request.
+            if self.power_manage_boot.is_none() {
+                self.power_manage_boot = Some(ts);
+                log.push(format!(
+                    "Remembered boot request time for node {}",
+                    self.node_id
+                ));
+            }
+        }
+        self.power.target = target;
+    }
+
+    /// Process a change of the power state of the node.
+    ///
+    /// After the period, this is technically not needed since we don't care for actual state
+    /// changes. After all, we only use this to arm a trigger to catch farmerbot wakes up. This
+    /// trigger is set when the node goes from up to down, and in doing so it also sends an uptime
+    /// report to chain. Since we are post period now, if the node goes to sleep now its sleep time
+    /// won't influence the current period. Regardless keep track of state changes and rely on the
+    /// fact that we only allow 1 uptime post period to do the proper thing.
+    pub fn on_power_state_changed(&mut self, ts: i64, state: PowerState, log: &mut Vec<String>) {
+        log.push(format!(
+            "Power state changed for node {} from {:?} to {:?}",
+            self.node_id, self.power.state, state,
+        ));
+        // Add exception to allow node 1 uptime ping once it gets back on which
+        // indicates a reboot.
+        // Also, we only allow this if the target is down as well.
+        if self.power.target == Power::Down
+            // Only on state transition
+            && self.power.state == PowerState::Up
+            && matches!(state, PowerState::Down(_))
+        {
+            // Either this is Some(timestamp), indicating a previous state
+            // transition which was not followed by an uptime ping once the node
+            // came online. In this case, we ignore that here. This would mean the
+            // node did not come up again.
+            // Otherwise, if None, set the current timestamp as time of going down.
+            if self.power_managed.is_none() {
+                // Also add an implicit uptime.
+                self.power_managed = Some(ts);
+                // While we are at it, credit uptime since last uptime event as
+                // well, as we will use this timestamp as the base for future
+                // uptime calculations.
+                // We don't have to overwrite this since future calculations will
+                // first work on the saved power_managed variable, and will have a
+                // reboot either way.
+                if self.mode == TrackingMode::InPeriod {
+                    if let Some((last_reported_at, _, mut total_uptime)) = self.uptime_info {
+                        let delta = ts - last_reported_at;
+                        assert!(delta >= 0, "Power state changes can't travel back in time");
+                        total_uptime += delta as u64;
+                        // We can set uptime to 0, node will reboot anyway.
+                        self.uptime_info = Some((ts, 0, total_uptime));
+                    }
+                }
+                log.push(format!(
+                    "Remembered farmer bot shutdown for node {}",
+                    self.node_id
+                ));
+            }
+        }
+        self.power.state = state;
+    }
+
+    /// Finish tracking for the period.
+    ///
+    /// At this point we are done fetching events. Note that for the case of power manager boot
+    /// requests, we haven't checked the case where the node does not respond at all. We already
+    /// fetched a days worth of blocks after the period ended, and don't keep track of power on
+    /// requests there. So any leftover requests here are already a day old, which is way too much.
+    /// So if the node has an outstanding power on request here, mark a boot failure.
+    ///
+    /// On top of this, if the node has more than the allowed amount of boot failures, stick a
+    /// violation on it if there isn't another one already.
+    pub fn finalize(&mut self, log: &mut Vec<String>) {
+        // First see if we need to mark another failure to boot in time.
+        if let Some(boot_request) = self.power_manage_boot {
+            // Ignore if this is the same as start, no need to slap a violation on what is likely a
+            // dead node.
+            if boot_request == self.start_block_ts {
+                log.push(format!("Not giving node {} a slow boot violation since it never tried to boot in the first place", self.node_id));
+            } else if boot_request > self.period.end() {
+                // Boot request (and possible failure) is entirely past the current period so we
+                // reserve the violation for next minting.
+                log.push(format!("Not giving node {} a slow boot violation since the wakup request happened post period", self.node_id));
+            } else {
+                self.boot_duration_violations += 1;
+                log.push(format!(
+                    "Detected farmer bot boot violation for node {}, request was done at {} but node never booted",
+                    self.node_id,
+                    Utc.timestamp_opt(boot_request, 0).unwrap().to_rfc2822(),
+                ));
+            }
+        }
+
+        // Then slap on a violation if needed
+        if self.boot_duration_violations > MAX_ALLOWED_BOOT_VIOLATIONS {
+            self.flag_violation(Violation::BootRequestExpired {
+                failed_boots: self.boot_duration_violations,
+            });
+        }
+    }
+
+    fn uptime_in_period(
+        &mut self,
+        height: u32,
+        ts: i64,
+        current_time: u64,
+        reported_uptime: u64,
+        log: &mut Vec<String>,
+    ) {
+        let start_ts = self.period.start();
+        // We are power managed and got a request to wake up.
+        match (self.power_managed, self.power_manage_boot) {
+            (Some(time_set_down), Some(boot_request)) => {
+                // Ignore the event if it is sent after the node is supposed to go down,
+                // this will be accounted for once the node starts up again.
+                // For the node to have been properly power managed, it must be booted
+                // after it was set to down.
+                if (current_time - reported_uptime) as i64 > time_set_down {
+                    // node got power managed to down
+                    let time_delta = current_time as i64 - time_set_down;
+                    assert!(time_delta >= 0, "uptime events can't travel back in time");
+                    let (_, _, mut total_uptime) = self.uptime_info.unwrap_or_default();
+                    // Only add uptime if node boot did not violate any constraints.
+                    let mut credit_uptime = true;
+                    if time_delta as u64 > MAX_POWER_MANAGER_DOWNTIME {
+                        credit_uptime = false;
+                        log.push(format!("Refusing to credit uptime for power managed node {} as the last boot was {time_delta} seconds ago, more than the allowed 24 hours", self.node_id));
+                    }
+                    if (current_time - reported_uptime) as i64 - boot_request
+                        > MAX_POWER_MANAGER_BOOT_TIME
+                    {
+                        credit_uptime = false;
+                        // Mark a violation on the node
+                        self.boot_duration_violations += 1;
+                        log.push(format!("Detected farmer bot boot violation for node {}, request was done at {} but node only came online at {}",
+                            self.node_id,
+                            Utc.timestamp_opt(boot_request, 0).unwrap().to_rfc2822(),
+                            Utc.timestamp_opt((current_time - reported_uptime) as i64, 0).unwrap().to_rfc2822()
+                        ));
+                    }
+                    if credit_uptime {
+                        // Check and scale to match the actual period start if needed
+                        if time_set_down < start_ts {
+                            total_uptime += (current_time as i64 - start_ts) as u64;
+                            log.push(format!(
+                                "Added {} seconds of uptime for node {}, scaled in period start",
+                                current_time as i64 - start_ts,
+                                self.node_id
+                            ));
+                        } else {
+                            total_uptime += time_delta as u64;
+                            log.push(format!(
+                                "Added {time_delta} seconds of uptime for node {}",
+                                self.node_id
+                            ));
+                        }
+                    }
+                    // Clear the fact that we got power managed, if it is still the case, it
+                    // will be set again in the proper event handler.
+                    self.power_managed = None;
+                    self.power_manage_boot = None;
+                    self.uptime_info = Some((current_time as i64, reported_uptime, total_uptime));
+                    // Also mark a boot
+                    self.boot_time =
+                        Some(((current_time - reported_uptime) as i64, current_time as i64));
+                } else {
+                    log.push(format!("Ignoring uptime event for node {} as it happened before the node powered down after being requested to do so", self.node_id));
+                }
+            }
+            // We are power managed but woke up without boot request. We explicitly ignore this: being
+            // put to sleep by the farmer bot requires a wakeup from the farmer bot. This case also
+            // means nodes just go to sleep anyhow.
+            (Some(_), None) => {
+                log.push(format!("Ignoring boot for node {} which is power managed, but did not get a boot request from the farmer bot", self.node_id));
+            }
+            // We got a wakeup request from farmer bot but we are not sleeping due to
+            // the farmer bot. This should not happen.
+            (None, Some(_)) => {
+                log.push(format!("Ignoring uptime for node {} after farmer bot asked for a boot while the node was not sleeping as a result of farmer bot", self.node_id));
+            }
+            (None, None) => {
+                if let Some((last_reported_at, last_reported_uptime, mut total_uptime)) =
+                    self.uptime_info
+                {
+                    let report_delta = current_time as i64 - last_reported_at;
+                    let uptime_delta = reported_uptime as i64 - last_reported_uptime as i64;
+                    // There are quite some situations here. Notice that due to the
+                    // blockchain only producing blocks every 6 seconds, and network delay
+                    // + a host of other issues, we will allow a node to report uptime with
+                    // "grace period" of a minute or so in either direction.
+                    //
+                    // 1. uptime_delta > report_delta + GRACE_PERIOD. Node is talking
+                    //    rubish.
+                    if uptime_delta > report_delta + UPTIME_GRACE_PERIOD_SECONDS {
+                        self.flag_violation(Violation::UptimeTooHigh {
+                            previous_uptime: last_reported_uptime,
+                            previous_timestamp: last_reported_at,
+                            reported_uptime,
+                            reported_timestamp: ts,
+                            block_reported: height,
+                        });
+                        self.uptime_info =
+                            Some((current_time as i64, reported_uptime, total_uptime));
+
+                        log.push(format!("Node {} reported an uptime increase of {uptime_delta} seconds, while reports are {report_delta} seconds appart", self.node_id));
+                        return;
+                    }
+                    // 2. The difference in uptime is within reason of the difference in
+                    //    report times, i.e. the node is properly reporting.
+                    if uptime_delta <= report_delta + UPTIME_GRACE_PERIOD_SECONDS
+                        && uptime_delta >= report_delta - UPTIME_GRACE_PERIOD_SECONDS
+                    {
+                        self.check_clock_skew(current_time, reported_uptime, log);
+
+                        // It is technically possible for the delta to be less than 0 and
+                        // within the expected time frame. If nodes boot, send uptime, then
+                        // immediately reboot that is possible. In those cases, handle that
+                        // below, as that is the reboot detection.
+                        if uptime_delta > 0 {
+                            // Simply add the uptime delta. If this is too large or low by a
+                            // couple of seconds it will be corrected by the next pings anyhow.
+                            // That being said, we also limit the amount of uptime credit
+                            // to the uptime report interval + grace period, as healthy
+                            // nodes _must_ ping every interval amount of time
+                            let credit = u64::min(
+                                uptime_delta as u64,
+                                (NODE_UPTIME_REPORT_INTERVAL_SECONDS + UPTIME_GRACE_PERIOD_SECONDS)
+                                    as u64,
+                            );
+                            total_uptime += credit;
+                            if credit != uptime_delta as u64 {
+                                log.push(format!("credited node {} with {credit} seconds of uptime, less than the reported {uptime_delta} seconds as the gap is too big", self.node_id));
+                            } else {
+                                log.push(format!(
+                                    "credited node {} with {credit} seconds of reported uptime",
+                                    self.node_id
+                                ));
+                            }
+                            self.uptime_info =
+                                Some((current_time as i64, reported_uptime, total_uptime));
+                            return;
+                        }
+                    }
+                    // 3. The difference in uptime is too low. Again there are multiple
+                    //    scenarios. Either way we consider the node rebooted. Depending on
+                    //    the reported uptime, the node reports legit uptime, or it reports
+                    //    an uptime which is too high.
+                    //
+                    //    1. Uptime is within bounds.
+                    if reported_uptime as i64 <= report_delta {
+                        let credit = u64::min(
+                            reported_uptime,
+                            (NODE_UPTIME_REPORT_INTERVAL_SECONDS + UPTIME_GRACE_PERIOD_SECONDS)
+                                as u64,
+                        );
+                        total_uptime += credit;
+                        if reported_uptime != credit {
+                            log.push(format!("credited node {} with {credit} seconds of uptime after a reboot, less than the reported {reported_uptime} seconds as the gap is too big", self.node_id));
+                        } else {
+                            log.push(format!(
+                                "credited node {} with {credit} seconds of reported uptime after a reboot",
+                                self.node_id
+                            ));
+                        }
+                        self.uptime_info =
+                            Some((current_time as i64, reported_uptime, total_uptime));
+                        self.boot_time =
+                            Some(((current_time - reported_uptime) as i64, current_time as i64));
+                        return;
+                    }
+                    self.invalid_reboot(
+                        height,
+                        ts,
+                        reported_uptime,
+                        last_reported_at,
+                        last_reported_uptime,
+                        log,
+                    );
+                } else {
+                    let period_duration = current_time as i64 - start_ts;
+                    // Make sure we don't give more credit than the current length of the
+                    // period.
+                    // Account for uptime period
+                    let up_in_period = u64::min(
+                        std::cmp::min(period_duration as u64, reported_uptime),
+                        (NODE_UPTIME_REPORT_INTERVAL_SECONDS + UPTIME_GRACE_PERIOD_SECONDS) as u64,
+                    );
+                    log.push(format!("Node {} reported uptime of {reported_uptime} seconds, scaled to {up_in_period} seconds", self.node_id));
+                    // Save uptime info
+                    self.uptime_info = Some((current_time as i64, reported_uptime, up_in_period));
+                    self.boot_time =
+                        Some(((current_time - reported_uptime) as i64, current_time as i64));
+                }
+            }
+        };
+    }
+
+    /// Collect post-period uptime events. Violations don't matter here, those will be handled next
+    /// period.
+    fn uptime_post_period(
+        &mut self,
+        height: u32,
+        ts: i64,
+        current_time: u64,
+        reported_uptime: u64,
+        log: &mut Vec<String>,
+    ) {
+        let start_ts = self.period.start();
+        let end_ts = self.period.end();
+        match (self.power_managed, self.power_manage_boot) {
+            (Some(time_set_down), Some(boot_request)) => {
+                // node got power managed to down
+                let time_delta = current_time as i64 - time_set_down;
+                assert!(time_delta >= 0, "uptime events can't travel back in time");
+                let mut total_uptime =
+                    if let Some((last_reported_at, _, total_uptime)) = self.uptime_info {
+                        if last_reported_at > end_ts {
+                            log.push(format!(
+                            "Ignoring more than 1 farmer bot uptime event after period for node {}",
+                            self.node_id,
+                        ));
+                            return;
+                        }
+                        total_uptime
+                    } else {
+                        0
+                    };
+
+                // Verify farmer bot boot constraints
+                let mut credit_uptime = true;
+                if time_delta as u64 > MAX_POWER_MANAGER_DOWNTIME {
+                    credit_uptime = false;
+                    log.push(format!("Refusing to credit uptime for power managed node {} post period as the last boot was {time_delta} seconds ago, more than the allowed 24 hours", self.node_id));
+                }
+                if (current_time - reported_uptime) as i64 - boot_request
+                    > MAX_POWER_MANAGER_BOOT_TIME
+                {
+                    credit_uptime = false;
+                    // Mark a violation on the node.
+                    self.boot_duration_violations += 1;
+                    log.push(format!("Detected farmer bot boot violation for node {} post period, request was done at {} but node only came online at {}",
+                        self.node_id,
+                        Utc.timestamp_opt(boot_request, 0).unwrap().to_rfc2822(),
+                        Utc.timestamp_opt((current_time - reported_uptime) as i64, 0).unwrap().to_rfc2822()
+                    ));
+                }
+                // All good, at uptime in period.
+                if credit_uptime {
+                    let uptime_diff = end_ts - i64::max(start_ts, time_set_down);
+                    if uptime_diff < 0 {
+                        log.push(format!(
+                            "Ignoring farmer bot wakeup for node {} which went down after the period ended",
+                            self.node_id
+                        ));
+                    } else {
+                        total_uptime += uptime_diff as u64;
+                        log.push(format!(
+                            "Added {uptime_diff} seconds of uptime for node {}, for farmer bot boot post period",
+                            self.node_id
+                        ));
+                    }
+                }
+
+                // Clear the fact that we got power managed, if it is still the case, it
+                // will be set again in the proper event handler.
+                self.power_managed = None;
+                self.power_manage_boot = None;
+                self.uptime_info = Some((current_time as i64, reported_uptime, total_uptime));
+                // Also mark a boot
+                self.boot_time =
+                    Some(((current_time - reported_uptime) as i64, current_time as i64));
+            }
+            // We are power managed but woke up without boot request. We explicitly ignore this: being
+            // put to sleep by the farmer bot requires a wakeup from the farmer bot. This case also
+            // means nodes just go to sleep anyhow.
+            (Some(_), None) => {
+                log.push(format!("Ignoring boot for node {} which is power managed, but did not get a boot request from the farmer bot in the period", self.node_id));
+            }
+            // We got a wakeup request from farmer bot but we are not sleeping due to
+            // the farmer bot. This should not happen.
+            (None, Some(_)) => {
+                log.push(format!("Ignoring uptime for node {} after farmer bot asked for a boot while the node was not sleeping as a result of farmer bot", self.node_id));
+            }
+            (None, None) => {
+                if let Some((last_reported_at, last_reported_uptime, mut total_uptime)) =
+                    self.uptime_info
+                {
+                    // only collect 1 uptime event after the period ended
+                    if last_reported_at >= end_ts {
+                        return;
+                    }
+                    let report_delta = current_time as i64 - last_reported_at;
+                    let uptime_delta = reported_uptime as i64 - last_reported_uptime as i64;
+                    let delta_in_period = end_ts - last_reported_at;
+                    // There are quite some situations here. Notice that due to the
+                    // blockchain only producing blocks every 6 seconds, and network delay
+                    // + a host of other issues, we will allow a node to report uptime with
+                    // "grace period" of a minute or so in either direction.
+                    //
+                    // 1. uptime_delta > report_delta + GRACE_PERIOD. Node is talking
+                    //    rubish.
+                    if uptime_delta > report_delta + UPTIME_GRACE_PERIOD_SECONDS {
+                        // We need to register the violation here as we won't be able to
+                        // next period (since we don't scrape points from before the period
+                        // atm).
+                        self.flag_violation(Violation::UptimeTooHigh {
+                            previous_uptime: last_reported_uptime,
+                            previous_timestamp: last_reported_at,
+                            reported_uptime,
+                            reported_timestamp: ts,
+                            block_reported: height,
+                        });
+                        self.uptime_info =
+                            Some((current_time as i64, reported_uptime, total_uptime));
+                        log.push(format!("Node {} reported an uptime increase of {uptime_delta} seconds, while reports are {report_delta} seconds appart", self.node_id));
+                        return;
+                    }
+                    // 2. The difference in uptime is within reason of the difference in
+                    //    report times, i.e. the node is properly reporting.
+                    if uptime_delta <= report_delta + UPTIME_GRACE_PERIOD_SECONDS
+                        && uptime_delta >= report_delta - UPTIME_GRACE_PERIOD_SECONDS
+                    {
+                        self.check_clock_skew(current_time, reported_uptime, log);
+
+                        // It is technically possible for the delta to be less than 0 and
+                        // within the expected time frame. If nodes boot, send uptime, then
+                        // immediately reboot that is possible. In those cases, handle that
+                        // below, as that is the reboot detection.
+                        if uptime_delta > 0 {
+                            // Simply add the uptime delta. If this is too large or low by a
+                            // couple of seconds it will be corrected by the next pings anyhow.
+                            //
+                            // Make sure we don't add too much based on the period.
+                            let credit = u64::min(
+                                delta_in_period as u64,
+                                (NODE_UPTIME_REPORT_INTERVAL_SECONDS + UPTIME_GRACE_PERIOD_SECONDS)
+                                    as u64,
+                            );
+                            total_uptime += credit;
+                            if credit != delta_in_period as u64 {
+                                log.push(format!("credited node {} with {credit} seconds of uptime, less than the reported {delta_in_period} seconds as the gap is too big", self.node_id));
+                            } else {
+                                log.push(format!(
+                                    "credited node {} with {credit} seconds of reported uptime",
+                                    self.node_id
+                                ));
+                            }
+                            self.uptime_info =
+                                Some((current_time as i64, reported_uptime, total_uptime));
+                            return;
+                        }
+                    }
+                    // 3. The difference in uptime is too low. Again there are multiple
+                    //    scenarios. Either way we consider the node rebooted. Depending on
+                    //    the reported uptime, the node reports legit uptime, or it reports
+                    //    an uptime which is too high.
+                    //
+                    //    1. Uptime is within bounds.
+                    if reported_uptime as i64 <= report_delta {
+                        // Account for the fact that we are actually out of the period
+                        let out_of_period = current_time - end_ts as u64;
+                        if out_of_period < reported_uptime {
+                            let credit = u64::min(
+                                reported_uptime - out_of_period,
+                                (NODE_UPTIME_REPORT_INTERVAL_SECONDS + UPTIME_GRACE_PERIOD_SECONDS)
+                                    as u64,
+                            );
+                            total_uptime += credit;
+                            if (reported_uptime - out_of_period) != credit {
+                                log.push(format!("credited node {} with {credit} seconds of uptime after a reboot, less than the reported {} seconds as the gap is too big", self.node_id, reported_uptime - out_of_period));
+                            } else {
+                                log.push(format!(
+                                    "credited node {} with {credit} seconds of reported uptime after a reboot",
+                                    self.node_id
+                                ));
+                            }
+                        }
+                        self.uptime_info =
+                            Some((current_time as i64, reported_uptime, total_uptime));
+                        self.boot_time =
+                            Some(((current_time - reported_uptime) as i64, current_time as i64));
+                        return;
+                    }
+                    self.invalid_reboot(
+                        height,
+                        ts,
+                        reported_uptime,
+                        last_reported_at,
+                        last_reported_uptime,
+                        log,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Check if the boot time derived from an uptime report deviates too much from the last known
+    /// boot time, and flag a violation if it does.
+    fn check_clock_skew(&mut self, current_time: u64, reported_uptime: u64, log: &mut Vec<String>) {
+        if let Some((boot, detected)) = self.boot_time {
+            let new_boot = (current_time - reported_uptime) as i64;
+            if (new_boot - boot).abs() >= CLOCK_SKEW_INTERVAL {
+                // This is a violation
+                self.flag_violation(Violation::ClockSkew {
+                    original_boot: boot,
+                    current_boot: new_boot,
+                    previous_timestamp: detected,
+                    reported_timestamp: current_time as i64,
+                });
+
+                log.push(format!("Node {} has a detected clock skew of {} seconds, more than the allowed {CLOCK_SKEW_INTERVAL} seconds", self.node_id, (new_boot - boot).abs()));
+            }
+        } else {
+            panic!("node does not have boot time but does have uptime")
+        }
+    }
+
+    /// Handle an uptime report which indicates a reboot, but where the reported uptime is higher
+    /// than the time since the last report.
+    fn invalid_reboot(
+        &mut self,
+        height: u32,
+        ts: i64,
+        reported_uptime: u64,
+        last_reported_at: i64,
+        last_reported_uptime: u64,
+        log: &mut Vec<String>,
+    ) {
+        //    2. Uptime is actually higher than difference in timestamp, but
+        //       not high enough to be valid. This means the node was
+        //       supposedly rebooted _before_ the previous uptime report,
+        //       meaning either that report is invalid or this report is
+        //       invalid.
+        if reported_uptime > last_reported_uptime {
+            if self.flag_violation(Violation::UptimeTooLow {
+                previous_uptime: last_reported_uptime,
+                previous_timestamp: last_reported_at,
+                reported_uptime,
+                reported_timestamp: ts,
+                block_reported: height,
+            }) {
+                log.push(format!("Node {} reported uptime of {reported_uptime} seconds, so time would have advanced slower on the node than in the universe", self.node_id));
+            }
+            return;
+        }
+        //    3. Uptime is too high, this is garbage
+        if self.flag_violation(Violation::InvalidReboot {
+            previous_uptime: last_reported_uptime,
+            previous_timestamp: last_reported_at,
+            reported_uptime,
+            reported_timestamp: ts,
+            block_reported: height,
+        }) {
+            log.push(format!("Node {} reported uptime of {reported_uptime} seconds, so time would have advanced faster on the node than in the universe", self.node_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn period() -> Period {
+        Period::at_offset(70)
+    }
+
+    /// A tracker for node 1, with a first uptime report `since_start` seconds into the period, of
+    /// a node which booted at the start of the period.
+    fn booted_tracker(since_start: i64) -> (UptimeTracker, Vec<String>) {
+        let period = period();
+        let mut tracker = UptimeTracker::new(1, period, period.start());
+        let mut log = vec![];
+        let now = period.start() + since_start;
+        tracker.on_uptime_report(1, now, now as u64, since_start as u64, &mut log);
+        (tracker, log)
+    }
+
+    fn report(tracker: &mut UptimeTracker, log: &mut Vec<String>, now: i64, uptime: u64) {
+        tracker.on_uptime_report(2, now, now as u64, uptime, log);
+    }
+
+    #[test]
+    fn first_report_is_scaled_to_period_start() {
+        let period = period();
+        let mut tracker = UptimeTracker::new(1, period, period.start());
+        let mut log = vec![];
+        let now = period.start() + 1000;
+        tracker.on_uptime_report(1, now, now as u64, 100_000, &mut log);
+        assert_eq!(tracker.measured_uptime(), Some(1000));
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn regular_reports_are_credited() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        report(&mut tracker, &mut log, start + 2500, 2500);
+        report(&mut tracker, &mut log, start + 4900, 4900);
+        assert_eq!(tracker.measured_uptime(), Some(4900));
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn uptime_increase_within_grace_period_is_accepted() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        report(
+            &mut tracker,
+            &mut log,
+            start + 2500,
+            2500 + UPTIME_GRACE_PERIOD_SECONDS as u64,
+        );
+        assert_eq!(
+            tracker.measured_uptime(),
+            Some(100 + 2400 + UPTIME_GRACE_PERIOD_SECONDS as u64)
+        );
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn uptime_increase_beyond_grace_period_is_a_violation() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        report(
+            &mut tracker,
+            &mut log,
+            start + 2500,
+            2500 + UPTIME_GRACE_PERIOD_SECONDS as u64 + 1,
+        );
+        assert_eq!(tracker.measured_uptime(), Some(100));
+        assert!(matches!(
+            tracker.violation(),
+            Violation::UptimeTooHigh {
+                previous_uptime: 100,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn gap_in_reports_is_capped() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        report(&mut tracker, &mut log, start + 10_100, 10_100);
+        assert_eq!(
+            tracker.measured_uptime(),
+            Some(100 + (NODE_UPTIME_REPORT_INTERVAL_SECONDS + UPTIME_GRACE_PERIOD_SECONDS) as u64)
+        );
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn reboot_credits_reported_uptime() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        report(&mut tracker, &mut log, start + 2500, 300);
+        assert_eq!(tracker.measured_uptime(), Some(400));
+        assert!(tracker.violation().is_none());
+        // The reboot resets the boot time used for clock skew detection.
+        report(&mut tracker, &mut log, start + 4900, 2700);
+        assert_eq!(tracker.measured_uptime(), Some(2800));
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn reboot_before_previous_report_is_a_violation() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        // Uptime went up, but too little for the node to have been up all along.
+        report(&mut tracker, &mut log, start + 5000, 4000);
+        report(&mut tracker, &mut log, start + 7400, 5000);
+        assert!(matches!(
+            tracker.violation(),
+            Violation::UptimeTooLow {
+                previous_uptime: 4000,
+                reported_uptime: 5000,
+                ..
+            }
+        ));
+
+        let (mut tracker, mut log) = booted_tracker(100);
+        // Uptime went down, but not enough to fit a reboot after the previous report.
+        report(&mut tracker, &mut log, start + 5000, 4000);
+        report(&mut tracker, &mut log, start + 7400, 3000);
+        assert!(matches!(
+            tracker.violation(),
+            Violation::InvalidReboot {
+                previous_uptime: 4000,
+                reported_uptime: 3000,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn clock_skew_is_a_violation() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        // Every report is within the grace period, but the boot time keeps drifting.
+        let drift = UPTIME_GRACE_PERIOD_SECONDS as u64;
+        report(&mut tracker, &mut log, start + 2500, 2500 - drift);
+        assert!(tracker.violation().is_none());
+        report(&mut tracker, &mut log, start + 4900, 4900 - 2 * drift);
+        assert!(matches!(
+            tracker.violation(),
+            Violation::ClockSkew {
+                original_boot,
+                current_boot,
+                ..
+            } if current_boot - original_boot == CLOCK_SKEW_INTERVAL
+        ));
+    }
+
+    /// Have the farmer bot put the node down at `down`, and request a boot at `boot_request`.
+    fn power_cycle(
+        tracker: &mut UptimeTracker,
+        log: &mut Vec<String>,
+        down: i64,
+        boot_request: i64,
+    ) {
+        tracker.on_power_target_changed(down, Power::Down, log);
+        tracker.on_power_state_changed(down, PowerState::Down(1), log);
+        tracker.on_power_target_changed(boot_request, Power::Up, log);
+    }
+
+    #[test]
+    fn farmerbot_sleep_is_credited() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        power_cycle(&mut tracker, &mut log, start + 300, start + 10_000);
+        // Uptime up to the shutdown is credited when the node goes down.
+        assert_eq!(tracker.measured_uptime(), Some(300));
+        // Booted 200 seconds after the request.
+        report(&mut tracker, &mut log, start + 10_500, 300);
+        assert_eq!(tracker.measured_uptime(), Some(10_500));
+        tracker.finalize(&mut log);
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn farmerbot_wakeup_without_request_is_ignored() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        tracker.on_power_target_changed(start + 300, Power::Down, &mut log);
+        tracker.on_power_state_changed(start + 300, PowerState::Down(1), &mut log);
+        report(&mut tracker, &mut log, start + 10_500, 300);
+        assert_eq!(tracker.measured_uptime(), Some(300));
+    }
+
+    #[test]
+    fn farmerbot_boot_deadline() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let start = period().start();
+        let late = MAX_POWER_MANAGER_BOOT_TIME + 100;
+
+        // A single late boot is not credited, but tolerated.
+        power_cycle(&mut tracker, &mut log, start + 300, start + 10_000);
+        report(&mut tracker, &mut log, start + 10_000 + late + 100, 100);
+        assert_eq!(tracker.measured_uptime(), Some(300));
+        tracker.on_power_state_changed(start + 12_000, PowerState::Up, &mut log);
+        assert!(tracker.violation().is_none());
+
+        // A second one is not.
+        power_cycle(&mut tracker, &mut log, start + 20_000, start + 30_000);
+        report(&mut tracker, &mut log, start + 30_000 + late + 100, 100);
+        tracker.finalize(&mut log);
+        assert!(matches!(
+            tracker.violation(),
+            Violation::BootRequestExpired { failed_boots: 2 }
+        ));
+    }
+
+    #[test]
+    fn finalize_counts_unanswered_boot_request() {
+        let (mut tracker, mut log) = booted_tracker(100);
+        let period = period();
+        let start = period.start();
+        let late = MAX_POWER_MANAGER_BOOT_TIME + 200;
+
+        power_cycle(&mut tracker, &mut log, start + 300, start + 10_000);
+        report(&mut tracker, &mut log, start + 10_000 + late, 100);
+        tracker.on_power_state_changed(start + 12_000, PowerState::Up, &mut log);
+        // The node never answers the second request.
+        power_cycle(&mut tracker, &mut log, start + 20_000, start + 30_000);
+        tracker.set_mode(TrackingMode::PostPeriod);
+        tracker.finalize(&mut log);
+        assert!(matches!(
+            tracker.violation(),
+            Violation::BootRequestExpired { failed_boots: 2 }
+        ));
+
+        // Requests after the end of the period are left for the next period.
+        let (mut tracker, mut log) = booted_tracker(100);
+        power_cycle(&mut tracker, &mut log, start + 300, start + 10_000);
+        report(&mut tracker, &mut log, start + 10_000 + late, 100);
+        tracker.on_power_state_changed(start + 12_000, PowerState::Up, &mut log);
+        tracker.set_mode(TrackingMode::PostPeriod);
+        power_cycle(
+            &mut tracker,
+            &mut log,
+            period.end() + 10,
+            period.end() + 100,
+        );
+        tracker.finalize(&mut log);
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn finalize_ignores_node_asleep_since_period_start() {
+        let period = period();
+        let start = period.start();
+        let power = NodePower {
+            state: PowerState::Down(1),
+            target: Power::Up,
+        };
+        let mut tracker = UptimeTracker::with_power(1, period, start, power, Some(start - 100));
+        let mut log = vec![];
+        tracker.set_mode(TrackingMode::PostPeriod);
+        tracker.finalize(&mut log);
+        assert!(tracker.violation().is_none());
+        assert_eq!(tracker.measured_uptime(), None);
+    }
+
+    /// A tracker of a node which booted 2000 seconds before the end of the period, and reported
+    /// 1000 seconds later.
+    fn tracker_before_end() -> (UptimeTracker, Vec<String>) {
+        let period = period();
+        let end = period.end();
+        let mut tracker = UptimeTracker::new(1, period, period.start());
+        let mut log = vec![];
+        tracker.on_uptime_report(1, end - 1000, (end - 1000) as u64, 1000, &mut log);
+        (tracker, log)
+    }
+
+    fn post_period_tracker() -> (UptimeTracker, Vec<String>) {
+        let (mut tracker, log) = tracker_before_end();
+        tracker.set_mode(TrackingMode::PostPeriod);
+        (tracker, log)
+    }
+
+    #[test]
+    fn post_period_report_credits_up_to_period_end() {
+        let (mut tracker, mut log) = post_period_tracker();
+        let end = period().end();
+        report(&mut tracker, &mut log, end + 1400, 3400);
+        assert_eq!(tracker.measured_uptime(), Some(1000 + 1000));
+        // Only the first report after the period is used.
+        report(&mut tracker, &mut log, end + 3800, 5800);
+        assert_eq!(tracker.measured_uptime(), Some(2000));
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn post_period_reboot_credits_uptime_in_period() {
+        let (mut tracker, mut log) = post_period_tracker();
+        let end = period().end();
+        // Rebooted 500 seconds before the end of the period.
+        report(&mut tracker, &mut log, end + 500, 1000);
+        assert_eq!(tracker.measured_uptime(), Some(1000 + 500));
+
+        // Rebooted after the end of the period.
+        let (mut tracker, mut log) = post_period_tracker();
+        report(&mut tracker, &mut log, end + 500, 400);
+        assert_eq!(tracker.measured_uptime(), Some(1000));
+        assert!(tracker.violation().is_none());
+    }
+
+    #[test]
+    fn post_period_grace_period_and_clock_skew() {
+        let (mut tracker, mut log) = post_period_tracker();
+        let end = period().end();
+        report(
+            &mut tracker,
+            &mut log,
+            end + 1400,
+            1000 + 2400 + UPTIME_GRACE_PERIOD_SECONDS as u64 + 1,
+        );
+        assert!(matches!(
+            tracker.violation(),
+            Violation::UptimeTooHigh { .. }
+        ));
+
+        // Drift of the boot time carries over from the period.
+        let period = period();
+        let mut tracker = UptimeTracker::new(1, period, period.start());
+        let mut log = vec![];
+        let drift = UPTIME_GRACE_PERIOD_SECONDS as u64;
+        tracker.on_uptime_report(1, end - 3000, (end - 3000) as u64, 1000, &mut log);
+        report(&mut tracker, &mut log, end - 1000, 3000 - drift);
+        assert!(tracker.violation().is_none());
+        tracker.set_mode(TrackingMode::PostPeriod);
+        report(&mut tracker, &mut log, end + 1000, 5000 - 2 * drift);
+        assert!(matches!(tracker.violation(), Violation::ClockSkew { .. }));
+    }
+
+    #[test]
+    fn post_period_farmerbot_boot() {
+        let (mut tracker, mut log) = tracker_before_end();
+        let end = period().end();
+        // Put down in the period, woken up after it.
+        power_cycle(&mut tracker, &mut log, end - 500, end + 100);
+        tracker.set_mode(TrackingMode::PostPeriod);
+        report(&mut tracker, &mut log, end + 400, 200);
+        // Uptime until the shutdown is credited when the node goes down, the rest of the period
+        // when the node boots.
+        assert_eq!(tracker.measured_uptime(), Some(2000));
+        tracker.finalize(&mut log);
+        assert!(tracker.violation().is_none());
+
+        // Late boots after the period still count towards the boot violations.
+        let (mut tracker, mut log) = tracker_before_end();
+        power_cycle(&mut tracker, &mut log, end - 500, end - 400);
+        tracker.set_mode(TrackingMode::PostPeriod);
+        let late = MAX_POWER_MANAGER_BOOT_TIME + 200;
+        report(&mut tracker, &mut log, end - 400 + late + 100, 100);
+        assert_eq!(tracker.measured_uptime(), Some(1500));
+        tracker.finalize(&mut log);
+        assert!(tracker.violation().is_none());
+    }
+}
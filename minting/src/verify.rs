@@ -0,0 +1,326 @@
+//! Verification of a submitted set of receipts against a recomputed period.
+//!
+//! Receipts are matched by hash first. Receipts which don't have an identical counterpart are
+//! then matched by node id, so a receipt for the same node with different content is reported as
+//! a single difference, with the fields which differ, rather than as a missing and an extra
+//! receipt.
+
+use crate::receipt::VersionedReceipt;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, fs, io, os::unix::prelude::OsStrExt, path::Path};
+
+/// A single field which differs between 2 receipts.
+pub struct FieldDiff {
+    /// Path of the field in the receipt, e.g. `reward.tft`.
+    pub field: String,
+    pub expected: Value,
+    pub submitted: Value,
+}
+
+/// A submitted receipt which does not match the recomputed receipt for the same node.
+pub struct ReceiptDiff {
+    pub node_id: u32,
+    pub expected_hash: [u8; 32],
+    pub submitted_hash: [u8; 32],
+    pub fields: Vec<FieldDiff>,
+}
+
+/// The result of comparing a recomputed set of receipts with a submitted set.
+#[derive(Default)]
+pub struct VerificationReport {
+    /// Amount of receipts which are identical in both sets.
+    pub matching: usize,
+    /// Recomputed receipts which are not present in the submitted set.
+    pub missing: Vec<([u8; 32], VersionedReceipt)>,
+    /// Submitted receipts which are not present in the recomputed set.
+    pub extra: Vec<([u8; 32], VersionedReceipt)>,
+    /// Submitted receipts which differ from the recomputed receipt for the same node.
+    pub different: Vec<ReceiptDiff>,
+}
+
+impl VerificationReport {
+    /// Whether the submitted set is identical to the recomputed set.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.different.is_empty()
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (hash, receipt) in &self.missing {
+            writeln!(
+                f,
+                "missing receipt {} for node {}",
+                hex::encode(hash),
+                receipt.node_id()
+            )?;
+        }
+        for (hash, receipt) in &self.extra {
+            writeln!(
+                f,
+                "extra receipt {} for node {}",
+                hex::encode(hash),
+                receipt.node_id()
+            )?;
+        }
+        for diff in &self.different {
+            writeln!(
+                f,
+                "receipt {} for node {} differs from expected receipt {}",
+                hex::encode(diff.submitted_hash),
+                diff.node_id,
+                hex::encode(diff.expected_hash)
+            )?;
+            for field in &diff.fields {
+                writeln!(
+                    f,
+                    "  {}: expected {}, submitted {}",
+                    field.field, field.expected, field.submitted
+                )?;
+            }
+        }
+        write!(
+            f,
+            "{} matching, {} missing, {} extra, {} different",
+            self.matching,
+            self.missing.len(),
+            self.extra.len(),
+            self.different.len()
+        )
+    }
+}
+
+/// Compare a recomputed set of receipts with a submitted set. Both sets are keyed by the hash
+/// under which the receipt is published.
+pub fn compare(
+    mut expected: BTreeMap<[u8; 32], VersionedReceipt>,
+    mut submitted: BTreeMap<[u8; 32], VersionedReceipt>,
+) -> VerificationReport {
+    let mut report = VerificationReport::default();
+
+    // The key of a submitted receipt is taken from its file name, so the content might still
+    // differ even if the hash matches.
+    let common: Vec<_> = expected
+        .keys()
+        .filter(|hash| submitted.contains_key(*hash))
+        .copied()
+        .collect();
+    for hash in common {
+        let expected_receipt = expected.remove(&hash).unwrap();
+        let submitted_receipt = submitted.remove(&hash).unwrap();
+        let fields = diff_receipts(&expected_receipt, &submitted_receipt);
+        if fields.is_empty() {
+            report.matching += 1;
+        } else {
+            report.different.push(ReceiptDiff {
+                node_id: submitted_receipt.node_id(),
+                expected_hash: hash,
+                submitted_hash: hash,
+                fields,
+            });
+        }
+    }
+
+    let mut by_node: BTreeMap<_, _> = submitted
+        .into_iter()
+        .map(|(hash, receipt)| (receipt.node_id(), (hash, receipt)))
+        .collect();
+    for (expected_hash, expected_receipt) in expected {
+        match by_node.remove(&expected_receipt.node_id()) {
+            Some((submitted_hash, submitted_receipt)) => report.different.push(ReceiptDiff {
+                node_id: expected_receipt.node_id(),
+                expected_hash,
+                submitted_hash,
+                fields: diff_receipts(&expected_receipt, &submitted_receipt),
+            }),
+            None => report.missing.push((expected_hash, expected_receipt)),
+        }
+    }
+    report.extra = by_node.into_values().collect();
+
+    report
+}
+
+/// Load all receipts in a directory, keyed by their file name. Receipts of all versions are
+/// accepted. The hash of the receipt is not checked against the file name, a mismatch shows up as
+/// a difference when comparing.
+pub fn load_receipts(dir: &Path) -> io::Result<BTreeMap<[u8; 32], VersionedReceipt>> {
+    let mut receipts = BTreeMap::new();
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        if !file.file_type()?.is_file() {
+            continue;
+        }
+        let mut hash = [0; 32];
+        hex::decode_to_slice(file.file_name().as_bytes(), &mut hash)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let data = fs::read(file.path())?;
+        let receipt = VersionedReceipt::from_json(&data)?;
+        receipts.insert(hash, receipt);
+    }
+    Ok(receipts)
+}
+
+/// Get all fields which differ between 2 receipts.
+fn diff_receipts(expected: &VersionedReceipt, submitted: &VersionedReceipt) -> Vec<FieldDiff> {
+    let mut diffs = Vec::new();
+    diff_values(
+        "",
+        &serde_json::to_value(expected).unwrap(),
+        &serde_json::to_value(submitted).unwrap(),
+        &mut diffs,
+    );
+    diffs
+}
+
+fn diff_values(path: &str, expected: &Value, submitted: &Value, diffs: &mut Vec<FieldDiff>) {
+    match (expected, submitted) {
+        (Value::Object(expected), Value::Object(submitted)) => {
+            for (key, expected_value) in expected {
+                let field = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                diff_values(
+                    &field,
+                    expected_value,
+                    submitted.get(key).unwrap_or(&Value::Null),
+                    diffs,
+                );
+            }
+            for (key, submitted_value) in submitted {
+                if !expected.contains_key(key) {
+                    diffs.push(FieldDiff {
+                        field: if path.is_empty() {
+                            key.clone()
+                        } else {
+                            format!("{path}.{key}")
+                        },
+                        expected: Value::Null,
+                        submitted: submitted_value.clone(),
+                    });
+                }
+            }
+        }
+        _ if expected != submitted => diffs.push(FieldDiff {
+            field: path.to_string(),
+            expected: expected.clone(),
+            submitted: submitted.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A v1 receipt for the node with the given reward, keyed by its hash.
+    fn receipt(node_id: u32, tft: u64) -> ([u8; 32], VersionedReceipt) {
+        let json = format!(
+            r#"{{"period":{{"start":1700000000,"end":1702629440}},"node_id":{node_id},"twin_id":7,"farm_id":3,"farm_name":"farm","stellar_payout_address":"GADDRESS","measured_uptime":2600000,"tft_connection_price":80,"cloud_units":{{"cu":1.5,"su":2.25,"nu":0.0}},"resource_units":{{"cru":4.0,"mru":16.0,"hru":1000.0,"sru":500.0}},"resource_utilization":{{"cru":0.5,"mru":0.25,"hru":0.0,"sru":0.125,"ip":0.0}},"reward":{{"musd":12345,"tft":{tft}}},"carbon_offset":null,"node_type":"DIY","farming_policy_id":1,"resource_rewards":{{"cu":2400,"su":1000,"nu":30,"ipv4":5}}}}"#
+        );
+        let receipt = VersionedReceipt::from_json(json.as_bytes()).unwrap();
+        (receipt.hash(), receipt)
+    }
+
+    #[test]
+    fn identical_sets_match() {
+        let report = compare(
+            BTreeMap::from([receipt(1, 10), receipt(2, 20)]),
+            BTreeMap::from([receipt(1, 10), receipt(2, 20)]),
+        );
+        assert!(report.is_ok());
+        assert_eq!(report.matching, 2);
+    }
+
+    #[test]
+    fn missing_and_extra_receipts_are_reported() {
+        let report = compare(
+            BTreeMap::from([receipt(1, 10), receipt(2, 20)]),
+            BTreeMap::from([receipt(1, 10), receipt(3, 30)]),
+        );
+        assert!(!report.is_ok());
+        assert_eq!(report.matching, 1);
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].1.node_id(), 2);
+        assert_eq!(report.extra.len(), 1);
+        assert_eq!(report.extra[0].1.node_id(), 3);
+        assert!(report.different.is_empty());
+    }
+
+    #[test]
+    fn receipt_of_the_same_node_with_other_content_is_a_difference() {
+        let (expected_hash, expected) = receipt(1, 10);
+        let (submitted_hash, submitted) = receipt(1, 11);
+        let report = compare(
+            BTreeMap::from([(expected_hash, expected)]),
+            BTreeMap::from([(submitted_hash, submitted)]),
+        );
+        assert!(report.missing.is_empty() && report.extra.is_empty());
+        assert_eq!(report.different.len(), 1);
+        let diff = &report.different[0];
+        assert_eq!(diff.node_id, 1);
+        assert_eq!(diff.expected_hash, expected_hash);
+        assert_eq!(diff.submitted_hash, submitted_hash);
+        assert_eq!(diff.fields.len(), 1);
+        assert_eq!(diff.fields[0].field, "reward.tft");
+        assert_eq!(diff.fields[0].expected, Value::from(10));
+        assert_eq!(diff.fields[0].submitted, Value::from(11));
+    }
+
+    #[test]
+    fn receipt_under_the_expected_hash_with_other_content_is_a_difference() {
+        let (hash, expected) = receipt(1, 10);
+        let report = compare(
+            BTreeMap::from([(hash, expected)]),
+            BTreeMap::from([(hash, receipt(1, 11).1)]),
+        );
+        assert_eq!(report.matching, 0);
+        assert_eq!(report.different.len(), 1);
+        assert_eq!(report.different[0].submitted_hash, hash);
+    }
+
+    #[test]
+    fn added_and_removed_fields_are_reported() {
+        let expected = serde_json::json!({"a": 1, "b": {"c": 2}});
+        let submitted = serde_json::json!({"b": {"c": 3}, "d": 4});
+        let mut diffs = Vec::new();
+        diff_values("", &expected, &submitted, &mut diffs);
+        let fields: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.field.as_str(), d.expected.clone(), d.submitted.clone()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("a", Value::from(1), Value::Null),
+                ("b.c", Value::from(2), Value::from(3)),
+                ("d", Value::Null, Value::from(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn receipts_are_loaded_by_file_name() {
+        let dir = std::env::temp_dir().join(format!("verify-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+        let (hash, receipt) = receipt(1, 10);
+        fs::write(
+            dir.join(hex::encode(hash)),
+            serde_json::to_vec(&receipt).unwrap(),
+        )
+        .unwrap();
+        let receipts = load_receipts(&dir).unwrap();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[&hash].node_id(), 1);
+
+        fs::write(dir.join("not-a-hash"), "{}").unwrap();
+        assert_eq!(
+            load_receipts(&dir).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
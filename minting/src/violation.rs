@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub enum Violation {
     /// No violation detected
     #[default]
@@ -48,6 +48,9 @@ pub enum Violation {
     InvalidPublicKey,
     /// A node is refering to a twin which does not exist.
     MissingTwin,
+    /// A node is refering to a farm which does not exist at the end of the period, so there is no
+    /// farm to pay the node to.
+    MissingFarm,
     /// Amount of boot requests the node failed to complete in the allotted time.
     BootRequestExpired { failed_boots: usize },
 }
@@ -63,6 +66,7 @@ impl fmt::Display for Violation {
             Violation::MissingRelay => f.pad("Node has uptime but the node twin does not have a relay set"),
             Violation::InvalidPublicKey => f.pad("Node twin has a public key set but it's not in a valid format"),
             Violation::MissingTwin => f.pad("Node twin does not exist"),
+            Violation::MissingFarm => f.pad("Node farm does not exist"),
             Violation::BootRequestExpired { failed_boots } => f.write_fmt(format_args!("Power managed node failed {failed_boots} boot requests")),
         }
     }